@@ -51,6 +51,20 @@ enum Command {
 
     /// Identify which users were in a given position during a given epoch. Can only be used by health authorities.
     IdentifyPosition { x: i64, y: i64, epoch: u64 },
+
+    /// Check whether a user was in a given position during a given epoch, without learning who else was. Can only be used by health authorities.
+    CheckPosition {
+        user_id: EntityId,
+        x: i64,
+        y: i64,
+        epoch: u64,
+    },
+
+    /// Count how many users were in each position during a given epoch. Can only be used by health authorities.
+    PositionHistogram { epoch: u64 },
+
+    /// List where every user was during a given epoch. Can only be used by health authorities.
+    EpochPositions { epoch: u64 },
 }
 
 #[tokio::main]
@@ -61,9 +75,11 @@ async fn main() -> eyre::Result<()> {
     let options = Options::from_args();
 
     // do not remove
-    let id =
-        model::keys::KeyStore::load_from_files(&options.entity_registry_path, &options.skeys_path)?
-            .my_id();
+    let id = model::keys::KeyStore::load_from_files(
+        &options.entity_registry_path,
+        Some(&options.skeys_path),
+    )?
+    .my_id();
     let _guard = tracing_utils::setup(env!("CARGO_PKG_NAME"), vec![("id", id.to_string())])?;
 
     true_main(options, id).await
@@ -73,7 +89,7 @@ async fn main() -> eyre::Result<()> {
 async fn true_main(options: Options, _id: u32) -> eyre::Result<()> {
     let keystore = Arc::new(KeyStore::load_from_files(
         options.entity_registry_path.clone(),
-        options.skeys_path.clone(),
+        Some(options.skeys_path.clone()),
     )?);
 
     let client = HdltApiClient::new(
@@ -109,6 +125,37 @@ async fn true_main(options: Options, _id: u32) -> eyre::Result<()> {
                 println!("> {}", id);
             }
         }
+        Command::CheckPosition {
+            user_id,
+            x,
+            y,
+            epoch,
+        } => {
+            let position = Position(x, y);
+            let present = client.is_user_at_position(user_id, position, epoch).await?;
+            println!(
+                "At epoch {} user {} was {}at position ({}, {})",
+                epoch,
+                user_id,
+                if present { "" } else { "not " },
+                position.0,
+                position.1
+            );
+        }
+        Command::PositionHistogram { epoch } => {
+            let histogram = client.obtain_position_histogram(epoch).await?;
+            println!("At epoch {} users were at the following positions:", epoch);
+            for (position, count) in histogram {
+                println!("> ({}, {}): {}", position.0, position.1, count);
+            }
+        }
+        Command::EpochPositions { epoch } => {
+            let positions = client.obtain_epoch_positions(epoch).await?;
+            println!("At epoch {} users were at the following positions:", epoch);
+            for (id, position) in positions {
+                println!("> {}: ({}, {})", id, position.0, position.1);
+            }
+        }
     }
 
     Ok(())
@@ -10,10 +10,12 @@ async fn main() -> eyre::Result<()> {
     let options = UserOptions::from_args();
 
     // do not remove
-    let id =
-        model::keys::KeyStore::load_from_files(&options.entity_registry_path, &options.skeys_path)?
-            .my_id()
-            .to_string();
+    let id = model::keys::KeyStore::load_from_files(
+        &options.entity_registry_path,
+        Some(&options.skeys_path),
+    )?
+    .my_id()
+    .to_string();
     let _guard = tracing_utils::setup(env!("CARGO_PKG_NAME"), vec![("id", id)])?;
 
     let (user, task_handle) = User::new(&options).await?;
@@ -3,9 +3,9 @@ use model::{
     keys::EntityId, keys::KeyStore, neighbourhood::are_neighbours, Position, ProximityProof,
     ProximityProofRequest, UnverifiedPositionProof,
 };
-use protos::driver::EpochUpdateRequest;
 use protos::driver::{correct_user_driver_server::CorrectUserDriver, InitialConfigRequest};
-use protos::util::Empty;
+use protos::driver::{EpochUpdateRequest, ObtainPositionRequest};
+use protos::util::{Empty, Position as GrpcPosition};
 
 use tonic::transport::Uri;
 use tonic::{Code as StatusCode, Request, Response, Status};
@@ -16,7 +16,7 @@ use std::sync::Arc;
 use tokio::sync::RwLock;
 
 use crate::hdlt_api::{HdltApiClient, HdltError};
-use crate::state::CorrectUserState;
+use crate::state::{CorrectUserState, Neighbour};
 use crate::witness_api::request_proof_correct;
 
 use futures::stream::{FuturesUnordered, StreamExt};
@@ -45,14 +45,16 @@ impl CorrectDriverService {
         &self,
         epoch: u64,
         position: Position,
-        neighbours: Vec<EntityId>,
+        correct: Vec<Neighbour>,
+        malicious: Vec<EntityId>,
         neighbour_faults: u64,
         server_faults: u64,
     ) {
         self.state.write().await.update(
             epoch,
             position,
-            neighbours,
+            correct,
+            malicious,
             neighbour_faults,
             server_faults,
         );
@@ -81,7 +83,12 @@ impl CorrectUserDriver for CorrectDriverService {
         self.update_state(
             message.new_epoch,
             position,
-            message.visible_neighbour_ids,
+            message
+                .correct_neighbours
+                .into_iter()
+                .map(Neighbour::from_proto)
+                .collect(),
+            message.malicious_neighbour_ids,
             message.neighbour_faults,
             message.server_faults,
         )
@@ -93,13 +100,41 @@ impl CorrectUserDriver for CorrectDriverService {
 
     #[instrument(skip(self))]
     async fn prove_position(&self, request: Request<Empty>) -> GrpcResult<Empty> {
+        let witnesses = self.state.write().await.take_witness_candidates();
         let state = self.state.read().await;
-        prove_position(&state, self.key_store.clone(), self.server_uris.clone())
-            .await
-            .map_err(|e| Status::new(StatusCode::Aborted, format!("{:#?}", e)))?;
+        prove_position(
+            &state,
+            witnesses,
+            self.key_store.clone(),
+            self.server_uris.clone(),
+        )
+        .await
+        .map_err(|e| Status::new(StatusCode::Aborted, format!("{:#?}", e)))?;
 
         Ok(Response::new(Empty {}))
     }
+
+    #[instrument(skip(self))]
+    async fn obtain_position(
+        &self,
+        request: Request<ObtainPositionRequest>,
+    ) -> GrpcResult<GrpcPosition> {
+        let epoch = request.into_inner().epoch;
+        let state = self.state.read().await;
+        let position = obtain_position(
+            &state,
+            self.key_store.clone(),
+            self.server_uris.clone(),
+            epoch,
+        )
+        .await
+        .map_err(|e| Status::new(StatusCode::Aborted, format!("{:#?}", e)))?;
+
+        Ok(Response::new(GrpcPosition {
+            x: position.0,
+            y: position.1,
+        }))
+    }
 }
 
 /// Prove the user location to the server
@@ -108,10 +143,11 @@ impl CorrectUserDriver for CorrectDriverService {
 ///
 async fn prove_position(
     state: &CorrectUserState,
+    witnesses: Vec<EntityId>,
     key_store: Arc<KeyStore>,
     server_uris: Vec<(u32, Uri)>,
 ) -> eyre::Result<()> {
-    let proofs = request_proximity_proofs(&state, key_store.clone())
+    let proofs = request_proximity_proofs(&state, witnesses, key_store.clone())
         .await
         .wrap_err("failed to get proximity proofs")?;
 
@@ -120,15 +156,16 @@ async fn prove_position(
         .wrap_err("failed to submit position report to server")
 }
 
-/// Gather proofs of proximity
+/// Gather proofs of proximity from (some of) the given witnesses
 #[instrument(skip(key_store))]
 async fn request_proximity_proofs(
     state: &CorrectUserState,
+    witnesses: Vec<EntityId>,
     key_store: Arc<KeyStore>,
 ) -> eyre::Result<Vec<ProximityProof>> {
     let proof_request = ProximityProofRequest::new(state.epoch(), state.position(), &key_store);
-    let mut futs: FuturesUnordered<_> = state
-        .neighbourhood()
+    let mut futs: FuturesUnordered<_> = witnesses
+        .into_iter()
         .map(|id| request_proof_correct(&state, proof_request.clone(), id, key_store.clone()))
         .collect();
     let mut proofs = Vec::with_capacity(state.neighbour_faults() as usize);
@@ -189,3 +226,25 @@ async fn submit_position_proof(
         })
         .await
 }
+
+/// Obtain the user's own position at an epoch from the server (with regular semantics)
+#[instrument(skip(key_store))]
+async fn obtain_position(
+    state: &CorrectUserState,
+    key_store: Arc<KeyStore>,
+    server_uris: Vec<(u32, Uri)>,
+    epoch: u64,
+) -> Result<Position, HdltError> {
+    let my_id = key_store.my_id();
+    let server_api = HdltApiClient::new(
+        server_uris,
+        key_store,
+        state.epoch(),
+        state.server_faults(),
+        state.neighbour_faults(),
+    )?;
+
+    server_api
+        .obtain_position_report_regular(my_id, epoch)
+        .await
+}
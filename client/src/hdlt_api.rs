@@ -1,15 +1,18 @@
-use std::collections::HashMap;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap};
 use std::fmt::Debug;
+use std::ops::Range;
 use std::sync::{
-    atomic::{AtomicU64, Ordering},
-    Arc,
+    atomic::{AtomicBool, AtomicU64, Ordering},
+    Arc, Mutex,
 };
 use std::time::Duration;
 
 use futures::stream::{FuturesUnordered, StreamExt};
+use net_utils::create_tcp_incoming;
 use protos::hdlt::hdlt_api_client::HdltApiClient as GrpcHdltApiClient;
 use protos::hdlt::CipheredRrMessage;
-use tokio::sync::{oneshot, RwLock};
+use tokio::sync::{mpsc, RwLock, RwLockReadGuard};
 use tonic::transport::{Channel, Server, Uri};
 use tonic::Status;
 use tower::timeout::Timeout;
@@ -17,19 +20,29 @@ use tracing::*;
 use tracing_utils::Request;
 
 use model::{
-    api::{ApiReply, ApiRequest, PoWCertified, RrMessage, RrMessageError, RrRequest},
-    keys::{EntityId, KeyStore, KeyStoreError, Nonce},
-    Position, PositionProofValidationError, UnverifiedMisbehaviorProof, UnverifiedPositionProof,
+    api::{
+        ApiClientError, ApiErrorCode, ApiReply, ApiRequest, AuthToken, Capabilities,
+        EpochAttestationError, PoWCertified, ReadCertificate, ReadCertificateError, RrMessage,
+        RrRequest, AUTH_METADATA_KEY,
+    },
+    keys::{EntityId, KeyStore, Nonce, Role},
+    MisbehaviorProof, MisbehaviorProofValidationError, Position, PositionProofValidationError,
+    ProofBundle, ProofBundleValidationError, UnverifiedMisbehaviorProof, UnverifiedPositionProof,
 };
 
 use thiserror::Error;
 use tracing::instrument;
 
-use crate::create_tcp_incoming;
-
 static REQUEST_ID: AtomicU64 = AtomicU64::new(0);
 
 const REQUEST_TIMEOUT: Duration = Duration::from_secs(15); // 15s ought to be enough
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How much later each tier of less preferred servers is contacted, see [HdltApiClient::set_server_priority]
+const PRIORITY_STAGGER: Duration = Duration::from_millis(100);
+
+/// How many reads [HdltApiClient::obtain_positions_for_users] has in flight at once
+const MAX_CONCURRENT_READS: usize = 16;
 
 #[derive(Debug)]
 pub struct HdltApiClient {
@@ -54,39 +67,56 @@ pub struct HdltApiClient {
 
     /// Notification mechanism
     notification: ReturnNotification,
-}
 
-#[derive(Debug, Error)]
-pub enum HdltError {
-    #[error("Error creating remote")]
-    InitializationError(#[source] tonic::transport::Error),
+    /// How atomic read values get back to us
+    return_transport: ReturnTransport,
 
-    #[error("Failed to serialize request")]
-    SerializationError(#[source] Box<bincode::ErrorKind>),
+    /// Priority of each server, 0 if absent
+    priorities: HashMap<u32, u32>,
 
-    #[error("Failed to cipher request")]
-    CipherError(#[source] KeyStoreError),
+    /// Whether [HdltApiClient::close] was called
+    closed: AtomicBool,
 
-    #[error("Server sent unexpected status")]
-    UnexpectedStatus(#[from] Status),
+    /// Results of [HdltApiClient::request_position_reports] in the current epoch
+    report_cache: Mutex<HashMap<(EntityId, Range<u64>), Vec<(u64, UnverifiedPositionProof)>>>,
+
+    /// Epoch of our latest acknowledged position report, so that reading it back
+    /// always sees it (see [ApiRequest::ObtainPositionReportRegular])
+    last_write: Mutex<Option<u64>>,
+
+    /// Optional features we advertise to servers
+    capabilities: Capabilities,
 
-    #[error("Invalid nonce")]
-    InvalidNonce,
+    /// Features agreed on with each server, see [HdltApiClient::exchange_capabilities]
+    negotiated: Mutex<HashMap<u32, Capabilities>>,
+}
+
+/// How servers return the value of an atomic read to the client
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReturnTransport {
+    /// Servers call back a temporary server spawned by the client, which must be reachable
+    Push,
 
-    #[error("Failed to decipher reply")]
-    DecipherError(#[source] KeyStoreError),
+    /// The client polls the servers, for when it can't accept connections (e.g. behind a NAT)
+    Pull,
+}
 
-    #[error("Failed to deserialize reply")]
-    DeserializationError(#[source] Box<bincode::ErrorKind>),
+impl Default for ReturnTransport {
+    fn default() -> Self {
+        ReturnTransport::Push
+    }
+}
 
-    #[error("Request reply protocol violation")]
-    RequestReplyProtocolViolation(#[from] RrMessageError),
+#[derive(Debug, Error)]
+pub enum HdltError {
+    #[error("Error creating remote")]
+    InitializationError(#[source] tonic::transport::Error),
 
-    #[error("Error executing request: {}", .0)]
-    ServerError(String),
+    #[error("Server sent unexpected status")]
+    UnexpectedStatus(#[from] Status),
 
-    #[error("Server sent unexpected reply message: {:#?}", .0)]
-    UnexpectedReply(ApiReply),
+    #[error(transparent)]
+    Api(#[from] ApiClientError),
 
     #[error("Could not get enough servers to answer our request")]
     NotEnoughServers,
@@ -96,11 +126,39 @@ pub enum HdltError {
 
     #[error("Invalid Position Proof")]
     InvalidPositionProof(#[from] PositionProofValidationError),
+
+    #[error("Invalid Misbehavior Proof")]
+    InvalidMisbehaviorProof(#[from] MisbehaviorProofValidationError),
+
+    #[error("Invalid Proof Bundle")]
+    InvalidProofBundle(#[from] ProofBundleValidationError),
+
+    #[error("Unknown server {}", .0)]
+    UnknownServer(EntityId),
+
+    #[error("Client was closed")]
+    Closed,
+
+    #[error("No servers are configured")]
+    NoServersConfigured,
+
+    #[error("Servers returned divergent values, not enough of them agree")]
+    DivergentReplies,
+
+    #[error("Invalid Epoch Attestation")]
+    InvalidEpochAttestation(#[from] EpochAttestationError),
+
+    #[error("Invalid Read Certificate")]
+    InvalidReadCertificate(#[from] ReadCertificateError),
 }
 
 type Result<T> = std::result::Result<T, HdltError>;
 
 impl HdltApiClient {
+    /// Create a client that talks to a set of servers, of which up to `server_faults` may be faulty
+    ///
+    /// Fails with [HdltError::NoServersConfigured] if there are no servers
+    ///
     pub fn new(
         uris: Vec<(u32, Uri)>,
         keystore: Arc<KeyStore>,
@@ -108,6 +166,10 @@ impl HdltApiClient {
         server_faults: u64,
         neighbour_faults: u64,
     ) -> Result<Self> {
+        if uris.is_empty() {
+            return Err(HdltError::NoServersConfigured);
+        }
+
         let channels = Arc::new(RwLock::new(
             uris.into_iter()
                 .map(|(id, uri)| {
@@ -128,9 +190,139 @@ impl HdltApiClient {
             server_faults,
             neighbour_faults,
             notification: ReturnNotification::new(),
+            return_transport: ReturnTransport::default(),
+            priorities: HashMap::new(),
+            closed: AtomicBool::new(false),
+            report_cache: Mutex::new(HashMap::new()),
+            last_write: Mutex::new(None),
+            capabilities: Capabilities::default(),
+            negotiated: Mutex::new(HashMap::new()),
         })
     }
 
+    /// Create a client that talks to exactly one server
+    ///
+    /// Meant to be used with the single-server methods ([HdltApiClient::submit_to] and
+    /// [HdltApiClient::obtain_from]), no server faults are tolerated
+    ///
+    pub fn new_single(
+        server_id: u32,
+        uri: Uri,
+        keystore: Arc<KeyStore>,
+        current_epoch: u64,
+        neighbour_faults: u64,
+    ) -> Result<Self> {
+        Self::new(
+            vec![(server_id, uri)],
+            keystore,
+            current_epoch,
+            0,
+            neighbour_faults,
+        )
+    }
+
+    /// Add a server to the set this client talks to (or replace its uri, if already known)
+    ///
+    /// Takes effect from the next request onwards
+    ///
+    pub async fn add_server(&self, server_id: u32, uri: Uri) -> Result<()> {
+        if self.closed.load(Ordering::SeqCst) {
+            return Err(HdltError::Closed);
+        }
+
+        let channel = Channel::builder(uri)
+            .connect_lazy()
+            .map_err(HdltError::InitializationError)?;
+        self.channels.write().await.insert(server_id, channel);
+        self.negotiated.lock().unwrap().remove(&server_id);
+
+        Ok(())
+    }
+
+    /// Remove a server from the set this client talks to
+    ///
+    /// Returns whether the server was known. Takes effect from the next request onwards
+    ///
+    pub async fn remove_server(&self, server_id: u32) -> bool {
+        self.negotiated.lock().unwrap().remove(&server_id);
+        self.channels.write().await.remove(&server_id).is_some()
+    }
+
+    /// Close the connections to every server, and abandon pending atomic reads
+    ///
+    /// Any further request fails with [HdltError::Closed] instead of reconnecting.
+    /// Takes `&self` so that a client shared between tasks can be closed by any of them.
+    ///
+    pub async fn close(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+        self.channels.write().await.clear();
+        self.report_cache.lock().unwrap().clear();
+        self.negotiated.lock().unwrap().clear();
+        self.notification.clear().await;
+    }
+
+    /// User submits position report to a single server
+    ///
+    /// Bypasses the quorum protocol and returns the raw server reply
+    ///
+    #[instrument]
+    pub async fn submit_to<P: Into<UnverifiedPositionProof> + Debug>(
+        &self,
+        server_id: u32,
+        proof: P,
+    ) -> Result<ApiReply> {
+        let proof = proof.into();
+        let pow_protected = PoWCertified::new(proof);
+
+        self.invoke_single(server_id, ApiRequest::SubmitPositionReport(pow_protected))
+            .await
+    }
+
+    /// Obtain a position report from a single server
+    ///
+    /// Bypasses the quorum protocol: returns the first value the server calls back with,
+    /// or its raw reply if it refuses the request
+    ///
+    #[instrument]
+    pub async fn obtain_from(
+        &self,
+        server_id: u32,
+        user_id: EntityId,
+        epoch: u64,
+    ) -> Result<ApiReply> {
+        let callback = self.spawn_callback_server_if_push().await;
+        let request_id = REQUEST_ID.fetch_add(1, Ordering::SeqCst);
+        let rx = self.notification.wait_on(request_id).await;
+
+        let reply = self
+            .invoke_single(
+                server_id,
+                ApiRequest::ObtainPositionReport {
+                    request_id,
+                    user_id,
+                    epoch,
+                    callback_uri: callback.as_ref().map(|(_, uri)| uri.clone()),
+                },
+            )
+            .await;
+
+        // the one server is trusted
+        let res = match reply {
+            Ok(ApiReply::Ok) => self
+                .wait_return(rx, callback.is_some(), &[server_id], request_id, 0)
+                .await
+                .map(|(epoch, position)| ApiReply::PositionReport(epoch, position)),
+            other => other,
+        };
+
+        // close temporary server
+        if let Some((server, _)) = callback {
+            server.abort();
+        }
+        self.notification.forget(request_id).await;
+        res
+    }
+
     /// User submits position report to server
     ///
     /// Invokes a protocol write (with atomic semantics)
@@ -140,16 +332,56 @@ impl HdltApiClient {
         &self,
         proof: P,
     ) -> Result<()> {
+        self.submit_position_report_with_progress(proof, |_| {})
+            .await
+            .map(|_| ())
+    }
+
+    /// User submits position report to server, being notified as each server acknowledges it
+    ///
+    /// `on_ack` is called with the id of each server that accepted the report, until a quorum is reached.
+    /// Returns how many servers it was called for.
+    ///
+    /// Invokes a protocol write (with atomic semantics)
+    ///
+    #[instrument(skip(on_ack))]
+    pub async fn submit_position_report_with_progress<P, F>(
+        &self,
+        proof: P,
+        on_ack: F,
+    ) -> Result<usize>
+    where
+        P: Into<UnverifiedPositionProof> + Debug,
+        F: Fn(EntityId),
+    {
         let proof = proof.into();
+        let report = proof
+            .witnesses
+            .first()
+            .map(|w| (w.request.prover_id, w.request.epoch));
+        let written = report
+            .filter(|&(prover_id, _)| prover_id == self.keystore.my_id())
+            .map(|(_, epoch)| epoch);
         let pow_protected = PoWCertified::new(proof);
 
-        self.invoke_atomic_write(ApiRequest::SubmitPositionReport(pow_protected))
-            .await
-            .and_then(|reply| match reply {
-                ApiReply::Ok => Ok(()),
-                ApiReply::Error(e) => Err(HdltError::ServerError(e)),
-                other => Err(HdltError::UnexpectedReply(other)),
-            })
+        let acks = self
+            .invoke_atomic_write(ApiRequest::SubmitPositionReport(pow_protected), on_ack)
+            .await;
+
+        // even a failed write may have reached some servers
+        if let Some((prover_id, epoch)) = report {
+            self.report_cache
+                .lock()
+                .unwrap()
+                .retain(|(user_id, range), _| *user_id != prover_id || !range.contains(&epoch));
+        }
+        let acks = acks?;
+
+        if let Some(epoch) = written {
+            let mut last_write = self.last_write.lock().unwrap();
+            *last_write = (*last_write).max(Some(epoch));
+        }
+        Ok(acks)
     }
 
     /// Health authority obtains position report from the server
@@ -164,19 +396,271 @@ impl HdltApiClient {
             request_id: REQUEST_ID.fetch_add(1, Ordering::SeqCst),
             user_id,
             epoch,
-            callback_uri: String::new(), // will be overriden
+            callback_uri: None, // will be overriden
         })
         .await
         .and_then(|reply| match reply {
             ApiReply::PositionReport(_, loc) => Ok(loc),
-            ApiReply::Error(e) => Err(HdltError::ServerError(e)),
-            other => Err(HdltError::UnexpectedReply(other)),
+            other => Err(ApiClientError::from(other).into()),
         })
     }
 
-    /// User obtains its own position reports from the server, for a specified range of epochs
+    /// Health authority obtains position report from the server
+    /// ** or **
+    /// User obtains its own position report from the server
     ///
-    /// Invokes a protocol read (with regular semantics)
+    /// Invokes a protocol read (with regular semantics): lighter than
+    /// [HdltApiClient::obtain_position_report], but not linearizable.
+    /// Users still always read their own writes
+    ///
+    #[instrument]
+    pub async fn obtain_position_report_regular(
+        &self,
+        user_id: EntityId,
+        epoch: u64,
+    ) -> Result<Position> {
+        let last_write = if user_id == self.keystore.my_id() {
+            *self.last_write.lock().unwrap()
+        } else {
+            None
+        };
+
+        let replies = self
+            .invoke_quorum_by_server(
+                ApiRequest::ObtainPositionReportRegular {
+                    user_id,
+                    epoch,
+                    last_write,
+                },
+                self.current_epoch,
+                self.current_epoch,
+            )
+            .await?;
+
+        // proofs can be checked, coarse positions must be agreed on by enough servers
+        let mut coarse = Vec::new();
+        let mut error: Option<HdltError> = None;
+        for (server_id, reply) in replies {
+            match reply {
+                ApiReply::PositionReports(reports) if reports.len() == 1 => {
+                    let (_, proof) = &reports[0];
+                    match proof
+                        .clone()
+                        .verify(self.neighbour_faults as usize, &self.keystore)
+                    {
+                        Ok(proof) if proof.prover_id() == user_id && proof.epoch() == epoch => {
+                            return Ok(proof.position())
+                        }
+                        Ok(_) => {
+                            warn!(server_id, "server sent a report for someone/somewhen else");
+                            error = Some(
+                                ApiClientError::UnexpectedReply(ApiReply::PositionReports(reports))
+                                    .into(),
+                            );
+                        }
+                        Err(err) => error = Some(err.into()),
+                    }
+                }
+                ApiReply::PositionReport(_, position) => coarse.push(position),
+                other => error = Some(ApiClientError::from(other).into()),
+            }
+        }
+
+        if let Some(&position) = coarse.iter().find(|&&position| {
+            coarse.iter().filter(|&&other| other == position).count() > self.server_faults as usize
+        }) {
+            return Ok(position);
+        }
+        match error {
+            Some(error) if coarse.is_empty() => Err(error),
+            _ => Err(HdltError::DivergentReplies),
+        }
+    }
+
+    /// Health authority obtains the positions of several users at an epoch from the server
+    ///
+    /// Invokes a protocol read (with regular semantics) per user, like
+    /// [HdltApiClient::obtain_position_report_regular], with several in flight at once.
+    /// Each user gets their own result: a failed read doesn't affect the others.
+    ///
+    #[instrument]
+    pub async fn obtain_positions_for_users(
+        &self,
+        user_ids: &[EntityId],
+        epoch: u64,
+    ) -> BTreeMap<EntityId, Result<Position>> {
+        futures::stream::iter(user_ids.iter().copied())
+            .map(|user_id| async move {
+                let position = self.obtain_position_report_regular(user_id, epoch).await;
+                (user_id, position)
+            })
+            .buffer_unordered(MAX_CONCURRENT_READS)
+            .collect()
+            .await
+    }
+
+    /// Health authority obtains position reports of a user at several epochs from the server
+    /// ** or **
+    /// User obtains its own position reports at several epochs from the server
+    ///
+    /// Invokes a single protocol read (with regular semantics) for all epochs, instead of
+    /// one per epoch like [HdltApiClient::obtain_position_report_regular].
+    /// Reports are verified one by one, so each epoch gets any valid report a server in the
+    /// quorum had for it. Epochs none of them had a report for are missing from the result,
+    /// which is sorted by epoch.
+    ///
+    #[instrument]
+    pub async fn obtain_position_reports_bulk(
+        &self,
+        user_id: EntityId,
+        epochs: &[u64],
+    ) -> Result<Vec<(u64, Position)>> {
+        let resps = self
+            .invoke_quorum(
+                ApiRequest::ObtainPositionReportsBulk {
+                    user_id,
+                    epochs: epochs.to_vec(),
+                },
+                self.current_epoch,
+                self.current_epoch,
+            )
+            .await?;
+
+        let mut positions = BTreeMap::new();
+        let mut refusal = None;
+        let mut any_reports = false;
+        for resp in resps {
+            let reports = match resp {
+                ApiReply::PositionReports(reports) => reports,
+                other => {
+                    refusal.get_or_insert(other);
+                    continue;
+                }
+            };
+            any_reports = true;
+
+            for (epoch, proof) in reports {
+                if !epochs.contains(&epoch) || positions.contains_key(&epoch) {
+                    continue;
+                }
+
+                match proof.verify(self.neighbour_faults as usize, &self.keystore) {
+                    Ok(proof) if proof.prover_id() == user_id && proof.epoch() == epoch => {
+                        positions.insert(epoch, proof.position());
+                    }
+                    Ok(_) => warn!(epoch, "server sent a report for someone/somewhen else"),
+                    Err(e) => warn!(epoch, "server sent an invalid report: {:?}", e),
+                }
+            }
+        }
+
+        match refusal {
+            Some(refusal) if !any_reports => Err(ApiClientError::from(refusal).into()),
+            _ => Ok(positions.into_iter().collect()),
+        }
+    }
+
+    /// Health authority obtains position report from the server, with a certificate for it
+    /// ** or **
+    /// User obtains its own position report from the server, with a certificate for it
+    ///
+    /// Invokes a protocol read (with regular semantics) where every server signs its reply.
+    /// The replies form a [ReadCertificate], which anyone that knows the servers can check
+    /// with [ReadCertificate::verify] (with the quorum size and neighbour faults of this client).
+    /// Replies are waited for until a quorum of them agree, and only those are certified.
+    /// Fails if no quorum agreed on the report.
+    ///
+    #[instrument]
+    pub async fn obtain_read_certificate(
+        &self,
+        user_id: EntityId,
+        epoch: u64,
+    ) -> Result<(Position, ReadCertificate)> {
+        let server_ids: Vec<_> = self.channels().await?.keys().copied().collect();
+        let quorum = (server_ids.len() + self.server_faults as usize) / 2 + 1;
+        let neighbour_faults = self.neighbour_faults as usize;
+
+        let request = ApiRequest::ObtainCertifiedPositionReport { user_id, epoch };
+        let mut pending: FuturesUnordered<_> = server_ids
+            .into_iter()
+            .map(|server_id| {
+                let request = request.clone();
+                async move { (server_id, self.invoke_single(server_id, request).await) }
+            })
+            .collect();
+
+        let mut replies = Vec::new();
+        let mut refusal = None;
+        while let Some((server_id, reply)) = pending.next().await {
+            match reply {
+                Ok(ApiReply::SignedReadReply(reply)) => replies.push(reply),
+                Ok(other) => {
+                    refusal.get_or_insert(other);
+                }
+                Err(e) => warn!("calling server {} failed: {:?}", server_id, e),
+            }
+
+            // the replies so far may not agree, but the ones still missing can fix that
+            if replies.len() >= quorum {
+                if let Ok((proof, certificate)) = ReadCertificate::certify(
+                    replies.clone(),
+                    &self.keystore,
+                    quorum,
+                    neighbour_faults,
+                ) {
+                    return Ok((proof.position(), certificate));
+                }
+            }
+        }
+
+        if replies.len() < quorum {
+            return Err(match refusal {
+                Some(refusal) => ApiClientError::from(refusal).into(),
+                None => HdltError::NotEnoughServers,
+            });
+        }
+        match ReadCertificate::certify(replies, &self.keystore, quorum, neighbour_faults) {
+            Ok((proof, certificate)) => Ok((proof.position(), certificate)),
+            Err(ReadCertificateError::Mismatched) => Err(HdltError::DivergentReplies),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Obtain a position report from every server, without any quorum reduction
+    ///
+    /// Returns the raw reply (or error) of each server, ordered by server id.
+    /// Meant for diagnosing disagreeing (possibly Byzantine) servers: replies are not verified.
+    ///
+    #[instrument]
+    pub async fn obtain_position_report_debug(
+        &self,
+        user_id: EntityId,
+        epoch: u64,
+    ) -> Vec<(EntityId, Result<ApiReply>)> {
+        let mut server_ids: Vec<_> = self.channels.read().await.keys().copied().collect();
+        server_ids.sort_unstable();
+
+        let replies = futures::future::join_all(server_ids.iter().map(|&server_id| {
+            self.invoke_single(
+                server_id,
+                ApiRequest::ObtainPositionReportRegular {
+                    user_id,
+                    epoch,
+                    last_write: None,
+                },
+            )
+        }))
+        .await;
+
+        server_ids.into_iter().zip(replies).collect()
+    }
+
+    /// Obtains a user's position reports from the server, for a specified range of epochs
+    ///
+    /// Users can only obtain their own reports, health authorities can obtain anyone's.
+    /// Invokes a protocol read (with regular semantics). Results are cached until the epoch of
+    /// this client changes (or it submits a report they could include), so repeating a query in
+    /// the same epoch doesn't contact the servers
     ///
     #[instrument]
     pub async fn request_position_reports(
@@ -184,21 +668,128 @@ impl HdltApiClient {
         user_id: EntityId,
         epoch_range: std::ops::Range<u64>,
     ) -> Result<Vec<(u64, UnverifiedPositionProof)>> {
+        let cache_key = (user_id, epoch_range.clone());
+        if let Some(reports) = self.report_cache.lock().unwrap().get(&cache_key) {
+            debug!("Position reports served from cache");
+            return Ok(reports.clone());
+        }
+
+        let reports = self
+            .invoke_regular_read(
+                ApiRequest::RequestPositionReports {
+                    user_id,
+                    epoch_start: epoch_range.start,
+                    epoch_end: epoch_range.end,
+                    include_diagnostics: false,
+                },
+                |resp| resp.key(),
+            )
+            .await
+            .and_then(|reply| match reply {
+                ApiReply::PositionReports(locs) => Ok(locs),
+                other => Err(ApiClientError::from(other).into()),
+            })?;
+
+        self.report_cache
+            .lock()
+            .unwrap()
+            .insert(cache_key, reports.clone());
+        Ok(reports)
+    }
+
+    /// Obtains a user's position reports from the server, for a specified range of epochs,
+    /// along with the epochs that were left out (for having no reports, or not enough witnesses)
+    /// and why
+    ///
+    /// Like [HdltApiClient::request_position_reports], but never cached
+    ///
+    #[instrument]
+    pub async fn request_position_reports_with_diagnostics(
+        &self,
+        user_id: EntityId,
+        epoch_range: std::ops::Range<u64>,
+    ) -> Result<(
+        Vec<(u64, UnverifiedPositionProof)>,
+        Vec<(u64, ApiErrorCode)>,
+    )> {
         self.invoke_regular_read(
             ApiRequest::RequestPositionReports {
+                user_id,
                 epoch_start: epoch_range.start,
                 epoch_end: epoch_range.end,
+                include_diagnostics: true,
             },
             |resp| resp.key(),
         )
         .await
         .and_then(|reply| match reply {
-            ApiReply::PositionReports(locs) => Ok(locs),
-            ApiReply::Error(e) => Err(HdltError::ServerError(e)),
-            other => Err(HdltError::UnexpectedReply(other)),
+            ApiReply::PositionReportsWithDiagnostics(reports, skipped) => Ok((reports, skipped)),
+            other => Err(ApiClientError::from(other).into()),
         })
     }
 
+    /// Assemble all the evidence about a user in a range of epochs into a [ProofBundle]
+    ///
+    /// Users can only bundle their own reports. Health authorities can bundle any user's,
+    /// including their proofs of misbehaviour, but fetch the reports one epoch at a time.
+    /// The bundle is verified before being returned.
+    ///
+    #[instrument]
+    pub async fn obtain_proof_bundle(
+        &self,
+        user_id: EntityId,
+        epoch_range: std::ops::Range<u64>,
+    ) -> Result<ProofBundle> {
+        let (reports, misbehaviour) = if self.keystore.my_role() == Role::HaClient {
+            let mut reports = Vec::new();
+            for epoch in epoch_range.clone() {
+                match self
+                    .invoke_regular_read(
+                        ApiRequest::ObtainPositionReportRegular {
+                            user_id,
+                            epoch,
+                            last_write: None,
+                        },
+                        |resp| resp.key(),
+                    )
+                    .await?
+                {
+                    ApiReply::PositionReports(mut r) if r.len() == 1 => {
+                        reports.push(r.pop().unwrap())
+                    }
+                    ApiReply::Error(ApiErrorCode::NoData, _)
+                    | ApiReply::Error(ApiErrorCode::InsufficientQuorum { .. }, _) => {}
+                    other => return Err(ApiClientError::from(other).into()),
+                }
+            }
+
+            let misbehaviour = self
+                .obtain_misbehaviour_proofs(user_id)
+                .await?
+                .into_iter()
+                .filter(|p| epoch_range.contains(&p.a().epoch()))
+                .map(UnverifiedMisbehaviorProof::from)
+                .collect();
+
+            (reports, misbehaviour)
+        } else {
+            let reports = self
+                .request_position_reports(user_id, epoch_range.clone())
+                .await?;
+            (reports, vec![])
+        };
+
+        let bundle = ProofBundle {
+            user_id,
+            range: epoch_range,
+            reports,
+            misbehaviour,
+        };
+        bundle.verify(self.neighbour_faults as usize, &self.keystore)?;
+
+        Ok(bundle)
+    }
+
     /// Health authority obtains all users at a position
     ///
     /// Invokes a protocol read (with regular semantics)
@@ -216,83 +807,352 @@ impl HdltApiClient {
         .await
         .and_then(|reply| match reply {
             ApiReply::UsersAtPosition(users) => Ok(users),
-            ApiReply::Error(e) => Err(HdltError::ServerError(e)),
-            other => Err(HdltError::UnexpectedReply(other)),
+            other => Err(ApiClientError::from(other).into()),
         })
     }
 
-    pub async fn submit_misbehaviour_proof<P: Into<UnverifiedMisbehaviorProof> + Debug>(
+    /// Health authority checks whether a user was at a position, without learning who else was
+    ///
+    /// The user was only there if more than `server_faults` servers say so: unlike a position
+    /// proof, a bare yes from a faulty server can't be told apart from a correct one
+    ///
+    #[instrument]
+    pub async fn is_user_at_position(
         &self,
-        proof: P,
-    ) -> Result<()> {
-        let proof = proof.into();
-        let request = ApiRequest::SubmitMisbehaviourProof(proof);
-
-        let num_servers = self.channels.read().await.len();
-        let mut futs = Vec::with_capacity(num_servers);
-        for (k, v) in self
-            .channels
-            .read()
-            .await
-            .iter()
-            .map(|(k, v)| (k.clone(), v.clone()))
-        {
-            let (request, grpc_request) =
-                self.prepare_request(request.clone(), self.current_epoch, k.clone())?;
-            let mut grpc_client = GrpcHdltApiClient::new(Timeout::new(v, REQUEST_TIMEOUT));
-            let response_fut = grpc_client.invoke(grpc_request).await;
-            futs.push(async move { (k, request, response_fut) });
+        user_id: EntityId,
+        position: Position,
+        epoch: u64,
+    ) -> Result<bool> {
+        let replies = self
+            .invoke_quorum(
+                ApiRequest::IsUserAtPosition {
+                    user_id,
+                    position,
+                    epoch,
+                },
+                self.current_epoch,
+                self.current_epoch,
+            )
+            .await?;
+
+        let mut present = 0;
+        let mut refusal = None;
+        for reply in replies {
+            match reply {
+                ApiReply::Bool(true) => present += 1,
+                ApiReply::Bool(false) => {}
+                other => refusal = Some(other),
+            }
         }
 
-        futures::future::join_all(futs).await;
+        match refusal {
+            Some(refusal) if present == 0 => Err(ApiClientError::from(refusal).into()),
+            _ => Ok(present > self.server_faults as usize),
+        }
+    }
 
-        Ok(())
+    /// Health authority obtains every proof of misbehaviour of a user
+    ///
+    /// Invokes a protocol read (with regular semantics)
+    ///
+    #[instrument]
+    pub async fn obtain_misbehaviour_proofs(
+        &self,
+        user_id: EntityId,
+    ) -> Result<Vec<MisbehaviorProof>> {
+        self.invoke_regular_read(ApiRequest::ObtainMisbehaviourProofs { user_id }, |resp| {
+            resp.key()
+        })
+        .await
+        .and_then(|reply| match reply {
+            ApiReply::MisbehaviourProofs(proofs) => proofs
+                .into_iter()
+                .map(|proof| Ok(proof.verify(&self.keystore)?))
+                .collect(),
+            other => Err(ApiClientError::from(other).into()),
+        })
     }
 
-    /// User invokes a request at the server, confidentially
+    /// Health authority that only sees coarse positions obtains the epochs a user misbehaved in,
+    /// with the two positions they claimed to be in
     ///
-    /// Implements the client side regular read protocol
+    /// Like [HdltApiClient::obtain_misbehaviour_proofs], but without proofs to check:
+    /// more than `server_faults` servers must agree on the reply
     ///
-    async fn invoke_regular_read(
+    #[instrument]
+    pub async fn obtain_misbehaviour_coarse(
         &self,
-        request: ApiRequest,
-        key: fn(&ApiReply) -> u64,
-    ) -> Result<ApiReply> {
-        let num_servers = self.channels.read().await.len();
-        let mut futs = FuturesUnordered::new();
-        let mut grpc_clients = Vec::with_capacity(num_servers);
-        for r in self.channels.read().await.iter() {
-            let (request, grpc_request) =
-                self.prepare_request(request.clone(), self.current_epoch, *r.0)?;
-            let mut grpc_client =
-                GrpcHdltApiClient::new(Timeout::new(r.1.clone(), REQUEST_TIMEOUT));
-            let key = *r.0;
-            let response_fut = grpc_client.invoke(grpc_request).await;
-            futs.push(async move { (key, request, response_fut) });
-            grpc_clients.push(grpc_client);
+        user_id: EntityId,
+    ) -> Result<Vec<(u64, Position, Position)>> {
+        let replies = self
+            .invoke_quorum(
+                ApiRequest::ObtainMisbehaviourProofs { user_id },
+                self.current_epoch,
+                self.current_epoch,
+            )
+            .await?;
+
+        let agreed = replies.iter().find(|&reply| {
+            replies.iter().filter(|&other| other == reply).count() > self.server_faults as usize
+        });
+        match agreed {
+            Some(ApiReply::CoarseMisbehaviour(misbehaviour)) => Ok(misbehaviour.clone()),
+            Some(other) => Err(ApiClientError::from(other.clone()).into()),
+            None => Err(HdltError::DivergentReplies),
         }
+    }
 
-        let mut resps = Vec::with_capacity(num_servers);
-        loop {
-            futures::select! {
-                res = futs.select_next_some() => {
-                    match res {
-                        (server_id, request, Ok(grpc_response)) => resps.push(self.parse_response(grpc_response, &request, self.current_epoch, server_id)?),
-                        (server_id, request, Err(e)) => {
-                            warn!("calling {:?} on server {} failed: {:?}", request, server_id, e);
+    /// Health authority obtains how many users were in each position at an epoch
+    ///
+    /// Invokes a protocol read (with regular semantics)
+    ///
+    #[instrument]
+    pub async fn obtain_position_histogram(&self, epoch: u64) -> Result<Vec<(Position, usize)>> {
+        self.invoke_regular_read(ApiRequest::ObtainPositionHistogram { epoch }, |resp| {
+            resp.key()
+        })
+        .await
+        .and_then(|reply| match reply {
+            ApiReply::PositionHistogram(histogram) => Ok(histogram),
+            other => Err(ApiClientError::from(other).into()),
+        })
+    }
+
+    /// Health authority obtains the position of every user at an epoch
+    ///
+    /// Invokes a protocol read (with regular semantics)
+    ///
+    #[instrument]
+    pub async fn obtain_epoch_positions(&self, epoch: u64) -> Result<Vec<(EntityId, Position)>> {
+        self.invoke_regular_read(ApiRequest::ObtainEpochPositions { epoch }, |resp| {
+            resp.key()
+        })
+        .await
+        .and_then(|reply| match reply {
+            ApiReply::EpochPositions(positions) => Ok(positions),
+            other => Err(ApiClientError::from(other).into()),
+        })
+    }
+
+    /// Health authority obtains the epochs a user has position reports for
+    /// ** or **
+    /// User obtains the epochs it has position reports for (e.g. before asking to be forgotten)
+    ///
+    /// Invokes a protocol read (with regular semantics)
+    ///
+    #[instrument]
+    pub async fn obtain_prover_epochs(&self, user_id: EntityId) -> Result<Vec<u64>> {
+        self.invoke_regular_read(ApiRequest::ObtainProverEpochs { user_id }, |resp| {
+            resp.key()
+        })
+        .await
+        .and_then(|reply| match reply {
+            ApiReply::Epochs(epochs) => Ok(epochs),
+            other => Err(ApiClientError::from(other).into()),
+        })
+    }
+
+    /// User erases all of its position reports from the servers
+    ///
+    /// Returns how many reports were erased, by the server that held the most of them.
+    ///
+    /// Invokes a protocol write (with atomic semantics)
+    ///
+    #[instrument]
+    pub async fn forget_me(&self) -> Result<u64> {
+        self.invoke_write(
+            ApiRequest::ForgetUser,
+            |_| {},
+            |reply| match reply {
+                ApiReply::Deleted(count) => Ok(count),
+                other => Err(ApiClientError::from(other).into()),
+            },
+        )
+        .await
+        .map(|counts| counts.into_iter().max().unwrap_or(0))
+    }
+
+    /// User reports another user it saw misbehaving
+    ///
+    /// Succeeds once a quorum of servers accepted the proof.
+    ///
+    #[instrument]
+    pub async fn report_misbehaviour<P: Into<UnverifiedMisbehaviorProof> + Debug>(
+        &self,
+        proof: P,
+    ) -> Result<()> {
+        self.invoke_quorum(
+            ApiRequest::ReportMisbehaviour(proof.into()),
+            self.current_epoch,
+            self.current_epoch,
+        )
+        .await?
+        .into_iter()
+        .map(|reply| match reply {
+            ApiReply::Ok => Ok(()),
+            other => Err(ApiClientError::from(other).into()),
+        })
+        .collect()
+    }
+
+    /// Obtain the epoch the servers are currently in
+    ///
+    /// Works regardless of this client's own epoch: the request is sent from the first epoch,
+    /// which servers still answer for this request only (later ones may be too far ahead of
+    /// theirs), and replies are accepted from any epoch. The result is vouched for by at least one correct server.
+    /// Every epoch must be attested by the server that replied with it (so each server only
+    /// counts once): other replies are ignored, and there must be more than `server_faults` left.
+    ///
+    #[instrument]
+    pub async fn get_epoch(&self) -> Result<u64> {
+        let mut epochs: Vec<_> = self
+            .invoke_quorum_by_server(ApiRequest::GetEpoch, 0, 0)
+            .await?
+            .into_iter()
+            .filter_map(|(server_id, reply)| match reply {
+                ApiReply::Epoch(attestation) if attestation.server_id() == server_id => {
+                    match attestation.verify(&self.keystore) {
+                        Ok(epoch) => Some(epoch),
+                        Err(e) => {
+                            warn!(
+                                "Server {} sent an invalid epoch attestation: {:?}",
+                                server_id, e
+                            );
+                            None
                         }
                     }
+                }
+                other => {
+                    warn!(
+                        "Server {} did not attest its own epoch: {:?}",
+                        server_id, other
+                    );
+                    None
+                }
+            })
+            .collect();
+        if epochs.len() <= self.server_faults as usize {
+            return Err(HdltError::NotEnoughServers);
+        }
 
-                    if resps.len() > (num_servers + self.server_faults as usize) / 2 {
-                        break;
-                    }
-                },
-                complete => {
-                    return Err(HdltError::NotEnoughServers);
+        // up to server_faults replies may be arbitrarily high
+        epochs.sort_unstable_by(|a, b| b.cmp(a));
+        Ok(epochs[self.server_faults as usize])
+    }
+
+    /// Current epoch of this client, used for request freshness
+    pub fn current_epoch(&self) -> u64 {
+        self.current_epoch
+    }
+
+    /// Update the epoch of this client (e.g. to the one obtained with [HdltApiClient::get_epoch])
+    pub fn set_current_epoch(&mut self, epoch: u64) {
+        if epoch != self.current_epoch {
+            self.report_cache.get_mut().unwrap().clear();
+        }
+        self.current_epoch = epoch;
+    }
+
+    /// How atomic read values get back to this client
+    pub fn return_transport(&self) -> ReturnTransport {
+        self.return_transport
+    }
+
+    /// Choose how atomic read values get back to this client (see [ReturnTransport])
+    pub fn set_return_transport(&mut self, return_transport: ReturnTransport) {
+        self.return_transport = return_transport;
+    }
+
+    /// Optional features this client advertises to servers
+    pub fn capabilities(&self) -> Capabilities {
+        self.capabilities
+    }
+
+    /// Choose the optional features to advertise to servers
+    ///
+    /// Forgets what was agreed on with every server: call [HdltApiClient::exchange_capabilities] again
+    ///
+    pub fn set_capabilities(&mut self, capabilities: Capabilities) {
+        self.capabilities = capabilities;
+        self.negotiated.get_mut().unwrap().clear();
+    }
+
+    /// Advertise our capabilities to every server, and remember what each one agreed to
+    ///
+    /// Servers that don't answer (e.g. ones that predate the exchange) are treated as
+    /// supporting no optional feature. Returns how many servers answered
+    ///
+    #[instrument(skip(self))]
+    pub async fn exchange_capabilities(&self) -> Result<usize> {
+        let server_ids: Vec<u32> = self.channels().await?.keys().copied().collect();
+        let replies: Vec<_> = server_ids
+            .into_iter()
+            .map(|server_id| async move {
+                let reply = self
+                    .invoke_single(server_id, ApiRequest::Hello(self.capabilities))
+                    .await;
+                (server_id, reply)
+            })
+            .collect::<FuturesUnordered<_>>()
+            .collect()
+            .await;
+
+        let mut negotiated = self.negotiated.lock().unwrap();
+        let mut answered = 0;
+        for (server_id, reply) in replies {
+            match reply {
+                Ok(ApiReply::Capabilities(theirs)) => {
+                    negotiated.insert(server_id, self.capabilities.negotiate(&theirs));
+                    answered += 1;
+                }
+                other => {
+                    debug!(
+                        server_id,
+                        ?other,
+                        "Server did not advertise its capabilities"
+                    );
+                    negotiated.remove(&server_id);
                 }
             }
         }
 
+        Ok(answered)
+    }
+
+    /// Features agreed on with a server, if it answered [HdltApiClient::exchange_capabilities]
+    pub fn server_capabilities(&self, server_id: u32) -> Option<Capabilities> {
+        self.negotiated.lock().unwrap().get(&server_id).copied()
+    }
+
+    /// Whether payloads exchanged with a server may be compressed
+    pub fn compression_enabled(&self, server_id: u32) -> bool {
+        self.server_capabilities(server_id)
+            .map_or(false, |agreed| agreed.compression)
+    }
+
+    /// Set the priority of a server (0 by default), e.g. to prefer nearby ones
+    ///
+    /// Quorum reads and writes start at the servers with the highest priority. Each tier of
+    /// lower priority servers is contacted [PRIORITY_STAGGER] later, unless a quorum was
+    /// already gathered by then. Servers with the same priority are contacted together.
+    ///
+    pub fn set_server_priority(&mut self, server_id: u32, priority: u32) {
+        self.priorities.insert(server_id, priority);
+    }
+
+    /// User invokes a request at the server, confidentially
+    ///
+    /// Implements the client side regular read protocol
+    ///
+    async fn invoke_regular_read(
+        &self,
+        request: ApiRequest,
+        key: fn(&ApiReply) -> u64,
+    ) -> Result<ApiReply> {
+        let resps = self
+            .invoke_quorum(request, self.current_epoch, self.current_epoch)
+            .await?;
+
         let max_key = key(&resps.iter().cloned().max_by_key(key).unwrap());
         let maximums : Vec<_> = resps.into_iter().filter(|v| key(v) == max_key).collect();
 
@@ -316,32 +1176,86 @@ impl HdltApiClient {
         Ok(maximums.into_iter().next().unwrap())
     }
 
+    /// User invokes a request at all servers, confidentially
+    ///
+    /// Waits for a (Byzantine) quorum of replies, failing as soon as one becomes impossible.
+    /// Requests are sent in `request_epoch` and replies are checked for freshness against `reply_epoch`
+    ///
+    async fn invoke_quorum(
+        &self,
+        request: ApiRequest,
+        request_epoch: u64,
+        reply_epoch: u64,
+    ) -> Result<Vec<ApiReply>> {
+        self.invoke_quorum_by_server(request, request_epoch, reply_epoch)
+            .await
+            .map(|resps| resps.into_iter().map(|(_, reply)| reply).collect())
+    }
+
+    /// Like [HdltApiClient::invoke_quorum], also returning which server sent each reply
+    ///
+    async fn invoke_quorum_by_server(
+        &self,
+        request: ApiRequest,
+        request_epoch: u64,
+        reply_epoch: u64,
+    ) -> Result<Vec<(EntityId, ApiReply)>> {
+        let schedule = self.schedule().await?;
+        let num_servers = schedule.len();
+        let mut futs = FuturesUnordered::new();
+        for (key, channel, delay) in schedule {
+            let (request, grpc_request) =
+                self.prepare_request(request.clone(), request_epoch, key)?;
+            let mut grpc_client = GrpcHdltApiClient::new(Timeout::new(channel, REQUEST_TIMEOUT));
+            futs.push(async move {
+                if !delay.is_zero() {
+                    tokio::time::sleep(delay).await;
+                }
+                let response = grpc_client.invoke(grpc_request).await;
+                (key, request, response)
+            });
+        }
+
+        let needed = (num_servers + self.server_faults as usize) / 2 + 1;
+        let mut resps = Vec::with_capacity(num_servers);
+        loop {
+            futures::select! {
+                res = futs.select_next_some() => {
+                    match res {
+                        (server_id, request, Ok(grpc_response)) => resps.push((server_id, self.parse_response(grpc_response, &request, reply_epoch, server_id)?)),
+                        (server_id, request, Err(e)) => {
+                            warn!("calling {:?} on server {} failed: {:?}", request, server_id, e);
+                        }
+                    }
+                    debug!(responses = resps.len(), needed, "quorum progress");
+
+                    if resps.len() >= needed {
+                        break;
+                    }
+                    if resps.len() + futs.len() < needed {
+                        warn!(responses = resps.len(), needed, "too many servers failed, giving up");
+                        return Err(HdltError::NotEnoughServers);
+                    }
+                },
+                complete => {
+                    return Err(HdltError::NotEnoughServers);
+                }
+            }
+        }
+
+        Ok(resps)
+    }
+
     /// User invokes a request at the server, confidentially
     ///
     /// Implements the client side atomic read protocol
     ///
     async fn invoke_atomic_read(&self, request: ApiRequest) -> Result<ApiReply> {
-        let cb_service = CallbackService::new(
-            self.current_epoch,
-            self.keystore.clone(),
-            self.notification.clone(),
-        );
-
-        // TODO: have some mechanism to choose the listening IP addr
-        let (server_incoming, server_addr) = create_tcp_incoming(&"127.0.0.1:0".parse().unwrap())
-            .await
-            .expect("failed to create callback server");
-        let server = tokio::spawn(async move {
-            Server::builder()
-                .add_service(protos::hdlt::hdlt_api_server::HdltApiServer::new(
-                    cb_service,
-                ))
-                .serve_with_incoming(server_incoming)
-                .await
-                .expect("callback server error");
-        });
+        // before spawning anything that would need cleaning up
+        let server_ids: Vec<_> = self.channels().await?.keys().copied().collect();
 
-        let callback_uri = format!("http://127.0.0.1:{}/", server_addr.port());
+        let callback = self.spawn_callback_server_if_push().await;
+        let callback_uri = callback.as_ref().map(|(_, uri)| uri.clone());
         let (request, req_id) = match request {
             ApiRequest::ObtainPositionReport {
                 request_id,
@@ -360,7 +1274,9 @@ impl HdltApiClient {
             _ => unreachable!("only implemented for ObtainPositionReport"),
         };
 
-        let mut futs = Vec::new();
+        let num_servers = server_ids.len();
+        let needed = (num_servers + self.server_faults as usize) / 2 + 1;
+        let mut futs = FuturesUnordered::new();
         for (k, v) in self
             .channels
             .read()
@@ -370,6 +1286,11 @@ impl HdltApiClient {
         {
             let (request, grpc_request) =
                 self.prepare_request(request.clone(), self.current_epoch.clone(), k.clone())?;
+            debug!(
+                correlation_id = request.correlation_id(),
+                server_id = k,
+                "sending atomic read request"
+            );
             futs.push(async move {
                 let mut grpc_client = GrpcHdltApiClient::new(Timeout::new(v, REQUEST_TIMEOUT));
                 let response = grpc_client.invoke(grpc_request).await;
@@ -378,20 +1299,190 @@ impl HdltApiClient {
             });
         }
 
-        let handle = tokio::spawn(async move { futures::future::join_all(futs).await });
         let rx = self.notification.wait_on(req_id).await;
+        let replies = async {
+            // servers reply as soon as they register the read, the value comes later.
+            // Servers without callbacks reply with the value right away instead
+            let mut responses = 0usize;
+            while let Some((server_id, request, response)) = futs.next().await {
+                let reply = match response {
+                    Ok(response) => {
+                        self.parse_response(response, &request, self.current_epoch, server_id)
+                    }
+                    Err(status) => Err(HdltError::from(status)),
+                };
+                match reply {
+                    Ok(ApiReply::PositionReports(reports)) => {
+                        for (epoch, proof) in reports {
+                            self.notification
+                                .send(req_id, (Returned::Proof(proof), server_id, epoch))
+                                .await;
+                        }
+                    }
+                    Ok(ApiReply::PositionReport(epoch, position)) => {
+                        self.notification
+                            .send(req_id, (Returned::Coarse(position), server_id, epoch))
+                            .await;
+                    }
+                    Ok(_) => responses += 1,
+                    Err(err) => warn!(server_id, ?err, "atomic read request failed"),
+                }
+                debug!(responses, needed, "atomic read quorum progress");
+            }
 
-        let res = rx.await.map_err(|_| HdltError::ChannelError)?;
+            // the value can still come from callbacks or polling
+            futures::future::pending::<()>().await
+        };
+
+        let res = tokio::select! {
+            res = self.wait_return(
+                rx,
+                callback.is_some(),
+                &server_ids,
+                req_id,
+                self.server_faults as usize,
+            ) => res,
+            _ = replies => unreachable!("replies never complete"),
+        };
 
         // close temporary server
-        server.abort();
-        handle.abort();
-        Ok(ApiReply::PositionReport(
-            res.2,
-            res.0
-                .verify(self.neighbour_faults as usize, &self.keystore)?
-                .position(),
-        ))
+        if let Some((server, _)) = callback {
+            server.abort();
+        }
+        self.notification.forget(req_id).await;
+
+        let (epoch, position) = res?;
+        Ok(ApiReply::PositionReport(epoch, position))
+    }
+
+    /// Wait for the value of an atomic read, until more than `server_faults` servers agree on it
+    ///
+    /// Values are received with `rx`, either pushed by servers (if `push`) or forwarded from
+    /// their replies. Without `push`, `server_ids` are also polled
+    ///
+    /// Either way, gives up after [REQUEST_TIMEOUT]
+    ///
+    async fn wait_return(
+        &self,
+        mut rx: mpsc::UnboundedReceiver<NotificationValue>,
+        push: bool,
+        server_ids: &[u32],
+        request_id: u64,
+        server_faults: usize,
+    ) -> Result<(u64, Position)> {
+        let mut values = ReturnedValues::new(server_ids.len(), server_faults);
+        if !push {
+            return self.poll_return(rx, server_ids, request_id, values).await;
+        }
+
+        let wait = async {
+            while let Some((returned, server_id, epoch)) = rx.recv().await {
+                let value = self.check_returned(server_id, returned, epoch);
+                if let Some(value) = values.add(server_id, value) {
+                    return Ok(value);
+                }
+                if values.divergent() {
+                    return Err(HdltError::DivergentReplies);
+                }
+            }
+
+            Err(HdltError::ChannelError)
+        };
+
+        match tokio::time::timeout(REQUEST_TIMEOUT, wait).await {
+            Ok(res) => res,
+            Err(_) => {
+                warn!(
+                    request_id,
+                    "timed out waiting for servers to return a value"
+                );
+                Err(HdltError::NotEnoughServers)
+            }
+        }
+    }
+
+    /// Poll servers for the value of an atomic read registered without a callback uri
+    ///
+    /// Gives up after [REQUEST_TIMEOUT], see [HdltApiClient::wait_return]
+    ///
+    async fn poll_return(
+        &self,
+        mut rx: mpsc::UnboundedReceiver<NotificationValue>,
+        server_ids: &[u32],
+        request_id: u64,
+        mut values: ReturnedValues,
+    ) -> Result<(u64, Position)> {
+        let deadline = tokio::time::Instant::now() + REQUEST_TIMEOUT;
+        loop {
+            while let Ok((returned, server_id, epoch)) = rx.try_recv() {
+                let value = self.check_returned(server_id, returned, epoch);
+                if let Some(value) = values.add(server_id, value) {
+                    return Ok(value);
+                }
+            }
+
+            // servers only return a value once
+            let pending: Vec<_> = server_ids
+                .iter()
+                .copied()
+                .filter(|&server_id| !values.returned(server_id))
+                .collect();
+            let replies = futures::future::join_all(pending.iter().map(|&server_id| {
+                self.invoke_single(server_id, ApiRequest::PollReturn { request_id })
+            }))
+            .await;
+
+            for (server_id, reply) in pending.into_iter().zip(replies) {
+                match reply {
+                    Ok(ApiReply::ReturnedValue(Some((epoch, proof)))) => {
+                        let value = self.check_returned(server_id, Returned::Proof(proof), epoch);
+                        if let Some(value) = values.add(server_id, value) {
+                            return Ok(value);
+                        }
+                    }
+                    Ok(ApiReply::ReturnedValue(None)) => {}
+                    Ok(other) => {
+                        warn!("polling server {} returned {:?}", server_id, other);
+                    }
+                    Err(e) => {
+                        warn!("polling server {} failed: {:?}", server_id, e);
+                    }
+                }
+            }
+
+            if values.divergent() {
+                return Err(HdltError::DivergentReplies);
+            }
+            if tokio::time::Instant::now() >= deadline {
+                return Err(HdltError::NotEnoughServers);
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    }
+
+    /// Verify a value returned by a server for an atomic read, obtaining the position in it
+    ///
+    /// Invalid values are ignored: they only show that server is faulty.
+    /// Coarse positions can't be checked, only trusted once enough servers agree on them
+    ///
+    fn check_returned(
+        &self,
+        server_id: EntityId,
+        returned: Returned,
+        epoch: u64,
+    ) -> Option<(u64, Position)> {
+        let proof = match returned {
+            Returned::Proof(proof) => proof,
+            Returned::Coarse(position) => return Some((epoch, position)),
+        };
+
+        match proof.verify(self.neighbour_faults as usize, &self.keystore) {
+            Ok(proof) => Some((epoch, proof.position())),
+            Err(err) => {
+                warn!(server_id, ?err, "server returned an invalid proof");
+                None
+            }
+        }
     }
 
     /// User invokes a request at the server, confidentially
@@ -399,20 +1490,50 @@ impl HdltApiClient {
     /// Implements the client side atomic write protocol
     /// Nice property: the epoch number can act as a timestamp
     ///
-    async fn invoke_atomic_write(&self, request: ApiRequest) -> Result<ApiReply> {
-        let num_servers = self.channels.read().await.len();
-        let mut futs = FuturesUnordered::new();
-        for (k, v) in self
-            .channels
-            .read()
-            .await
-            .iter()
-            .map(|(k, v)| (k.clone(), v.clone()))
-        {
+    /// Returns the number of servers that acknowledged the write, a quorum.
+    /// Fails as soon as a quorum becomes impossible.
+    ///
+    async fn invoke_atomic_write(
+        &self,
+        request: ApiRequest,
+        on_ack: impl Fn(EntityId),
+    ) -> Result<usize> {
+        self.invoke_write(request, on_ack, |reply| {
+            // on a write, all must reply with ok
+            if let ApiReply::Ok = reply {
+                Ok(())
+            } else {
+                Err(ApiClientError::UnexpectedReply(reply).into())
+            }
+        })
+        .await
+        .map(|acks| acks.len())
+    }
+
+    /// Like [invoke_atomic_write](Self::invoke_atomic_write), for writes that reply with more
+    /// than [ApiReply::Ok]
+    ///
+    /// `accept` turns each server's reply into a value, or rejects it. Returns the values of the
+    /// servers that acknowledged the write.
+    ///
+    async fn invoke_write<T>(
+        &self,
+        request: ApiRequest,
+        on_ack: impl Fn(EntityId),
+        accept: impl Fn(ApiReply) -> Result<T>,
+    ) -> Result<Vec<T>> {
+        let accept = &accept;
+        let schedule = self.schedule().await?;
+        let num_servers = schedule.len();
+        let mut futs = FuturesUnordered::new();
+        for (k, v, delay) in schedule {
             let (request, grpc_request) =
                 self.prepare_request(request.clone(), self.current_epoch, k)?;
 
             futs.push(async move {
+                if !delay.is_zero() {
+                    tokio::time::sleep(delay).await;
+                }
                 let mut grpc_client =
                     GrpcHdltApiClient::new(Timeout::new(v.clone(), REQUEST_TIMEOUT));
 
@@ -423,32 +1544,35 @@ impl HdltApiClient {
                     .and_then(|grpc_response| {
                         self.parse_response(grpc_response, &request, self.current_epoch, k)
                     })
-                    .and_then(|reply| {
-                        // on a write, all must reply with ok
-                        if let ApiReply::Ok = reply {
-                            Ok(())
-                        } else {
-                            Err(HdltError::UnexpectedReply(reply))
-                        }
-                    })
+                    .and_then(accept)
+                    .map(|value| (k, value))
                     .map_err(|e| (k, request, e))
             });
         }
 
-        let mut replies = 0usize;
+        let needed = (num_servers + self.server_faults as usize) / 2 + 1;
+        let mut replies = Vec::with_capacity(num_servers);
         loop {
             futures::select! {
                 res = futs.select_next_some() => {
                     match res {
-                        Ok(()) => replies += 1,
+                        Ok((server_id, value)) => {
+                            replies.push(value);
+                            on_ack(server_id);
+                        }
                         Err((server_id, request, e)) => {
                             warn!("calling {:?} on server {} failed: {:?}", request, server_id, e);
                         }
                     }
+                    debug!(responses = replies.len(), needed, "atomic write quorum progress");
 
-                    if replies > (num_servers + self.server_faults as usize) / 2 {
+                    if replies.len() >= needed {
                         break;
                     }
+                    if replies.len() + futs.len() < needed {
+                        warn!(responses = replies.len(), needed, "too many servers failed, giving up");
+                        return Err(HdltError::NotEnoughServers);
+                    }
                 },
                 complete => {
                     return Err(HdltError::NotEnoughServers);
@@ -456,7 +1580,114 @@ impl HdltApiClient {
             }
         }
 
-        Ok(ApiReply::Ok)
+        Ok(replies)
+    }
+
+    /// Servers to contact for a quorum, most preferred first, with how long to wait before
+    /// contacting each of them
+    ///
+    async fn schedule(&self) -> Result<Vec<(u32, Channel, Duration)>> {
+        let mut servers: Vec<_> = self
+            .channels()
+            .await?
+            .iter()
+            .map(|(&id, channel)| {
+                let priority = self.priorities.get(&id).copied().unwrap_or(0);
+                (priority, id, channel.clone())
+            })
+            .collect();
+        servers.sort_by_key(|&(priority, id, _)| (Reverse(priority), id));
+
+        let mut tier = 0;
+        let mut last_priority = None;
+        Ok(servers
+            .into_iter()
+            .map(|(priority, id, channel)| {
+                if last_priority.map_or(false, |last| last != priority) {
+                    tier += 1;
+                }
+                last_priority = Some(priority);
+
+                (id, channel, PRIORITY_STAGGER * tier)
+            })
+            .collect())
+    }
+
+    /// Channels to every server, unless this client was closed
+    ///
+    /// Fails with [HdltError::NoServersConfigured] if every server was removed, since no
+    /// quorum can be reached without servers
+    ///
+    async fn channels(&self) -> Result<RwLockReadGuard<'_, HashMap<u32, Channel>>> {
+        if self.closed.load(Ordering::SeqCst) {
+            return Err(HdltError::Closed);
+        }
+
+        let channels = self.channels.read().await;
+        if channels.is_empty() {
+            return Err(HdltError::NoServersConfigured);
+        }
+
+        Ok(channels)
+    }
+
+    /// User invokes a request at a single server, confidentially
+    ///
+    /// No quorum: the raw reply of the server is returned
+    ///
+    async fn invoke_single(&self, server_id: u32, request: ApiRequest) -> Result<ApiReply> {
+        let channel = self
+            .channels()
+            .await?
+            .get(&server_id)
+            .cloned()
+            .ok_or(HdltError::UnknownServer(server_id))?;
+
+        let (request, grpc_request) =
+            self.prepare_request(request, self.current_epoch, server_id)?;
+        let mut grpc_client = GrpcHdltApiClient::new(Timeout::new(channel, REQUEST_TIMEOUT));
+        let grpc_response = grpc_client.invoke(grpc_request).await?;
+
+        self.parse_response(grpc_response, &request, self.current_epoch, server_id)
+    }
+
+    /// Spawn a temporary server to receive atomic read values, if they are to be pushed
+    ///
+    /// See [HdltApiClient::spawn_callback_server]
+    ///
+    async fn spawn_callback_server_if_push(&self) -> Option<(tokio::task::JoinHandle<()>, String)> {
+        match self.return_transport {
+            ReturnTransport::Push => Some(self.spawn_callback_server().await),
+            ReturnTransport::Pull => None,
+        }
+    }
+
+    /// Spawn a temporary server to receive atomic read values
+    ///
+    /// Returns the server task handle and the uri to be used as callback
+    ///
+    async fn spawn_callback_server(&self) -> (tokio::task::JoinHandle<()>, String) {
+        let cb_service = CallbackService::new(
+            self.current_epoch,
+            self.keystore.clone(),
+            self.notification.clone(),
+        );
+
+        // TODO: have some mechanism to choose the listening IP addr
+        let (server_incoming, server_addr) = create_tcp_incoming(&"127.0.0.1:0".parse().unwrap())
+            .await
+            .expect("failed to create callback server");
+        let server = tokio::spawn(async move {
+            Server::builder()
+                .add_service(protos::hdlt::hdlt_api_server::HdltApiServer::new(
+                    cb_service,
+                ))
+                .serve_with_incoming(server_incoming)
+                .await
+                .expect("callback server error");
+        });
+
+        (server, format!("http://127.0.0.1:{}/", server_addr.port()))
     }
 
     /// Prepare a request
@@ -465,6 +1696,7 @@ impl HdltApiClient {
     ///  - This has an implicit authenticated because both
     ///     the user and the server derive a key in the same way
     ///
+    #[instrument(skip(self, payload), fields(plaintext_len, ciphertext_len))]
     fn prepare_request(
         &self,
         payload: ApiRequest,
@@ -473,22 +1705,34 @@ impl HdltApiClient {
     ) -> Result<(RrRequest<ApiRequest>, tonic::Request<CipheredRrMessage>)> {
         let request_msg = RrMessage::new_request(current_epoch, payload);
 
-        let plaintext = bincode::serialize(&request_msg).map_err(HdltError::SerializationError)?;
+        let plaintext =
+            bincode::serialize(&request_msg).map_err(ApiClientError::SerializationError)?;
         let (ciphertext, nonce) = self
             .keystore
             .cipher(server_id, &plaintext)
-            .map_err(HdltError::CipherError)?;
-        let grpc_request = Request!(CipheredRrMessage {
+            .map_err(ApiClientError::CipherError)?;
+        Span::current().record("plaintext_len", &plaintext.len());
+        Span::current().record("ciphertext_len", &ciphertext.len());
+        let mut grpc_request = Request!(CipheredRrMessage {
             sender_id: self.keystore.my_id(),
             ciphertext,
             nonce: nonce.0.to_vec(),
         });
+        let token = AuthToken::new(&self.keystore).map_err(ApiClientError::AuthTokenError)?;
+        grpc_request.metadata_mut().insert(
+            AUTH_METADATA_KEY,
+            token.encode().parse().unwrap(), // encoding is always valid ascii
+        );
 
         let request = request_msg.downcast_request(current_epoch).unwrap(); // impossible to fail
 
         Ok((request, grpc_request))
     }
 
+    #[instrument(
+        skip(self, grpc_response, request),
+        fields(plaintext_len, ciphertext_len)
+    )]
     fn parse_response(
         &self,
         grpc_response: tonic::Response<CipheredRrMessage>,
@@ -497,39 +1741,128 @@ impl HdltApiClient {
         server_id: u32,
     ) -> Result<ApiReply> {
         let grpc_response = grpc_response.into_inner();
-        let nonce = Nonce::from_slice(&grpc_response.nonce).ok_or(HdltError::InvalidNonce)?;
+        Span::current().record("ciphertext_len", &grpc_response.ciphertext.len());
+        let nonce = Nonce::from_slice(&grpc_response.nonce).ok_or(ApiClientError::InvalidNonce)?;
 
         let plaintext = self
             .keystore
             .decipher(server_id, &grpc_response.ciphertext, &nonce)
-            .map_err(HdltError::DecipherError)?;
+            .map_err(ApiClientError::DecipherError)?;
+        Span::current().record("plaintext_len", &plaintext.len());
         let reply_rr_message: RrMessage<ApiReply> =
-            bincode::deserialize(&plaintext).map_err(HdltError::DeserializationError)?;
+            bincode::deserialize(&plaintext).map_err(ApiClientError::DeserializationError)?;
 
         Ok(reply_rr_message
-            .downcast_reply(&request, current_epoch)?
+            .downcast_reply(&request, current_epoch)
+            .map_err(ApiClientError::from)?
             .into_inner())
     }
 }
 
-type NotificationValue = (UnverifiedPositionProof, EntityId, u64);
+/// Values of an atomic read returned by each server, until enough of them agree on one
+///
+/// Byzantine servers may return anything: a value is only trusted once more servers
+/// than can be faulty returned it.
+#[derive(Debug)]
+struct ReturnedValues {
+    num_servers: usize,
+    needed: usize,
+
+    /// Value returned by each server, or `None` if it was invalid
+    values: HashMap<EntityId, Option<(u64, Position)>>,
+}
+
+impl ReturnedValues {
+    fn new(num_servers: usize, server_faults: usize) -> Self {
+        ReturnedValues {
+            num_servers,
+            needed: server_faults + 1,
+            values: HashMap::new(),
+        }
+    }
+
+    /// Record the value a server returned, only counting its first one
+    ///
+    /// Returns the value once enough servers agree on it
+    fn add(
+        &mut self,
+        server_id: EntityId,
+        value: Option<(u64, Position)>,
+    ) -> Option<(u64, Position)> {
+        let value = (*self.values.entry(server_id).or_insert(value))?;
+
+        if self.agreeing(value) >= self.needed {
+            Some(value)
+        } else {
+            None
+        }
+    }
+
+    fn returned(&self, server_id: EntityId) -> bool {
+        self.values.contains_key(&server_id)
+    }
+
+    /// Whether no value can be agreed on anymore, even if all servers yet to return one agree
+    fn divergent(&self) -> bool {
+        let remaining = self.num_servers.saturating_sub(self.values.len());
+        let best = self
+            .values
+            .values()
+            .flatten()
+            .map(|&value| self.agreeing(value))
+            .max()
+            .unwrap_or(0);
+
+        best + remaining < self.needed
+    }
+
+    fn agreeing(&self, value: (u64, Position)) -> usize {
+        self.values
+            .values()
+            .filter(|other| **other == Some(value))
+            .count()
+    }
+}
+
+/// Value of an atomic read, and the server that returned it
+type NotificationValue = (Returned, EntityId, u64);
+
+/// What a server returned for an atomic read
+#[derive(Debug)]
+enum Returned {
+    Proof(UnverifiedPositionProof),
+
+    /// Bare position, for requestors that only see coarse positions: servers must agree on it
+    Coarse(Position),
+}
 
 #[derive(Debug)]
-struct ReturnNotification(Arc<RwLock<HashMap<u64, oneshot::Sender<NotificationValue>>>>);
+struct ReturnNotification(Arc<RwLock<HashMap<u64, mpsc::UnboundedSender<NotificationValue>>>>);
 impl ReturnNotification {
     pub fn new() -> Self {
         Self(Arc::new(RwLock::new(HashMap::new())))
     }
 
-    pub async fn wait_on(&self, request_id: u64) -> oneshot::Receiver<NotificationValue> {
-        let (tx, rx) = oneshot::channel();
+    /// Start receiving the values servers return for a request, until [forget](Self::forget)
+    pub async fn wait_on(&self, request_id: u64) -> mpsc::UnboundedReceiver<NotificationValue> {
+        let (tx, rx) = mpsc::unbounded_channel();
         self.0.write().await.insert(request_id, tx);
         rx
     }
 
+    /// Stop receiving values for a request
+    pub async fn forget(&self, request_id: u64) {
+        self.0.write().await.remove(&request_id);
+    }
+
+    /// Abandon every wait: the receivers are notified that no value is coming
+    pub async fn clear(&self) {
+        self.0.write().await.clear();
+    }
+
     pub async fn send(&self, request_id: u64, val: NotificationValue) {
-        if let Some(tx) = self.0.write().await.remove(&request_id) {
-            if let Err(_) = tx.send(val) {
+        if let Some(tx) = self.0.read().await.get(&request_id) {
+            if tx.send(val).is_err() {
                 warn!("Sending failed: probably dropped receiver");
             }
         }
@@ -565,26 +1898,30 @@ impl<'a> CallbackService {
         &self,
         request_id: u64,
         proof: UnverifiedPositionProof,
-        client_id: EntityId,
+        server_id: EntityId,
         epoch: u64,
     ) {
         self.notification
-            .send(request_id, (proof, client_id, epoch))
+            .send(request_id, (Returned::Proof(proof), server_id, epoch))
             .await
     }
 
+    #[instrument(skip(self, message), fields(plaintext_len, ciphertext_len))]
     fn decipher_rr_message(&self, message: CipheredRrMessage) -> (RrMessage<ApiRequest>, EntityId) {
+        Span::current().record("ciphertext_len", &message.ciphertext.len());
         let nonce = Nonce::from_slice(&message.nonce).expect("invalid nonce in message");
         let plaintext = self
             .keystore
             .decipher(message.sender_id, &message.ciphertext, &nonce)
             .expect("cannot decipher incoming message");
+        Span::current().record("plaintext_len", &plaintext.len());
         let rr_message: RrMessage<ApiRequest> =
             bincode::deserialize(&plaintext).expect("cannot decode incoming message");
 
         (rr_message, message.sender_id)
     }
 
+    #[instrument(skip(self, message), fields(plaintext_len, ciphertext_len))]
     fn cipher_rr_message(
         &self,
         message: RrMessage<ApiReply>,
@@ -596,6 +1933,8 @@ impl<'a> CallbackService {
             .keystore
             .cipher(partner_id, &plaintext)
             .expect("could not cipher reply");
+        Span::current().record("plaintext_len", &plaintext.len());
+        Span::current().record("ciphertext_len", &ciphertext.len());
 
         CipheredRrMessage {
             sender_id: self.keystore.my_id(),
@@ -607,6 +1946,7 @@ impl<'a> CallbackService {
 
 #[tonic::async_trait]
 impl protos::hdlt::hdlt_api_server::HdltApi for CallbackService {
+    #[instrument(skip(self, request), fields(correlation_id))]
     async fn invoke(
         &self,
         request: tonic::Request<CipheredRrMessage>,
@@ -619,11 +1959,13 @@ impl protos::hdlt::hdlt_api_server::HdltApi for CallbackService {
         match request.as_ref() {
             ApiRequest::ReturnAtomicValue {
                 proof,
-                client_id,
                 epoch,
                 request_id,
+                correlation_id,
+                ..
             } => {
-                self.return_value(*request_id, proof.clone(), *client_id, *epoch)
+                Span::current().record("correlation_id", correlation_id);
+                self.return_value(*request_id, proof.clone(), requestor_id, *epoch)
                     .await
             }
 
@@ -636,3 +1978,911 @@ impl protos::hdlt::hdlt_api_server::HdltApi for CallbackService {
         )))
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use model::api::{ApiErrorCode, EpochAttestation};
+    use model::keys::{EntityPrivComponent, EntityPubComponent, Role};
+    use model::{PositionProof, ProximityProof, ProximityProofRequest};
+    use std::sync::Mutex;
+    use tracing_subscriber::layer::SubscriberExt;
+    use tracing_utils::recorder::FieldRecorder;
+
+    /// Reply of a [MockServer] to a request
+    type MockAnswer = Arc<dyn Fn(&ApiRequest) -> ApiReply + Send + Sync>;
+
+    /// Server that answers every request, or fails them all without an answer
+    struct MockServer {
+        keystore: KeyStore,
+        answer: Option<MockAnswer>,
+
+        /// Every server logs its id here when it gets a request
+        contacted: Arc<Mutex<Vec<EntityId>>>,
+    }
+
+    /// Mock servers for a test, with ids from 0 in the order of their answers
+    #[derive(Default)]
+    struct MockServers {
+        /// How each server answers requests, or `None` for a server that fails them all
+        answers: Vec<Option<MockAnswer>>,
+        server_faults: u64,
+
+        /// Other entities the client knows (e.g. users)
+        entities: Vec<EntityPubComponent>,
+    }
+
+    /// Answer that is the same reply to every request
+    fn always(reply: ApiReply) -> Option<MockAnswer> {
+        Some(Arc::new(move |_: &ApiRequest| reply.clone()))
+    }
+
+    /// Answers of servers that each always reply the same
+    fn replying(replies: Vec<ApiReply>) -> Vec<Option<MockAnswer>> {
+        replies.into_iter().map(always).collect()
+    }
+
+    impl MockServers {
+        /// Spawn the servers, returning a client for them and the ids of servers in the order
+        /// they were contacted
+        async fn spawn(self) -> (HdltApiClient, Arc<Mutex<Vec<EntityId>>>) {
+            let MockServers {
+                answers,
+                server_faults,
+                entities,
+            } = self;
+            model::ensure_init();
+
+            let contacted = Arc::new(Mutex::new(Vec::new()));
+            let client_priv = EntityPrivComponent::new(300, Role::HaClient);
+            let client_pub = client_priv.pub_component();
+            let mut client_keystore = KeyStore::new(client_priv);
+            for entity in entities {
+                client_keystore.add_entity(entity).unwrap();
+            }
+            let mut uris = Vec::new();
+            for (id, answer) in (0..).zip(answers) {
+                let server_priv = EntityPrivComponent::new(id, Role::Server);
+                client_keystore
+                    .add_entity(server_priv.pub_component())
+                    .unwrap();
+                let mut keystore = KeyStore::new(server_priv);
+                keystore.add_entity(client_pub.clone()).unwrap();
+
+                let (incoming, addr) = create_tcp_incoming(&"127.0.0.1:0".parse().unwrap())
+                    .await
+                    .unwrap();
+                tokio::spawn(
+                    Server::builder()
+                        .add_service(protos::hdlt::hdlt_api_server::HdltApiServer::new(
+                            MockServer {
+                                keystore,
+                                answer,
+                                contacted: Arc::clone(&contacted),
+                            },
+                        ))
+                        .serve_with_incoming(incoming),
+                );
+                uris.push((id, format!("http://{}", addr).parse().unwrap()));
+            }
+
+            let client =
+                HdltApiClient::new(uris, Arc::new(client_keystore), 0, server_faults, 0).unwrap();
+            (client, contacted)
+        }
+    }
+
+    #[tonic::async_trait]
+    impl protos::hdlt::hdlt_api_server::HdltApi for MockServer {
+        async fn invoke(
+            &self,
+            request: tonic::Request<CipheredRrMessage>,
+        ) -> std::result::Result<tonic::Response<CipheredRrMessage>, tonic::Status> {
+            self.contacted.lock().unwrap().push(self.keystore.my_id());
+            let answer = match &self.answer {
+                Some(answer) => answer,
+                None => return Err(tonic::Status::unavailable("mock server is down")),
+            };
+
+            let request = request.into_inner();
+            let nonce = Nonce::from_slice(&request.nonce).unwrap();
+            let plaintext = self
+                .keystore
+                .decipher(request.sender_id, &request.ciphertext, &nonce)
+                .unwrap();
+            let rr_message: RrMessage<ApiRequest> = bincode::deserialize(&plaintext).unwrap();
+            let rr_request = rr_message.downcast_request(0).unwrap();
+
+            let reply = answer(&rr_request);
+            let reply = RrMessage::new_reply(&rr_request, 0, reply);
+            let plaintext = bincode::serialize(&reply).unwrap();
+            let (ciphertext, nonce) = self.keystore.cipher(request.sender_id, &plaintext).unwrap();
+
+            Ok(tonic::Response::new(CipheredRrMessage {
+                sender_id: self.keystore.my_id(),
+                ciphertext,
+                nonce: nonce.0.to_vec(),
+            }))
+        }
+    }
+
+    #[tokio::test]
+    async fn capabilities_negotiation() {
+        let compressing = Capabilities {
+            compression: true,
+            ..Capabilities::default()
+        };
+        let (mut client, _) = MockServers {
+            answers: vec![
+                always(ApiReply::Capabilities(compressing)),
+                always(ApiReply::Capabilities(Capabilities::default())),
+                None,
+            ],
+            ..MockServers::default()
+        }
+        .spawn()
+        .await;
+
+        // nothing is used before the exchange
+        assert!(!client.compression_enabled(0));
+
+        // we don't advertise compression by default
+        assert_eq!(client.exchange_capabilities().await.unwrap(), 2);
+        assert_eq!(client.server_capabilities(0), Some(Capabilities::default()));
+        assert!(!client.compression_enabled(0));
+
+        // once we do, only servers that advertise it too get it
+        client.set_capabilities(compressing);
+        assert_eq!(client.server_capabilities(0), None);
+        assert_eq!(client.exchange_capabilities().await.unwrap(), 2);
+        assert!(client.compression_enabled(0));
+        assert!(!client.compression_enabled(1));
+        assert_eq!(client.server_capabilities(2), None);
+        assert!(!client.compression_enabled(2));
+
+        // forgotten along with the server
+        assert!(client.remove_server(0).await);
+        assert!(!client.compression_enabled(0));
+    }
+
+    #[tokio::test]
+    async fn user_at_position() {
+        let (client, _) = MockServers {
+            answers: vec![
+                always(ApiReply::Bool(false)),
+                always(ApiReply::Bool(true)),
+                always(ApiReply::Bool(false)),
+            ],
+            server_faults: 1,
+            ..MockServers::default()
+        }
+        .spawn()
+        .await;
+        // a single (possibly faulty) server isn't enough
+        assert!(!client
+            .is_user_at_position(1, Position(0, 0), 0)
+            .await
+            .unwrap());
+
+        let (client, _) = MockServers {
+            answers: vec![
+                always(ApiReply::Bool(true)),
+                always(ApiReply::Bool(true)),
+                always(ApiReply::Bool(false)),
+            ],
+            server_faults: 1,
+            ..MockServers::default()
+        }
+        .spawn()
+        .await;
+        assert!(client
+            .is_user_at_position(1, Position(0, 0), 0)
+            .await
+            .unwrap());
+
+        let (client, _) = MockServers {
+            answers: vec![always(ApiReply::Bool(false)); 3],
+            server_faults: 1,
+            ..MockServers::default()
+        }
+        .spawn()
+        .await;
+        assert!(!client
+            .is_user_at_position(1, Position(0, 0), 0)
+            .await
+            .unwrap());
+    }
+
+    #[tokio::test]
+    async fn positions_for_users() {
+        model::ensure_init();
+        let witness_priv = EntityPrivComponent::new(450, Role::User);
+        let mut users = vec![witness_priv.pub_component()];
+        let mut witness = KeyStore::new(witness_priv);
+
+        // users 400 to 409 at (i, -i), each witnessed by user 450
+        let mut reports = HashMap::new();
+        for (i, user_id) in (0..).zip(400..410) {
+            let prover_priv = EntityPrivComponent::new(user_id, Role::User);
+            users.push(prover_priv.pub_component());
+            witness.add_entity(prover_priv.pub_component()).unwrap();
+            let prover = KeyStore::new(prover_priv);
+
+            let position = Position(i, -i);
+            let request = ProximityProofRequest::new(0, position, &prover);
+            let proof = ProximityProof::new(request, position, &witness).unwrap();
+            let proof = PositionProof::new(vec![proof], 0).unwrap();
+            reports.insert(user_id, UnverifiedPositionProof::from(proof));
+        }
+
+        let answer: MockAnswer = Arc::new(move |request: &ApiRequest| match request {
+            ApiRequest::ObtainPositionReportRegular { user_id, epoch, .. } => {
+                match reports.get(user_id) {
+                    Some(proof) => ApiReply::PositionReports(vec![(*epoch, proof.clone())]),
+                    None => ApiReply::Error(ApiErrorCode::NoData, "no report".into()),
+                }
+            }
+            _ => ApiReply::Error(ApiErrorCode::Other, "unexpected request".into()),
+        });
+        let (client, _) = MockServers {
+            answers: vec![Some(answer); 3],
+            server_faults: 1,
+            entities: users,
+        }
+        .spawn()
+        .await;
+
+        let user_ids: Vec<_> = (400..410).collect();
+        let positions = client.obtain_positions_for_users(&user_ids, 0).await;
+        assert_eq!(positions.len(), 10);
+        for (i, user_id) in (0..).zip(400..410) {
+            assert_eq!(positions[&user_id].as_ref().unwrap(), &Position(i, -i));
+        }
+
+        // a user without reports doesn't affect the others
+        let positions = client.obtain_positions_for_users(&[401, 499], 0).await;
+        assert_eq!(positions[&401].as_ref().unwrap(), &Position(1, -1));
+        assert!(positions[&499].is_err());
+    }
+
+    #[tokio::test]
+    async fn regular_read_wrong_report() {
+        model::ensure_init();
+        let users_priv: Vec<_> = (400..402)
+            .map(|id| EntityPrivComponent::new(id, Role::User))
+            .collect();
+        let users: Vec<_> = users_priv.iter().map(|u| u.pub_component()).collect();
+        let keystores: Vec<_> = users_priv
+            .into_iter()
+            .map(|user_priv| {
+                let mut keystore = KeyStore::new(user_priv);
+                for user in &users {
+                    keystore.add_entity(user.clone()).unwrap();
+                }
+                keystore
+            })
+            .collect();
+
+        // each user witnessed by the other one
+        let report = |prover: usize, epoch: u64, position: Position| {
+            let request = ProximityProofRequest::new(epoch, position, &keystores[prover]);
+            let proof = ProximityProof::new(request, position, &keystores[1 - prover]).unwrap();
+            let proof = PositionProof::new(vec![proof], 0).unwrap();
+            ApiReply::PositionReports(vec![(epoch, proof.into())])
+        };
+        let right = report(0, 0, Position(1, 1));
+        let other_user = report(1, 0, Position(5, 5));
+        let other_epoch = report(0, 1, Position(7, 7));
+
+        // 3 servers, 1 fault: every server replies, only one of them with the right report
+        let replies = vec![other_user.clone(), other_epoch.clone(), right];
+        let (client, _) = MockServers {
+            answers: replying(replies),
+            server_faults: 1,
+            entities: users.clone(),
+        }
+        .spawn()
+        .await;
+        assert_eq!(
+            client.obtain_position_report_regular(400, 0).await.unwrap(),
+            Position(1, 1)
+        );
+
+        let replies = vec![other_user.clone(), other_epoch, other_user];
+        let (client, _) = MockServers {
+            answers: replying(replies),
+            server_faults: 1,
+            entities: users,
+        }
+        .spawn()
+        .await;
+        assert!(matches!(
+            client.obtain_position_report_regular(400, 0).await,
+            Err(HdltError::Api(ApiClientError::UnexpectedReply(_)))
+        ));
+    }
+
+    #[tokio::test]
+    async fn read_quorum_progress() {
+        let recorder = FieldRecorder::new(&["responses", "needed"]);
+        let _guard =
+            tracing::subscriber::set_default(tracing_subscriber::registry().with(recorder.clone()));
+
+        let (client, _) = MockServers {
+            answers: vec![always(ApiReply::UsersAtPosition(vec![])); 3],
+            server_faults: 1,
+            ..MockServers::default()
+        }
+        .spawn()
+        .await;
+        assert!(client
+            .obtain_users_at_position(Position(0, 0), 0)
+            .await
+            .unwrap()
+            .is_empty());
+
+        // 3 servers, 1 fault: 3 replies are needed, one event per reply
+        let progress: Vec<_> = recorder
+            .numbers("responses")
+            .into_iter()
+            .zip(recorder.numbers("needed"))
+            .collect();
+        assert_eq!(progress, vec![(1, 3), (2, 3), (3, 3)]);
+    }
+
+    #[tokio::test]
+    async fn message_sizes() {
+        let recorder = FieldRecorder::new(&["plaintext_len", "ciphertext_len"]);
+        let _guard =
+            tracing::subscriber::set_default(tracing_subscriber::registry().with(recorder.clone()));
+
+        let (client, _) = MockServers {
+            answers: vec![always(ApiReply::UsersAtPosition(vec![]))],
+            ..MockServers::default()
+        }
+        .spawn()
+        .await;
+        assert!(client
+            .obtain_users_at_position(Position(0, 0), 0)
+            .await
+            .unwrap()
+            .is_empty());
+
+        // request ciphered, then reply deciphered
+        let recorded = recorder.recorded();
+        let fields: Vec<_> = recorded.iter().map(|(field, _)| *field).collect();
+        assert_eq!(
+            fields,
+            vec![
+                "plaintext_len",
+                "ciphertext_len",
+                "ciphertext_len",
+                "plaintext_len"
+            ]
+        );
+        let lens: Vec<u64> = recorded
+            .iter()
+            .map(|(_, len)| len.parse().unwrap())
+            .collect();
+        assert!(lens[1] > lens[0]);
+        assert!(lens[2] > lens[3]);
+    }
+
+    #[tokio::test]
+    async fn write_ack_count() {
+        let (client, _) = MockServers {
+            answers: vec![always(ApiReply::Ok); 3],
+            ..MockServers::default()
+        }
+        .spawn()
+        .await;
+        let acks = client
+            .submit_position_report_with_progress(
+                UnverifiedPositionProof { witnesses: vec![] },
+                |_| {},
+            )
+            .await
+            .unwrap();
+
+        // 3 servers, no faults: 2 acks are needed
+        assert_eq!(acks, 2);
+    }
+
+    #[tokio::test]
+    async fn write_without_quorum() {
+        let refusal = ApiReply::Error(ApiErrorCode::Other, "down".into());
+        let (client, _) = MockServers {
+            answers: vec![
+                always(ApiReply::Ok),
+                always(refusal.clone()),
+                always(refusal),
+            ],
+            ..MockServers::default()
+        }
+        .spawn()
+        .await;
+
+        let res = tokio::time::timeout(
+            Duration::from_secs(5),
+            client.submit_position_report(UnverifiedPositionProof { witnesses: vec![] }),
+        )
+        .await
+        .expect("write without quorum should fail, not hang");
+        assert!(matches!(res, Err(HdltError::NotEnoughServers)));
+    }
+
+    #[tokio::test]
+    async fn forget_me_is_a_write() {
+        // a server that erased nothing is still an acknowledgement
+        let (client, _) = MockServers {
+            answers: vec![
+                always(ApiReply::Deleted(3)),
+                always(ApiReply::Deleted(0)),
+                always(ApiReply::Deleted(2)),
+            ],
+            ..MockServers::default()
+        }
+        .spawn()
+        .await;
+        assert!((2..=3).contains(&client.forget_me().await.unwrap()));
+
+        let refusal = ApiReply::Error(ApiErrorCode::Other, "down".into());
+        let (client, _) = MockServers {
+            answers: vec![
+                always(ApiReply::Deleted(3)),
+                always(refusal.clone()),
+                always(refusal),
+            ],
+            ..MockServers::default()
+        }
+        .spawn()
+        .await;
+        assert!(matches!(
+            client.forget_me().await,
+            Err(HdltError::NotEnoughServers)
+        ));
+    }
+
+    #[tokio::test]
+    async fn server_priorities() {
+        let (mut client, contacted) = MockServers {
+            answers: vec![always(ApiReply::UsersAtPosition(vec![])); 4],
+            server_faults: 1,
+            ..MockServers::default()
+        }
+        .spawn()
+        .await;
+        client.set_server_priority(2, 2);
+        client.set_server_priority(3, 1);
+
+        // 4 servers, 1 fault: 3 replies are needed, so the least preferred tier is needed too
+        client
+            .obtain_users_at_position(Position(0, 0), 0)
+            .await
+            .unwrap();
+        let order = contacted.lock().unwrap().clone();
+        assert_eq!(order[..2], [2, 3]);
+        assert!(order[2..].iter().all(|&id| id == 0 || id == 1));
+
+        let (mut client, contacted) = MockServers {
+            answers: vec![always(ApiReply::Ok); 4],
+            server_faults: 1,
+            ..MockServers::default()
+        }
+        .spawn()
+        .await;
+        client.set_server_priority(1, 1);
+        client.set_server_priority(2, 2);
+        client.set_server_priority(3, 1);
+
+        // the preferred tiers are enough for a quorum
+        client
+            .submit_position_report(UnverifiedPositionProof { witnesses: vec![] })
+            .await
+            .unwrap();
+        let mut order = contacted.lock().unwrap().clone();
+        assert_eq!(order[0], 2);
+        order[1..3].sort_unstable();
+        assert_eq!(order[1..3], [1, 3]);
+    }
+
+    #[tokio::test]
+    async fn epoch_attested_by_replier() {
+        // a server everyone knows, but that isn't among the ones replying
+        let other_server = EntityPrivComponent::new(10, Role::Server);
+        let other_pub = || vec![other_server.pub_component()];
+        let attestation = EpochAttestation::new(&KeyStore::new(other_server.clone()), 5).unwrap();
+
+        // one server relaying another's attestation isn't enough...
+        let replies = vec![
+            ApiReply::Epoch(attestation.clone()),
+            ApiReply::Error(ApiErrorCode::NoData, "none".into()),
+            ApiReply::Epoch(attestation.clone()),
+        ];
+        let (client, _) = MockServers {
+            answers: replying(replies),
+            entities: other_pub(),
+        }
+        .spawn()
+        .await;
+        assert!(matches!(
+            client.get_epoch().await,
+            Err(HdltError::NotEnoughServers)
+        ));
+
+        // ...even when every server does
+        let replies = vec![ApiReply::Epoch(attestation); 3];
+        let (client, _) = MockServers {
+            answers: replying(replies),
+            server_faults: 1,
+            entities: other_pub(),
+        }
+        .spawn()
+        .await;
+        assert!(matches!(
+            client.get_epoch().await,
+            Err(HdltError::NotEnoughServers)
+        ));
+    }
+
+    #[tokio::test]
+    async fn report_cache() {
+        let (mut client, contacted) = MockServers {
+            answers: vec![always(ApiReply::PositionReports(vec![]))],
+            ..MockServers::default()
+        }
+        .spawn()
+        .await;
+
+        // the same query in the same epoch only reaches the servers once
+        for _ in 0..2 {
+            assert!(client
+                .request_position_reports(1, 0..2)
+                .await
+                .unwrap()
+                .is_empty());
+        }
+        assert_eq!(contacted.lock().unwrap().len(), 1);
+
+        // other queries aren't served from the cache
+        client.request_position_reports(1, 0..3).await.unwrap();
+        assert_eq!(contacted.lock().unwrap().len(), 2);
+
+        // submitting a report forgets the queries that could include it, even if it fails
+        client.request_position_reports(2, 0..3).await.unwrap();
+        client.request_position_reports(1, 3..5).await.unwrap();
+        let report = {
+            let prover_priv = EntityPrivComponent::new(1, Role::User);
+            let mut witness = KeyStore::new(EntityPrivComponent::new(2, Role::User));
+            witness.add_entity(prover_priv.pub_component()).unwrap();
+            let prover = KeyStore::new(prover_priv);
+
+            let request = ProximityProofRequest::new(2, Position(0, 0), &prover);
+            let proof = ProximityProof::new(request, Position(0, 0), &witness).unwrap();
+            PositionProof::new(vec![proof], 0).unwrap()
+        };
+        assert!(client.submit_position_report(report).await.is_err());
+        let mut cached: Vec<_> = client
+            .report_cache
+            .lock()
+            .unwrap()
+            .keys()
+            .cloned()
+            .collect();
+        cached.sort_by_key(|(user_id, range)| (*user_id, range.start));
+        assert_eq!(cached, vec![(1, 3..5), (2, 0..3)]);
+        let before = contacted.lock().unwrap().len();
+        client.request_position_reports(1, 0..3).await.unwrap();
+        client.request_position_reports(1, 0..2).await.unwrap();
+        assert_eq!(contacted.lock().unwrap().len(), before + 2);
+
+        // results are forgotten once the epoch advances
+        client.set_current_epoch(0);
+        assert_eq!(client.report_cache.lock().unwrap().len(), 4);
+        client.set_current_epoch(1);
+        assert!(client.report_cache.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn close() {
+        let (client, _) = MockServers {
+            answers: vec![always(ApiReply::UsersAtPosition(vec![])); 3],
+            ..MockServers::default()
+        }
+        .spawn()
+        .await;
+        client
+            .obtain_users_at_position(Position(0, 0), 0)
+            .await
+            .unwrap();
+
+        client.close().await;
+        assert!(matches!(
+            client.obtain_users_at_position(Position(0, 0), 0).await,
+            Err(HdltError::Closed)
+        ));
+        assert!(matches!(
+            client
+                .submit_position_report(UnverifiedPositionProof { witnesses: vec![] })
+                .await,
+            Err(HdltError::Closed)
+        ));
+        assert!(matches!(
+            client.obtain_position_report(1, 0).await,
+            Err(HdltError::Closed)
+        ));
+
+        // servers can't be added back either
+        assert!(matches!(
+            client
+                .add_server(0, "http://127.0.0.1:1/".parse().unwrap())
+                .await,
+            Err(HdltError::Closed)
+        ));
+    }
+
+    #[tokio::test]
+    async fn no_servers() {
+        model::ensure_init();
+        let keystore = Arc::new(KeyStore::new(EntityPrivComponent::new(300, Role::HaClient)));
+        assert!(matches!(
+            HdltApiClient::new(vec![], keystore, 0, 0, 0),
+            Err(HdltError::NoServersConfigured)
+        ));
+
+        // every server removed after creation
+        let (client, _) = MockServers {
+            answers: vec![always(ApiReply::UsersAtPosition(vec![])); 2],
+            ..MockServers::default()
+        }
+        .spawn()
+        .await;
+        assert!(client.remove_server(0).await);
+        assert!(client.remove_server(1).await);
+
+        let timeout = Duration::from_secs(5);
+        let res = tokio::time::timeout(timeout, client.obtain_users_at_position(Position(0, 0), 0));
+        assert!(matches!(res.await, Ok(Err(HdltError::NoServersConfigured))));
+        let res = tokio::time::timeout(timeout, client.obtain_position_report_regular(1, 0));
+        assert!(matches!(res.await, Ok(Err(HdltError::NoServersConfigured))));
+        let res = tokio::time::timeout(timeout, client.obtain_position_report(1, 0));
+        assert!(matches!(res.await, Ok(Err(HdltError::NoServersConfigured))));
+        let res = tokio::time::timeout(
+            timeout,
+            client.submit_position_report(UnverifiedPositionProof { witnesses: vec![] }),
+        );
+        assert!(matches!(res.await, Ok(Err(HdltError::NoServersConfigured))));
+        let res = tokio::time::timeout(timeout, client.exchange_capabilities());
+        assert!(matches!(res.await, Ok(Err(HdltError::NoServersConfigured))));
+    }
+
+    /// Users 400 (prover) and 401 (witness), and values of atomic reads of 400's position
+    fn atomic_read_values(positions: &[Position]) -> (Vec<EntityPubComponent>, Vec<ApiReply>) {
+        model::ensure_init();
+        let prover_priv = EntityPrivComponent::new(400, Role::User);
+        let witness_priv = EntityPrivComponent::new(401, Role::User);
+        let users = vec![prover_priv.pub_component(), witness_priv.pub_component()];
+        let prover = KeyStore::new(prover_priv);
+        let mut witness = KeyStore::new(witness_priv);
+        witness.add_entity(users[0].clone()).unwrap();
+
+        let replies = positions
+            .iter()
+            .map(|&position| {
+                let request = ProximityProofRequest::new(0, position, &prover);
+                let proof = ProximityProof::new(request, position, &witness).unwrap();
+                let proof = PositionProof::new(vec![proof], 0).unwrap();
+                ApiReply::ReturnedValue(Some((0, proof.into())))
+            })
+            .collect();
+
+        (users, replies)
+    }
+
+    #[tokio::test]
+    async fn atomic_read_agreement() {
+        // one byzantine server returns a different (but valid) value
+        let (users, replies) = atomic_read_values(&[
+            Position(1, 1),
+            Position(1, 1),
+            Position(1, 1),
+            Position(2, 2),
+        ]);
+        let (mut client, _) = MockServers {
+            answers: replying(replies),
+            server_faults: 1,
+            entities: users,
+        }
+        .spawn()
+        .await;
+        client.set_return_transport(ReturnTransport::Pull);
+        assert_eq!(
+            client.obtain_position_report(400, 0).await.unwrap(),
+            Position(1, 1)
+        );
+
+        // no two servers agree
+        let (users, replies) =
+            atomic_read_values(&[Position(1, 1), Position(2, 2), Position(3, 3)]);
+        let (mut client, _) = MockServers {
+            answers: replying(replies),
+            server_faults: 1,
+            entities: users,
+        }
+        .spawn()
+        .await;
+        client.set_return_transport(ReturnTransport::Pull);
+        assert!(matches!(
+            client.obtain_position_report(400, 0).await,
+            Err(HdltError::DivergentReplies)
+        ));
+    }
+
+    #[tokio::test]
+    async fn atomic_read_without_callbacks() {
+        // servers without callbacks reply with the value right away, and never return one
+        let (users, replies) = atomic_read_values(&[Position(1, 1); 3]);
+        let answers = || {
+            replies
+                .iter()
+                .map(|reply| match reply {
+                    ApiReply::ReturnedValue(Some(value)) => {
+                        let value = value.clone();
+                        Some(Arc::new(move |request: &ApiRequest| match request {
+                            ApiRequest::ObtainPositionReport { .. } => {
+                                ApiReply::PositionReports(vec![value.clone()])
+                            }
+                            _ => ApiReply::ReturnedValue(None),
+                        }) as MockAnswer)
+                    }
+                    _ => unreachable!(),
+                })
+                .collect()
+        };
+
+        let (client, _) = MockServers {
+            answers: answers(),
+            server_faults: 1,
+            entities: users.clone(),
+        }
+        .spawn()
+        .await;
+        assert_eq!(
+            client.obtain_position_report(400, 0).await.unwrap(),
+            Position(1, 1)
+        );
+
+        let (mut client, _) = MockServers {
+            answers: answers(),
+            server_faults: 1,
+            entities: users,
+        }
+        .spawn()
+        .await;
+        client.set_return_transport(ReturnTransport::Pull);
+        assert_eq!(
+            client.obtain_position_report(400, 0).await.unwrap(),
+            Position(1, 1)
+        );
+    }
+
+    #[tokio::test]
+    async fn coarse_positions() {
+        let coarse = |positions: &[Position]| {
+            positions
+                .iter()
+                .map(|&position| ApiReply::PositionReport(0, position))
+                .collect::<Vec<_>>()
+        };
+
+        // coarse positions can't be checked, enough servers must agree on them
+        let replies = coarse(&[Position(10, 10), Position(0, 0), Position(10, 10)]);
+        let (client, _) = MockServers {
+            answers: replying(replies),
+            server_faults: 1,
+            ..MockServers::default()
+        }
+        .spawn()
+        .await;
+        assert_eq!(
+            client.obtain_position_report_regular(1, 0).await.unwrap(),
+            Position(10, 10)
+        );
+        assert_eq!(
+            client.obtain_position_report(1, 0).await.unwrap(),
+            Position(10, 10)
+        );
+
+        let replies = coarse(&[Position(10, 10), Position(0, 0), Position(20, 20)]);
+        let (client, _) = MockServers {
+            answers: replying(replies),
+            server_faults: 1,
+            ..MockServers::default()
+        }
+        .spawn()
+        .await;
+        assert!(matches!(
+            client.obtain_position_report_regular(1, 0).await,
+            Err(HdltError::DivergentReplies)
+        ));
+
+        let misbehaviour = vec![(3, Position(0, 0), Position(10, 10))];
+        let (client, _) = MockServers {
+            answers: vec![
+                always(ApiReply::CoarseMisbehaviour(misbehaviour.clone())),
+                always(ApiReply::CoarseMisbehaviour(vec![])),
+                always(ApiReply::CoarseMisbehaviour(misbehaviour.clone())),
+            ],
+            server_faults: 1,
+            ..MockServers::default()
+        }
+        .spawn()
+        .await;
+        assert_eq!(
+            client.obtain_misbehaviour_coarse(1).await.unwrap(),
+            misbehaviour
+        );
+    }
+
+    #[tokio::test]
+    async fn all_servers_failing() {
+        let (client, _) = MockServers {
+            answers: vec![None; 3],
+            server_faults: 1,
+            ..MockServers::default()
+        }
+        .spawn()
+        .await;
+
+        let res = tokio::time::timeout(
+            Duration::from_secs(5),
+            client.obtain_users_at_position(Position(0, 0), 0),
+        )
+        .await
+        .expect("read without quorum should fail, not hang");
+        assert!(matches!(res, Err(HdltError::NotEnoughServers)));
+
+        let res = tokio::time::timeout(
+            Duration::from_secs(5),
+            client.submit_position_report(UnverifiedPositionProof { witnesses: vec![] }),
+        )
+        .await
+        .expect("write without quorum should fail, not hang");
+        assert!(matches!(res, Err(HdltError::NotEnoughServers)));
+    }
+
+    #[tokio::test]
+    async fn position_report_debug() {
+        let replies = vec![
+            ApiReply::PositionReports(vec![]),
+            ApiReply::Error(ApiErrorCode::Other, "no data".into()),
+            ApiReply::UsersAtPosition(vec![42]),
+        ];
+        let (client, _) = MockServers {
+            answers: replying(replies.clone()),
+            server_faults: 1,
+            ..MockServers::default()
+        }
+        .spawn()
+        .await;
+
+        // a server we can't talk to (we don't have its keys)
+        client
+            .add_server(3, "http://127.0.0.1:1".parse().unwrap())
+            .await
+            .unwrap();
+
+        let results = client.obtain_position_report_debug(1, 0).await;
+        assert_eq!(
+            results.iter().map(|(id, _)| *id).collect::<Vec<_>>(),
+            vec![0, 1, 2, 3]
+        );
+        for ((_, result), expected) in results.iter().zip(&replies) {
+            assert_eq!(result.as_ref().unwrap(), expected);
+        }
+        assert!(matches!(
+            results[3].1,
+            Err(HdltError::Api(ApiClientError::CipherError(_)))
+        ));
+    }
+}
@@ -8,22 +8,23 @@ pub(crate) mod malicious_witness;
 pub(crate) mod state;
 mod witness_api;
 
-pub use hdlt_api::{HdltApiClient, HdltError};
+pub use hdlt_api::{HdltApiClient, HdltError, ReturnTransport};
+pub use state::WitnessSelection;
 
 use std::net::SocketAddr;
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Duration;
 
+use futures::Stream;
 use structopt::StructOpt;
 use tokio::{net::TcpStream, sync::RwLock};
-use tokio_stream::wrappers::TcpListenerStream;
-use tokio_stream::{Stream, StreamExt};
 use tonic::transport::{Server, Uri};
 
-use eyre::eyre;
 use tracing::*;
 
 use model::keys::KeyStore;
+use net_utils::{create_tcp_incoming_with, IncomingOptions, Keepalive};
 use protos::driver::correct_user_driver_server::CorrectUserDriverServer;
 use protos::driver::malicious_user_driver_server::MaliciousUserDriverServer;
 use protos::witness::witness_server::WitnessServer;
@@ -63,6 +64,24 @@ pub struct UserOptions {
     /// Secret keys password.
     #[structopt(long, short = "p", env = "SECRET_KEYS_PASSWORD")]
     pub skeys_password: Option<String>,
+
+    /// Most witness requests to send per epoch (unbounded by default).
+    ///
+    /// Proving a position fails when this is below the number of witnesses needed.
+    #[structopt(long)]
+    pub max_witness_requests: Option<usize>,
+
+    /// Which neighbours to request witness from first: nearest-first or random.
+    #[structopt(long, default_value = "nearest-first")]
+    pub witness_selection: WitnessSelection,
+
+    /// Keep Nagle's algorithm on connections from the driver and other users (off by default).
+    #[structopt(long)]
+    pub nagle: bool,
+
+    /// Seconds a connection may be idle before it's probed with TCP keepalive (0 disables it).
+    #[structopt(long, default_value = "60")]
+    pub keepalive_idle_secs: u64,
 }
 
 #[derive(Debug)]
@@ -79,9 +98,11 @@ impl User {
     pub async fn new(options: &UserOptions) -> eyre::Result<(Self, UserBgTaskHandle)> {
         let keystore = open_keystore(options)?;
 
-        let (incoming, listen_addr) = create_tcp_incoming(&options.bind_addr).await?;
+        let (incoming, listen_addr) =
+            create_tcp_incoming_with(&options.bind_addr, incoming_options(options)).await?;
 
         let is_malicious = options.malicious;
+        let witness_limits = (options.max_witness_requests, options.witness_selection);
         let ks = Arc::clone(&keystore);
         let su = options
             .server_uris
@@ -94,7 +115,7 @@ impl User {
             let res = if is_malicious {
                 malicious_driver_server(incoming, ks, su).await
             } else {
-                driver_server(incoming, ks, su).await
+                driver_server(incoming, ks, su, witness_limits).await
             };
 
             if let Err(err) = &res {
@@ -132,21 +153,31 @@ impl User {
 }
 
 fn open_keystore(options: &UserOptions) -> eyre::Result<Arc<KeyStore>> {
-    let mut keystore = KeyStore::load_from_files(
+    let keystore = KeyStore::load_from_files_with_password(
         options.entity_registry_path.clone(),
-        options.skeys_path.clone(),
+        Some(options.skeys_path.clone()),
+        options.skeys_password.as_deref(),
     )?;
 
-    if keystore.is_locked() {
-        let password = options.skeys_password.as_ref().ok_or_else(|| {
-            eyre!("Secret keys are password-protected. Please supply their password")
-        })?;
-        keystore.unlock(password)?;
-    }
-
     Ok(Arc::new(keystore))
 }
 
+fn incoming_options(options: &UserOptions) -> IncomingOptions {
+    let keepalive = match options.keepalive_idle_secs {
+        0 => None,
+        secs => Some(Keepalive {
+            idle: Duration::from_secs(secs),
+            ..Keepalive::default()
+        }),
+    };
+
+    IncomingOptions {
+        nodelay: !options.nagle,
+        keepalive,
+        ..IncomingOptions::default()
+    }
+}
+
 async fn ctrl_c() {
     use std::future;
 
@@ -181,8 +212,13 @@ async fn driver_server(
     incoming: IncomingType!(),
     keystore: Arc<KeyStore>,
     server_uris: Vec<(u32, Uri)>,
+    (max_witness_requests, witness_selection): (Option<usize>, WitnessSelection),
 ) -> eyre::Result<()> {
-    let state = Arc::new(RwLock::new(CorrectUserState::new()));
+    let state = Arc::new(RwLock::new(CorrectUserState::new(
+        keystore.my_id(),
+        max_witness_requests,
+        witness_selection,
+    )));
     let server = Server::builder()
         .add_service(CorrectUserDriverServer::new(CorrectDriverService::new(
             state.clone(),
@@ -197,19 +233,3 @@ async fn driver_server(
     info!("Correct User Driver Server listening");
     server.await.map_err(eyre::Report::from)
 }
-
-pub async fn create_tcp_incoming(
-    bind_addr: &SocketAddr,
-) -> eyre::Result<(IncomingType!(), SocketAddr)> {
-    let listener = tokio::net::TcpListener::bind(bind_addr).await?;
-    let listen_addr = listener.local_addr()?;
-
-    let listener_stream = TcpListenerStream::new(listener).map(|res| {
-        res.and_then(|socket| {
-            socket.set_nodelay(true)?;
-            Ok(socket)
-        })
-    });
-
-    Ok((listener_stream, listen_addr))
-}
@@ -51,7 +51,7 @@ impl Witness for MaliciousWitnessService {
             let guard = self.state.read().await;
             (
                 guard.epoch(),
-                Position(unv_ppreq.position.0 + 1, unv_ppreq.position.1 + 1),
+                unv_ppreq.position + Position(1, 1),
                 guard.malicious_type(),
             )
         };
@@ -1,20 +1,29 @@
 /// Client State
-use model::{keys::EntityId, neighbourhood::are_neighbours, Position};
+use model::neighbourhood::{are_neighbours, choose_witnesses, NeighbourhoodConfig};
+use model::{keys::EntityId, Position};
+use rand::seq::SliceRandom;
 use rand::Rng;
 use std::collections::HashMap;
+use std::str::FromStr;
 use tonic::transport::Uri;
 
 /// State of a correct user
 #[derive(Debug, Default)]
 pub struct CorrectUserState {
+    /// Id of the user itself
+    id: EntityId,
+
     /// Current Epoch
     epoch: u64,
 
     /// Current position
     position: Position,
 
-    /// Visible neighbours
-    visible_neighbours: Vec<EntityId>,
+    /// Correct users in the system
+    correct_neighbours: Vec<Neighbour>,
+
+    /// Malicious users in the neighbourhood
+    malicious_neighbours: Vec<EntityId>,
 
     /// Map of the Uris for all users in the system
     id_to_uri: HashMap<EntityId, Uri>,
@@ -24,18 +33,36 @@ pub struct CorrectUserState {
 
     /// Upper bound on faults in the neighbourhood
     server_faults: u64,
+
+    /// Most witness requests to send per epoch (unbounded if None)
+    max_witness_requests: Option<usize>,
+
+    /// How to pick which neighbours to request witness from
+    witness_selection: WitnessSelection,
+
+    /// Witness requests sent in the current epoch
+    witness_requests: usize,
 }
 
 impl CorrectUserState {
     /// Create a new state
-    pub fn new() -> Self {
+    pub fn new(
+        id: EntityId,
+        max_witness_requests: Option<usize>,
+        witness_selection: WitnessSelection,
+    ) -> Self {
         CorrectUserState {
+            id,
             epoch: 0,
             position: Position(0, 0),
-            visible_neighbours: vec![],
+            correct_neighbours: vec![],
+            malicious_neighbours: vec![],
             neighbour_faults: 0,
             server_faults: 0,
             id_to_uri: HashMap::new(),
+            max_witness_requests,
+            witness_selection,
+            witness_requests: 0,
         }
     }
 
@@ -44,15 +71,18 @@ impl CorrectUserState {
         &mut self,
         epoch: u64,
         position: Position,
-        neighbours: Vec<EntityId>,
+        correct: Vec<Neighbour>,
+        malicious: Vec<EntityId>,
         neighbour_faults: u64,
         server_faults: u64,
     ) {
         self.epoch = epoch;
         self.position = position;
-        self.visible_neighbours = neighbours;
+        self.correct_neighbours = correct;
+        self.malicious_neighbours = malicious;
         self.neighbour_faults = neighbour_faults;
         self.server_faults = server_faults;
+        self.witness_requests = 0;
     }
 
     /// Getter for epoch
@@ -89,9 +119,66 @@ impl CorrectUserState {
         &self.id_to_uri[&id]
     }
 
-    /// Iterator over the neighbourhood
-    pub fn neighbourhood(&self) -> impl Iterator<Item = EntityId> + '_ {
-        self.visible_neighbours.iter().copied()
+    /// The neighbourhood: correct neighbours nearest first, then malicious ones
+    pub fn neighbourhood(&self) -> Vec<EntityId> {
+        let mut neighbourhood = choose_witnesses(
+            (self.id, self.position),
+            self.correct_neighbours.iter().map(|n| (n.id, n.position)),
+            &NeighbourhoodConfig::default(),
+            usize::MAX,
+        );
+        neighbourhood.extend(self.malicious_neighbours.iter().copied());
+
+        neighbourhood
+    }
+
+    /// Pick neighbours to request witness from, counting them as requested.
+    ///
+    /// Never picks more than what is left of the epoch's witness request limit.
+    pub fn take_witness_candidates(&mut self) -> Vec<EntityId> {
+        let left = self
+            .max_witness_requests
+            .map_or(usize::MAX, |max| max.saturating_sub(self.witness_requests));
+
+        let mut candidates = self.neighbourhood();
+        if self.witness_selection == WitnessSelection::Random {
+            candidates.shuffle(&mut rand::thread_rng());
+        }
+        candidates.truncate(left);
+
+        self.witness_requests += candidates.len();
+        candidates
+    }
+}
+
+/// How a correct user picks which neighbours to request witness from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WitnessSelection {
+    /// Nearest first, see [CorrectUserState::neighbourhood]
+    NearestFirst,
+
+    /// Uniformly at random
+    Random,
+}
+
+impl Default for WitnessSelection {
+    fn default() -> Self {
+        WitnessSelection::NearestFirst
+    }
+}
+
+impl FromStr for WitnessSelection {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "nearest-first" => Ok(WitnessSelection::NearestFirst),
+            "random" => Ok(WitnessSelection::Random),
+            _ => Err(format!(
+                "unknown witness selection {:?} (expected nearest-first or random)",
+                s
+            )),
+        }
     }
 }
 
@@ -287,3 +374,76 @@ impl MaliciousUserState {
             .copied()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// The user itself, then users 1 to 10 farther and farther away
+    fn neighbours() -> Vec<Neighbour> {
+        (0..=10)
+            .map(|id| Neighbour {
+                position: Position(id as i64, 0),
+                id,
+            })
+            .collect()
+    }
+
+    fn state_with(
+        max_witness_requests: Option<usize>,
+        selection: WitnessSelection,
+    ) -> CorrectUserState {
+        let mut state = CorrectUserState::new(0, max_witness_requests, selection);
+        state.update(0, Position(0, 0), neighbours(), vec![], 1, 0);
+        state
+    }
+
+    #[test]
+    fn witness_requests_capped() {
+        for &selection in &[WitnessSelection::NearestFirst, WitnessSelection::Random] {
+            let mut state = state_with(Some(4), selection);
+
+            // retrying in the same epoch doesn't go over the cap
+            let mut requested = state.take_witness_candidates();
+            requested.extend(state.take_witness_candidates());
+            assert_eq!(requested.len(), 4);
+            assert!(requested.iter().all(|id| (1..=10).contains(id)));
+
+            // the cap is per epoch
+            state.update(1, Position(0, 0), neighbours(), vec![], 1, 0);
+            assert_eq!(state.take_witness_candidates().len(), 4);
+            assert!(state.take_witness_candidates().is_empty());
+        }
+    }
+
+    #[test]
+    fn witness_selection() {
+        let mut state = state_with(Some(3), WitnessSelection::NearestFirst);
+        assert_eq!(state.take_witness_candidates(), vec![1, 2, 3]);
+
+        let mut state = state_with(None, WitnessSelection::Random);
+        let mut candidates = state.take_witness_candidates();
+        candidates.sort_unstable();
+        assert_eq!(candidates, (1..=10).collect::<Vec<_>>());
+
+        assert_eq!("random".parse(), Ok(WitnessSelection::Random));
+        assert!("farthest-first".parse::<WitnessSelection>().is_err());
+    }
+
+    #[test]
+    fn neighbourhood() {
+        let mut state = CorrectUserState::new(0, None, WitnessSelection::NearestFirst);
+        let mut correct = neighbours();
+        correct.reverse();
+        correct.push(Neighbour {
+            position: Position(500, 0),
+            id: 11,
+        });
+        state.update(0, Position(0, 0), correct, vec![20, 21], 1, 0);
+
+        // never itself nor far away users, and malicious users last
+        let mut expected: Vec<_> = (1..=10).collect();
+        expected.extend(&[20, 21]);
+        assert_eq!(state.neighbourhood(), expected);
+    }
+}
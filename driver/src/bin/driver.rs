@@ -1,9 +1,11 @@
 use std::convert::TryFrom;
 use std::fs;
+use std::path::PathBuf;
 use std::time::Duration;
 use structopt::StructOpt;
 
-use driver::{Conf, Driver};
+use driver::{Conf, Driver, Recorder, Recording};
+use rand::prelude::*;
 use tracing::*;
 
 #[derive(StructOpt)]
@@ -15,9 +17,34 @@ struct Options {
     #[structopt(short, long)]
     count: Option<usize>,
 
+    /// Push the initial configuration and a single epoch update (no sleep), then exit
+    #[structopt(long, conflicts_with = "count")]
+    once: bool,
+
     /// Tick interval (in seconds)
     #[structopt(short, long, parse(try_from_str = parse_duration_secs), default_value = "30")]
     interval: Duration,
+
+    /// Tick interval jitter (in percent of the interval, up to 100)
+    #[structopt(short, long, parse(try_from_str = parse_percent), default_value = "0")]
+    jitter: u8,
+
+    /// After each tick, check that the servers know every correct user's position
+    #[structopt(long)]
+    verify: bool,
+
+    /// After each tick, append the true position of every correct user to this file
+    ///
+    /// One JSON object per line and epoch, like `{"epoch":0,"positions":[{"id":1,"x":2,"y":3}]}`.
+    /// The file can later be replayed with `--replay`.
+    #[structopt(long)]
+    ground_truth: Option<PathBuf>,
+
+    /// Move correct users as recorded in this ground truth file, instead of randomly
+    ///
+    /// Stops after the last recorded epoch, unless a count is given.
+    #[structopt(long, conflicts_with = "once")]
+    replay: Option<PathBuf>,
 }
 
 #[tokio::main]
@@ -32,42 +59,111 @@ async fn main() -> eyre::Result<()> {
         .and_then(|conf| json::parse(&conf).map_err(eyre::Report::from))
         .and_then(|conf| Conf::try_from(&conf).map_err(eyre::Report::from))?;
 
-    let driver = Driver::new(config).await?;
+    if options.once {
+        return once(config).await;
+    }
+
+    let driver = match &options.replay {
+        Some(path) => Driver::replaying(config, Recording::load(path)?).await?,
+        None => Driver::new(config).await?,
+    };
+    let mut recorder = options
+        .ground_truth
+        .as_deref()
+        .map(Recorder::open)
+        .transpose()?;
 
     if let Some(c) = options.count {
         for _ in 0..c {
-            tick(&driver, options.interval).await?;
+            tick(
+                &driver,
+                jittered_interval(options.interval, options.jitter),
+                options.verify,
+                recorder.as_mut(),
+            )
+            .await?;
         }
     } else {
-        loop {
-            tick(&driver, options.interval).await?;
+        while !driver.halted().await {
+            tick(
+                &driver,
+                jittered_interval(options.interval, options.jitter),
+                options.verify,
+                recorder.as_mut(),
+            )
+            .await?;
         }
+        info!("Reached the last epoch, exiting");
     }
 
+    info!("Run summary:\n{}", driver.summary().await);
+
     Ok(())
 }
 
-async fn tick(driver: &Driver, interval: Duration) -> eyre::Result<()> {
-    async fn tick_inner(driver: &Driver) -> eyre::Result<()> {
+async fn once(config: Conf) -> eyre::Result<()> {
+    let driver = Driver::new(config).await?;
+
+    info!("Tick");
+    driver.tick().await?;
+    info!("Advanced to epoch {}", driver.current_epoch().await);
+
+    Ok(())
+}
+
+async fn tick(
+    driver: &Driver,
+    interval: Duration,
+    verify: bool,
+    recorder: Option<&mut Recorder>,
+) -> eyre::Result<()> {
+    async fn tick_inner(
+        driver: &Driver,
+        verify: bool,
+        recorder: Option<&mut Recorder>,
+    ) -> eyre::Result<()> {
+        let epoch = driver.current_epoch().await;
         info!("Tick");
         driver.tick().await?;
 
+        if let Some(recorder) = recorder {
+            let positions = driver.ground_truth(epoch).await;
+            recorder.record(epoch, &positions)?;
+        }
+
         info!("Asking users to prove their positions");
         if let Err(errs) = driver.prove_position_all().await {
             warn!("Some users could not prove their position: {:#?}", errs);
         }
 
+        if verify {
+            info!("Checking the servers know users' positions");
+            let mismatches = driver.verify_positions().await;
+            if !mismatches.is_empty() {
+                warn!("Some positions do not match: {:#?}", mismatches);
+            }
+        }
+
         info!("Advanced to epoch {}", driver.current_epoch().await);
 
         Ok(())
     }
 
-    async fn with_sleep(driver: &Driver, interval: Duration) -> eyre::Result<()> {
-        tokio::join!(tick_inner(driver), tokio::time::sleep(interval)).0
+    async fn with_sleep(
+        driver: &Driver,
+        interval: Duration,
+        verify: bool,
+        recorder: Option<&mut Recorder>,
+    ) -> eyre::Result<()> {
+        tokio::join!(
+            tick_inner(driver, verify, recorder),
+            tokio::time::sleep(interval)
+        )
+        .0
     }
 
     tokio::select! {
-        res = with_sleep(driver, interval) => res,
+        res = with_sleep(driver, interval, verify, recorder) => res,
         _ = ctrl_c() => {
             info!("Ctrl+C signal received, exiting");
             std::process::exit(0);
@@ -84,7 +180,428 @@ async fn ctrl_c() {
     }
 }
 
+/// Randomly stretch or shrink the interval by up to `jitter` percent
+///
+/// Avoids ticks from several drivers aligning
+fn jittered_interval(interval: Duration, jitter: u8) -> Duration {
+    if jitter == 0 {
+        return interval;
+    }
+
+    let jitter = jitter as f64 / 100.0;
+    interval.mul_f64(thread_rng().gen_range(1.0 - jitter..=1.0 + jitter))
+}
+
+fn parse_percent(input: &str) -> Result<u8, String> {
+    match input.parse() {
+        Ok(p) if p <= 100 => Ok(p),
+        Ok(_) => Err("percentage must be at most 100".to_owned()),
+        Err(e) => Err(format!("{}", e)),
+    }
+}
+
 fn parse_duration_secs(input: &str) -> Result<Duration, std::num::ParseIntError> {
     let secs = input.parse()?;
     Ok(Duration::from_secs(secs))
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use driver::{NodeSummary, RetryPolicy};
+    use model::WorldBounds;
+    use protos::driver::correct_server_driver_server::{
+        CorrectServerDriver, CorrectServerDriverServer,
+    };
+    use protos::driver::correct_user_driver_server::{CorrectUserDriver, CorrectUserDriverServer};
+    use protos::driver::{
+        EpochUpdateRequest, InitialConfigRequest, ObtainPositionRequest, ServerConfigUpdate,
+    };
+    use protos::util::{Empty, Position};
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+    use tonic::transport::Uri;
+    use tonic::{Request, Response, Status};
+
+    /// Server that is too busy for the next `busy_updates` config updates
+    #[derive(Default, Clone)]
+    struct MockServer {
+        initial_configs: Arc<Mutex<usize>>,
+        epochs: Arc<Mutex<Vec<u64>>>,
+        faults: Arc<Mutex<Vec<(u64, u64)>>>,
+        busy_updates: Arc<Mutex<usize>>,
+    }
+
+    #[tonic::async_trait]
+    impl CorrectServerDriver for MockServer {
+        async fn initial_config(
+            &self,
+            _request: Request<InitialConfigRequest>,
+        ) -> Result<Response<Empty>, Status> {
+            *self.initial_configs.lock().unwrap() += 1;
+            Ok(Response::new(Empty {}))
+        }
+
+        async fn update_config(
+            &self,
+            request: Request<ServerConfigUpdate>,
+        ) -> Result<Response<Empty>, Status> {
+            {
+                let mut busy_updates = self.busy_updates.lock().unwrap();
+                if *busy_updates > 0 {
+                    *busy_updates -= 1;
+                    return Err(Status::unavailable("busy"));
+                }
+            }
+
+            let update = request.into_inner();
+            self.epochs.lock().unwrap().push(update.new_epoch);
+            self.faults
+                .lock()
+                .unwrap()
+                .push((update.neighbour_faults, update.server_faults));
+            Ok(Response::new(Empty {}))
+        }
+    }
+
+    /// User that refuses to prove its position if `faulty`
+    #[derive(Default, Clone)]
+    struct MockUser {
+        faulty: bool,
+        updates: Arc<Mutex<Vec<EpochUpdateRequest>>>,
+    }
+
+    #[tonic::async_trait]
+    impl CorrectUserDriver for MockUser {
+        async fn initial_config(
+            &self,
+            _request: Request<InitialConfigRequest>,
+        ) -> Result<Response<Empty>, Status> {
+            Ok(Response::new(Empty {}))
+        }
+
+        async fn update_epoch(
+            &self,
+            request: Request<EpochUpdateRequest>,
+        ) -> Result<Response<Empty>, Status> {
+            self.updates.lock().unwrap().push(request.into_inner());
+            Ok(Response::new(Empty {}))
+        }
+
+        async fn prove_position(
+            &self,
+            _request: Request<Empty>,
+        ) -> Result<Response<Empty>, Status> {
+            if self.faulty {
+                Err(Status::unavailable("no witnesses"))
+            } else {
+                Ok(Response::new(Empty {}))
+            }
+        }
+
+        async fn obtain_position(
+            &self,
+            _request: Request<ObtainPositionRequest>,
+        ) -> Result<Response<Position>, Status> {
+            Err(Status::unimplemented("not needed"))
+        }
+    }
+
+    async fn spawn_mock_user(faulty: bool) -> (MockUser, Uri, tokio::task::JoinHandle<()>) {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let mock = MockUser {
+            faulty,
+            ..MockUser::default()
+        };
+        let server = tokio::spawn({
+            let mock = mock.clone();
+            async move {
+                tonic::transport::Server::builder()
+                    .add_service(CorrectUserDriverServer::new(mock))
+                    .serve_with_incoming(tokio_stream::wrappers::TcpListenerStream::new(listener))
+                    .await
+                    .unwrap()
+            }
+        });
+
+        (mock, format!("http://{}", addr).parse().unwrap(), server)
+    }
+
+    async fn spawn_mock() -> (MockServer, Uri, tokio::task::JoinHandle<()>) {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let mock = MockServer::default();
+        let server = tokio::spawn({
+            let mock = mock.clone();
+            async move {
+                tonic::transport::Server::builder()
+                    .add_service(CorrectServerDriverServer::new(mock))
+                    .serve_with_incoming(tokio_stream::wrappers::TcpListenerStream::new(listener))
+                    .await
+                    .unwrap()
+            }
+        });
+
+        (mock, format!("http://{}", addr).parse().unwrap(), server)
+    }
+
+    #[tokio::test]
+    async fn once_pushes_one_round() {
+        let (mock, uri, server) = spawn_mock().await;
+
+        let mut id_to_uri = HashMap::new();
+        id_to_uri.insert(0, uri);
+        once(Conf {
+            bounds: WorldBounds::from_dims(10, 10).unwrap(),
+            max_neighbourhood_faults: 0,
+            max_server_faults: 0,
+            correct_servers: vec![0],
+            correct_users: vec![],
+            malicious_users: vec![],
+            id_to_uri,
+            starting_positions: HashMap::new(),
+            server_faults: HashMap::new(),
+            max_epoch: None,
+            retry: RetryPolicy::default(),
+        })
+        .await
+        .unwrap();
+        server.abort();
+
+        // initial setup already sends the configuration for epoch 0 once
+        assert_eq!(*mock.initial_configs.lock().unwrap(), 1);
+        assert_eq!(*mock.epochs.lock().unwrap(), vec![0, 0]);
+    }
+
+    #[tokio::test]
+    async fn per_server_faults() {
+        let (mock0, uri0, server0) = spawn_mock().await;
+        let (mock1, uri1, server1) = spawn_mock().await;
+
+        let mut id_to_uri = HashMap::new();
+        id_to_uri.insert(0, uri0);
+        id_to_uri.insert(1, uri1);
+        let mut server_faults = HashMap::new();
+        server_faults.insert(1, (3, 2));
+        once(Conf {
+            bounds: WorldBounds::from_dims(10, 10).unwrap(),
+            max_neighbourhood_faults: 1,
+            max_server_faults: 0,
+            correct_servers: vec![0, 1],
+            correct_users: vec![],
+            malicious_users: vec![],
+            id_to_uri,
+            starting_positions: HashMap::new(),
+            server_faults,
+            max_epoch: None,
+            retry: RetryPolicy::default(),
+        })
+        .await
+        .unwrap();
+        server0.abort();
+        server1.abort();
+
+        assert_eq!(*mock0.faults.lock().unwrap(), vec![(1, 0), (1, 0)]);
+        assert_eq!(*mock1.faults.lock().unwrap(), vec![(3, 2), (3, 2)]);
+    }
+
+    #[tokio::test]
+    async fn retry_busy_server() {
+        let (mock, uri, server) = spawn_mock().await;
+
+        let mut id_to_uri = HashMap::new();
+        id_to_uri.insert(0, uri);
+        let conf = Conf {
+            bounds: WorldBounds::from_dims(10, 10).unwrap(),
+            max_neighbourhood_faults: 0,
+            max_server_faults: 0,
+            correct_servers: vec![0],
+            correct_users: vec![],
+            malicious_users: vec![],
+            id_to_uri,
+            starting_positions: HashMap::new(),
+            server_faults: HashMap::new(),
+            max_epoch: None,
+            retry: RetryPolicy {
+                retries: 1,
+                backoff: Duration::from_millis(1),
+            },
+        };
+
+        let driver = Driver::new(conf.clone()).await.unwrap();
+        *mock.busy_updates.lock().unwrap() = 1;
+        driver.tick().await.unwrap();
+        assert_eq!(*mock.epochs.lock().unwrap(), vec![0, 0]);
+        assert_eq!(
+            driver.summary().await.nodes[&0],
+            NodeSummary {
+                successes: 1,
+                failures: 0
+            }
+        );
+
+        // without retries, the tick fails
+        let driver = Driver::new(Conf {
+            retry: RetryPolicy::none(),
+            ..conf
+        })
+        .await
+        .unwrap();
+        *mock.busy_updates.lock().unwrap() = 1;
+        assert!(driver.tick().await.is_err());
+        server.abort();
+
+        assert_eq!(*mock.epochs.lock().unwrap(), vec![0, 0, 0]);
+    }
+
+    #[tokio::test]
+    async fn summary_after_count() {
+        let (_mock, server_uri, server) = spawn_mock().await;
+        let (_user_mock, user_uri, user) = spawn_mock_user(false).await;
+        let (_faulty_mock, faulty_uri, faulty) = spawn_mock_user(true).await;
+
+        let mut id_to_uri = HashMap::new();
+        id_to_uri.insert(0, server_uri);
+        id_to_uri.insert(1, user_uri);
+        id_to_uri.insert(2, faulty_uri);
+        let driver = Driver::new(Conf {
+            bounds: WorldBounds::from_dims(10, 10).unwrap(),
+            max_neighbourhood_faults: 0,
+            max_server_faults: 0,
+            correct_servers: vec![0],
+            correct_users: vec![1, 2],
+            malicious_users: vec![],
+            id_to_uri,
+            starting_positions: HashMap::new(),
+            server_faults: HashMap::new(),
+            max_epoch: None,
+            retry: RetryPolicy::default(),
+        })
+        .await
+        .unwrap();
+
+        for _ in 0..2 {
+            tick(&driver, Duration::from_secs(0), false, None)
+                .await
+                .unwrap();
+        }
+        server.abort();
+        user.abort();
+        faulty.abort();
+
+        let summary = driver.summary().await;
+        assert_eq!(summary.epochs, 2);
+        assert_eq!(summary.proofs, 2);
+        assert_eq!(
+            summary.nodes[&0],
+            NodeSummary {
+                successes: 2,
+                failures: 0
+            }
+        );
+        // epoch updates and proofs
+        assert_eq!(
+            summary.nodes[&1],
+            NodeSummary {
+                successes: 4,
+                failures: 0
+            }
+        );
+        assert_eq!(
+            summary.nodes[&2],
+            NodeSummary {
+                successes: 2,
+                failures: 2
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn replay_recorded_run() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let path = tmpdir.path().join("ground_truth");
+
+        async fn run(path: &std::path::Path, replay: bool) -> Vec<Vec<EpochUpdateRequest>> {
+            let (_server_mock, server_uri, server) = spawn_mock().await;
+            let (user1_mock, user1_uri, user1) = spawn_mock_user(false).await;
+            let (user2_mock, user2_uri, user2) = spawn_mock_user(false).await;
+
+            let mut id_to_uri = HashMap::new();
+            id_to_uri.insert(0, server_uri);
+            id_to_uri.insert(1, user1_uri);
+            id_to_uri.insert(2, user2_uri);
+            let config = Conf {
+                bounds: WorldBounds::from_dims(3, 3).unwrap(),
+                max_neighbourhood_faults: 0,
+                max_server_faults: 0,
+                correct_servers: vec![0],
+                correct_users: vec![1, 2],
+                malicious_users: vec![],
+                id_to_uri,
+                starting_positions: HashMap::new(),
+                server_faults: HashMap::new(),
+                max_epoch: None,
+                retry: RetryPolicy::default(),
+            };
+
+            if replay {
+                let driver = Driver::replaying(config, Recording::load(path).unwrap())
+                    .await
+                    .unwrap();
+                while !driver.halted().await {
+                    tick(&driver, Duration::from_secs(0), false, None)
+                        .await
+                        .unwrap();
+                }
+            } else {
+                let driver = Driver::new(config).await.unwrap();
+                let mut recorder = Recorder::open(path).unwrap();
+                for _ in 0..4 {
+                    tick(&driver, Duration::from_secs(0), false, Some(&mut recorder))
+                        .await
+                        .unwrap();
+                }
+            }
+            server.abort();
+            user1.abort();
+            user2.abort();
+
+            [user1_mock, user2_mock]
+                .iter()
+                .map(|user| user.updates.lock().unwrap().clone())
+                .collect()
+        }
+
+        let recorded = run(&path, false).await;
+        assert_eq!(recorded[0].len(), 4);
+
+        let replayed = run(&path, true).await;
+        assert_eq!(replayed, recorded);
+    }
+
+    #[test]
+    fn no_jitter() {
+        let interval = Duration::from_secs(30);
+        assert_eq!(jittered_interval(interval, 0), interval);
+    }
+
+    #[test]
+    fn jitter_bounds() {
+        let interval = Duration::from_secs(30);
+        let intervals: Vec<_> = (0..100).map(|_| jittered_interval(interval, 10)).collect();
+
+        for i in &intervals {
+            assert!(*i >= Duration::from_secs(27) && *i <= Duration::from_secs(33));
+        }
+        assert!(intervals.iter().any(|i| *i != intervals[0]));
+    }
+
+    #[test]
+    fn percent() {
+        assert_eq!(parse_percent("42"), Ok(42));
+        assert_eq!(parse_percent("100"), Ok(100));
+        assert!(parse_percent("101").is_err());
+        assert!(parse_percent("-1").is_err());
+    }
+}
@@ -1,13 +1,17 @@
 use eyre::eyre;
 use json::JsonValue;
 use model::keys::EntityId;
+use model::{Position, WorldBounds};
+use std::time::Duration;
 use std::{collections::HashMap, convert::TryFrom};
 use tonic::transport::Uri;
 
+use crate::RetryPolicy;
+
 #[derive(Clone)]
 pub struct Conf {
-    /// width x height
-    pub dims: (usize, usize),
+    /// Positions users can be in, from the configured width x height
+    pub bounds: WorldBounds,
 
     /// Neighbourhood fault tolerance
     pub max_neighbourhood_faults: usize,
@@ -28,6 +32,25 @@ pub struct Conf {
 
     /// Mapping of IDs to URIs
     pub id_to_uri: HashMap<EntityId, Uri>,
+
+    /// Pinned starting positions of correct users
+    ///
+    /// Users without one start at a random position
+    pub starting_positions: HashMap<EntityId, Position>,
+
+    /// Fault tolerances of servers that don't use the global ones,
+    /// as (neighbourhood faults, server faults)
+    ///
+    /// Meant to observe how servers diverge when misconfigured
+    pub server_faults: HashMap<EntityId, (usize, usize)>,
+
+    /// Last epoch to drive, if any
+    ///
+    /// Once reached, the system halts: see [crate::Driver::tick]
+    pub max_epoch: Option<u64>,
+
+    /// How requests that fail for a transient reason are retried
+    pub retry: RetryPolicy,
 }
 
 impl Conf {
@@ -56,6 +79,14 @@ impl Conf {
         (neigh, type_code)
     }
 
+    /// Fault tolerances to push to a server, as (neighbourhood faults, server faults)
+    pub fn faults_of_server(&self, id: EntityId) -> (usize, usize) {
+        self.server_faults
+            .get(&id)
+            .copied()
+            .unwrap_or((self.max_neighbourhood_faults, self.max_server_faults))
+    }
+
     pub fn id_to_uri(&self, id: EntityId) -> &Uri {
         &self.id_to_uri[&id]
     }
@@ -92,10 +123,10 @@ impl TryFrom<&JsonValue> for Conf {
         if json["height"].as_usize().is_none() {
             return Err(eyre!("height needs to be an unsigned integer"));
         }
-        let dims = (
+        let bounds = WorldBounds::from_dims(
             json["width"].as_usize().unwrap(),
             json["height"].as_usize().unwrap(),
-        );
+        )?;
 
         if json["max_neighbourhood_faults"].as_usize().is_none() {
             return Err(eyre!(
@@ -109,6 +140,28 @@ impl TryFrom<&JsonValue> for Conf {
         }
         let max_server_faults = json["max_server_faults"].as_usize().unwrap();
 
+        let max_epoch = if json.has_key("max_epoch") {
+            match json["max_epoch"].as_u64() {
+                Some(max_epoch) => Some(max_epoch),
+                None => return Err(eyre!("max_epoch needs to be an unsigned integer")),
+            }
+        } else {
+            None
+        };
+
+        let mut retry = RetryPolicy::default();
+        if json.has_key("retries") {
+            retry.retries = json["retries"]
+                .as_usize()
+                .ok_or_else(|| eyre!("retries needs to be an unsigned integer"))?;
+        }
+        if json.has_key("retry_backoff_ms") {
+            retry.backoff = json["retry_backoff_ms"]
+                .as_u64()
+                .map(Duration::from_millis)
+                .ok_or_else(|| eyre!("retry_backoff_ms needs to be an unsigned integer"))?;
+        }
+
         if !json["users"].is_array() {
             return Err(eyre!("users needs to be an array"));
         }
@@ -116,6 +169,7 @@ impl TryFrom<&JsonValue> for Conf {
         let mut correct_users = Vec::with_capacity(json["users"].len());
         let mut malicious_users = Vec::with_capacity(json["users"].len());
         let mut id_to_uri = HashMap::new();
+        let mut starting_positions = HashMap::new();
         for c in json["users"].members() {
             if !c.has_key("entity_id") {
                 return Err(eyre!("user requires an entity_id"));
@@ -144,10 +198,29 @@ impl TryFrom<&JsonValue> for Conf {
 
                 };
                 malicious_users.push((entity_id, type_code));
+                if c.has_key("position") {
+                    return Err(eyre!("only correct users can have a starting position"));
+                }
             } else {
                 correct_users.push(entity_id);
             }
 
+            if c.has_key("position") {
+                let position = &c["position"];
+                if !position.is_array()
+                    || position.len() != 2
+                    || position.members().any(|coord| coord.as_i64().is_none())
+                {
+                    return Err(eyre!("user position must be an array of two integers"));
+                }
+                let position =
+                    Position(position[0].as_i64().unwrap(), position[1].as_i64().unwrap());
+                if !bounds.contains(&position) {
+                    return Err(eyre!("user position must be within the grid"));
+                }
+                starting_positions.insert(entity_id, position);
+            }
+
             let uri: Uri = c["uri"].as_str().unwrap().parse()?;
             id_to_uri.insert(entity_id, uri);
         }
@@ -155,6 +228,7 @@ impl TryFrom<&JsonValue> for Conf {
         malicious_users.shrink_to_fit();
 
         let mut correct_servers = Vec::with_capacity(json["servers"].len());
+        let mut server_faults = HashMap::new();
         for s in json["servers"].members() {
             if !s.has_key("entity_id") {
                 return Err(eyre!("server requires an entity_id"));
@@ -170,19 +244,206 @@ impl TryFrom<&JsonValue> for Conf {
             }
 
             let entity_id: EntityId = s["entity_id"].as_u32().unwrap();
+
+            if s.has_key("max_neighbourhood_faults") || s.has_key("max_server_faults") {
+                let faults = |key: &str, default: usize| {
+                    if s.has_key(key) {
+                        s[key]
+                            .as_usize()
+                            .ok_or_else(|| eyre!("server {} needs to be an unsigned integer", key))
+                    } else {
+                        Ok(default)
+                    }
+                };
+                server_faults.insert(
+                    entity_id,
+                    (
+                        faults("max_neighbourhood_faults", max_neighbourhood_faults)?,
+                        faults("max_server_faults", max_server_faults)?,
+                    ),
+                );
+            }
+
             let uri: Uri = s["uri"].as_str().unwrap().parse()?;
             correct_servers.push(entity_id);
             id_to_uri.insert(entity_id, uri);
         }
 
         Ok(Conf {
-            dims,
+            bounds,
             max_neighbourhood_faults,
             max_server_faults,
             correct_servers,
             correct_users,
             malicious_users,
             id_to_uri,
+            starting_positions,
+            server_faults,
+            max_epoch,
+            retry,
         })
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse(users: &str) -> eyre::Result<Conf> {
+        let json = json::parse(&format!(
+            r#"{{
+                "width": 10,
+                "height": 20,
+                "max_neighbourhood_faults": 1,
+                "max_server_faults": 0,
+                "servers": [],
+                "users": {}
+            }}"#,
+            users
+        ))
+        .unwrap();
+
+        Conf::try_from(&json)
+    }
+
+    #[test]
+    fn max_epoch() {
+        assert_eq!(parse("[]").unwrap().max_epoch, None);
+
+        let json = json::parse(
+            r#"{
+                "width": 10,
+                "height": 20,
+                "max_neighbourhood_faults": 1,
+                "max_server_faults": 0,
+                "max_epoch": 5,
+                "servers": [],
+                "users": []
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(Conf::try_from(&json).unwrap().max_epoch, Some(5));
+
+        let mut json = json;
+        json["max_epoch"] = (-1).into();
+        assert!(Conf::try_from(&json).is_err());
+    }
+
+    #[test]
+    fn retry() {
+        assert_eq!(parse("[]").unwrap().retry, RetryPolicy::default());
+
+        let json = json::parse(
+            r#"{
+                "width": 10,
+                "height": 20,
+                "max_neighbourhood_faults": 1,
+                "max_server_faults": 0,
+                "retries": 5,
+                "retry_backoff_ms": 20,
+                "servers": [],
+                "users": []
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            Conf::try_from(&json).unwrap().retry,
+            RetryPolicy {
+                retries: 5,
+                backoff: Duration::from_millis(20)
+            }
+        );
+
+        let mut bad = json.clone();
+        bad["retries"] = (-1).into();
+        assert!(Conf::try_from(&bad).is_err());
+        let mut bad = json;
+        bad["retry_backoff_ms"] = "20".into();
+        assert!(Conf::try_from(&bad).is_err());
+    }
+
+    #[test]
+    fn starting_positions() {
+        let conf = parse(
+            r#"[
+                { "entity_id": 1, "uri": "http://localhost:5001", "position": [3, 19] },
+                { "entity_id": 2, "uri": "http://localhost:5002" }
+            ]"#,
+        )
+        .unwrap();
+
+        assert_eq!(conf.correct_users, vec![1, 2]);
+        assert_eq!(conf.starting_positions.len(), 1);
+        assert_eq!(conf.starting_positions[&1], Position(3, 19));
+    }
+
+    #[test]
+    fn server_faults() {
+        let json = json::parse(
+            r#"{
+                "width": 10,
+                "height": 20,
+                "max_neighbourhood_faults": 1,
+                "max_server_faults": 0,
+                "servers": [
+                    { "entity_id": 1, "uri": "http://localhost:4001", "max_neighbourhood_faults": 3 },
+                    { "entity_id": 2, "uri": "http://localhost:4002", "max_server_faults": 2 },
+                    { "entity_id": 3, "uri": "http://localhost:4003" }
+                ],
+                "users": []
+            }"#,
+        )
+        .unwrap();
+        let conf = Conf::try_from(&json).unwrap();
+        assert_eq!(conf.server_faults.len(), 2);
+        assert_eq!(conf.faults_of_server(1), (3, 0));
+        assert_eq!(conf.faults_of_server(2), (1, 2));
+        assert_eq!(conf.faults_of_server(3), (1, 0));
+
+        let mut json = json;
+        json["servers"][0]["max_neighbourhood_faults"] = "3".into();
+        assert!(Conf::try_from(&json).is_err());
+    }
+
+    #[test]
+    fn bad_starting_positions() {
+        for position in &[
+            "[3]",
+            "[3, 4, 5]",
+            "[3, \"a\"]",
+            "\"3, 4\"",
+            "[10, 0]",
+            "[0, -1]",
+        ] {
+            assert!(parse(&format!(
+                r#"[{{ "entity_id": 1, "uri": "http://localhost:5001", "position": {} }}]"#,
+                position
+            ))
+            .is_err());
+        }
+
+        assert!(parse(
+            r#"[{ "entity_id": 1, "uri": "http://localhost:5001", "position": [0, 0], "malicious": "T" }]"#
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn empty_world() {
+        let json = json::parse(
+            r#"{
+                "width": 0,
+                "height": 20,
+                "max_neighbourhood_faults": 1,
+                "max_server_faults": 0,
+                "servers": [],
+                "users": []
+            }"#,
+        )
+        .unwrap();
+        assert!(Conf::try_from(&json).is_err());
+
+        let conf = parse("[]").unwrap();
+        assert_eq!(conf.bounds.max(), Position(9, 19));
+    }
+}
@@ -1,34 +1,26 @@
 use model::keys::EntityId;
+use model::WorldBounds;
 use protos::driver::correct_server_driver_client::CorrectServerDriverClient;
 use protos::driver::InitialConfigRequest;
 use protos::driver::ServerConfigUpdate;
 use std::collections::HashMap;
 use tonic::transport::{Channel, Uri};
-use tonic::{Response, Status};
+use tonic::Response;
 use tracing_utils::Request;
 
-use thiserror::Error;
+use super::error::{DriverClientError, Result};
 use tracing::instrument;
 
 #[derive(Debug)]
 pub struct CorrectServerDriver(Channel);
 
-#[derive(Debug, Error)]
-pub enum ServerDriverError {
-    #[error("Server sent unexpected status")]
-    UnexpectedStatus(#[from] Status),
-
-    #[error("Error creating remote")]
-    InitializationError(#[source] tonic::transport::Error),
-}
-
-type Result<T> = std::result::Result<T, ServerDriverError>;
-
 impl CorrectServerDriver {
-    pub fn new(uri: Uri) -> Result<Self> {
+    /// Connect to the entity at `uri`
+    pub async fn connect(uri: Uri) -> Result<Self> {
         let channel = Channel::builder(uri)
-            .connect_lazy()
-            .map_err(ServerDriverError::InitializationError)?;
+            .connect()
+            .await
+            .map_err(DriverClientError::Connect)?;
 
         Ok(CorrectServerDriver(channel))
     }
@@ -40,12 +32,14 @@ impl CorrectServerDriver {
         neighbour_faults: u64,
         server_faults: u64,
         n_servers: u64,
+        max_epoch: Option<u64>,
     ) -> Result<Response<protos::util::Empty>> {
         let mut server = CorrectServerDriverClient::new(self.0.clone());
         let request = Request!(ServerConfigUpdate {
             new_epoch,
             neighbour_faults,
             server_faults,
+            max_epoch: max_epoch.unwrap_or(u64::MAX),
         });
 
         server.update_config(request).await.map_err(|e| e.into())
@@ -56,11 +50,14 @@ impl CorrectServerDriver {
         &self,
         id_to_uri: &HashMap<EntityId, Uri>,
         servers: Vec<EntityId>,
+        bounds: &WorldBounds,
     ) -> Result<Response<protos::util::Empty>> {
         let mut client = CorrectServerDriverClient::new(self.0.clone());
         let request = Request!(InitialConfigRequest {
             id_uri_map: id_to_uri.iter().map(|(&k, v)| (k, v.to_string())).collect(),
-            servers
+            servers,
+            world_min: Some(bounds.min().into()),
+            world_max: Some(bounds.max().into()),
         });
 
         client.initial_config(request).await.map_err(|e| e.into())
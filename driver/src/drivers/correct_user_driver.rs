@@ -1,37 +1,28 @@
 use std::collections::HashMap;
 
 use model::keys::EntityId;
-use model::Position;
+use model::{Position, WorldBounds};
 use protos::driver::correct_user_driver_client::CorrectUserDriverClient as GrpcCorrectUserDriverClient;
 use protos::driver::EpochUpdateRequest;
 use protos::driver::InitialConfigRequest;
-use protos::util::Position as GrpcPosition;
+use protos::driver::ObtainPositionRequest;
+use protos::util::{Neighbour, Position as GrpcPosition};
 use tonic::transport::{Channel, Uri};
-use tonic::Status;
 use tracing_utils::Request;
 
-use thiserror::Error;
+use super::error::{DriverClientError, Result};
 use tracing::instrument;
 
 #[derive(Debug)]
 pub struct CorrectUserDriver(Channel);
 
-#[derive(Debug, Error)]
-pub enum CorrectClientDriverError {
-    #[error("Server sent unexpected status")]
-    UnexpectedStatus(#[from] Status),
-
-    #[error("Error creating remote")]
-    InitializationError(#[source] tonic::transport::Error),
-}
-
-type Result<T> = std::result::Result<T, CorrectClientDriverError>;
-
 impl CorrectUserDriver {
-    pub fn new(uri: Uri) -> Result<Self> {
+    /// Connect to the entity at `uri`
+    pub async fn connect(uri: Uri) -> Result<Self> {
         let channel = Channel::builder(uri)
-            .connect_lazy()
-            .map_err(CorrectClientDriverError::InitializationError)?;
+            .connect()
+            .await
+            .map_err(DriverClientError::Connect)?;
 
         Ok(CorrectUserDriver(channel))
     }
@@ -41,7 +32,8 @@ impl CorrectUserDriver {
         &self,
         epoch: u64,
         pos: Position,
-        neighbours: Vec<EntityId>,
+        correct_neighbours: Vec<(EntityId, Position)>,
+        mal_neighbours: Vec<EntityId>,
         neighbour_faults: usize,
         server_faults: usize,
     ) -> Result<()> {
@@ -49,7 +41,14 @@ impl CorrectUserDriver {
         let request = Request!(EpochUpdateRequest {
             new_epoch: epoch,
             new_position: Some(GrpcPosition { x: pos.0, y: pos.1 }),
-            visible_neighbour_ids: neighbours,
+            correct_neighbours: correct_neighbours
+                .into_iter()
+                .map(|(id, pos)| Neighbour {
+                    id,
+                    pos: Some(GrpcPosition { x: pos.0, y: pos.1 })
+                })
+                .collect(),
+            malicious_neighbour_ids: mal_neighbours,
             neighbour_faults: neighbour_faults as u64,
             server_faults: server_faults as u64
         });
@@ -63,11 +62,14 @@ impl CorrectUserDriver {
         &self,
         id_to_uri: &HashMap<EntityId, Uri>,
         servers: Vec<EntityId>,
+        bounds: &WorldBounds,
     ) -> Result<()> {
         let mut client = GrpcCorrectUserDriverClient::new(self.0.clone());
         let request = Request!(InitialConfigRequest {
             id_uri_map: id_to_uri.iter().map(|(&k, v)| (k, v.to_string())).collect(),
-            servers
+            servers,
+            world_min: Some(bounds.min().into()),
+            world_max: Some(bounds.max().into()),
         });
 
         client.initial_config(request).await?;
@@ -82,4 +84,23 @@ impl CorrectUserDriver {
         client.prove_position(request).await?;
         Ok(())
     }
+
+    /// Ask the user to obtain its own position at an epoch from the servers
+    ///
+    /// Positions outside the world's `bounds` are bad responses.
+    #[instrument]
+    pub async fn obtain_position(&self, epoch: u64, bounds: &WorldBounds) -> Result<Position> {
+        let mut client = GrpcCorrectUserDriverClient::new(self.0.clone());
+        let request = Request!(ObtainPositionRequest { epoch });
+
+        let position: Position = client.obtain_position(request).await?.into_inner().into();
+        if !bounds.contains(&position) {
+            return Err(DriverClientError::BadResponse(format!(
+                "position {:?} is outside the grid",
+                position
+            )));
+        }
+
+        Ok(position)
+    }
 }
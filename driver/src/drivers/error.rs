@@ -0,0 +1,120 @@
+use thiserror::Error;
+use tonic::{Code, Status};
+
+/// Why a request to a driven entity failed
+#[derive(Debug, Error)]
+pub enum DriverClientError {
+    #[error("Could not connect to remote")]
+    Connect(#[source] tonic::transport::Error),
+
+    #[error("Remote did not reply in time")]
+    Timeout(#[source] Status),
+
+    #[error("Remote sent unexpected status")]
+    Rpc(#[source] Status),
+
+    #[error("Remote sent a malformed reply: {}", .0)]
+    BadResponse(String),
+}
+
+pub(crate) type Result<T> = std::result::Result<T, DriverClientError>;
+
+impl DriverClientError {
+    /// Whether the same request may succeed if made again
+    ///
+    /// True for connection failures, timeouts and remotes that are momentarily unable to reply
+    pub fn is_retriable(&self) -> bool {
+        match self {
+            DriverClientError::Connect(_) | DriverClientError::Timeout(_) => true,
+            DriverClientError::Rpc(status) => matches!(
+                status.code(),
+                Code::Unavailable | Code::ResourceExhausted | Code::Aborted
+            ),
+            DriverClientError::BadResponse(_) => false,
+        }
+    }
+
+    /// Whether the request surely wasn't acted on, so it may be made again even if doing it
+    /// twice isn't the same as doing it once
+    ///
+    /// Like [is_retriable](Self::is_retriable), but false for timeouts and aborted requests
+    pub fn is_retriable_unhandled(&self) -> bool {
+        match self {
+            DriverClientError::Connect(_) => true,
+            DriverClientError::Rpc(status) => {
+                matches!(status.code(), Code::Unavailable | Code::ResourceExhausted)
+            }
+            DriverClientError::Timeout(_) | DriverClientError::BadResponse(_) => false,
+        }
+    }
+}
+
+impl From<Status> for DriverClientError {
+    fn from(status: Status) -> Self {
+        match status.code() {
+            Code::DeadlineExceeded => DriverClientError::Timeout(status),
+            _ => DriverClientError::Rpc(status),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::drivers::{CorrectServerDriver, CorrectUserDriver, MaliciousUserDriver};
+    use tonic::transport::Uri;
+
+    /// Address nothing listens on
+    async fn refusing_uri() -> Uri {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+
+        format!("http://{}", addr).parse().unwrap()
+    }
+
+    #[tokio::test]
+    async fn connection_refused() {
+        let uri = refusing_uri().await;
+
+        assert!(matches!(
+            CorrectServerDriver::connect(uri.clone()).await,
+            Err(DriverClientError::Connect(_))
+        ));
+        assert!(matches!(
+            CorrectUserDriver::connect(uri.clone()).await,
+            Err(DriverClientError::Connect(_))
+        ));
+        assert!(matches!(
+            MaliciousUserDriver::connect(uri).await,
+            Err(DriverClientError::Connect(_))
+        ));
+    }
+
+    #[test]
+    fn status_kinds() {
+        assert!(matches!(
+            DriverClientError::from(Status::deadline_exceeded("too slow")),
+            DriverClientError::Timeout(_)
+        ));
+        assert!(matches!(
+            DriverClientError::from(Status::unavailable("no witnesses")),
+            DriverClientError::Rpc(status) if status.code() == Code::Unavailable
+        ));
+
+        assert!(DriverClientError::from(Status::deadline_exceeded("")).is_retriable());
+        assert!(DriverClientError::from(Status::unavailable("")).is_retriable());
+        assert!(!DriverClientError::from(Status::invalid_argument("")).is_retriable());
+        assert!(!DriverClientError::BadResponse("".to_owned()).is_retriable());
+
+        assert!(!DriverClientError::from(Status::deadline_exceeded("")).is_retriable_unhandled());
+        assert!(!DriverClientError::from(Status::aborted("")).is_retriable_unhandled());
+        assert!(DriverClientError::from(Status::unavailable("")).is_retriable_unhandled());
+    }
+
+    #[test]
+    fn into_eyre() {
+        let report: eyre::Report = DriverClientError::from(Status::internal("oops")).into();
+        assert!(report.downcast_ref::<DriverClientError>().is_some());
+    }
+}
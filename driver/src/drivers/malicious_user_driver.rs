@@ -1,4 +1,4 @@
-use model::Position;
+use model::{Position, WorldBounds};
 use std::collections::HashMap;
 
 use model::keys::EntityId;
@@ -8,31 +8,22 @@ use protos::driver::MaliciousEpochUpdateRequest;
 use protos::util::Neighbour;
 use protos::util::Position as GrpcPosition;
 use tonic::transport::{Channel, Uri};
-use tonic::{Response, Status};
+use tonic::Response;
 use tracing_utils::Request;
 
-use thiserror::Error;
+use super::error::{DriverClientError, Result};
 use tracing::instrument;
 
 #[derive(Debug)]
 pub struct MaliciousUserDriver(Channel);
 
-#[derive(Debug, Error)]
-pub enum MaliciousUserDriverError {
-    #[error("Server sent unexpected status")]
-    UnexpectedStatus(#[from] Status),
-
-    #[error("Error creating remote")]
-    InitializationError(#[source] tonic::transport::Error),
-}
-
-type Result<T> = std::result::Result<T, MaliciousUserDriverError>;
-
 impl MaliciousUserDriver {
-    pub fn new(uri: Uri) -> Result<Self> {
+    /// Connect to the entity at `uri`
+    pub async fn connect(uri: Uri) -> Result<Self> {
         let channel = Channel::builder(uri)
-            .connect_lazy()
-            .map_err(MaliciousUserDriverError::InitializationError)?;
+            .connect()
+            .await
+            .map_err(DriverClientError::Connect)?;
 
         Ok(MaliciousUserDriver(channel))
     }
@@ -71,11 +62,14 @@ impl MaliciousUserDriver {
         &self,
         id_to_uri: &HashMap<EntityId, Uri>,
         servers: Vec<EntityId>,
+        bounds: &WorldBounds,
     ) -> Result<Response<protos::util::Empty>> {
         let mut client = GrpcMaliciousUserDriverClient::new(self.0.clone());
         let request = Request!(InitialConfigRequest {
             id_uri_map: id_to_uri.iter().map(|(&k, v)| (k, v.to_string())).collect(),
-            servers
+            servers,
+            world_min: Some(bounds.min().into()),
+            world_max: Some(bounds.max().into()),
         });
 
         client.initial_config(request).await.map_err(|e| e.into())
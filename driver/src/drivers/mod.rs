@@ -1,7 +1,12 @@
 pub(crate) mod correct_server_driver;
 pub(crate) mod correct_user_driver;
+pub(crate) mod error;
 pub(crate) mod malicious_user_driver;
+pub(crate) mod retry;
 
 pub use correct_server_driver::CorrectServerDriver;
 pub use correct_user_driver::CorrectUserDriver;
+pub use error::DriverClientError;
 pub use malicious_user_driver::MaliciousUserDriver;
+pub use retry::RetryPolicy;
+pub(crate) use retry::{with_retry, with_retry_non_idempotent};
@@ -0,0 +1,179 @@
+use std::future::Future;
+use std::time::Duration;
+
+use tracing::warn;
+
+use super::error::{DriverClientError, Result};
+
+/// How requests to entities are retried when they fail for a reason that may go away,
+/// see [DriverClientError::is_retriable](super::DriverClientError::is_retriable)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Attempts after the first one
+    pub retries: usize,
+
+    /// Wait before the first retry, doubled before every following one
+    pub backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            retries: 2,
+            backoff: Duration::from_millis(100),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Never retry
+    pub fn none() -> Self {
+        RetryPolicy {
+            retries: 0,
+            backoff: Duration::from_millis(0),
+        }
+    }
+}
+
+/// Make a request until it succeeds, fails for good or runs out of retries
+pub(crate) async fn with_retry<T, F, Fut>(policy: &RetryPolicy, request: F) -> Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    retry_while(policy, request, DriverClientError::is_retriable).await
+}
+
+/// Like [with_retry], for requests that must not be acted on twice: only retried when they
+/// surely weren't handled, see [DriverClientError::is_retriable_unhandled]
+pub(crate) async fn with_retry_non_idempotent<T, F, Fut>(
+    policy: &RetryPolicy,
+    request: F,
+) -> Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    retry_while(policy, request, DriverClientError::is_retriable_unhandled).await
+}
+
+async fn retry_while<T, F, Fut>(
+    policy: &RetryPolicy,
+    mut request: F,
+    retriable: fn(&DriverClientError) -> bool,
+) -> Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    let mut backoff = policy.backoff;
+    let mut retries = 0;
+
+    loop {
+        match request().await {
+            Err(e) if retriable(&e) && retries < policy.retries => {
+                retries += 1;
+                warn!(error = ?e, retries, "Request failed, retrying in {:?}", backoff);
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+            }
+            res => return res,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tonic::Status;
+
+    const FAST: RetryPolicy = RetryPolicy {
+        retries: 2,
+        backoff: Duration::from_millis(1),
+    };
+
+    /// Count attempts of a request that fails with `status` the first `failures` times
+    async fn attempts(policy: &RetryPolicy, failures: usize, status: Status) -> (usize, bool) {
+        attempts_while(policy, failures, status, DriverClientError::is_retriable).await
+    }
+
+    /// Like [attempts], retrying the errors `retriable` allows
+    async fn attempts_while(
+        policy: &RetryPolicy,
+        failures: usize,
+        status: Status,
+        retriable: fn(&DriverClientError) -> bool,
+    ) -> (usize, bool) {
+        let attempts = AtomicUsize::new(0);
+        let res = retry_while(
+            policy,
+            || {
+                let attempt = attempts.fetch_add(1, Ordering::SeqCst);
+                let status = status.clone();
+                async move {
+                    if attempt < failures {
+                        Err(DriverClientError::from(status))
+                    } else {
+                        Ok(())
+                    }
+                }
+            },
+            retriable,
+        )
+        .await;
+
+        (attempts.into_inner(), res.is_ok())
+    }
+
+    #[tokio::test]
+    async fn retries_transient_errors() {
+        assert_eq!(
+            attempts(&FAST, 0, Status::unavailable("busy")).await,
+            (1, true)
+        );
+        assert_eq!(
+            attempts(&FAST, 2, Status::unavailable("busy")).await,
+            (3, true)
+        );
+        assert_eq!(
+            attempts(&FAST, 2, Status::deadline_exceeded("slow")).await,
+            (3, true)
+        );
+
+        // up to a point
+        assert_eq!(
+            attempts(&FAST, 3, Status::unavailable("busy")).await,
+            (3, false)
+        );
+        assert_eq!(
+            attempts(&RetryPolicy::none(), 1, Status::unavailable("busy")).await,
+            (1, false)
+        );
+    }
+
+    #[tokio::test]
+    async fn gives_up_on_permanent_errors() {
+        assert_eq!(
+            attempts(&FAST, 1, Status::invalid_argument("bad")).await,
+            (1, false)
+        );
+        assert_eq!(
+            attempts(&FAST, 1, Status::permission_denied("nope")).await,
+            (1, false)
+        );
+    }
+
+    #[tokio::test]
+    async fn non_idempotent_not_retried_after_timeout() {
+        let unhandled = DriverClientError::is_retriable_unhandled;
+        assert_eq!(
+            attempts_while(&FAST, 1, Status::deadline_exceeded("slow"), unhandled).await,
+            (1, false)
+        );
+        assert_eq!(
+            attempts_while(&FAST, 2, Status::unavailable("busy"), unhandled).await,
+            (3, true)
+        );
+    }
+}
@@ -1,5 +1,8 @@
 #![deny(unsafe_op_in_unsafe_fn)]
 
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
 use tokio::sync::RwLock;
 use tracing::*;
 
@@ -10,9 +13,11 @@ use futures::{
 };
 
 use model::keys::EntityId;
+use model::Position;
 
 mod drivers;
 use drivers::*;
+pub use drivers::{DriverClientError, RetryPolicy};
 
 mod conf;
 pub use conf::Conf;
@@ -20,16 +25,67 @@ pub use conf::Conf;
 mod state;
 pub use state::State;
 
+mod replay;
+pub use replay::{Recorder, Recording};
+
 pub struct Driver {
     state: RwLock<State>,
     config: Conf,
+    summary: RwLock<Summary>,
+}
+
+/// What the driver did so far, see [Driver::summary]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Summary {
+    /// Epochs pushed to every entity
+    pub epochs: u64,
+
+    /// Outcome of the requests made to each entity (epoch updates and position proofs)
+    pub nodes: BTreeMap<EntityId, NodeSummary>,
+
+    /// Position proofs users reported as created
+    pub proofs: u64,
+}
+
+/// Outcome of the requests made to an entity, see [Summary]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct NodeSummary {
+    pub successes: u64,
+    pub failures: u64,
+}
+
+/// How the position of a correct user, as obtained from the servers, differs from the truth
+#[derive(Debug)]
+pub enum Mismatch {
+    /// The servers have the user somewhere else
+    WrongPosition {
+        expected: Position,
+        reported: Position,
+    },
+
+    /// The user could not obtain its position from the servers
+    Unavailable(eyre::Report),
 }
 
 impl Driver {
     pub async fn new(config: Conf) -> eyre::Result<Driver> {
+        let state = State::new(&config);
+        Driver::with_state(config, state).await
+    }
+
+    /// Drive users through the positions of a previous run instead of random ones
+    ///
+    /// Halts after the last recorded epoch, see [State::replaying]
+    pub async fn replaying(config: Conf, recording: Recording) -> eyre::Result<Driver> {
+        let state = State::replaying(&config, recording)?;
+        Driver::with_state(config, state).await
+    }
+
+    async fn with_state(config: Conf, state: State) -> eyre::Result<Driver> {
         let driver = Driver {
-            state: RwLock::new(State::new(&config)),
+            state: RwLock::new(state),
             config,
+            summary: RwLock::new(Summary::default()),
         };
 
         driver.initial_setup().await?;
@@ -37,55 +93,97 @@ impl Driver {
         Ok(driver)
     }
 
+    /// Push the current epoch to every entity, then advance to the next one
+    ///
+    /// A no-op once the configured max epoch was driven (see [Driver::halted])
     #[instrument(skip(self))]
     pub async fn tick(&self) -> eyre::Result<()> {
-        let cs_futs = self
-            .config
-            .correct_servers
-            .iter()
-            .map(|id| self.update_correct_server(*id).boxed());
+        if self.halted().await {
+            debug!("Max epoch reached, not ticking");
+            return Ok(());
+        }
 
-        let cu_futs = self
-            .config
-            .correct_users
-            .iter()
-            .map(|id| self.update_correct_user(*id).boxed());
+        let cs_futs = self.config.correct_servers.iter().map(|&id| {
+            self.update_correct_server(id)
+                .map(move |res| (id, res))
+                .boxed()
+        });
 
-        let mu_futs = self
-            .config
-            .malicious_users
-            .iter()
-            .map(|(id, _)| self.update_malicious_user(*id).boxed());
+        let cu_futs = self.config.correct_users.iter().map(|&id| {
+            self.update_correct_user(id)
+                .map(move |res| (id, res))
+                .boxed()
+        });
+
+        let mu_futs = self.config.malicious_users.iter().map(|&(id, _)| {
+            self.update_malicious_user(id)
+                .map(move |res| (id, res))
+                .boxed()
+        });
 
         let mut futs: FuturesUnordered<_> = cs_futs.chain(cu_futs).chain(mu_futs).collect();
 
-        while let Some(res) = futs.next().await {
-            if res.is_err() {
-                res?;
-            }
+        while let Some((id, res)) = futs.next().await {
+            self.record(id, res.is_ok()).await;
+            res?;
         }
 
         self.state.write().await.advance(&self.config);
+        self.summary.write().await.epochs += 1;
         Ok(())
     }
 
+    /// What the driver did so far: epochs driven, and how requests to each entity went
+    pub async fn summary(&self) -> Summary {
+        self.summary.read().await.clone()
+    }
+
+    /// True position of every correct user at an epoch, ordered by user
+    ///
+    /// Only the current and the previous epoch are known: any other one has no positions.
+    pub async fn ground_truth(&self, epoch: u64) -> Vec<(EntityId, Position)> {
+        let state = self.state.read().await;
+        let mut positions: Vec<_> = state
+            .positions_at(epoch)
+            .map(|grid| grid.iter().map(|(id, pos)| (*id, *pos)).collect())
+            .unwrap_or_default();
+        positions.sort_unstable_by_key(|(id, _)| *id);
+
+        positions
+    }
+
     pub async fn current_epoch(&self) -> u64 {
         self.state.read().await.epoch()
     }
 
+    /// Whether the configured max epoch was driven, so ticking any further does nothing
+    pub async fn halted(&self) -> bool {
+        self.state.read().await.halted()
+    }
+
     #[instrument(skip(self))]
     pub async fn prove_position(&self, uid: EntityId) -> eyre::Result<()> {
         let uri = self.config.id_to_uri(uid).clone();
 
-        if self.config.correct_users.contains(&uid) {
-            let client = CorrectUserDriver::new(uri)?;
-            client.prove_position().await?;
-        } else {
-            let client = MaliciousUserDriver::new(uri)?;
-            client.prove_position().await?;
+        let res: eyre::Result<()> = async {
+            if self.config.correct_users.contains(&uid) {
+                let client = CorrectUserDriver::connect(uri).await?;
+                client.prove_position().await?;
+            } else {
+                let client = MaliciousUserDriver::connect(uri).await?;
+                client.prove_position().await?;
+            }
+
+            Ok(())
         }
+        .await;
 
-        Ok(())
+        self.record(uid, res.is_ok()).await;
+        if res.is_ok() {
+            self.summary.write().await.proofs += 1;
+        }
+
+        res
     }
 
     #[instrument(skip(self))]
@@ -113,14 +211,67 @@ impl Driver {
         }
     }
 
+    /// Check that the servers know where every correct user was in the last ticked epoch
+    ///
+    /// Each correct user obtains its own position from the servers, which is compared to the
+    /// position the driver gave them. Meant to be called after [Driver::prove_position_all].
+    /// Returns the users whose positions don't match (none before the first tick).
+    #[instrument(skip(self))]
+    pub async fn verify_positions(&self) -> Vec<(EntityId, Mismatch)> {
+        let epoch = match self.state.read().await.previous_positions() {
+            Some((epoch, _)) => epoch,
+            None => return vec![],
+        };
+        let expected: HashMap<_, _> = self.ground_truth(epoch).await.into_iter().collect();
+
+        let futs = self.config.correct_users.iter().map(|&uid| {
+            let expected = expected[&uid];
+            async move {
+                let reported =
+                    match CorrectUserDriver::connect(self.config.id_to_uri(uid).clone()).await {
+                        Ok(client) => client.obtain_position(epoch, &self.config.bounds).await,
+                        Err(e) => Err(e),
+                    };
+
+                match reported {
+                    Ok(reported) if reported == expected => None,
+                    Ok(reported) => Some((uid, Mismatch::WrongPosition { expected, reported })),
+                    Err(e) => Some((uid, Mismatch::Unavailable(e.into()))),
+                }
+            }
+        });
+
+        let mismatches: Vec<_> = join_all(futs).await.into_iter().flatten().collect();
+        for (uid, mismatch) in &mismatches {
+            warn!(uid, ?mismatch, "Position mismatch");
+        }
+
+        mismatches
+    }
+
+    async fn record(&self, id: EntityId, success: bool) {
+        let mut summary = self.summary.write().await;
+        let node = summary.nodes.entry(id).or_default();
+        if success {
+            node.successes += 1;
+        } else {
+            node.failures += 1;
+        }
+    }
+
     #[instrument(skip(self))]
     async fn initial_setup(&self) -> eyre::Result<()> {
         let cs_futs = self.config.correct_servers.iter().map(|id| {
             async move {
                 debug!("Sending initial config to correct server {}", id);
-                let client = CorrectServerDriver::new(self.config.id_to_uri[&id].clone())?;
+                let client =
+                    CorrectServerDriver::connect(self.config.id_to_uri[&id].clone()).await?;
                 client
-                    .initial_config(&self.config.id_to_uri, self.config.correct_servers.clone())
+                    .initial_config(
+                        &self.config.id_to_uri,
+                        self.config.correct_servers.clone(),
+                        &self.config.bounds,
+                    )
                     .await
                     .map(|_| ())
                     .map_err(eyre::Report::from)?;
@@ -137,9 +288,13 @@ impl Driver {
             .map(|uri| {
                 async move {
                     debug!("Sending initial config to correct user at {}", &uri);
-                    let client = CorrectUserDriver::new(uri.clone())?;
+                    let client = CorrectUserDriver::connect(uri.clone()).await?;
                     client
-                        .initial_config(&self.config.id_to_uri, self.config.correct_servers.clone())
+                        .initial_config(
+                            &self.config.id_to_uri,
+                            self.config.correct_servers.clone(),
+                            &self.config.bounds,
+                        )
                         .await
                         .map(|_| ())
                         .map_err(eyre::Report::from)
@@ -155,9 +310,13 @@ impl Driver {
             .map(|uri| {
                 async move {
                     debug!("Sending initial config to malicious user at {}", &uri);
-                    let client = MaliciousUserDriver::new(uri.clone())?;
+                    let client = MaliciousUserDriver::connect(uri.clone()).await?;
                     client
-                        .initial_config(&self.config.id_to_uri, self.config.correct_servers.clone())
+                        .initial_config(
+                            &self.config.id_to_uri,
+                            self.config.correct_servers.clone(),
+                            &self.config.bounds,
+                        )
                         .await
                         .map(|_| ())
                         .map_err(eyre::Report::from)
@@ -179,18 +338,23 @@ impl Driver {
 
     #[instrument(skip(self))]
     async fn update_correct_server(&self, id: EntityId) -> eyre::Result<()> {
-        let uri = self.config.id_to_uri(id).clone();
-        let client = CorrectServerDriver::new(uri)?;
-        let state = self.state.read().await;
-
-        client
-            .update_config(
-                state.epoch(),
-                self.config.max_neighbourhood_faults as u64,
-                self.config.max_server_faults as u64,
-                self.config.correct_servers.len() as u64,
-            )
-            .await?;
+        let uri = self.config.id_to_uri(id);
+        let epoch = self.state.read().await.epoch();
+
+        let (neighbourhood_faults, server_faults) = self.config.faults_of_server(id);
+        with_retry(&self.config.retry, move || async move {
+            let client = CorrectServerDriver::connect(uri.clone()).await?;
+            client
+                .update_config(
+                    epoch,
+                    neighbourhood_faults as u64,
+                    server_faults as u64,
+                    self.config.correct_servers.len() as u64,
+                    self.config.max_epoch,
+                )
+                .await
+        })
+        .await?;
         info!("Correct server updated");
 
         Ok(())
@@ -198,20 +362,32 @@ impl Driver {
 
     #[instrument(skip(self))]
     async fn update_correct_user(&self, id: EntityId) -> eyre::Result<()> {
-        let uri = self.config.id_to_uri(id).clone();
-        let client = CorrectUserDriver::new(uri)?;
-        let state = self.state.read().await;
-
-        let visible = state.get_visible_neighbourhood(&self.config, id);
-        client
-            .update_epoch(
+        let uri = self.config.id_to_uri(id);
+        let (epoch, position, corrects, malicious) = {
+            let state = self.state.read().await;
+            (
                 state.epoch(),
                 state.position_of(id),
-                visible,
-                self.config.max_neighbourhood_faults,
-                self.config.max_server_faults,
+                state.get_correct_users(),
+                state.get_malicious_neighbourhood(&self.config),
             )
-            .await?;
+        };
+
+        let (corrects, malicious) = (&corrects, &malicious);
+        with_retry_non_idempotent(&self.config.retry, move || async move {
+            let client = CorrectUserDriver::connect(uri.clone()).await?;
+            client
+                .update_epoch(
+                    epoch,
+                    position,
+                    corrects.clone(),
+                    malicious.clone(),
+                    self.config.max_neighbourhood_faults,
+                    self.config.max_server_faults,
+                )
+                .await
+        })
+        .await?;
         info!("Correct user updated");
 
         Ok(())
@@ -219,25 +395,93 @@ impl Driver {
 
     #[instrument(skip(self))]
     async fn update_malicious_user(&self, id: EntityId) -> eyre::Result<()> {
-        let uri = self.config.id_to_uri(id).clone();
-        let client = MaliciousUserDriver::new(uri)?;
-        let state = self.state.read().await;
-
-        let corrects = state.get_correct_users();
+        let uri = self.config.id_to_uri(id);
+        let (epoch, corrects) = {
+            let state = self.state.read().await;
+            (state.epoch(), state.get_correct_users())
+        };
         let (malicious, type_code) = self.config.get_malicious_neighbours(id);
 
-        client
-            .update_epoch(
-                state.epoch(),
-                corrects,
-                malicious,
-                self.config.max_neighbourhood_faults as u64,
-                self.config.max_server_faults as u64,
-                type_code,
-            )
-            .await?;
+        let (corrects, malicious) = (&corrects, &malicious);
+        with_retry_non_idempotent(&self.config.retry, move || async move {
+            let client = MaliciousUserDriver::connect(uri.clone()).await?;
+            client
+                .update_epoch(
+                    epoch,
+                    corrects.clone(),
+                    malicious.clone(),
+                    self.config.max_neighbourhood_faults as u64,
+                    self.config.max_server_faults as u64,
+                    type_code,
+                )
+                .await
+        })
+        .await?;
         info!("Malicious user updated");
 
         Ok(())
     }
 }
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Epochs driven: {}", self.epochs)?;
+        writeln!(f, "Position proofs created: {}", self.proofs)?;
+        for (id, node) in &self.nodes {
+            writeln!(
+                f,
+                "Entity {}: {} successful requests, {} failed",
+                id, node.successes, node.failures
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use model::WorldBounds;
+
+    #[tokio::test]
+    async fn ground_truth_matches_grid() {
+        let config = Conf {
+            bounds: WorldBounds::from_dims(10, 20).unwrap(),
+            max_neighbourhood_faults: 0,
+            max_server_faults: 0,
+            correct_servers: vec![],
+            correct_users: (1..=5).collect(),
+            malicious_users: vec![],
+            id_to_uri: HashMap::new(),
+            starting_positions: HashMap::new(),
+            server_faults: HashMap::new(),
+            max_epoch: None,
+            retry: RetryPolicy::default(),
+        };
+        let driver = Driver {
+            state: RwLock::new(State::new(&config)),
+            config,
+            summary: RwLock::new(Summary::default()),
+        };
+
+        let grid = |driver: &Driver| {
+            let state = driver.state.try_read().unwrap();
+            (1..=5)
+                .map(|id| (id, state.position_of(id)))
+                .collect::<Vec<_>>()
+        };
+
+        let first = grid(&driver);
+        assert_eq!(driver.ground_truth(0).await, first);
+
+        driver.state.write().await.advance(&driver.config);
+        assert_eq!(driver.ground_truth(1).await, grid(&driver));
+
+        // the previous epoch is still known, older and future ones aren't
+        assert_eq!(driver.ground_truth(0).await, first);
+        driver.state.write().await.advance(&driver.config);
+        assert!(driver.ground_truth(0).await.is_empty());
+        assert!(driver.ground_truth(3).await.is_empty());
+    }
+}
@@ -0,0 +1,163 @@
+use eyre::eyre;
+use model::keys::EntityId;
+use model::Position;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+
+/// Positions of correct users in each epoch of a previous run, to drive them again
+///
+/// Read from what a [Recorder] wrote, see [crate::Driver::replaying]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Recording {
+    epochs: BTreeMap<u64, HashMap<EntityId, Position>>,
+}
+
+/// Appends the positions of correct users in each epoch to a file, so the run can be replayed
+///
+/// One JSON object per line and epoch, like `{"epoch":0,"positions":[{"id":1,"x":2,"y":3}]}`.
+pub struct Recorder {
+    file: File,
+}
+
+impl Recording {
+    /// Parse a recording, as written by a [Recorder]
+    pub fn parse(recording: &str) -> eyre::Result<Recording> {
+        let mut epochs = BTreeMap::new();
+
+        for (idx, line) in recording.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let line_no = idx + 1;
+
+            let entry = json::parse(line).map_err(|e| eyre!("line {}: {}", line_no, e))?;
+            let epoch = entry["epoch"]
+                .as_u64()
+                .ok_or_else(|| eyre!("line {}: epoch needs to be an unsigned integer", line_no))?;
+            if !entry["positions"].is_array() {
+                return Err(eyre!("line {}: positions needs to be an array", line_no));
+            }
+
+            let mut grid = HashMap::new();
+            for position in entry["positions"].members() {
+                let (id, x, y) = match (
+                    position["id"].as_u32(),
+                    position["x"].as_i64(),
+                    position["y"].as_i64(),
+                ) {
+                    (Some(id), Some(x), Some(y)) => (id, x, y),
+                    _ => {
+                        return Err(eyre!(
+                            "line {}: positions need an integer id, x and y",
+                            line_no
+                        ))
+                    }
+                };
+
+                if grid.insert(id, Position(x, y)).is_some() {
+                    return Err(eyre!(
+                        "line {}: user {} has more than one position",
+                        line_no,
+                        id
+                    ));
+                }
+            }
+
+            if epochs.insert(epoch, grid).is_some() {
+                return Err(eyre!(
+                    "line {}: epoch {} was already recorded",
+                    line_no,
+                    epoch
+                ));
+            }
+        }
+
+        Ok(Recording { epochs })
+    }
+
+    /// Read and parse a recording file
+    pub fn load(path: &Path) -> eyre::Result<Recording> {
+        Recording::parse(&std::fs::read_to_string(path)?)
+    }
+
+    /// Positions of correct users at an epoch, if it was recorded
+    pub fn positions_at(&self, epoch: u64) -> Option<&HashMap<EntityId, Position>> {
+        self.epochs.get(&epoch)
+    }
+
+    /// Recorded epochs with their positions, in order
+    pub fn epochs(&self) -> impl Iterator<Item = (u64, &HashMap<EntityId, Position>)> {
+        self.epochs.iter().map(|(epoch, grid)| (*epoch, grid))
+    }
+}
+
+impl Recorder {
+    /// Record to a file, after anything it already has
+    pub fn open(path: &Path) -> io::Result<Recorder> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Recorder { file })
+    }
+
+    /// Append the positions of an epoch, as a line of JSON
+    pub fn record(&mut self, epoch: u64, positions: &[(EntityId, Position)]) -> io::Result<()> {
+        let positions: Vec<_> = positions
+            .iter()
+            .map(|(id, pos)| json::object! { id: *id, x: pos.0, y: pos.1 })
+            .collect();
+        let line = json::object! { epoch: epoch, positions: positions };
+
+        writeln!(self.file, "{}", line.dump())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn record_parse() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let path = tmpdir.path().join("recording");
+
+        let mut recorder = Recorder::open(&path).unwrap();
+        recorder
+            .record(0, &[(1, Position(0, 1)), (2, Position(3, 4))])
+            .unwrap();
+        recorder.record(1, &[(1, Position(5, 6))]).unwrap();
+        drop(recorder);
+
+        // appends to what is there
+        Recorder::open(&path)
+            .unwrap()
+            .record(2, &[(1, Position(-1, 7))])
+            .unwrap();
+
+        let recording = Recording::load(&path).unwrap();
+        assert_eq!(
+            recording.epochs().map(|(e, _)| e).collect::<Vec<_>>(),
+            vec![0, 1, 2]
+        );
+        assert_eq!(recording.positions_at(0).unwrap()[&2], Position(3, 4));
+        assert_eq!(recording.positions_at(1).unwrap().len(), 1);
+        assert_eq!(recording.positions_at(2).unwrap()[&1], Position(-1, 7));
+        assert!(recording.positions_at(3).is_none());
+    }
+
+    #[test]
+    fn bad_recordings() {
+        assert!(Recording::parse("").unwrap().positions_at(0).is_none());
+
+        for bad in &[
+            "not json",
+            r#"{"positions":[]}"#,
+            r#"{"epoch":0,"positions":{}}"#,
+            r#"{"epoch":0,"positions":[{"id":1,"x":2}]}"#,
+            r#"{"epoch":0,"positions":[{"id":1,"x":2,"y":3},{"id":1,"x":4,"y":5}]}"#,
+            "{\"epoch\":0,\"positions\":[]}\n{\"epoch\":0,\"positions\":[]}",
+        ] {
+            assert!(Recording::parse(bad).is_err(), "{} was accepted", bad);
+        }
+    }
+}
@@ -1,15 +1,25 @@
-use crate::Conf;
+use crate::{Conf, Recording};
+use eyre::eyre;
 use model::keys::EntityId;
-use model::neighbourhood::are_neighbours;
-use model::Position;
+use model::{Position, WorldBounds};
 use rand::prelude::*;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 pub struct State {
     epoch: u64,
 
     /// Positions of correct users
     grid: HashMap<EntityId, Position>,
+
+    /// Epoch and positions of correct users before the last advance
+    previous: Option<(u64, HashMap<EntityId, Position>)>,
+
+    /// Whether an advance was refused for being past the configured max epoch
+    /// (or the end of the replayed recording)
+    halted: bool,
+
+    /// Positions to move correct users to instead of random ones, if replaying a previous run
+    replay: Option<Recording>,
 }
 
 impl State {
@@ -21,74 +31,311 @@ impl State {
                 .correct_users
                 .iter()
                 .map(|id| {
-                    let pos = Position(
-                        rng.gen_range(0..conf.dims.0 as i64),
-                        rng.gen_range(0..conf.dims.1 as i64),
-                    );
+                    let pos = conf
+                        .starting_positions
+                        .get(id)
+                        .copied()
+                        .unwrap_or_else(|| random_position(&mut rng, &conf.bounds));
                     (*id, pos)
                 })
                 .collect(),
+            previous: None,
+            halted: false,
+            replay: None,
         }
     }
 
+    /// Start at the first epoch of a recording, and move users as recorded on every advance
+    ///
+    /// Every recorded epoch must have the positions of exactly the configured correct users,
+    /// all within the configured bounds.
+    /// Advancing past the last recorded epoch (or to one that is missing) halts the state.
+    pub fn replaying(conf: &Conf, recording: Recording) -> eyre::Result<Self> {
+        let users: HashSet<_> = conf.correct_users.iter().copied().collect();
+        for (epoch, grid) in recording.epochs() {
+            if grid.keys().copied().collect::<HashSet<_>>() != users {
+                return Err(eyre!(
+                    "recorded epoch {} does not have the positions of exactly the configured correct users",
+                    epoch
+                ));
+            }
+            if let Some((id, pos)) = grid.iter().find(|(_, pos)| !conf.bounds.contains(pos)) {
+                return Err(eyre!(
+                    "recorded position {:?} of user {} at epoch {} is outside the world",
+                    pos,
+                    id,
+                    epoch
+                ));
+            }
+        }
+
+        let grid = recording
+            .positions_at(0)
+            .cloned()
+            .ok_or_else(|| eyre!("recording does not start at epoch 0"))?;
+
+        Ok(State {
+            epoch: 0,
+            grid,
+            previous: None,
+            halted: false,
+            replay: Some(recording),
+        })
+    }
+
     pub fn epoch(&self) -> u64 {
         self.epoch
     }
 
+    /// Whether the configured max epoch was reached and driven, so there's nothing left to do
+    pub fn halted(&self) -> bool {
+        self.halted
+    }
+
     pub fn position_of(&self, id: EntityId) -> Position {
         self.grid.get(&id).copied().unwrap()
     }
 
-    /// Generate neighbourhoods for a correct user.
-    /// A neighbourhood is a vector of (EntityId, x, y) tuples.
-    ///
-    /// Here neighbourhood is definded by the `neighbourhood` function
-    /// (it could be further abstracted, but there is no need)
+    /// Pick the malicious users a correct user can see, besides the correct users
+    /// (which it picks its neighbours from on its own).
     ///
-    pub fn get_visible_neighbourhood(&self, conf: &Conf, id: EntityId) -> Vec<EntityId> {
-        /// Fill a neighbourhood with some malicious users
-        /// (making sure they never exceed the incorrectness limit)
-        ///
-        fn fill_neighbourhood(neighbourhood: &mut Vec<EntityId>, conf: &Conf) {
-            let mut rng = thread_rng();
-            let n_malicious: usize = rng.gen_range(0..=conf.max_neighbourhood_faults);
-
-            neighbourhood.reserve(n_malicious);
-            for (entity_id, _) in conf.malicious_users.choose_multiple(&mut rng, n_malicious) {
-                neighbourhood.push(*entity_id)
-            }
-        }
-
-        let pos = self.position_of(id);
-        let mut neighbourhood = self
-            .grid
-            .iter()
-            .filter(|(nid, _)| **nid != id)
-            .filter(|(_, npos)| are_neighbours(pos, **npos))
-            .map(|(id, _)| *id)
-            .collect();
-
-        fill_neighbourhood(&mut neighbourhood, conf);
+    /// Never more than the incorrectness limit.
+    pub fn get_malicious_neighbourhood(&self, conf: &Conf) -> Vec<EntityId> {
+        let mut rng = thread_rng();
+        let n_malicious: usize = rng.gen_range(0..=conf.max_neighbourhood_faults);
 
-        neighbourhood
+        conf.malicious_users
+            .choose_multiple(&mut rng, n_malicious)
+            .map(|(entity_id, _)| *entity_id)
+            .collect()
     }
 
     /// Generate the full set of correct EntityId's, with positions.
-    /// This is what the malicious nodes receive.
+    /// This is what users pick their neighbours from.
     pub fn get_correct_users(&self) -> Vec<(EntityId, Position)> {
-        self.grid.iter().map(|(id, pos)| (*id, *pos)).collect()
+        let mut users: Vec<_> = self.grid.iter().map(|(id, pos)| (*id, *pos)).collect();
+        users.sort_unstable_by_key(|(id, _)| *id);
+
+        users
+    }
+
+    /// Epoch and positions of correct users before the last advance (if any)
+    ///
+    /// These are the positions users currently hold after a tick
+    pub fn previous_positions(&self) -> Option<(u64, &HashMap<EntityId, Position>)> {
+        self.previous.as_ref().map(|(epoch, grid)| (*epoch, grid))
+    }
+
+    /// Positions of correct users at an epoch, if it is the current or the previous one
+    pub fn positions_at(&self, epoch: u64) -> Option<&HashMap<EntityId, Position>> {
+        if epoch == self.epoch {
+            Some(&self.grid)
+        } else {
+            self.previous_positions()
+                .filter(|(previous_epoch, _)| *previous_epoch == epoch)
+                .map(|(_, grid)| grid)
+        }
     }
 
     /// Advance the epoch
+    ///
+    /// Past the configured max epoch or the end of the replayed recording, nothing changes
+    /// and the state becomes [halted](Self::halted)
     pub fn advance(&mut self, conf: &Conf) {
-        let mut rng = thread_rng();
+        if conf
+            .max_epoch
+            .map_or(false, |max_epoch| self.epoch >= max_epoch)
+        {
+            self.halted = true;
+            return;
+        }
+
+        let replayed = match &self.replay {
+            Some(recording) => match recording.positions_at(self.epoch + 1) {
+                Some(grid) => Some(grid.clone()),
+                None => {
+                    self.halted = true;
+                    return;
+                }
+            },
+            None => None,
+        };
+
+        self.previous = Some((self.epoch, self.grid.clone()));
         self.epoch += 1;
 
+        if let Some(grid) = replayed {
+            self.grid = grid;
+            return;
+        }
+
+        let mut rng = thread_rng();
         for pos in self.grid.values_mut() {
-            *pos = Position(
-                rng.gen_range(0..conf.dims.0 as i64),
-                rng.gen_range(0..conf.dims.1 as i64),
-            );
+            *pos = random_position(&mut rng, &conf.bounds);
+        }
+    }
+}
+
+/// Uniformly random position in the world
+fn random_position(rng: &mut impl Rng, bounds: &WorldBounds) -> Position {
+    Position(
+        rng.gen_range(bounds.min().0..=bounds.max().0),
+        rng.gen_range(bounds.min().1..=bounds.max().1),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::RetryPolicy;
+
+    #[test]
+    fn starting_positions() {
+        let mut starting_positions = HashMap::new();
+        starting_positions.insert(1, Position(3, 19));
+        starting_positions.insert(2, Position(0, 0));
+        let conf = Conf {
+            bounds: WorldBounds::from_dims(10, 20).unwrap(),
+            max_neighbourhood_faults: 0,
+            max_server_faults: 0,
+            correct_servers: vec![],
+            correct_users: (1..=50).collect(),
+            malicious_users: vec![],
+            id_to_uri: HashMap::new(),
+            starting_positions,
+            server_faults: HashMap::new(),
+            max_epoch: None,
+            retry: RetryPolicy::default(),
+        };
+
+        let state = State::new(&conf);
+        assert_eq!(state.position_of(1), Position(3, 19));
+        assert_eq!(state.position_of(2), Position(0, 0));
+
+        let random: Vec<_> = (3..=50).map(|id| state.position_of(id)).collect();
+        for pos in &random {
+            assert!((0..10).contains(&pos.0) && (0..20).contains(&pos.1));
         }
+        assert!(random.iter().any(|pos| *pos != random[0]));
+    }
+
+    #[test]
+    fn advance_keeps_previous() {
+        let conf = Conf {
+            bounds: WorldBounds::from_dims(10, 20).unwrap(),
+            max_neighbourhood_faults: 0,
+            max_server_faults: 0,
+            correct_servers: vec![],
+            correct_users: (1..=5).collect(),
+            malicious_users: vec![],
+            id_to_uri: HashMap::new(),
+            starting_positions: HashMap::new(),
+            server_faults: HashMap::new(),
+            max_epoch: None,
+            retry: RetryPolicy::default(),
+        };
+
+        let mut state = State::new(&conf);
+        assert!(state.previous_positions().is_none());
+
+        let grid = state.grid.clone();
+        state.advance(&conf);
+        assert_eq!(state.epoch(), 1);
+        assert_eq!(state.previous_positions(), Some((0, &grid)));
+    }
+
+    #[test]
+    fn advance_stops_at_max_epoch() {
+        let conf = Conf {
+            bounds: WorldBounds::from_dims(10, 20).unwrap(),
+            max_neighbourhood_faults: 0,
+            max_server_faults: 0,
+            correct_servers: vec![],
+            correct_users: (1..=5).collect(),
+            malicious_users: vec![],
+            id_to_uri: HashMap::new(),
+            starting_positions: HashMap::new(),
+            server_faults: HashMap::new(),
+            max_epoch: Some(2),
+            retry: RetryPolicy::default(),
+        };
+
+        let mut state = State::new(&conf);
+        state.advance(&conf);
+        state.advance(&conf);
+        assert_eq!(state.epoch(), 2);
+        assert!(!state.halted());
+
+        let grid = state.grid.clone();
+        let previous = state.previous.clone();
+        state.advance(&conf);
+        state.advance(&conf);
+        assert_eq!(state.epoch(), 2);
+        assert!(state.halted());
+        assert_eq!(state.grid, grid);
+        assert_eq!(state.previous, previous);
+    }
+
+    #[test]
+    fn replay_recording() {
+        let conf = Conf {
+            bounds: WorldBounds::from_dims(10, 20).unwrap(),
+            max_neighbourhood_faults: 0,
+            max_server_faults: 0,
+            correct_servers: vec![],
+            correct_users: vec![1, 2],
+            malicious_users: vec![],
+            id_to_uri: HashMap::new(),
+            starting_positions: HashMap::new(),
+            server_faults: HashMap::new(),
+            max_epoch: None,
+            retry: RetryPolicy::default(),
+        };
+        let recording = Recording::parse(concat!(
+            r#"{"epoch":0,"positions":[{"id":1,"x":0,"y":0},{"id":2,"x":1,"y":1}]}"#,
+            "\n",
+            r#"{"epoch":1,"positions":[{"id":1,"x":2,"y":2},{"id":2,"x":3,"y":3}]}"#,
+        ))
+        .unwrap();
+
+        let mut state = State::replaying(&conf, recording.clone()).unwrap();
+        assert_eq!(&state.grid, recording.positions_at(0).unwrap());
+        state.advance(&conf);
+        assert_eq!(state.epoch(), 1);
+        assert_eq!(&state.grid, recording.positions_at(1).unwrap());
+        assert!(!state.halted());
+
+        // nothing left to replay
+        state.advance(&conf);
+        assert_eq!(state.epoch(), 1);
+        assert!(state.halted());
+
+        // the recording must be of the configured users, from the start
+        let conf = Conf {
+            correct_users: vec![1, 2, 3],
+            ..conf
+        };
+        assert!(State::replaying(&conf, recording).is_err());
+        let late = Recording::parse(r#"{"epoch":1,"positions":[{"id":1,"x":0,"y":0}]}"#).unwrap();
+        assert!(State::replaying(
+            &Conf {
+                correct_users: vec![1],
+                ..conf.clone()
+            },
+            late
+        )
+        .is_err());
+
+        // nor can it leave the world
+        let outside =
+            Recording::parse(r#"{"epoch":0,"positions":[{"id":1,"x":10,"y":0}]}"#).unwrap();
+        assert!(State::replaying(
+            &Conf {
+                correct_users: vec![1],
+                ..conf
+            },
+            outside
+        )
+        .is_err());
     }
 }
@@ -0,0 +1,96 @@
+use crate::maybe_tracing::*;
+use crate::util::{TestConfig, TestEnv};
+use client::ReturnTransport;
+use model::Position;
+
+fn config() -> TestConfig {
+    TestConfig {
+        n_servers: 4,
+        n_faulty_servers: 0,
+        n_correct_users: 3,
+        n_ha_clients: 1,
+        n_malicious_users: 0,
+        max_neigh_faults: 1,
+        max_server_faults: 1,
+        dims: (400, 400),
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn atomic_read_through_callback() {
+    let _guard = tracing_utils::setup(
+        env!("CARGO_PKG_NAME"),
+        vec![("test", "atomic_read_through_callback")],
+    )
+    .unwrap();
+
+    let env = TestEnv::new(config()).await;
+
+    // no tick: the driver would leave its epoch one ahead of the servers'
+    let epoch = env.current_epoch().await;
+    let proof = env.position_proof(
+        epoch,
+        0,
+        Position(20, 20),
+        &[(1, Position(10, 10)), (2, Position(30, 30))],
+    );
+
+    info!("Submitting proof");
+    env.user_api_client(0)
+        .await
+        .submit_position_report(proof)
+        .await
+        .unwrap();
+
+    info!("Reading position, with the value pushed to a callback server");
+    let ha_client = env.ha_client(0).await;
+    assert_eq!(ha_client.return_transport(), ReturnTransport::Push);
+    assert_eq!(
+        ha_client
+            .obtain_position_report(env.user_id(0), epoch)
+            .await
+            .unwrap(),
+        Position(20, 20)
+    );
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn atomic_read_through_callback_with_server_down() {
+    let _guard = tracing_utils::setup(
+        env!("CARGO_PKG_NAME"),
+        vec![("test", "atomic_read_through_callback_with_server_down")],
+    )
+    .unwrap();
+
+    let env = TestEnv::new(config()).await;
+
+    // no tick: the driver would leave its epoch one ahead of the servers'
+    let epoch = env.current_epoch().await;
+    let proof = env.position_proof(
+        epoch,
+        0,
+        Position(20, 20),
+        &[(1, Position(10, 10)), (2, Position(30, 30))],
+    );
+
+    info!("Stopping a server");
+    env.stop_server(3);
+
+    info!("Submitting proof to the remaining servers");
+    env.user_api_client(0)
+        .await
+        .submit_position_report(proof)
+        .await
+        .unwrap();
+
+    info!("Reading position, with the value pushed to a callback server");
+    let ha_client = env.ha_client(0).await;
+    assert_eq!(ha_client.return_transport(), ReturnTransport::Push);
+    assert_eq!(
+        ha_client
+            .obtain_position_report(env.user_id(0), epoch)
+            .await
+            .unwrap(),
+        Position(20, 20)
+    );
+}
@@ -0,0 +1,50 @@
+use crate::maybe_tracing::*;
+use crate::util::{TestConfig, TestEnv};
+use model::Position;
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn client_syncs_to_server_epoch() {
+    let _guard = tracing_utils::setup(
+        env!("CARGO_PKG_NAME"),
+        vec![("test", "client_syncs_to_server_epoch")],
+    )
+    .unwrap();
+
+    let env = TestEnv::new(TestConfig {
+        n_servers: 4,
+        n_faulty_servers: 0,
+        n_correct_users: 1,
+        n_ha_clients: 1,
+        n_malicious_users: 0,
+        max_neigh_faults: 1,
+        max_server_faults: 1,
+        dims: (400, 400),
+    })
+    .await;
+
+    // no tick: the driver would leave its epoch one ahead of the servers'
+    let epoch = env.current_epoch().await;
+
+    let mut ha_client = env.ha_client(0).await;
+    ha_client.set_current_epoch(epoch + 42);
+
+    info!("Querying with an epoch ahead of the servers'");
+    assert!(ha_client
+        .obtain_users_at_position(Position(0, 0), epoch)
+        .await
+        .is_err());
+
+    info!("Obtaining server epoch");
+    let server_epoch = ha_client.get_epoch().await.unwrap();
+    assert_eq!(server_epoch, epoch);
+
+    info!("Querying after syncing to the server epoch");
+    ha_client.set_current_epoch(server_epoch);
+    assert_eq!(
+        ha_client
+            .obtain_users_at_position(Position(0, 0), epoch)
+            .await
+            .unwrap(),
+        vec![]
+    );
+}
@@ -11,6 +11,7 @@ async fn happy_replicated_path_test() {
 
     let env = TestEnv::new(TestConfig {
         n_servers: 4,
+        n_faulty_servers: 0,
         n_correct_users: 5,
         n_ha_clients: 0,
         n_malicious_users: 0,
@@ -20,5 +20,13 @@ pub mod maybe_tracing {
     }
 }
 
+mod atomic_read;
+mod get_epoch;
 mod happy;
 mod happy_replicated;
+mod poll_return;
+mod regular_read;
+mod server_membership;
+mod single_server;
+mod verify_positions;
+mod write_progress;
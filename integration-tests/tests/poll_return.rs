@@ -0,0 +1,104 @@
+use crate::maybe_tracing::*;
+use crate::util::{TestConfig, TestEnv};
+use client::ReturnTransport;
+use model::api::ApiReply;
+use model::Position;
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn pull_matches_push_read() {
+    let _guard = tracing_utils::setup(
+        env!("CARGO_PKG_NAME"),
+        vec![("test", "pull_matches_push_read")],
+    )
+    .unwrap();
+
+    let env = TestEnv::new(TestConfig {
+        n_servers: 4,
+        n_faulty_servers: 0,
+        n_correct_users: 3,
+        n_ha_clients: 1,
+        n_malicious_users: 0,
+        max_neigh_faults: 1,
+        max_server_faults: 1,
+        dims: (400, 400),
+    })
+    .await;
+
+    // no tick: the driver would leave its epoch one ahead of the servers'
+    let epoch = env.current_epoch().await;
+    let proof = env.position_proof(
+        epoch,
+        0,
+        Position(20, 20),
+        &[(1, Position(10, 10)), (2, Position(30, 30))],
+    );
+
+    info!("Submitting proof");
+    env.user_api_client(0)
+        .await
+        .submit_position_report(proof)
+        .await
+        .unwrap();
+
+    let prover_id = env.user_id(0);
+
+    info!("Reading position with pushed values");
+    let push_client = env.ha_client(0).await;
+    assert_eq!(push_client.return_transport(), ReturnTransport::Push);
+    let pushed = push_client
+        .obtain_position_report(prover_id, epoch)
+        .await
+        .unwrap();
+
+    info!("Reading position with pulled values");
+    let mut pull_client = env.ha_client(0).await;
+    pull_client.set_return_transport(ReturnTransport::Pull);
+    let pulled = pull_client
+        .obtain_position_report(prover_id, epoch)
+        .await
+        .unwrap();
+
+    assert_eq!(pushed, Position(20, 20));
+    assert_eq!(pushed, pulled);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn single_server_pull() {
+    let _guard =
+        tracing_utils::setup(env!("CARGO_PKG_NAME"), vec![("test", "single_server_pull")]).unwrap();
+
+    let env = TestEnv::new(TestConfig {
+        n_servers: 1,
+        n_faulty_servers: 0,
+        n_correct_users: 2,
+        n_ha_clients: 0,
+        n_malicious_users: 0,
+        max_neigh_faults: 1,
+        max_server_faults: 0,
+        dims: (400, 400),
+    })
+    .await;
+
+    let epoch = env.current_epoch().await;
+    let prover_id = env.user_id(0);
+    let proof = env.position_proof(epoch, 0, Position(0, 0), &[(1, Position(10, 10))]);
+
+    let server_id = env.servers[0].0;
+    let mut client = env.single_server_client(0, prover_id).await;
+    client.set_return_transport(ReturnTransport::Pull);
+
+    info!("Submitting proof to a single server");
+    assert_eq!(
+        client.submit_to(server_id, proof).await.unwrap(),
+        ApiReply::Ok
+    );
+
+    info!("Polling position from a single server");
+    assert_eq!(
+        client
+            .obtain_from(server_id, prover_id, epoch)
+            .await
+            .unwrap(),
+        ApiReply::PositionReport(epoch, Position(0, 0))
+    );
+}
@@ -0,0 +1,173 @@
+use crate::maybe_tracing::*;
+use crate::util::{TestConfig, TestEnv};
+use model::Position;
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn regular_matches_atomic_read() {
+    let _guard = tracing_utils::setup(
+        env!("CARGO_PKG_NAME"),
+        vec![("test", "regular_matches_atomic_read")],
+    )
+    .unwrap();
+
+    let env = TestEnv::new(TestConfig {
+        n_servers: 4,
+        n_faulty_servers: 0,
+        n_correct_users: 3,
+        n_ha_clients: 1,
+        n_malicious_users: 0,
+        max_neigh_faults: 1,
+        max_server_faults: 1,
+        dims: (400, 400),
+    })
+    .await;
+
+    // no tick: the driver would leave its epoch one ahead of the servers'
+    let epoch = env.current_epoch().await;
+    let proof = env.position_proof(
+        epoch,
+        0,
+        Position(20, 20),
+        &[(1, Position(10, 10)), (2, Position(30, 30))],
+    );
+
+    info!("Submitting proof");
+    env.user_api_client(0)
+        .await
+        .submit_position_report(proof)
+        .await
+        .unwrap();
+
+    let ha_client = env.ha_client(0).await;
+    let prover_id = env.user_id(0);
+
+    info!("Reading position with regular semantics");
+    let regular = ha_client
+        .obtain_position_report_regular(prover_id, epoch)
+        .await
+        .unwrap();
+
+    info!("Reading position with atomic semantics");
+    let atomic = ha_client
+        .obtain_position_report(prover_id, epoch)
+        .await
+        .unwrap();
+
+    assert_eq!(regular, Position(20, 20));
+    assert_eq!(regular, atomic);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn bulk_matches_individual_reads() {
+    let _guard = tracing_utils::setup(
+        env!("CARGO_PKG_NAME"),
+        vec![("test", "bulk_matches_individual_reads")],
+    )
+    .unwrap();
+
+    let env = TestEnv::new(TestConfig {
+        n_servers: 4,
+        n_faulty_servers: 0,
+        n_correct_users: 3,
+        n_ha_clients: 1,
+        n_malicious_users: 0,
+        max_neigh_faults: 1,
+        max_server_faults: 1,
+        dims: (400, 400),
+    })
+    .await;
+
+    // users only submit for the current epoch: past ones go straight to storage
+    info!("Storing proofs for epochs 0, 1 and 3");
+    for epoch in [0u64, 1, 3].iter().copied() {
+        let position = Position(epoch as i64, epoch as i64);
+        let proof = env.position_proof(epoch, 0, position, &[(1, position), (2, position)]);
+        for i in 0..4 {
+            env.server(i)
+                .store()
+                .add_proof(proof.clone(), epoch)
+                .await
+                .unwrap();
+        }
+    }
+
+    let ha_client = env.ha_client(0).await;
+    let prover_id = env.user_id(0);
+    let epochs = [0, 1, 2, 3];
+
+    info!("Reading positions in bulk");
+    let bulk = ha_client
+        .obtain_position_reports_bulk(prover_id, &epochs)
+        .await
+        .unwrap();
+
+    info!("Reading positions one by one");
+    let mut individual = Vec::new();
+    for &epoch in &epochs {
+        if let Ok(position) = ha_client
+            .obtain_position_report_regular(prover_id, epoch)
+            .await
+        {
+            individual.push((epoch, position));
+        }
+    }
+
+    assert_eq!(
+        bulk,
+        vec![
+            (0, Position(0, 0)),
+            (1, Position(1, 1)),
+            (3, Position(3, 3))
+        ]
+    );
+    assert_eq!(bulk, individual);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn read_certificate() {
+    let _guard =
+        tracing_utils::setup(env!("CARGO_PKG_NAME"), vec![("test", "read_certificate")]).unwrap();
+
+    let env = TestEnv::new(TestConfig {
+        n_servers: 4,
+        n_faulty_servers: 0,
+        n_correct_users: 3,
+        n_ha_clients: 1,
+        n_malicious_users: 0,
+        max_neigh_faults: 1,
+        max_server_faults: 1,
+        dims: (400, 400),
+    })
+    .await;
+
+    let epoch = env.current_epoch().await;
+    let position = Position(5, 5);
+    let proof = env.position_proof(epoch, 0, position, &[(1, position), (2, position)]);
+    for i in 0..4 {
+        env.server(i)
+            .store()
+            .add_proof(proof.clone(), epoch)
+            .await
+            .unwrap();
+    }
+
+    info!("Reading position with a certificate");
+    let ha_client = env.ha_client(0).await;
+    let prover_id = env.user_id(0);
+    let (read_position, certificate) = ha_client
+        .obtain_read_certificate(prover_id, epoch)
+        .await
+        .unwrap();
+    assert_eq!(read_position, position);
+
+    // a user that took no part in the read can check the servers agreed
+    info!("Checking the certificate as another user");
+    let checker = env.keystore_for_entity(env.user_id(2));
+    let verified = certificate.verify(&checker, 3, 1).unwrap();
+    assert_eq!(verified.position(), position);
+    assert!(certificate.replies().len() >= 3);
+    for reply in certificate.replies() {
+        reply.verify(&checker).unwrap();
+        assert_eq!(reply.server_epoch(), epoch);
+    }
+}
@@ -0,0 +1,65 @@
+use crate::maybe_tracing::*;
+use crate::util::{TestConfig, TestEnv};
+use model::api::ApiReply;
+use model::Position;
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn client_follows_server_membership() {
+    let _guard = tracing_utils::setup(
+        env!("CARGO_PKG_NAME"),
+        vec![("test", "client_follows_server_membership")],
+    )
+    .unwrap();
+
+    let env = TestEnv::new(TestConfig {
+        n_servers: 2,
+        n_faulty_servers: 0,
+        n_correct_users: 2,
+        n_ha_clients: 1,
+        n_malicious_users: 0,
+        max_neigh_faults: 1,
+        max_server_faults: 0,
+        dims: (400, 400),
+    })
+    .await;
+
+    // no tick: the driver would leave its epoch one ahead of the servers'
+    // the proof is for the next epoch, so the reply holding it is more recent than any error
+    let epoch = env.current_epoch().await + 1;
+    let prover_id = env.user_id(0);
+    let proof = env.position_proof(epoch, 0, Position(0, 0), &[(1, Position(10, 10))]);
+
+    let (server_id, server) = &env.servers[0];
+    info!("Submitting proof to a single server");
+    assert_eq!(
+        env.single_server_client(0, prover_id)
+            .await
+            .submit_to(*server_id, proof)
+            .await
+            .unwrap(),
+        ApiReply::Ok
+    );
+
+    let ha_client = env.ha_client(0).await;
+    assert!(ha_client.remove_server(*server_id).await);
+    assert!(!ha_client.remove_server(*server_id).await);
+
+    info!("Reading without the server that has the proof");
+    assert!(ha_client
+        .obtain_position_report_regular(prover_id, epoch)
+        .await
+        .is_err());
+
+    info!("Reading after adding it back");
+    ha_client
+        .add_server(*server_id, server.uri())
+        .await
+        .unwrap();
+    assert_eq!(
+        ha_client
+            .obtain_position_report_regular(prover_id, epoch)
+            .await
+            .unwrap(),
+        Position(0, 0)
+    );
+}
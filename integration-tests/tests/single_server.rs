@@ -0,0 +1,49 @@
+use crate::maybe_tracing::*;
+use crate::util::{TestConfig, TestEnv};
+use model::api::ApiReply;
+use model::Position;
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn single_server_submit_obtain() {
+    let _guard = tracing_utils::setup(
+        env!("CARGO_PKG_NAME"),
+        vec![("test", "single_server_submit_obtain")],
+    )
+    .unwrap();
+
+    let env = TestEnv::new(TestConfig {
+        n_servers: 1,
+        n_faulty_servers: 0,
+        n_correct_users: 2,
+        n_ha_clients: 0,
+        n_malicious_users: 0,
+        max_neigh_faults: 1,
+        max_server_faults: 0,
+        dims: (400, 400),
+    })
+    .await;
+
+    // no tick: the driver would leave its epoch one ahead of the servers'
+    let epoch = env.current_epoch().await;
+
+    let prover_id = env.user_id(0);
+    let proof = env.position_proof(epoch, 0, Position(0, 0), &[(1, Position(10, 10))]);
+
+    let server_id = env.servers[0].0;
+    let client = env.single_server_client(0, prover_id).await;
+
+    info!("Submitting proof to a single server");
+    assert_eq!(
+        client.submit_to(server_id, proof).await.unwrap(),
+        ApiReply::Ok
+    );
+
+    info!("Obtaining position from a single server");
+    assert_eq!(
+        client
+            .obtain_from(server_id, prover_id, epoch)
+            .await
+            .unwrap(),
+        ApiReply::PositionReport(epoch, Position(0, 0))
+    );
+}
@@ -0,0 +1,129 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+use model::api::{ApiErrorCode, ApiReply, ApiRequest, RrMessage};
+use model::keys::{EntityId, KeyStore, Nonce};
+use model::{PositionProof, UnverifiedPositionProof};
+use protos::hdlt::hdlt_api_server::{HdltApi, HdltApiServer};
+use protos::hdlt::CipheredRrMessage;
+use server::Uri;
+use tonic::{Request, Response, Status};
+
+type BgTaskHandle = server::ServerBgTaskHandle;
+
+/// Proofs to answer position reads with, by prover and epoch
+type Lies = Arc<Mutex<HashMap<(EntityId, u64), UnverifiedPositionProof>>>;
+
+/// Byzantine server: answers regular position reads with whatever proofs it was told to, and
+/// refuses every other request
+///
+/// It speaks the same protocol as a correct server, but is not known to the driver.
+pub struct FaultyServer {
+    id: EntityId,
+    listen_addr: SocketAddr,
+    lies: Lies,
+}
+
+impl FaultyServer {
+    pub async fn new(keystore: KeyStore) -> (Self, BgTaskHandle) {
+        let id = keystore.my_id();
+        let lies = Lies::default();
+        let service = FaultyApiService {
+            keystore,
+            lies: Arc::clone(&lies),
+        };
+
+        let (incoming, listen_addr) = net_utils::create_tcp_incoming(&"[::1]:0".parse().unwrap())
+            .await
+            .expect("failed to spawn faulty server");
+        let bg_task = tokio::spawn(async move {
+            tonic::transport::Server::builder()
+                .add_service(HdltApiServer::new(service))
+                .serve_with_incoming(incoming)
+                .await
+                .map_err(eyre::Report::from)
+        });
+
+        let server = FaultyServer {
+            id,
+            listen_addr,
+            lies,
+        };
+        (server, bg_task)
+    }
+
+    pub fn id(&self) -> EntityId {
+        self.id
+    }
+
+    /// Compute server's URI/endpoint for a client-to-be, like [server::Server::uri].
+    pub fn uri(&self) -> Uri {
+        Uri::builder()
+            .scheme("http")
+            .authority(format!("[{}]:{}", self.listen_addr.ip(), self.listen_addr.port()).as_str())
+            .path_and_query("/")
+            .build()
+            .unwrap()
+    }
+
+    /// Answer reads of the prover's position, in the proof's epoch, with this proof
+    pub fn lie_about(&self, proof: PositionProof) {
+        self.lies
+            .lock()
+            .unwrap()
+            .insert((proof.prover_id(), proof.epoch()), proof.into());
+    }
+}
+
+struct FaultyApiService {
+    keystore: KeyStore,
+    lies: Lies,
+}
+
+#[tonic::async_trait]
+impl HdltApi for FaultyApiService {
+    async fn invoke(
+        &self,
+        request: Request<CipheredRrMessage>,
+    ) -> Result<Response<CipheredRrMessage>, Status> {
+        let message = request.into_inner();
+        let partner_id = message.sender_id;
+        let nonce = Nonce::from_slice(&message.nonce)
+            .ok_or_else(|| Status::invalid_argument("invalid nonce"))?;
+        let plaintext = self
+            .keystore
+            .decipher(partner_id, &message.ciphertext, &nonce)
+            .map_err(|_| Status::unauthenticated("cannot decipher request"))?;
+        let request = bincode::deserialize::<RrMessage<ApiRequest>>(&plaintext)
+            .ok()
+            .and_then(|message| message.downcast_request(0).ok())
+            .ok_or_else(|| Status::invalid_argument("not a request"))?;
+
+        let lie = match request.as_ref() {
+            ApiRequest::ObtainPositionReportRegular { user_id, epoch, .. } => {
+                let lies = self.lies.lock().unwrap();
+                lies.get(&(*user_id, *epoch))
+                    .map(|proof| (*epoch, proof.clone()))
+            }
+            _ => None,
+        };
+        let reply = match lie {
+            Some(report) => ApiReply::PositionReports(vec![report]),
+            None => ApiReply::Error(ApiErrorCode::Other, "not telling".to_owned()),
+        };
+
+        let reply = RrMessage::new_reply(&request, request.epoch(), reply);
+        let plaintext = bincode::serialize(&reply).expect("could not serialize reply");
+        let (ciphertext, nonce) = self
+            .keystore
+            .cipher(partner_id, &plaintext)
+            .expect("could not cipher reply");
+
+        Ok(Response::new(CipheredRrMessage {
+            sender_id: self.keystore.my_id(),
+            ciphertext,
+            nonce: nonce.0.to_vec(),
+        }))
+    }
+}
@@ -1,5 +1,7 @@
+pub mod faulty_server;
 pub mod test_config;
 pub mod test_env;
 
+pub use faulty_server::FaultyServer;
 pub use test_config::TestConfig;
 pub use test_env::TestEnv;
@@ -41,9 +41,10 @@ lazy_static! {
     };
 }
 
-// TODO: we probably want to have malicous servers
 pub struct TestConfig {
     pub n_servers: usize,
+    /// Byzantine servers, in addition to the correct ones (see [super::FaultyServer])
+    pub n_faulty_servers: usize,
     pub n_correct_users: usize,
     pub n_malicious_users: usize,
     pub n_ha_clients: usize,
@@ -54,7 +55,11 @@ pub struct TestConfig {
 
 impl TestConfig {
     pub fn assert_valid(&self) {
-        assert_lt!(self.n_servers, SERVER_RANGE.len(), "too many servers");
+        assert_lt!(
+            self.n_servers + self.n_faulty_servers,
+            SERVER_RANGE.len(),
+            "too many servers"
+        );
         assert_lt!(self.n_correct_users, USER_RANGE.len(), "too many users");
         assert_lt!(
             self.n_malicious_users,
@@ -70,6 +75,7 @@ impl TestConfig {
 
     pub fn all_entity_ids(&self) -> impl Iterator<Item = EntityId> {
         self.server_ids()
+            .chain(self.faulty_server_ids())
             .chain(self.user_ids())
             .chain(self.malicious_user_ids())
             .chain(self.ha_client_ids())
@@ -80,6 +86,13 @@ impl TestConfig {
         entity_ids(SERVER_RANGE, self.n_servers as u32)
     }
 
+    /// Ids of the faulty servers, right after those of the correct ones
+    #[inline(always)]
+    pub fn faulty_server_ids(&self) -> impl Iterator<Item = EntityId> {
+        let start = SERVER_RANGE.start + self.n_servers as u32;
+        entity_ids(start..SERVER_RANGE.end, self.n_faulty_servers as u32)
+    }
+
     #[inline(always)]
     pub fn user_ids(&self) -> impl Iterator<Item = EntityId> {
         entity_ids(USER_RANGE, self.n_correct_users as u32)
@@ -134,13 +147,17 @@ impl TestConfig {
         }
 
         driver::Conf {
-            dims: self.dims,
+            bounds: model::WorldBounds::from_dims(self.dims.0, self.dims.1).unwrap(),
             correct_servers: self.server_ids().collect(),
             correct_users: self.user_ids().collect(),
             malicious_users: self.malicious_user_ids().map(|id| (id, 0)).collect(),
             id_to_uri,
             max_neighbourhood_faults: self.max_neigh_faults,
             max_server_faults: self.max_server_faults,
+            starting_positions: HashMap::new(),
+            server_faults: HashMap::new(),
+            max_epoch: None,
+            retry: driver::RetryPolicy::default(),
         }
     }
 }
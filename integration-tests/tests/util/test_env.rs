@@ -1,9 +1,11 @@
-use std::{path::PathBuf, sync::Arc, time::Duration};
+use std::{num::NonZeroUsize, path::PathBuf, sync::Arc, time::Duration};
 
 use driver::Driver;
 use model::keys::{EntityId, KeyStore};
+use model::{Position, PositionProof, ProximityProof, ProximityProofRequest};
 use std::collections::HashMap;
 
+use super::faulty_server::FaultyServer;
 use super::test_config::TestConfig;
 
 use client::HdltApiClient;
@@ -17,6 +19,7 @@ pub struct TestEnv {
     config: TestConfig,
     pub driver: Driver,
     pub servers: Vec<(u32, Server)>,
+    pub faulty_servers: Vec<FaultyServer>,
     pub users: Vec<User>,
     pub malicious_users: Vec<User>,
     bg_tasks: Vec<BgTaskHandle>,
@@ -43,7 +46,21 @@ impl TestEnv {
             bg_tasks.push(bg_task);
         }
 
-        let server_uris: Vec<_> = servers.iter().map(|(_, s)| s.uri()).collect();
+        let mut faulty_servers = Vec::new();
+        for id in config.faulty_server_ids() {
+            let (registry_path, me_path) = keystore_paths.get(&id).unwrap();
+            let keystore = KeyStore::load_from_files(registry_path, Some(me_path)).unwrap();
+            let (server, bg_task) = FaultyServer::new(keystore).await;
+            faulty_servers.push(server);
+            bg_tasks.push(bg_task);
+        }
+
+        // users index servers by position, and faulty servers have the ids after the correct ones
+        let server_uris: Vec<_> = servers
+            .iter()
+            .map(|(_, s)| s.uri())
+            .chain(faulty_servers.iter().map(|s| s.uri()))
+            .collect();
         let mut users = Vec::new();
         for fut in config
             .user_ids()
@@ -79,6 +96,7 @@ impl TestEnv {
             config,
             driver,
             servers,
+            faulty_servers,
             users,
             malicious_users,
             bg_tasks,
@@ -97,6 +115,16 @@ impl TestEnv {
         &self.servers[i].1
     }
 
+    pub fn faulty_server(&self, i: usize) -> &FaultyServer {
+        &self.faulty_servers[i]
+    }
+
+    /// Stop a server, as if it crashed. It stops accepting connections right away.
+    pub fn stop_server(&self, i: usize) {
+        // servers are spawned first
+        self.bg_tasks[i].abort()
+    }
+
     pub fn user(&self, i: usize) -> &User {
         &self.users[i]
     }
@@ -123,7 +151,11 @@ impl TestEnv {
         let keystore = self.keystore_for_entity(id);
         let current_epoch = self.current_epoch().await;
         HdltApiClient::new(
-            self.servers.iter().map(|(id, s)| (*id, s.uri())).collect(),
+            self.servers
+                .iter()
+                .map(|(id, s)| (*id, s.uri()))
+                .chain(self.faulty_servers.iter().map(|s| (s.id(), s.uri())))
+                .collect(),
             Arc::new(keystore),
             current_epoch,
             self.config.max_server_faults as u64,
@@ -132,9 +164,51 @@ impl TestEnv {
         .unwrap()
     }
 
-    fn keystore_for_entity(&self, id: EntityId) -> KeyStore {
+    pub async fn single_server_client(&self, server: usize, id: EntityId) -> HdltApiClient {
+        let (server_id, server) = &self.servers[server];
+        let keystore = self.keystore_for_entity(id);
+        let current_epoch = self.current_epoch().await;
+        HdltApiClient::new_single(
+            *server_id,
+            server.uri(),
+            Arc::new(keystore),
+            current_epoch,
+            self.config.max_neigh_faults as u64,
+        )
+        .unwrap()
+    }
+
+    /// Build a position proof for a user, witnessed by other users
+    pub fn position_proof(
+        &self,
+        epoch: u64,
+        prover: usize,
+        position: Position,
+        witnesses: &[(usize, Position)],
+    ) -> PositionProof {
+        let request = ProximityProofRequest::new(
+            epoch,
+            position,
+            &self.keystore_for_entity(self.user_id(prover)),
+        );
+        let witnesses = witnesses
+            .iter()
+            .map(|(witness, witness_position)| {
+                ProximityProof::new(
+                    request.clone(),
+                    *witness_position,
+                    &self.keystore_for_entity(self.user_id(*witness)),
+                )
+                .unwrap()
+            })
+            .collect();
+
+        PositionProof::new(witnesses, self.config.max_neigh_faults).unwrap()
+    }
+
+    pub fn keystore_for_entity(&self, id: EntityId) -> KeyStore {
         let (registry_path, me_path) = self.config.keystore_path(&self._tempdir, id);
-        KeyStore::load_from_files(registry_path, me_path).unwrap()
+        KeyStore::load_from_files(registry_path, Some(me_path)).unwrap()
     }
 }
 
@@ -160,7 +234,18 @@ async fn spawn_server(
         skeys_path,
         skeys_password: None,
         storage_path: tempdir.path().join(format!("server_storage_{}", id)),
+        storage_shards: NonZeroUsize::new(1).unwrap(),
         bind_addr: "[::1]:0".parse().unwrap(),
+        max_in_flight: 256,
+        no_callbacks: false,
+        verify_storage: false,
+        coarsen_grid: None,
+        max_witnesses_factor: 8,
+        admin_bind_addr: None,
+        admin_token: None,
+        admin_allow_remote: false,
+        config_path: None,
+        require_auth_metadata: true,
     };
 
     Server::new(&options).await.expect("failed to spawn server")
@@ -172,7 +257,7 @@ async fn spawn_user(
     server_uris: Vec<Uri>,
     is_malicious: bool,
 ) -> (User, BgTaskHandle) {
-    use client::UserOptions;
+    use client::{UserOptions, WitnessSelection};
 
     let (entity_registry_path, skeys_path) = keystore_paths.get(&id).unwrap().clone();
 
@@ -183,6 +268,10 @@ async fn spawn_user(
         server_uris,
         malicious: is_malicious,
         bind_addr: "[::1]:0".parse().unwrap(),
+        max_witness_requests: None,
+        witness_selection: WitnessSelection::NearestFirst,
+        nagle: false,
+        keepalive_idle_secs: 60,
     };
 
     User::new(&options).await.expect("failed to spawn user")
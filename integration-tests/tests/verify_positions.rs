@@ -0,0 +1,87 @@
+use crate::maybe_tracing::*;
+use crate::util::{TestConfig, TestEnv};
+use driver::Mismatch;
+use model::Position;
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn verify_positions_match() {
+    let _guard = tracing_utils::setup(
+        env!("CARGO_PKG_NAME"),
+        vec![("test", "verify_positions_match")],
+    )
+    .unwrap();
+
+    // small grid: everyone is everyone's neighbour
+    let env = TestEnv::new(TestConfig {
+        n_servers: 1,
+        n_faulty_servers: 0,
+        n_correct_users: 3,
+        n_ha_clients: 0,
+        n_malicious_users: 0,
+        max_neigh_faults: 1,
+        max_server_faults: 0,
+        dims: (10, 10),
+    })
+    .await;
+
+    // nothing to check before the first tick
+    assert!(env.driver.verify_positions().await.is_empty());
+
+    info!("Tick");
+    env.tick().await;
+
+    info!("Asking users to prove their positions");
+    env.driver.prove_position_all().await.unwrap();
+
+    info!("Verifying positions");
+    let mismatches = env.driver.verify_positions().await;
+    assert!(mismatches.is_empty(), "{:#?}", mismatches);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn verify_positions_faulty_server() {
+    let _guard = tracing_utils::setup(
+        env!("CARGO_PKG_NAME"),
+        vec![("test", "verify_positions_faulty_server")],
+    )
+    .unwrap();
+
+    // a quorum is both servers, so users hear from the faulty one too
+    let env = TestEnv::new(TestConfig {
+        n_servers: 1,
+        n_faulty_servers: 1,
+        n_correct_users: 3,
+        n_ha_clients: 0,
+        n_malicious_users: 0,
+        max_neigh_faults: 1,
+        max_server_faults: 1,
+        dims: (10, 10),
+    })
+    .await;
+
+    info!("Tick");
+    env.tick().await;
+    // the driver is one epoch ahead of users and servers after a tick
+    let epoch = env.current_epoch().await - 1;
+
+    // users don't prove their positions: the faulty server makes one up for user 0
+    info!("Making the faulty server lie");
+    let forged_position = Position(1000, 1000);
+    let forged = env.position_proof(epoch, 0, forged_position, &[(1, Position(1000, 1001))]);
+    env.faulty_server(0).lie_about(forged);
+
+    info!("Verifying positions");
+    let mismatches = env.driver.verify_positions().await;
+    assert_eq!(mismatches.len(), 3, "{:#?}", mismatches);
+    for (uid, mismatch) in &mismatches {
+        if *uid == env.user_id(0) {
+            assert!(matches!(
+                mismatch,
+                Mismatch::WrongPosition { reported, expected }
+                    if *reported == forged_position && *expected != forged_position
+            ));
+        } else {
+            assert!(matches!(mismatch, Mismatch::Unavailable(_)));
+        }
+    }
+}
@@ -0,0 +1,52 @@
+use std::sync::Mutex;
+
+use crate::maybe_tracing::*;
+use crate::util::{TestConfig, TestEnv};
+use model::Position;
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn submit_reports_ack_progress() {
+    let _guard = tracing_utils::setup(
+        env!("CARGO_PKG_NAME"),
+        vec![("test", "submit_reports_ack_progress")],
+    )
+    .unwrap();
+
+    let env = TestEnv::new(TestConfig {
+        n_servers: 4,
+        n_faulty_servers: 0,
+        n_correct_users: 2,
+        n_ha_clients: 0,
+        n_malicious_users: 0,
+        max_neigh_faults: 1,
+        max_server_faults: 1,
+        dims: (400, 400),
+    })
+    .await;
+
+    // no tick: the driver would leave its epoch one ahead of the servers'
+    let epoch = env.current_epoch().await;
+    let proof = env.position_proof(epoch, 0, Position(0, 0), &[(1, Position(10, 10))]);
+
+    let acks = Mutex::new(Vec::new());
+    info!("Submitting proof");
+    let n_acks = env
+        .user_api_client(0)
+        .await
+        .submit_position_report_with_progress(proof, |server_id| {
+            acks.lock().unwrap().push(server_id)
+        })
+        .await
+        .unwrap();
+
+    // one call per acking server, stopping at the quorum: (4 + 1) / 2 + 1
+    let mut acks = acks.into_inner().unwrap();
+    assert_eq!(acks.len(), 3);
+    assert_eq!(n_acks, 3);
+    acks.sort_unstable();
+    acks.dedup();
+    assert_eq!(acks.len(), 3);
+    assert!(acks
+        .iter()
+        .all(|id| env.servers.iter().any(|(server_id, _)| server_id == id)));
+}
@@ -1,4 +1,7 @@
-use std::{collections::HashMap, path::PathBuf};
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+};
 
 use eyre::{eyre, Result, WrapErr};
 use model::keys::{EntityId, EntityPrivComponent, KeyStore, Role};
@@ -46,6 +49,21 @@ pub enum Command {
         #[structopt(long, short)]
         new_password: Option<String>,
     },
+
+    /// Check that private keys are consistent with an entity registry
+    Verify {
+        /// Path to entity registry.
+        #[structopt()]
+        registry_path: PathBuf,
+
+        /// Path to secret keys.
+        #[structopt()]
+        key_path: PathBuf,
+
+        /// Key password. Omit if there is none.
+        #[structopt(long, short)]
+        password: Option<String>,
+    },
 }
 
 fn main() -> Result<()> {
@@ -62,9 +80,32 @@ fn main() -> Result<()> {
             old_password,
             new_password,
         } => change_password(key_path, old_password, new_password),
+        Command::Verify {
+            registry_path,
+            key_path,
+            password,
+        } => verify(registry_path, key_path, password),
     }
 }
 
+fn verify(registry_path: PathBuf, key_path: PathBuf, password: Option<String>) -> Result<()> {
+    let keystore = KeyStore::load_from_files_with_password(
+        &registry_path,
+        Some(&key_path),
+        password.as_deref(),
+    )?;
+    let id = keystore.my_id();
+    let fingerprint = keystore
+        .entity(id)
+        .expect("current entity is always in the registry")
+        .fingerprint();
+
+    println!("Entity {} is consistent with the registry", id);
+    println!("Entity {} fingerprint: {}", id, fingerprint);
+
+    Ok(())
+}
+
 fn change_password(
     key_path: PathBuf,
     old_password: Option<String>,
@@ -120,6 +161,17 @@ fn generate_keys(
             .wrap_err(format!("Failed to add entity {} to keystore", privkey.id))?;
     }
 
+    // so admins can compare registries
+    let mut ids: Vec<_> = privkeys.keys().copied().collect();
+    ids.sort_unstable();
+    for id in ids {
+        println!(
+            "Entity {} fingerprint: {}",
+            id,
+            privkeys[&id].pub_component().fingerprint()
+        );
+    }
+
     // store keystore for each user
     for (id, privkeys) in privkeys.into_iter() {
         keystore
@@ -138,6 +190,20 @@ fn validate_gen_keys_options(
     servers: &[EntityId],
     ha_clients: &[EntityId],
 ) -> Result<()> {
+    for (role, ids) in &[
+        ("user", users),
+        ("server", servers),
+        ("HA client", ha_clients),
+    ] {
+        if let Some(id) = first_duplicate(ids) {
+            return Err(eyre!(
+                "Entity {} is listed more than once as a {}",
+                id,
+                role
+            ));
+        }
+    }
+
     if let Some(id) = users.iter().find(|id| servers.contains(id)) {
         return Err(eyre!(
             "Entity {} cannot be a user and a server at the same time",
@@ -158,3 +224,79 @@ fn validate_gen_keys_options(
     }
     Ok(())
 }
+
+/// First id to appear again later in the list, if any
+fn first_duplicate(ids: &[EntityId]) -> Option<EntityId> {
+    let mut seen = HashSet::new();
+    ids.iter().copied().find(|&id| !seen.insert(id))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use model::keys::KeyStoreLoadError;
+
+    #[test]
+    fn verify_keys() {
+        model::ensure_init();
+        let dir = tempfile::tempdir().unwrap();
+        let registry_path = dir.path().join(ENTITY_REGISTRY_PATH);
+        let key_path = dir.path().join("1_privkeys.json");
+        let other_key_path = dir.path().join("other_privkeys.json");
+
+        let me = EntityPrivComponent::new(1, Role::User);
+        KeyStore::new(me.clone())
+            .save_to_files(&registry_path, &key_path)
+            .unwrap();
+        verify(registry_path.clone(), key_path.clone(), None).unwrap();
+
+        // same id, different keys
+        EntityPrivComponent::new(1, Role::User)
+            .save_to_file(&other_key_path)
+            .unwrap();
+        let err = verify(registry_path.clone(), other_key_path, None).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<KeyStoreLoadError>(),
+            Some(KeyStoreLoadError::ConsistencyError(_))
+        ));
+
+        // locked keys need the password
+        change_password(key_path.clone(), None, Some("pass".to_owned())).unwrap();
+        assert!(matches!(
+            verify(registry_path.clone(), key_path.clone(), None)
+                .unwrap_err()
+                .downcast_ref::<KeyStoreLoadError>(),
+            Some(KeyStoreLoadError::Locked)
+        ));
+        assert!(matches!(
+            verify(
+                registry_path.clone(),
+                key_path.clone(),
+                Some("wrong".to_owned())
+            )
+            .unwrap_err()
+            .downcast_ref::<KeyStoreLoadError>(),
+            Some(KeyStoreLoadError::UnlockError(_))
+        ));
+        verify(registry_path, key_path, Some("pass".to_owned())).unwrap();
+    }
+
+    #[test]
+    fn duplicate_ids() {
+        validate_gen_keys_options(&[1, 2], &[100, 101], &[200]).unwrap();
+
+        let cases: &[(Vec<EntityId>, Vec<EntityId>, Vec<EntityId>, EntityId)] = &[
+            (vec![1, 5, 5], vec![100], vec![200], 5),
+            (vec![1], vec![100, 101, 100], vec![200], 100),
+            (vec![1], vec![100], vec![200, 200], 200),
+        ];
+        for (users, servers, ha_clients, dup) in cases {
+            let err = validate_gen_keys_options(users, servers, ha_clients).unwrap_err();
+            assert!(err.to_string().contains(&format!("Entity {} ", dup)));
+        }
+
+        // across roles is still a different error
+        let err = validate_gen_keys_options(&[1], &[1], &[]).unwrap_err();
+        assert!(err.to_string().contains("a user and a server"));
+    }
+}
@@ -0,0 +1,176 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use sodiumoxide::base64;
+use thiserror::Error;
+
+use crate::keys::{EntityId, KeyStore, KeyStoreError, Signature};
+
+/// Metadata key under which an [AuthToken] is sent along with requests.
+pub const AUTH_METADATA_KEY: &str = "x-hdlt-auth";
+
+/// Separates what is signed in an [AuthToken] from anything else signed with the same keys.
+const DOMAIN_TAG: &[u8] = b"hdlt-auth-v1";
+
+/// Signed claim of who is sending a request, checked at the transport layer.
+///
+/// Lets servers turn away requests from unknown entities before deciphering them.
+/// It does not replace the authentication of [RrMessage](super::RrMessage)s: tokens are only
+/// signed, not bound to a request, so they can be reused for as long as they are fresh.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuthToken {
+    entity_id: EntityId,
+
+    /// Seconds since the unix epoch
+    issued_at: u64,
+
+    signature: Signature,
+}
+
+/// A validation error of an [AuthToken].
+#[derive(Error, Debug)]
+pub enum AuthTokenError {
+    #[error("Malformed authentication token")]
+    Malformed,
+
+    #[error("Authentication token is not fresh (issued at {}, now is {})", .issued_at, .now)]
+    NotFresh { issued_at: u64, now: u64 },
+
+    #[error("Invalid authentication token signature")]
+    InvalidSignature(#[source] KeyStoreError),
+}
+
+impl AuthToken {
+    /// Create a token for the owner of the keystore, issued now.
+    pub fn new(keystore: &KeyStore) -> Result<Self, KeyStoreError> {
+        let entity_id = keystore.my_id();
+        let issued_at = unix_now();
+        let signature = keystore.try_sign(&signed_bytes(entity_id, issued_at))?;
+
+        Ok(AuthToken {
+            entity_id,
+            issued_at,
+            signature,
+        })
+    }
+
+    /// Entity the token claims to be from (only trustworthy after [verify](Self::verify)).
+    pub fn entity_id(&self) -> EntityId {
+        self.entity_id
+    }
+
+    /// Check the signature, and that the token was issued no more than `max_age` from now.
+    ///
+    /// Tokens issued up to `max_age` in the future are accepted too, to tolerate clock drift.
+    pub fn verify(&self, keystore: &KeyStore, max_age: Duration) -> Result<(), AuthTokenError> {
+        let now = unix_now();
+        if now.saturating_sub(self.issued_at) > max_age.as_secs()
+            || self.issued_at.saturating_sub(now) > max_age.as_secs()
+        {
+            return Err(AuthTokenError::NotFresh {
+                issued_at: self.issued_at,
+                now,
+            });
+        }
+
+        keystore
+            .verify_signature(
+                self.entity_id,
+                &signed_bytes(self.entity_id, self.issued_at),
+                &self.signature,
+            )
+            .map_err(AuthTokenError::InvalidSignature)
+    }
+
+    /// Encode as a metadata value, `<entity id>.<issued at>.<base64 signature>`.
+    pub fn encode(&self) -> String {
+        format!(
+            "{}.{}.{}",
+            self.entity_id,
+            self.issued_at,
+            base64::encode(self.signature.as_ref(), base64::Variant::Original)
+        )
+    }
+
+    /// Decode a metadata value made with [encode](Self::encode).
+    pub fn decode(encoded: &str) -> Result<Self, AuthTokenError> {
+        let mut parts = encoded.splitn(3, '.');
+        let mut next = || parts.next().ok_or(AuthTokenError::Malformed);
+
+        let entity_id = next()?.parse().map_err(|_| AuthTokenError::Malformed)?;
+        let issued_at = next()?.parse().map_err(|_| AuthTokenError::Malformed)?;
+        let signature = base64::decode(next()?, base64::Variant::Original)
+            .ok()
+            .and_then(|bytes| Signature::from_slice(&bytes))
+            .ok_or(AuthTokenError::Malformed)?;
+
+        Ok(AuthToken {
+            entity_id,
+            issued_at,
+            signature,
+        })
+    }
+}
+
+fn signed_bytes(entity_id: EntityId, issued_at: u64) -> Vec<u8> {
+    let mut bytes = DOMAIN_TAG.to_vec();
+    bytes.extend_from_slice(&entity_id.to_le_bytes());
+    bytes.extend_from_slice(&issued_at.to_le_bytes());
+    bytes
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::keys::test_data::KeyStoreTestData;
+    use lazy_static::lazy_static;
+
+    lazy_static! {
+        static ref KEYSTORES: KeyStoreTestData = KeyStoreTestData::new();
+    }
+
+    const MAX_AGE: Duration = Duration::from_secs(60);
+
+    #[test]
+    fn encode_verify() {
+        let token = AuthToken::new(&KEYSTORES.user1).unwrap();
+        let decoded = AuthToken::decode(&token.encode()).unwrap();
+        assert_eq!(decoded, token);
+        assert_eq!(decoded.entity_id(), KEYSTORES.user1.my_id());
+        decoded.verify(&KEYSTORES.server, MAX_AGE).unwrap();
+
+        assert!(matches!(
+            AuthToken::decode("1.2"),
+            Err(AuthTokenError::Malformed)
+        ));
+        assert!(matches!(
+            AuthToken::decode("1.2.notbase64!"),
+            Err(AuthTokenError::Malformed)
+        ));
+    }
+
+    #[test]
+    fn forged() {
+        // someone else's id
+        let mut token = AuthToken::new(&KEYSTORES.user1).unwrap();
+        token.entity_id = KEYSTORES.user2.my_id();
+        assert!(matches!(
+            token.verify(&KEYSTORES.server, MAX_AGE),
+            Err(AuthTokenError::InvalidSignature(_))
+        ));
+
+        // stale
+        let mut token = AuthToken::new(&KEYSTORES.user1).unwrap();
+        token.issued_at -= 2 * MAX_AGE.as_secs();
+        assert!(matches!(
+            token.verify(&KEYSTORES.server, MAX_AGE),
+            Err(AuthTokenError::NotFresh { .. })
+        ));
+    }
+}
@@ -0,0 +1,77 @@
+use serde::{Deserialize, Serialize};
+
+/// Version of the request/reply protocol spoken by this build.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Optional features an entity supports, exchanged with [ApiRequest::Hello](super::ApiRequest::Hello).
+///
+/// A feature is only used with a peer when both advertise it, see [negotiate](Self::negotiate).
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capabilities {
+    /// Highest protocol version supported
+    pub protocol_version: u32,
+
+    /// Whether request and reply payloads may be compressed
+    pub compression: bool,
+
+    /// Whether replies may be streamed instead of sent whole
+    pub streaming: bool,
+}
+
+impl Default for Capabilities {
+    /// The current protocol version, without any optional feature.
+    fn default() -> Self {
+        Capabilities {
+            protocol_version: PROTOCOL_VERSION,
+            compression: false,
+            streaming: false,
+        }
+    }
+}
+
+impl Capabilities {
+    /// What can be used with a peer that advertised `theirs`: only the features both support,
+    /// and the highest protocol version both speak.
+    pub fn negotiate(&self, theirs: &Capabilities) -> Capabilities {
+        Capabilities {
+            protocol_version: self.protocol_version.min(theirs.protocol_version),
+            compression: self.compression && theirs.compression,
+            streaming: self.streaming && theirs.streaming,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn negotiate() {
+        let plain = Capabilities::default();
+        let compressing = Capabilities {
+            compression: true,
+            ..Capabilities::default()
+        };
+        let old = Capabilities {
+            protocol_version: 0,
+            compression: true,
+            streaming: true,
+        };
+
+        assert!(compressing.negotiate(&compressing).compression);
+        assert_eq!(compressing.negotiate(&plain), plain);
+        assert_eq!(plain.negotiate(&compressing), plain);
+
+        // same in both directions
+        let agreed = compressing.negotiate(&old);
+        assert_eq!(agreed, old.negotiate(&compressing));
+        assert_eq!(
+            agreed,
+            Capabilities {
+                protocol_version: 0,
+                compression: true,
+                streaming: false,
+            }
+        );
+    }
+}
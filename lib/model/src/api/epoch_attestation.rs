@@ -0,0 +1,132 @@
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::base64_serialization::Base64SerializationExt;
+use crate::keys::{EntityId, KeyStore, KeyStoreError, Role, Signature};
+
+/// Separates what is signed in an [EpochAttestation] from anything else signed with the same keys.
+const DOMAIN_TAG: &[u8] = b"hdlt-epoch-v1";
+
+/// A server's signed claim of the epoch it is in.
+///
+/// Unlike the epoch of a [RrMessage](super::RrMessage), which only its recipient can check,
+/// an attestation can be checked by anyone that knows the server, so it can be passed along.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct EpochAttestation {
+    server_id: EntityId,
+    epoch: u64,
+
+    #[serde(with = "Base64SerializationExt")]
+    signature: Signature,
+}
+
+#[derive(Error, Debug)]
+pub enum EpochAttestationError {
+    #[error("Attestation signer {} does not exist or isn't a server", .0)]
+    NotAServer(EntityId),
+
+    #[error("Invalid Signature")]
+    BadSignature(#[from] KeyStoreError),
+}
+
+impl EpochAttestation {
+    /// Attest to an epoch as the keystore owner, which must be a server.
+    pub fn new(keystore: &KeyStore, epoch: u64) -> Result<Self, EpochAttestationError> {
+        let server_id = keystore.my_id();
+        if keystore.my_role() != Role::Server {
+            return Err(EpochAttestationError::NotAServer(server_id));
+        }
+
+        let signature = keystore.try_sign(&signed_bytes(server_id, epoch))?;
+        Ok(EpochAttestation {
+            server_id,
+            epoch,
+            signature,
+        })
+    }
+
+    /// Check the attestation was signed by the server it names, returning the attested epoch.
+    pub fn verify(&self, keystore: &KeyStore) -> Result<u64, EpochAttestationError> {
+        if keystore.role_of(self.server_id) != Some(Role::Server) {
+            return Err(EpochAttestationError::NotAServer(self.server_id));
+        }
+
+        keystore.verify_signature(
+            self.server_id,
+            &signed_bytes(self.server_id, self.epoch),
+            &self.signature,
+        )?;
+        Ok(self.epoch)
+    }
+
+    /// Server the attestation claims to be from (only trustworthy after [verify](Self::verify)).
+    pub fn server_id(&self) -> EntityId {
+        self.server_id
+    }
+
+    /// Attested epoch (only trustworthy after [verify](Self::verify)).
+    pub fn epoch(&self) -> u64 {
+        self.epoch
+    }
+}
+
+fn signed_bytes(server_id: EntityId, epoch: u64) -> Vec<u8> {
+    let mut bytes = DOMAIN_TAG.to_vec();
+    bytes.extend_from_slice(&server_id.to_le_bytes());
+    bytes.extend_from_slice(&epoch.to_le_bytes());
+    bytes
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::keys::test_data::KeyStoreTestData;
+    use lazy_static::lazy_static;
+
+    lazy_static! {
+        static ref KEYSTORES: KeyStoreTestData = KeyStoreTestData::new();
+    }
+
+    #[test]
+    fn sign_verify() {
+        let attestation = EpochAttestation::new(&KEYSTORES.server, 42).unwrap();
+        assert_eq!(attestation.server_id(), KEYSTORES.server.my_id());
+        for keystore in KEYSTORES.iter() {
+            assert_eq!(attestation.verify(keystore).unwrap(), 42);
+        }
+
+        // survives the trip through a reply
+        let serialized = bincode::serialize(&attestation).unwrap();
+        let deserialized: EpochAttestation = bincode::deserialize(&serialized).unwrap();
+        assert_eq!(deserialized.verify(&KEYSTORES.user1).unwrap(), 42);
+
+        // only servers attest epochs
+        assert!(matches!(
+            EpochAttestation::new(&KEYSTORES.user1, 42),
+            Err(EpochAttestationError::NotAServer(_))
+        ));
+    }
+
+    #[test]
+    fn tampered() {
+        let attestation = EpochAttestation::new(&KEYSTORES.server, 42).unwrap();
+
+        let mut tampered = attestation.clone();
+        tampered.epoch = 43;
+        assert!(matches!(
+            tampered.verify(&KEYSTORES.user1),
+            Err(EpochAttestationError::BadSignature(_))
+        ));
+
+        // signed by someone that isn't a server
+        let mut tampered = attestation;
+        tampered.server_id = KEYSTORES.user2.my_id();
+        tampered.signature = KEYSTORES
+            .user2
+            .sign(&signed_bytes(tampered.server_id, tampered.epoch));
+        assert!(matches!(
+            tampered.verify(&KEYSTORES.user1),
+            Err(EpochAttestationError::NotAServer(_))
+        ));
+    }
+}
@@ -0,0 +1,93 @@
+use thiserror::Error;
+
+use super::{ApiErrorCode, ApiReply, RrMessageError};
+use crate::keys::KeyStoreError;
+
+/// Transport-agnostic error of an entity invoking the HDLT Server API.
+///
+/// Shared by every API client, so that the same failure (and the same reply)
+/// is reported the same way regardless of who sent the request.
+#[derive(Debug, Error)]
+pub enum ApiClientError {
+    #[error("Failed to serialize request")]
+    SerializationError(#[source] Box<bincode::ErrorKind>),
+
+    #[error("Failed to cipher request")]
+    CipherError(#[source] KeyStoreError),
+
+    #[error("Failed to sign authentication token")]
+    AuthTokenError(#[source] KeyStoreError),
+
+    #[error("Invalid nonce")]
+    InvalidNonce,
+
+    #[error("Failed to decipher reply")]
+    DecipherError(#[source] KeyStoreError),
+
+    #[error("Failed to deserialize reply")]
+    DeserializationError(#[source] Box<bincode::ErrorKind>),
+
+    #[error("Request reply protocol violation")]
+    RequestReplyProtocolViolation(#[from] RrMessageError),
+
+    #[error("Error executing request: {}", .1)]
+    ServerError(ApiErrorCode, String),
+
+    #[error("Server sent unexpected reply message: {:#?}", .0)]
+    UnexpectedReply(ApiReply),
+}
+
+pub type ApiClientResult<T> = Result<T, ApiClientError>;
+
+/// Maps a reply that was not the expected one to an error.
+/// Error replies become [ApiClientError::ServerError], anything else is unexpected.
+impl From<ApiReply> for ApiClientError {
+    fn from(reply: ApiReply) -> Self {
+        match reply {
+            ApiReply::Error(code, message) => ApiClientError::ServerError(code, message),
+            other => ApiClientError::UnexpectedReply(other),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn from_reply() {
+        assert!(matches!(
+            ApiClientError::from(ApiReply::Error(ApiErrorCode::StaleProof, "stale".into())),
+            ApiClientError::ServerError(ApiErrorCode::StaleProof, m) if m == "stale"
+        ));
+        assert!(matches!(
+            ApiClientError::from(ApiReply::Ok),
+            ApiClientError::UnexpectedReply(ApiReply::Ok)
+        ));
+    }
+
+    #[test]
+    fn from_rr_message_error() {
+        assert!(matches!(
+            ApiClientError::from(RrMessageError::StaleMessage),
+            ApiClientError::RequestReplyProtocolViolation(RrMessageError::StaleMessage)
+        ));
+    }
+
+    #[test]
+    fn messages() {
+        assert_eq!(
+            ApiClientError::from(RrMessageError::StaleMessage).to_string(),
+            "Request reply protocol violation"
+        );
+        assert_eq!(
+            ApiClientError::DecipherError(KeyStoreError::EntityNotFound(1)).to_string(),
+            "Failed to decipher reply"
+        );
+        assert_eq!(
+            ApiClientError::from(ApiReply::Error(ApiErrorCode::StaleProof, "stale".into()))
+                .to_string(),
+            "Error executing request: stale"
+        );
+    }
+}
@@ -6,6 +6,21 @@ pub use rr_message::*;
 mod pow;
 pub use pow::*;
 
+mod error;
+pub use error::*;
+
+mod auth;
+pub use auth::*;
+
+mod epoch_attestation;
+pub use epoch_attestation::*;
+
+mod read_certificate;
+pub use read_certificate::*;
+
+mod capabilities;
+pub use capabilities::*;
+
 use crate::{keys::EntityId, Position, UnverifiedMisbehaviorProof, UnverifiedPositionProof};
 
 /// An HDLT Server API request payload.
@@ -26,22 +41,88 @@ pub enum ApiRequest {
     /// Regular users may only query their own position. HA clients may query
     /// any user's position.
     ///
-    /// Successful reply: [ApiReply::PositionReport]
+    /// The value is pushed to `callback_uri` with [ApiRequest::ReturnAtomicValue] when ready.
+    /// Without a `callback_uri` it must be pulled instead, with [ApiRequest::PollReturn].
+    ///
+    /// Servers that don't make callbacks answer it like [ApiRequest::ObtainPositionReportRegular].
+    /// Requestors that may only see coarse positions are answered right away too, with a
+    /// position rounded by the server instead of a proof.
+    ///
+    /// Successful reply: [ApiReply::Ok], or [ApiReply::PositionReports] from servers without callbacks,
+    /// or [ApiReply::PositionReport] for requestors that only see coarse positions
     /// Error reply: [ApiReply::Error]
     ObtainPositionReport {
         request_id: u64,
         user_id: EntityId,
         epoch: u64,
-        callback_uri: String,
+        callback_uri: Option<String>,
+    },
+
+    /// Query the value of an [ApiRequest::ObtainPositionReport] registered without a callback uri.
+    ///
+    /// Only the entity that registered the read can poll it. A value is only returned once.
+    ///
+    /// Successful reply: [ApiReply::ReturnedValue]
+    /// Error reply: [ApiReply::Error]
+    PollReturn { request_id: u64 },
+
+    /// Query the position of a given user at a given epoch, with regular semantics.
+    ///
+    /// Lightweight alternative to [ApiRequest::ObtainPositionReport]: the server replies
+    /// directly, without registering callbacks.
+    /// Regular users may only query their own position. HA clients may query
+    /// any user's position.
+    ///
+    /// Users reading their own reports may set `last_write` to the epoch of their latest
+    /// acknowledged submission: the server then waits (for a bounded time) until it has a report
+    /// at least that recent before answering, so users always read their own writes.
+    ///
+    /// Successful reply: [ApiReply::PositionReports] (with a single report),
+    /// or [ApiReply::PositionReport] for requestors that only see coarse positions
+    /// Error reply: [ApiReply::Error]
+    ObtainPositionReportRegular {
+        user_id: EntityId,
+        epoch: u64,
+        last_write: Option<u64>,
     },
 
+    /// Query the position of a given user at several epochs, with regular semantics.
+    ///
+    /// Like an [ApiRequest::ObtainPositionReportRegular] for each epoch, in a single request.
+    /// Epochs without a report (or without enough witnesses for one) are left out of the reply.
+    /// Requestors that may only see coarse positions can't use it.
+    ///
+    /// Successful reply: [ApiReply::PositionReports]
+    /// Error reply: [ApiReply::Error]
+    ObtainPositionReportsBulk { user_id: EntityId, epochs: Vec<u64> },
+
+    /// Query the position of a given user at a given epoch, with regular semantics, and have
+    /// the reply signed so it can go in a [ReadCertificate].
+    ///
+    /// Requestors that may only see coarse positions can't use it.
+    ///
+    /// Successful reply: [ApiReply::SignedReadReply]
+    /// Error reply: [ApiReply::Error]
+    ObtainCertifiedPositionReport { user_id: EntityId, epoch: u64 },
+
     /// Get all position reports from a user in a given epoch range.
     ///
     /// Regular users may only query their own position. HA clients may query
     /// any user's position.
     ///
-    /// Successful reply: [ApiReply::PositionReport]
-    RequestPositionReports { epoch_start: u64, epoch_end: u64 },
+    /// Epochs without a report (or without enough witnesses for one) are left out of the reply.
+    /// With `include_diagnostics`, the reply also says which ones were and why, in epoch order.
+    /// Epochs too far ahead of the server's current one for anyone to have submitted a report
+    /// are ignored.
+    ///
+    /// Successful reply: [ApiReply::PositionReports],
+    /// or [ApiReply::PositionReportsWithDiagnostics] with `include_diagnostics`
+    RequestPositionReports {
+        user_id: EntityId,
+        epoch_start: u64,
+        epoch_end: u64,
+        include_diagnostics: bool,
+    },
 
     /// Query the users present in a given position at a given epoch.
     ///
@@ -50,6 +131,70 @@ pub enum ApiRequest {
     /// Successful reply: [ApiReply::PositionReport]
     ObtainUsersAtPosition { position: Position, epoch: u64 },
 
+    /// Query whether a given user was in a given position at a given epoch.
+    ///
+    /// Unlike [ApiRequest::ObtainUsersAtPosition], nothing is revealed about other users.
+    /// Only HA clients can request this.
+    ///
+    /// Successful reply: [ApiReply::Bool]
+    /// Error reply: [ApiReply::Error]
+    IsUserAtPosition {
+        user_id: EntityId,
+        position: Position,
+        epoch: u64,
+    },
+
+    /// Query every proof of misbehaviour of a given user, one per epoch they misbehaved in.
+    ///
+    /// Only HA clients can request this.
+    ///
+    /// Successful reply: [ApiReply::MisbehaviourProofs],
+    /// or [ApiReply::CoarseMisbehaviour] for requestors that only see coarse positions
+    /// Error reply: [ApiReply::Error]
+    ObtainMisbehaviourProofs { user_id: EntityId },
+
+    /// Query how many users were in each position at a given epoch.
+    ///
+    /// Only HA clients can request this.
+    ///
+    /// Successful reply: [ApiReply::PositionHistogram]
+    /// Error reply: [ApiReply::Error]
+    ObtainPositionHistogram { epoch: u64 },
+
+    /// Query the position of every user at a given epoch.
+    ///
+    /// Only users with enough witnesses for a position proof, and not known to have
+    /// misbehaved in that epoch, are included.
+    /// Only HA clients can request this.
+    ///
+    /// Successful reply: [ApiReply::EpochPositions]
+    /// Error reply: [ApiReply::Error]
+    ObtainEpochPositions { epoch: u64 },
+
+    /// Query the epochs a given user has position reports for.
+    ///
+    /// Regular users may only query their own epochs. HA clients may query any user's.
+    ///
+    /// Successful reply: [ApiReply::Epochs]
+    /// Error reply: [ApiReply::Error]
+    ObtainProverEpochs { user_id: EntityId },
+
+    /// Erase every position report of the requestor.
+    ///
+    /// Only users can request this, and only for themselves. Reports submitted afterwards are refused.
+    ///
+    /// Successful reply: [ApiReply::Deleted]
+    /// Error reply: [ApiReply::Error]
+    ForgetUser,
+
+    /// Report a user seen misbehaving, with the proof of it.
+    ///
+    /// Only users can request this. The proof is verified before being stored.
+    ///
+    /// Successful reply: [ApiReply::Ok]
+    /// Error reply: [ApiReply::Error]
+    ReportMisbehaviour(UnverifiedMisbehaviorProof),
+
     /// Server adding a new value to answer map
     ///
     AddValue {
@@ -61,15 +206,33 @@ pub enum ApiRequest {
 
     /// Server returning Read
     ///
+    /// `correlation_id` is the one of the request that registered the callback,
+    /// see [RrRequest::correlation_id].
     ReturnAtomicValue {
         request_id: u64,
+        correlation_id: u64,
         proof: UnverifiedPositionProof,
         epoch: u64,
         client_id: EntityId,
     },
 
-    /// Notify servers of Byzantine Users
+    /// Older name of [ApiRequest::ReportMisbehaviour], handled the same way.
     SubmitMisbehaviourProof(UnverifiedMisbehaviorProof),
+
+    /// Query the epoch the server is currently in.
+    ///
+    /// Can be used by any entity, and answered even if sent from a stale epoch.
+    ///
+    /// Successful reply: [ApiReply::Epoch]
+    GetEpoch,
+
+    /// Advertise the requestor's [Capabilities], and query the server's.
+    ///
+    /// Can be used by any entity. Optional features are only used once both ends
+    /// advertised them (see [Capabilities::negotiate]).
+    ///
+    /// Successful reply: [ApiReply::Capabilities]
+    Hello(Capabilities),
 }
 
 /// An HDLT Server API reply payload.
@@ -78,43 +241,162 @@ pub enum ApiRequest {
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub enum ApiReply {
     /// Generic successful indication.
-    /// The successful reply for [ApiRequest::SubmitPositionReport].
+    /// The successful reply for [ApiRequest::SubmitPositionReport] and
+    /// [ApiRequest::ReportMisbehaviour].
     Ok,
 
     /// Position of a given user at a given epoch.
-    /// The successful reply for [ApiRequest::ObtainPositionReport].
+    /// The successful reply for [ApiRequest::ObtainPositionReport], and the one servers give
+    /// requestors that only see coarse positions.
     ///
     /// @bsd: Shouldn't this return the PositionProof (you know, as the name indicates??) (TODO)
     PositionReport(u64, Position),
 
     /// Position of a given user at a series of epochs.
-    /// The successful reply for [ApiRequest::RequestPositionReports].
+    /// The successful reply for [ApiRequest::RequestPositionReports],
+    /// [ApiRequest::ObtainPositionReportRegular] and [ApiRequest::ObtainPositionReportsBulk].
     PositionReports(Vec<(u64, UnverifiedPositionProof)>),
 
+    /// Position of a given user at a series of epochs, and the reason each epoch in the
+    /// range without a report was left out.
+    /// The successful reply for [ApiRequest::RequestPositionReports] with `include_diagnostics`.
+    PositionReportsWithDiagnostics(
+        Vec<(u64, UnverifiedPositionProof)>,
+        Vec<(u64, ApiErrorCode)>,
+    ),
+
+    /// Position report of a given user at a given epoch, signed by the server.
+    /// The successful reply for [ApiRequest::ObtainCertifiedPositionReport].
+    SignedReadReply(SignedReadReply),
+
     /// Users in the given position at the given epoch.
     /// The successful reply for [ApiRequest::ObtainUsersAtPosition].
     UsersAtPosition(Vec<EntityId>),
 
-    /// Generic server error message. Can be a reply to any request.
-    Error(String),
+    /// Answer to a yes or no question.
+    /// The successful reply for [ApiRequest::IsUserAtPosition].
+    Bool(bool),
+
+    /// Proofs of misbehaviour of a given user, oldest first.
+    /// The successful reply for [ApiRequest::ObtainMisbehaviourProofs].
+    MisbehaviourProofs(Vec<UnverifiedMisbehaviorProof>),
+
+    /// Epochs a given user misbehaved in, with the two positions they claimed to be in
+    /// rounded to a grid, oldest first.
+    /// The reply servers give requestors that only see coarse positions for
+    /// [ApiRequest::ObtainMisbehaviourProofs].
+    CoarseMisbehaviour(Vec<(u64, Position, Position)>),
+
+    /// Value of an atomic read, if it's ready.
+    /// The successful reply for [ApiRequest::PollReturn].
+    ReturnedValue(Option<(u64, UnverifiedPositionProof)>),
+
+    /// Number of users in each occupied position at the given epoch.
+    /// The successful reply for [ApiRequest::ObtainPositionHistogram].
+    PositionHistogram(Vec<(Position, usize)>),
+
+    /// Position of each user at the given epoch, ordered by user.
+    /// The successful reply for [ApiRequest::ObtainEpochPositions].
+    EpochPositions(Vec<(EntityId, Position)>),
+
+    /// Epochs a given user has position reports for, oldest first.
+    /// The successful reply for [ApiRequest::ObtainProverEpochs].
+    Epochs(Vec<u64>),
+
+    /// Number of position reports erased.
+    /// The successful reply for [ApiRequest::ForgetUser].
+    Deleted(u64),
+
+    /// Epoch the server is currently in, signed by the server.
+    /// The successful reply for [ApiRequest::GetEpoch].
+    Epoch(EpochAttestation),
+
+    /// Features the server supports.
+    /// The successful reply for [ApiRequest::Hello].
+    Capabilities(Capabilities),
+
+    /// Server error, with a machine-readable code and a human-readable message.
+    /// Can be a reply to any request.
+    Error(ApiErrorCode, String),
 
     /// Special error: the requestor is faulty and is denied service
     YouAreNoGood(UnverifiedMisbehaviorProof),
 }
 
+/// Machine-readable reason for an [ApiReply::Error].
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ApiErrorCode {
+    /// An equally or more recent position proof was already submitted by the user.
+    StaleProof,
+
+    /// The user is known to have been in two places at the same time.
+    InconsistentUser,
+
+    /// The server could not access its storage.
+    StorageFailure,
+
+    /// The server already answered a request with the same challenge in this epoch.
+    ReplayedRequest,
+
+    /// The server has no report at all for the requested user and epoch.
+    NoData,
+
+    /// The server has a report for the requested user and epoch, but without enough witnesses.
+    InsufficientQuorum { required: usize, available: usize },
+
+    /// The server is handling too many requests and refused this one. It may be retried later.
+    ResourceExhausted,
+
+    /// Any other error, described only by the message.
+    Other,
+}
+
 impl ApiReply {
     pub fn key(&self) -> u64 {
         match self {
             // Timestamp == epoch
             ApiReply::PositionReport(epoch, _) => *epoch,
 
+            // Timestamp == epoch
+            ApiReply::ReturnedValue(v) => v.as_ref().map(|(e, _)| *e).unwrap_or(0),
+
             // Timestamp == epoch
             ApiReply::PositionReports(v) => *v.iter().map(|(e, _)| e).max().unwrap_or(&0u64),
 
+            // Timestamp == epoch
+            ApiReply::PositionReportsWithDiagnostics(v, _) => {
+                *v.iter().map(|(e, _)| e).max().unwrap_or(&0u64)
+            }
+
             // Not a timestamp per se, but this request give a particular epoch either way
             // This however returns the longest list === most recent response
             ApiReply::UsersAtPosition(v) => v.len() as u64,
 
+            // Not ordered: a single server claiming the user was there can't be trusted,
+            // enough of them must agree instead
+            ApiReply::Bool(_) => 0,
+
+            ApiReply::Epoch(attestation) => attestation.epoch(),
+
+            // Timestamp == epoch
+            ApiReply::SignedReadReply(reply) => reply.epoch(),
+
+            // More proofs === more recent response
+            ApiReply::MisbehaviourProofs(v) => v.len() as u64,
+            ApiReply::CoarseMisbehaviour(v) => v.len() as u64,
+
+            // More users === more recent response
+            ApiReply::PositionHistogram(v) => v.iter().map(|(_, count)| *count as u64).sum(),
+
+            // More users === more recent response
+            ApiReply::EpochPositions(v) => v.len() as u64,
+
+            // More epochs === more recent response
+            ApiReply::Epochs(v) => v.len() as u64,
+
+            // More reports erased === more complete response
+            ApiReply::Deleted(count) => *count,
+
             _ => 0,
         }
     }
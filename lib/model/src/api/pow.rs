@@ -48,6 +48,13 @@ where
             Err(self)
         }
     }
+
+    /// Inner object, without validating the proof-of-work.
+    ///
+    /// Only for sanity checks cheaper than validating it.
+    pub fn inner_unchecked(&self) -> &T {
+        &self.inner
+    }
 }
 
 fn inner_bytes<T: Serialize>(inner: &T) -> Vec<u8> {
@@ -0,0 +1,389 @@
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::base64_serialization::Base64SerializationExt;
+use crate::keys::{EntityId, KeyStore, KeyStoreError, Role, Signature};
+use crate::{PositionProof, PositionProofValidationError, UnverifiedPositionProof};
+
+/// Separates what is signed in a [SignedReadReply] from anything else signed with the same keys.
+const DOMAIN_TAG: &[u8] = b"hdlt-read-v1";
+
+/// A server's signed statement that, while in `server_epoch`, it answered a read of
+/// `user_id`'s position at `epoch` with `proof`.
+///
+/// The server epoch is signed along with the rest, so it is attested like an
+/// [EpochAttestation](super::EpochAttestation).
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct SignedReadReply {
+    server_id: EntityId,
+    server_epoch: u64,
+    user_id: EntityId,
+    epoch: u64,
+    proof: UnverifiedPositionProof,
+
+    #[serde(with = "Base64SerializationExt")]
+    signature: Signature,
+}
+
+/// Replies of a quorum of servers that agreed on the result of a read.
+///
+/// Lets anyone that knows the servers check the agreement, see [verify](Self::verify).
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ReadCertificate {
+    replies: Vec<SignedReadReply>,
+}
+
+#[derive(Error, Debug)]
+pub enum ReadCertificateError {
+    #[error("Reply signer {} does not exist or isn't a server", .0)]
+    NotAServer(EntityId),
+
+    #[error("Invalid Signature")]
+    BadSignature(#[from] KeyStoreError),
+
+    #[error("Server {} replied more than once", .0)]
+    DuplicateServer(EntityId),
+
+    #[error("Replies are not all about the same read result")]
+    Mismatched,
+
+    #[error("Not enough replies for a quorum (needs {}, has {})", .required, .available)]
+    NotEnoughReplies { required: usize, available: usize },
+
+    #[error("Invalid Position Proof")]
+    InvalidProof(#[from] PositionProofValidationError),
+
+    #[error("Position proof is not for the user and epoch that were read")]
+    UnrelatedProof,
+}
+
+impl SignedReadReply {
+    /// Sign the result of a read as the keystore owner, which must be a server.
+    pub fn new(
+        keystore: &KeyStore,
+        server_epoch: u64,
+        user_id: EntityId,
+        epoch: u64,
+        proof: UnverifiedPositionProof,
+    ) -> Result<Self, ReadCertificateError> {
+        let server_id = keystore.my_id();
+        if keystore.my_role() != Role::Server {
+            return Err(ReadCertificateError::NotAServer(server_id));
+        }
+
+        let bytes = signed_bytes(server_id, server_epoch, user_id, epoch, &proof);
+        let signature = keystore.try_sign(&bytes)?;
+        Ok(SignedReadReply {
+            server_id,
+            server_epoch,
+            user_id,
+            epoch,
+            proof,
+            signature,
+        })
+    }
+
+    /// Check the reply was signed by the server it names.
+    pub fn verify(&self, keystore: &KeyStore) -> Result<(), ReadCertificateError> {
+        if keystore.role_of(self.server_id) != Some(Role::Server) {
+            return Err(ReadCertificateError::NotAServer(self.server_id));
+        }
+
+        let bytes = signed_bytes(
+            self.server_id,
+            self.server_epoch,
+            self.user_id,
+            self.epoch,
+            &self.proof,
+        );
+        keystore.verify_signature(self.server_id, &bytes, &self.signature)?;
+        Ok(())
+    }
+
+    /// Server that signed the reply (only trustworthy after [verify](Self::verify)).
+    pub fn server_id(&self) -> EntityId {
+        self.server_id
+    }
+
+    /// Epoch the server was in when it replied (only trustworthy after [verify](Self::verify)).
+    pub fn server_epoch(&self) -> u64 {
+        self.server_epoch
+    }
+
+    /// User whose position was read.
+    pub fn user_id(&self) -> EntityId {
+        self.user_id
+    }
+
+    /// Epoch of the position that was read.
+    pub fn epoch(&self) -> u64 {
+        self.epoch
+    }
+
+    /// Proof of the position that was read (unverified).
+    pub fn proof(&self) -> &UnverifiedPositionProof {
+        &self.proof
+    }
+}
+
+impl ReadCertificate {
+    /// Gather replies into a certificate, without checking them.
+    pub fn new(replies: Vec<SignedReadReply>) -> Self {
+        ReadCertificate { replies }
+    }
+
+    /// The replies that make up the certificate.
+    pub fn replies(&self) -> &[SignedReadReply] {
+        &self.replies
+    }
+
+    /// Check that at least `quorum` distinct servers signed replies with the same valid proof,
+    /// returning it.
+    ///
+    /// Replies that are invalid, repeated or that disagree with the others don't invalidate the
+    /// certificate, as long as the largest subset that agrees is enough (see [certify](Self::certify)).
+    pub fn verify(
+        &self,
+        keystore: &KeyStore,
+        quorum: usize,
+        neighbour_faults: usize,
+    ) -> Result<PositionProof, ReadCertificateError> {
+        ReadCertificate::certify(self.replies.clone(), keystore, quorum, neighbour_faults)
+            .map(|(proof, _)| proof)
+    }
+
+    /// Certify the largest subset of valid replies, from distinct servers, that agree on the
+    /// result of the read, returning its proof and a certificate with only those replies.
+    ///
+    /// Fails if that subset has fewer than `quorum` replies, with the first problem found in
+    /// the other replies (if any) or [ReadCertificateError::Mismatched] if they just disagree.
+    pub fn certify(
+        replies: Vec<SignedReadReply>,
+        keystore: &KeyStore,
+        quorum: usize,
+        neighbour_faults: usize,
+    ) -> Result<(PositionProof, ReadCertificate), ReadCertificateError> {
+        let mut first_error = None;
+        let mut servers = HashSet::new();
+        let mut groups: Vec<Vec<SignedReadReply>> = Vec::new();
+        for reply in replies {
+            if let Err(e) = reply.verify(keystore) {
+                first_error.get_or_insert(e);
+                continue;
+            }
+            if !servers.insert(reply.server_id) {
+                first_error.get_or_insert(ReadCertificateError::DuplicateServer(reply.server_id));
+                continue;
+            }
+
+            let result = (reply.user_id, reply.epoch, &reply.proof);
+            match groups
+                .iter_mut()
+                .find(|group| (group[0].user_id, group[0].epoch, &group[0].proof) == result)
+            {
+                Some(group) => group.push(reply),
+                None => groups.push(vec![reply]),
+            }
+        }
+
+        let mismatched = groups.len() > 1;
+        // the first of the largest ones, so the result doesn't depend on anything else
+        let agreeing = groups
+            .into_iter()
+            .rev()
+            .max_by_key(Vec::len)
+            .unwrap_or_default();
+        if agreeing.len() < quorum || agreeing.is_empty() {
+            return Err(match first_error {
+                Some(e) => e,
+                None if mismatched => ReadCertificateError::Mismatched,
+                None => ReadCertificateError::NotEnoughReplies {
+                    required: quorum,
+                    available: agreeing.len(),
+                },
+            });
+        }
+
+        let first = &agreeing[0];
+        let proof = first.proof.clone().verify(neighbour_faults, keystore)?;
+        if proof.prover_id() != first.user_id || proof.epoch() != first.epoch {
+            return Err(ReadCertificateError::UnrelatedProof);
+        }
+
+        Ok((proof, ReadCertificate { replies: agreeing }))
+    }
+}
+
+fn signed_bytes(
+    server_id: EntityId,
+    server_epoch: u64,
+    user_id: EntityId,
+    epoch: u64,
+    proof: &UnverifiedPositionProof,
+) -> Vec<u8> {
+    let mut bytes = DOMAIN_TAG.to_vec();
+    bytes.extend_from_slice(&server_id.to_le_bytes());
+    bytes.extend_from_slice(&server_epoch.to_le_bytes());
+    bytes.extend_from_slice(&user_id.to_le_bytes());
+    bytes.extend_from_slice(&epoch.to_le_bytes());
+    bytes.extend_from_slice(&proof.digest());
+    bytes
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::keys::test_data::KeyStoreSetBuilder;
+    use crate::{Position, ProximityProof, ProximityProofRequest};
+    use lazy_static::lazy_static;
+
+    lazy_static! {
+        /// Users 1 to 3, then servers 100 to 103
+        static ref KEYSTORES: Vec<KeyStore> = KeyStoreSetBuilder::new()
+            .entities(1..=3, Role::User)
+            .entities(100..=103, Role::Server)
+            .build();
+        static ref PROOF: UnverifiedPositionProof = {
+            let request = ProximityProofRequest::new(7, Position(1, 1), &KEYSTORES[0]);
+            let witness = ProximityProof::new(request, Position(1, 2), &KEYSTORES[1]).unwrap();
+            PositionProof::new(vec![witness], 1).unwrap().into()
+        };
+    }
+
+    fn servers() -> &'static [KeyStore] {
+        &KEYSTORES[3..]
+    }
+
+    fn signed_by(servers: &[KeyStore]) -> ReadCertificate {
+        ReadCertificate::new(
+            servers
+                .iter()
+                .map(|server| SignedReadReply::new(server, 9, 1, 7, PROOF.clone()).unwrap())
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn assemble_verify() {
+        let certificate = signed_by(&servers()[..3]);
+
+        // anyone can check it, e.g. a user that wasn't part of the read
+        let checker = &KEYSTORES[2];
+        let proof = certificate.verify(checker, 3, 1).unwrap();
+        assert_eq!(proof.prover_id(), 1);
+        assert_eq!(proof.position(), Position(1, 1));
+
+        for reply in certificate.replies() {
+            reply.verify(checker).unwrap();
+            assert_eq!(reply.server_epoch(), 9);
+        }
+        assert_eq!(
+            certificate
+                .replies()
+                .iter()
+                .map(|r| r.server_id())
+                .collect::<Vec<_>>(),
+            vec![100, 101, 102]
+        );
+
+        // survives being passed along
+        let serialized = bincode::serialize(&certificate).unwrap();
+        let deserialized: ReadCertificate = bincode::deserialize(&serialized).unwrap();
+        deserialized.verify(checker, 3, 1).unwrap();
+
+        // replies past the quorum may disagree, or be invalid: the agreeing ones are certified
+        let mut replies = signed_by(&servers()[..3]).replies;
+        replies.insert(
+            1,
+            SignedReadReply::new(&servers()[3], 9, 1, 8, PROOF.clone()).unwrap(),
+        );
+        let mut forged = replies[0].clone();
+        forged.server_id = 103;
+        replies.push(forged);
+        let (proof, agreeing) = ReadCertificate::certify(replies.clone(), checker, 3, 1).unwrap();
+        assert_eq!(proof.prover_id(), 1);
+        assert_eq!(
+            agreeing
+                .replies()
+                .iter()
+                .map(|r| r.server_id())
+                .collect::<Vec<_>>(),
+            vec![100, 101, 102]
+        );
+        agreeing.verify(checker, 3, 1).unwrap();
+        ReadCertificate::new(replies.clone())
+            .verify(checker, 3, 1)
+            .unwrap();
+        assert!(matches!(
+            ReadCertificate::new(replies).verify(checker, 4, 1),
+            Err(ReadCertificateError::BadSignature(_))
+        ));
+
+        // but one more server is needed for a bigger quorum
+        assert!(matches!(
+            certificate.verify(checker, 4, 1),
+            Err(ReadCertificateError::NotEnoughReplies {
+                required: 4,
+                available: 3
+            })
+        ));
+    }
+
+    #[test]
+    fn tampered() {
+        let checker = &KEYSTORES[2];
+
+        // server epoch changed after signing
+        let mut certificate = signed_by(&servers()[..3]);
+        certificate.replies[1].server_epoch = 10;
+        assert!(matches!(
+            certificate.verify(checker, 3, 1),
+            Err(ReadCertificateError::BadSignature(_))
+        ));
+
+        // the same server counted twice
+        let mut certificate = signed_by(&servers()[..2]);
+        certificate.replies.push(certificate.replies[0].clone());
+        assert!(matches!(
+            certificate.verify(checker, 3, 1),
+            Err(ReadCertificateError::DuplicateServer(100))
+        ));
+
+        // servers that disagree
+        let mut certificate = signed_by(&servers()[..3]);
+        certificate.replies[2] =
+            SignedReadReply::new(&servers()[2], 9, 1, 8, PROOF.clone()).unwrap();
+        assert!(matches!(
+            certificate.verify(checker, 3, 1),
+            Err(ReadCertificateError::Mismatched)
+        ));
+
+        // a proof of another epoch
+        let certificate = ReadCertificate::new(
+            servers()
+                .iter()
+                .map(|server| SignedReadReply::new(server, 9, 1, 8, PROOF.clone()).unwrap())
+                .collect(),
+        );
+        assert!(matches!(
+            certificate.verify(checker, 3, 1),
+            Err(ReadCertificateError::UnrelatedProof)
+        ));
+
+        // a bad reply is only a problem when the others aren't enough
+        let mut certificate = signed_by(&servers()[..3]);
+        certificate.replies[1].server_epoch = 10;
+        assert!(matches!(
+            certificate.verify(checker, 2, 1),
+            Ok(proof) if proof.prover_id() == 1
+        ));
+
+        // only servers sign replies
+        assert!(matches!(
+            SignedReadReply::new(&KEYSTORES[0], 9, 1, 7, PROOF.clone()),
+            Err(ReadCertificateError::NotAServer(1))
+        ));
+    }
+}
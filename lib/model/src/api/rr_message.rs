@@ -172,6 +172,20 @@ macro_rules! msg_impls {
 msg_impls!(RrRequest);
 msg_impls!(RrReply);
 
+impl<Inner> RrRequest<Inner> {
+    /// Identifier for correlating log entries related to this request.
+    ///
+    /// Derived from the (random) request challenge, so it is unique with high probability.
+    pub fn correlation_id(&self) -> u64 {
+        self.challenge
+    }
+
+    /// Epoch the request was sent in.
+    pub fn epoch(&self) -> u64 {
+        self.epoch
+    }
+}
+
 /// Generate a random u64 (cryptographically secure)
 fn rand_u64() -> u64 {
     use sodiumoxide::randombytes::randombytes_into;
@@ -240,6 +254,14 @@ mod test {
         assert!(msg.downcast_reply(&*REQ, 0).is_ok());
     }
 
+    #[test]
+    fn correlation_id() {
+        assert_eq!(REQ.correlation_id(), 0);
+
+        let req = RrMessage::new_request(0, ()).downcast_request(0).unwrap();
+        assert_eq!(req.correlation_id(), req.challenge);
+    }
+
     #[test]
     fn downcast_type() {
         assert!(matches!(
@@ -1,6 +1,6 @@
 use std::convert::TryInto;
 
-use serde::{Deserialize, Deserializer, Serializer};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use sodiumoxide::base64;
 use sodiumoxide::crypto::{
     box_::curve25519xsalsa20poly1305 as box_, pwhash::scryptsalsa208sha256 as pwhash,
@@ -66,6 +66,54 @@ impl<const N: usize> Base64SerializationExt for [u8; N] {
     }
 }
 
+/// Serialize an optional value as a base64 string (or nothing), see [Base64SerializationExt].
+///
+/// For use with `#[serde(serialize_with = "serialize_opt", deserialize_with = "deserialize_opt")]`.
+/// Add `#[serde(default)]` as well to accept a missing field as [None].
+pub fn serialize_opt<T, S>(data: &Option<T>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    T: Base64SerializationExt,
+    S: Serializer,
+{
+    match data {
+        Some(data) => serializer.serialize_some(&Base64Ref(data)),
+        None => serializer.serialize_none(),
+    }
+}
+
+/// Deserialize an optional value from a base64 string (or nothing), see [serialize_opt].
+pub fn deserialize_opt<'de, T, D>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    T: Base64SerializationExt,
+    D: Deserializer<'de>,
+{
+    Option::<Base64Owned<T>>::deserialize(deserializer).map(|data| data.map(|data| data.0))
+}
+
+/// Adapts a [Base64SerializationExt] reference to [Serialize]
+struct Base64Ref<'a, T>(&'a T);
+
+impl<T: Base64SerializationExt> Serialize for Base64Ref<'_, T> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        T::serialize(self.0, serializer)
+    }
+}
+
+/// Adapts a [Base64SerializationExt] value to [Deserialize]
+struct Base64Owned<T>(T);
+
+impl<'de, T: Base64SerializationExt> Deserialize<'de> for Base64Owned<T> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        T::deserialize(deserializer).map(Base64Owned)
+    }
+}
+
 macro_rules! gen_impl_Base64SerializationExt {
     ($type:ty) => {
         impl Base64SerializationExt for $type {
@@ -95,6 +143,71 @@ gen_impl_Base64SerializationExt!(box_::SecretKey);
 gen_impl_Base64SerializationExt!(box_::Nonce);
 gen_impl_Base64SerializationExt!(sign::PublicKey);
 gen_impl_Base64SerializationExt!(sign::SecretKey);
-gen_impl_Base64SerializationExt!(sign::Signature);
+gen_impl_Base64SerializationExt!(crate::keys::Signature);
 gen_impl_Base64SerializationExt!(pwhash::Salt);
 gen_impl_Base64SerializationExt!(secretbox::Nonce);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Signed {
+        #[serde(with = "Base64SerializationExt")]
+        bytes: Vec<u8>,
+
+        #[serde(
+            default,
+            serialize_with = "serialize_opt",
+            deserialize_with = "deserialize_opt"
+        )]
+        extra: Option<Vec<u8>>,
+    }
+
+    fn round_trip(value: Signed) -> String {
+        let json = serde_json::to_string(&value).unwrap();
+        assert_eq!(serde_json::from_str::<Signed>(&json).unwrap(), value);
+
+        let bin = bincode::serialize(&value).unwrap();
+        assert_eq!(bincode::deserialize::<Signed>(&bin).unwrap(), value);
+
+        json
+    }
+
+    #[test]
+    fn plain_bytes() {
+        let json = round_trip(Signed {
+            bytes: vec![0xde, 0xad, 0xbe, 0xef],
+            extra: None,
+        });
+        assert_eq!(json, r#"{"bytes":"3q2+7w==","extra":null}"#);
+    }
+
+    #[test]
+    fn some_bytes() {
+        let json = round_trip(Signed {
+            bytes: vec![],
+            extra: Some(vec![0xde, 0xad, 0xbe, 0xef]),
+        });
+        assert_eq!(json, r#"{"bytes":"","extra":"3q2+7w=="}"#);
+    }
+
+    #[test]
+    fn none() {
+        round_trip(Signed {
+            bytes: vec![1],
+            extra: None,
+        });
+
+        // a missing field is none as well
+        assert_eq!(
+            serde_json::from_str::<Signed>(r#"{"bytes":"AQ=="}"#).unwrap(),
+            Signed {
+                bytes: vec![1],
+                extra: None,
+            }
+        );
+
+        assert!(serde_json::from_str::<Signed>(r#"{"bytes":"AQ==","extra":"!!"}"#).is_err());
+    }
+}
@@ -0,0 +1,112 @@
+use std::fmt::Debug;
+use std::time::SystemTime;
+
+use super::EntityId;
+
+/// Cryptographic operation performed by a [KeyStore](super::KeyStore).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditOperation {
+    Sign,
+    VerifySignature,
+    Cipher,
+    Decipher,
+}
+
+/// Who did what, when and how it went, for a single [AuditOperation].
+///
+/// Never includes keys or messages.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuditRecord {
+    pub operation: AuditOperation,
+
+    /// Entity that performed the operation, none for verifier-only stores
+    pub actor: Option<EntityId>,
+
+    /// Author of a verified signature, or partner of a (de)ciphered message
+    pub partner: Option<EntityId>,
+
+    pub at: SystemTime,
+    pub success: bool,
+}
+
+/// Receives an [AuditRecord] for every cryptographic operation of a [KeyStore](super::KeyStore)
+/// it is set on, see [KeyStore::set_audit_sink](super::KeyStore::set_audit_sink).
+///
+/// Called synchronously, from whichever thread performed the operation: it should be quick.
+pub trait AuditSink: Debug + Send + Sync {
+    fn record(&self, record: AuditRecord);
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::keys::test_data::KeyStoreSetBuilder;
+    use crate::keys::Role;
+
+    #[derive(Debug, Default)]
+    struct MockSink(Mutex<Vec<AuditRecord>>);
+
+    impl AuditSink for MockSink {
+        fn record(&self, record: AuditRecord) {
+            self.0.lock().unwrap().push(record);
+        }
+    }
+
+    impl MockSink {
+        fn outcomes(&self) -> Vec<(AuditOperation, Option<EntityId>, Option<EntityId>, bool)> {
+            self.0
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|r| (r.operation, r.actor, r.partner, r.success))
+                .collect()
+        }
+    }
+
+    #[test]
+    fn records_outcomes() {
+        let mut keystores = KeyStoreSetBuilder::new()
+            .entities(1..=2, Role::User)
+            .build();
+        let sink = Arc::new(MockSink::default());
+        keystores[0].set_audit_sink(Some(sink.clone()));
+
+        let start = SystemTime::now();
+        let signature = keystores[0].sign(b"message");
+        assert!(keystores[0]
+            .verify_signature(2, b"message", &signature)
+            .is_err());
+        assert!(keystores[0]
+            .verify_signature(1, b"message", &signature)
+            .is_ok());
+
+        let (ciphertext, nonce) = keystores[1].cipher(1, b"message").unwrap();
+        assert!(keystores[0].decipher(2, &ciphertext, &nonce).is_ok());
+        assert!(keystores[0].decipher(2, b"garbage", &nonce).is_err());
+
+        assert_eq!(
+            sink.outcomes(),
+            vec![
+                (AuditOperation::Sign, Some(1), None, true),
+                (AuditOperation::VerifySignature, Some(1), Some(2), false),
+                (AuditOperation::VerifySignature, Some(1), Some(1), true),
+                (AuditOperation::Decipher, Some(1), Some(2), true),
+                (AuditOperation::Decipher, Some(1), Some(2), false),
+            ]
+        );
+        assert!(sink
+            .0
+            .lock()
+            .unwrap()
+            .iter()
+            .all(|r| r.at >= start && r.at <= SystemTime::now()));
+
+        // only the store it was set on is audited
+        keystores[1].sign(b"message");
+        keystores[0].set_audit_sink(None);
+        keystores[0].sign(b"message");
+        assert_eq!(sink.outcomes().len(), 5);
+    }
+}
@@ -1,9 +1,13 @@
+use std::convert::TryInto;
 use std::fmt;
 use std::fs;
+use std::hash::{Hash, Hasher};
 use std::path::Path;
 
 use serde::{Deserialize, Serialize};
-use sodiumoxide::crypto::{box_::curve25519xsalsa20poly1305 as box_, sign::ed25519 as sign};
+use sodiumoxide::crypto::{
+    box_::curve25519xsalsa20poly1305 as box_, hash::sha256, sign::ed25519 as sign,
+};
 use thiserror::Error;
 
 use super::sealable::{Sealable, SealableError};
@@ -13,7 +17,39 @@ use crate::base64_serialization::Base64SerializationExt;
 pub type EntityId = u32;
 
 pub use sodiumoxide::crypto::box_::Nonce;
-pub use sodiumoxide::crypto::sign::Signature;
+
+/// An ed25519 signature.
+///
+/// Equality is checked in constant time, to avoid leaking how much of a signature matches through timing.
+#[derive(Clone, Copy, Debug)]
+pub struct Signature(pub [u8; sign::SIGNATUREBYTES]);
+
+impl Signature {
+    /// Creates a signature from its bytes, if they have the right length.
+    pub fn from_slice(bytes: &[u8]) -> Option<Signature> {
+        bytes.try_into().ok().map(Signature)
+    }
+}
+
+impl AsRef<[u8]> for Signature {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl PartialEq for Signature {
+    fn eq(&self, other: &Signature) -> bool {
+        sodiumoxide::utils::memcmp(&self.0, &other.0)
+    }
+}
+
+impl Eq for Signature {}
+
+impl Hash for Signature {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.hash(state)
+    }
+}
 
 #[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
 pub struct EntityPubComponent {
@@ -55,6 +91,15 @@ pub enum EntityPrivComponentLoadError {
     IoError(#[from] std::io::Error),
 }
 
+#[derive(Error, Debug)]
+pub enum EntityPrivComponentImportError {
+    #[error("Signing secret key must be {} bytes long, got {}", sign::SECRETKEYBYTES, .0)]
+    BadSigningKeyLength(usize),
+
+    #[error("Ciphering secret key must be {} bytes long, got {}", box_::SECRETKEYBYTES, .0)]
+    BadCipherKeyLength(usize),
+}
+
 #[derive(Error, Debug)]
 #[error("Failed to decipher data")]
 pub struct DecipherError;
@@ -76,6 +121,26 @@ impl EntityPrivComponent {
         }
     }
 
+    /// Wraps existing libsodium secret keys (ed25519 for signing, curve25519 for ciphering).
+    pub fn from_raw(
+        id: EntityId,
+        role: Role,
+        sig_skey: &[u8],
+        cipher_skey: &[u8],
+    ) -> Result<Self, EntityPrivComponentImportError> {
+        let sig_skey = sign::SecretKey::from_slice(sig_skey)
+            .ok_or_else(|| EntityPrivComponentImportError::BadSigningKeyLength(sig_skey.len()))?;
+        let cipher_skey = box_::SecretKey::from_slice(cipher_skey)
+            .ok_or_else(|| EntityPrivComponentImportError::BadCipherKeyLength(cipher_skey.len()))?;
+
+        Ok(EntityPrivComponent {
+            id,
+            role,
+            sig_skey: Sealable::Unsealed(sig_skey),
+            cipher_skey: Sealable::Unsealed(cipher_skey),
+        })
+    }
+
     pub fn load_from_file<P: AsRef<Path>>(path: P) -> Result<Self, EntityPrivComponentLoadError> {
         let encoded = fs::read_to_string(path)?;
         let entity = serde_json::from_str(&encoded)?;
@@ -121,7 +186,8 @@ impl EntityPrivComponent {
     }
 
     pub fn sign(&self, message: &[u8]) -> Signature {
-        sign::sign_detached(message, self.sig_skey.get())
+        let signature = sign::sign_detached(message, self.sig_skey.get());
+        Signature::from_slice(signature.as_ref()).expect("ed25519 signatures have a fixed size")
     }
 
     pub fn cipher(&self, partner: &EntityPubComponent, plaintext: &[u8]) -> (Vec<u8>, Nonce) {
@@ -151,6 +217,12 @@ impl EntityPrivComponent {
         )
         .map_err(|_| DecipherError)
     }
+
+    /// Shared key with a partner, which can replace this entity's [cipher](Self::cipher) and
+    /// [decipher](Self::decipher) when talking to them, at a fraction of the cost.
+    pub fn precompute(&self, partner: &EntityPubComponent) -> box_::PrecomputedKey {
+        box_::precompute(&partner.cipher_pubkey, self.cipher_skey.get())
+    }
 }
 
 impl EntityPubComponent {
@@ -159,12 +231,24 @@ impl EntityPubComponent {
         message: &[u8],
         signature: &Signature,
     ) -> Result<(), SignatureVerificationError> {
-        if sign::verify_detached(signature, message, &self.sig_pubkey) {
+        let signature = sign::Signature::from_slice(signature.as_ref())
+            .expect("ed25519 signatures have a fixed size");
+        if sign::verify_detached(&signature, message, &self.sig_pubkey) {
             Ok(())
         } else {
             Err(SignatureVerificationError)
         }
     }
+
+    /// Short digest of the public keys, for comparing registries out-of-band.
+    ///
+    /// Hex-encoded first 8 bytes of the SHA-256 of the signing and ciphering public keys.
+    pub fn fingerprint(&self) -> String {
+        let bytes = [self.sig_pubkey.as_ref(), self.cipher_pubkey.as_ref()].concat();
+        let sha256::Digest(digest) = sha256::hash(&bytes);
+
+        digest[..8].iter().map(|b| format!("{:02x}", b)).collect()
+    }
 }
 
 impl fmt::Debug for EntityPrivComponent {
@@ -196,6 +280,99 @@ mod test {
         assert!(entity == loaded_entity);
     }
 
+    #[test]
+    fn signature_equality() {
+        crate::ensure_init();
+
+        let entity = EntityPrivComponent::new(1, Role::User);
+        let signature = entity.sign(b"message");
+
+        assert_eq!(signature, entity.sign(b"message"));
+        assert_eq!(Signature::from_slice(signature.as_ref()), Some(signature));
+        assert_ne!(signature, entity.sign(b"other message"));
+        assert!(Signature::from_slice(&signature.0[1..]).is_none());
+
+        let mut tampered = signature;
+        tampered.0[sign::SIGNATUREBYTES - 1] ^= 1;
+        assert_ne!(signature, tampered);
+    }
+
+    #[test]
+    fn from_raw() {
+        crate::ensure_init();
+
+        let (sig_pkey, sig_skey) = sign::gen_keypair();
+        let (cipher_pkey, cipher_skey) = box_::gen_keypair();
+
+        let entity =
+            EntityPrivComponent::from_raw(1, Role::User, &sig_skey.0, &cipher_skey.0).unwrap();
+        assert_eq!(
+            entity.pub_component(),
+            EntityPubComponent {
+                id: 1,
+                role: Role::User,
+                sig_pubkey: sig_pkey,
+                cipher_pubkey: cipher_pkey,
+            }
+        );
+
+        let signature = entity.sign(b"message");
+        assert!(entity
+            .pub_component()
+            .verify_signature(b"message", &signature)
+            .is_ok());
+    }
+
+    #[test]
+    fn fingerprint() {
+        crate::ensure_init();
+
+        let entity = EntityPrivComponent::new(1, Role::User).pub_component();
+        let fingerprint = entity.fingerprint();
+        assert_eq!(fingerprint.len(), 16);
+        assert!(fingerprint.chars().all(|c| c.is_ascii_hexdigit()));
+        assert_eq!(entity.clone().fingerprint(), fingerprint);
+
+        // only the keys matter
+        let renamed = EntityPubComponent {
+            id: 2,
+            role: Role::Server,
+            ..entity.clone()
+        };
+        assert_eq!(renamed.fingerprint(), fingerprint);
+
+        let other = EntityPrivComponent::new(1, Role::User).pub_component();
+        assert_ne!(other.fingerprint(), fingerprint);
+        let mixed = EntityPubComponent {
+            cipher_pubkey: other.cipher_pubkey,
+            ..entity
+        };
+        assert_ne!(mixed.fingerprint(), fingerprint);
+    }
+
+    #[test]
+    fn from_raw_bad_lengths() {
+        crate::ensure_init();
+
+        let (_, sig_skey) = sign::gen_keypair();
+        let (_, cipher_skey) = box_::gen_keypair();
+
+        assert!(matches!(
+            EntityPrivComponent::from_raw(1, Role::User, &sig_skey.0[1..], &cipher_skey.0),
+            Err(EntityPrivComponentImportError::BadSigningKeyLength(l)) if l == sign::SECRETKEYBYTES - 1
+        ));
+        assert!(matches!(
+            EntityPrivComponent::from_raw(1, Role::User, &sig_skey.0, &[]),
+            Err(EntityPrivComponentImportError::BadCipherKeyLength(0))
+        ));
+
+        // keys of the wrong kind
+        assert!(matches!(
+            EntityPrivComponent::from_raw(1, Role::User, &cipher_skey.0, &sig_skey.0),
+            Err(EntityPrivComponentImportError::BadSigningKeyLength(_))
+        ));
+    }
+
     #[test]
     fn priv_to_pub_equal() {
         crate::ensure_init();
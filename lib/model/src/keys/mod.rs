@@ -1,26 +1,47 @@
 use std::collections::HashMap;
 use std::fs;
 use std::path::Path;
+use std::sync::Arc;
+use std::time::SystemTime;
 
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
 mod entity;
 pub use entity::{EntityId, EntityPrivComponent, EntityPubComponent};
-pub use entity::{EntityPrivComponentLoadError, EntityPrivComponentSaveError};
-pub use entity::{Nonce, Signature};
+pub use entity::{
+    EntityPrivComponentImportError, EntityPrivComponentLoadError, EntityPrivComponentSaveError,
+};
+pub use entity::{Nonce, Signature, SignatureVerificationError};
+
+mod multi;
+pub use multi::MultiKeyStore;
+
+mod audit;
+pub use audit::{AuditOperation, AuditRecord, AuditSink};
 
 mod sealable;
 
-use self::{
-    entity::{DecipherError, SignatureVerificationError},
-    sealable::SealableError,
-};
+mod shared_keys;
+
+use self::{entity::DecipherError, sealable::SealableError, shared_keys::SharedKeyCache};
+
+/// Number of partners whose shared keys are kept around by a [KeyStore].
+const SHARED_KEY_CACHE_CAPACITY: usize = 256;
 
 #[derive(Debug, Clone)]
 pub struct KeyStore {
-    registry: HashMap<EntityId, EntityPubComponent>,
-    me: EntityPrivComponent,
+    /// Public components of every known entity. May be shared with other stores, see [MultiKeyStore]
+    registry: Arc<HashMap<EntityId, EntityPubComponent>>,
+
+    /// Our own identity. Verifier-only stores have none, see [load_from_files](Self::load_from_files)
+    me: Option<EntityPrivComponent>,
+
+    /// Keys shared with the partners we talk to the most, to speed up [KeyStore::cipher] and [KeyStore::decipher]
+    shared_keys: SharedKeyCache,
+
+    /// Told about every cryptographic operation, if any. Shared with clones of the store
+    audit_sink: Option<Arc<dyn AuditSink>>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
@@ -40,6 +61,9 @@ pub enum KeyStoreSaveError {
 
     #[error("Failed to save current entity")]
     MeSaveError(#[from] EntityPrivComponentSaveError),
+
+    #[error("There is no current entity to save (verifier-only store)")]
+    NoIdentity,
 }
 
 #[derive(Error, Debug)]
@@ -55,6 +79,12 @@ pub enum KeyStoreLoadError {
 
     #[error("Failed to load current entity")]
     MeLoadError(#[from] EntityPrivComponentLoadError),
+
+    #[error("Current entity keys are password-protected, but no password was given")]
+    Locked,
+
+    #[error("Could not unlock current entity keys")]
+    UnlockError(#[source] SealableError),
 }
 
 #[derive(Error, Debug)]
@@ -77,6 +107,9 @@ pub enum KeyStoreError {
 
     #[error("Could not unlock private keys")]
     UnlockError(#[source] SealableError),
+
+    #[error("No private keys (verifier-only store)")]
+    NoIdentity,
 }
 
 impl KeyStore {
@@ -84,22 +117,57 @@ impl KeyStore {
         let mut registry = HashMap::new();
         registry.insert(me.id, me.pub_component());
 
-        KeyStore { registry, me }
+        KeyStore {
+            registry: Arc::new(registry),
+            me: Some(me),
+            shared_keys: SharedKeyCache::new(SHARED_KEY_CACHE_CAPACITY),
+            audit_sink: None,
+        }
     }
 
+    /// Load the registry and, if `me_path` is given, the current entity keys.
+    ///
+    /// Without them the store is verifier-only: it can check signatures of registered
+    /// entities, but not sign or cipher anything (see [try_sign](Self::try_sign)).
     pub fn load_from_files<P1: AsRef<Path>, P2: AsRef<Path>>(
         registry_path: P1,
-        me_path: P2,
+        me_path: Option<P2>,
+    ) -> Result<Self, KeyStoreLoadError> {
+        Self::load_from_files_with_password(registry_path, me_path, None)
+    }
+
+    /// Like [load_from_files](Self::load_from_files), but unlocking the current entity keys
+    /// with `password` if they are locked. They can only be checked against the registry unlocked.
+    pub fn load_from_files_with_password<P1: AsRef<Path>, P2: AsRef<Path>>(
+        registry_path: P1,
+        me_path: Option<P2>,
+        password: Option<&str>,
     ) -> Result<Self, KeyStoreLoadError> {
         let registry_enc = fs::read_to_string(registry_path)?;
         let mut registry = serde_json::from_str(&registry_enc)?;
 
-        let me = EntityPrivComponent::load_from_file(me_path)?;
-
-        // guarantee consistency
-        assert_registry_consistent(&mut registry, &me)?;
+        let me = match me_path {
+            Some(me_path) => {
+                let mut me = EntityPrivComponent::load_from_file(me_path)?;
+                if me.is_locked() {
+                    let password = password.ok_or(KeyStoreLoadError::Locked)?;
+                    me.unlock(password)
+                        .map_err(KeyStoreLoadError::UnlockError)?;
+                }
+
+                // guarantee consistency
+                assert_registry_consistent(&mut registry, &me)?;
+                Some(me)
+            }
+            None => None,
+        };
 
-        Ok(KeyStore { registry, me })
+        Ok(KeyStore {
+            registry: Arc::new(registry),
+            me,
+            shared_keys: SharedKeyCache::new(SHARED_KEY_CACHE_CAPACITY),
+            audit_sink: None,
+        })
     }
 
     pub fn save_to_files<P1: AsRef<Path>, P2: AsRef<Path>>(
@@ -107,57 +175,122 @@ impl KeyStore {
         registry_path: P1,
         me_path: P2,
     ) -> Result<(), KeyStoreSaveError> {
-        let registry = serde_json::to_string_pretty(&self.registry)?;
+        let registry = serde_json::to_string_pretty(&*self.registry)?;
         fs::write(registry_path, registry)?;
 
-        self.me.save_to_file(me_path)?;
+        self.me
+            .as_ref()
+            .ok_or(KeyStoreSaveError::NoIdentity)?
+            .save_to_file(me_path)?;
 
         Ok(())
     }
 
     pub fn lock(&mut self, password: &str) -> Result<(), KeyStoreError> {
-        self.me.lock(password).map_err(KeyStoreError::LockError)
+        // shared keys are as good as our private key
+        self.shared_keys.clear();
+        self.me
+            .as_mut()
+            .ok_or(KeyStoreError::NoIdentity)?
+            .lock(password)
+            .map_err(KeyStoreError::LockError)
     }
 
     pub fn unlock(&mut self, password: &str) -> Result<(), KeyStoreError> {
-        self.me.unlock(password).map_err(KeyStoreError::UnlockError)
+        self.me
+            .as_mut()
+            .ok_or(KeyStoreError::NoIdentity)?
+            .unlock(password)
+            .map_err(KeyStoreError::UnlockError)
     }
 
     pub fn is_locked(&self) -> bool {
-        self.me.is_locked()
+        self.me
+            .as_ref()
+            .map_or(false, EntityPrivComponent::is_locked)
+    }
+
+    /// Whether we have an identity of our own, i.e. this is not a verifier-only store.
+    pub fn has_identity(&self) -> bool {
+        self.me.is_some()
     }
 
     pub fn add_entity(
         &mut self,
         entity: EntityPubComponent,
     ) -> Result<(), KeyStoreConsistencyError> {
-        if !self.registry.get(&entity.id).map_or(true, |u| *u == entity) {
-            // a *different* entity with this ID already exists
-            return Err(KeyStoreConsistencyError(entity.id));
-        }
+        insert_entity(&mut self.registry, entity)
+    }
 
-        self.registry.insert(entity.id, entity);
+    /// Remove an entity from the registry, returning it if it was there.
+    ///
+    /// The current entity (see [set_me](Self::set_me)) cannot be removed.
+    pub fn remove_entity(&mut self, id: EntityId) -> Option<EntityPubComponent> {
+        if self.me.as_ref().map_or(false, |me| me.id == id) {
+            return None;
+        }
 
-        Ok(())
+        self.shared_keys.remove(id);
+        Arc::make_mut(&mut self.registry).remove(&id)
     }
 
     pub fn set_me(&mut self, me: EntityPrivComponent) -> Result<(), KeyStoreConsistencyError> {
-        assert_registry_consistent(&mut self.registry, &me)?;
-        self.me = me;
+        assert_registry_consistent(Arc::make_mut(&mut self.registry), &me)?;
+        self.me = Some(me);
+        self.shared_keys.clear();
 
         Ok(())
     }
 
+    /// Public component of an entity in the registry (including the current one).
+    pub fn entity(&self, id: EntityId) -> Option<&EntityPubComponent> {
+        self.registry.get(&id)
+    }
+
     pub fn role_of(&self, id: EntityId) -> Option<Role> {
         self.registry.get(&id).map(|entity| entity.role)
     }
 
+    /// ID of the current entity. Panics in verifier-only stores.
     pub fn my_id(&self) -> EntityId {
-        self.me.id
+        self.me().id
     }
 
+    /// Role of the current entity. Panics in verifier-only stores.
     pub fn my_role(&self) -> Role {
-        self.me.role
+        self.me().role
+    }
+
+    fn me(&self) -> &EntityPrivComponent {
+        self.me
+            .as_ref()
+            .expect("verifier-only KeyStore has no identity")
+    }
+
+    /// Report every cryptographic operation from now on to `sink`, or stop reporting them.
+    pub fn set_audit_sink(&mut self, sink: Option<Arc<dyn AuditSink>>) {
+        self.audit_sink = sink;
+    }
+
+    /// Run a cryptographic operation, reporting its outcome to the audit sink (if any).
+    fn audited<T>(
+        &self,
+        operation: AuditOperation,
+        partner: Option<EntityId>,
+        run: impl FnOnce() -> Result<T, KeyStoreError>,
+    ) -> Result<T, KeyStoreError> {
+        let result = run();
+        if let Some(sink) = &self.audit_sink {
+            sink.record(AuditRecord {
+                operation,
+                actor: self.me.as_ref().map(|me| me.id),
+                partner,
+                at: SystemTime::now(),
+                success: result.is_ok(),
+            });
+        }
+
+        result
     }
 
     pub fn cipher(
@@ -165,12 +298,19 @@ impl KeyStore {
         partner_id: EntityId,
         plaintext: &[u8],
     ) -> Result<(Vec<u8>, Nonce), KeyStoreError> {
-        let partner = self
-            .registry
-            .get(&partner_id)
-            .ok_or_else(|| KeyStoreError::EntityNotFound(partner_id))?;
-
-        Ok(self.me.cipher(&partner, plaintext))
+        self.audited(AuditOperation::Cipher, Some(partner_id), || {
+            let me = self.me.as_ref().ok_or(KeyStoreError::NoIdentity)?;
+            let partner = self
+                .registry
+                .get(&partner_id)
+                .ok_or_else(|| KeyStoreError::EntityNotFound(partner_id))?;
+
+            let key = self
+                .shared_keys
+                .get_or_insert_with(partner_id, || me.precompute(partner));
+
+            Ok(shared_keys::cipher(&key, plaintext))
+        })
     }
 
     pub fn decipher(
@@ -179,16 +319,36 @@ impl KeyStore {
         ciphertext: &[u8],
         nonce: &Nonce,
     ) -> Result<Vec<u8>, KeyStoreError> {
-        let partner = self
-            .registry
-            .get(&partner_id)
-            .ok_or_else(|| KeyStoreError::EntityNotFound(partner_id))?;
-
-        Ok(self.me.decipher(&partner, ciphertext, nonce)?)
+        self.audited(AuditOperation::Decipher, Some(partner_id), || {
+            let me = self.me.as_ref().ok_or(KeyStoreError::NoIdentity)?;
+            let partner = self
+                .registry
+                .get(&partner_id)
+                .ok_or_else(|| KeyStoreError::EntityNotFound(partner_id))?;
+
+            let key = self
+                .shared_keys
+                .get_or_insert_with(partner_id, || me.precompute(partner));
+
+            shared_keys::decipher(&key, ciphertext, nonce)
+                .ok_or(KeyStoreError::DecipherError(DecipherError))
+        })
     }
 
+    /// Sign a message as the current entity. Panics in verifier-only stores, see [try_sign](Self::try_sign).
     pub fn sign(&self, message: &[u8]) -> Signature {
-        self.me.sign(message)
+        self.try_sign(message)
+            .expect("verifier-only KeyStore has no identity")
+    }
+
+    /// Like [sign](Self::sign), but failing in verifier-only stores.
+    pub fn try_sign(&self, message: &[u8]) -> Result<Signature, KeyStoreError> {
+        self.audited(AuditOperation::Sign, None, || {
+            self.me
+                .as_ref()
+                .map(|me| me.sign(message))
+                .ok_or(KeyStoreError::NoIdentity)
+        })
     }
 
     pub fn verify_signature(
@@ -197,13 +357,30 @@ impl KeyStore {
         message: &[u8],
         signature: &Signature,
     ) -> Result<(), KeyStoreError> {
-        let author = self
-            .registry
-            .get(&author_id)
-            .ok_or_else(|| KeyStoreError::EntityNotFound(author_id))?;
+        self.audited(AuditOperation::VerifySignature, Some(author_id), || {
+            let author = self
+                .registry
+                .get(&author_id)
+                .ok_or_else(|| KeyStoreError::EntityNotFound(author_id))?;
+
+            Ok(author.verify_signature(message, signature)?)
+        })
+    }
+}
 
-        Ok(author.verify_signature(message, signature)?)
+/// Add an entity to a (possibly shared) registry, unless a different one has the same ID.
+fn insert_entity(
+    registry: &mut Arc<HashMap<EntityId, EntityPubComponent>>,
+    entity: EntityPubComponent,
+) -> Result<(), KeyStoreConsistencyError> {
+    if !registry.get(&entity.id).map_or(true, |u| *u == entity) {
+        // a *different* entity with this ID already exists
+        return Err(KeyStoreConsistencyError(entity.id));
     }
+
+    Arc::make_mut(registry).insert(entity.id, entity);
+
+    Ok(())
 }
 
 fn assert_registry_consistent(
@@ -219,9 +396,67 @@ fn assert_registry_consistent(
     }
 }
 
+/// Ready-made [KeyStore]s for tests, that don't touch the filesystem.
+///
+/// Available to other crates with the `test-utils` feature.
+#[cfg(any(test, feature = "test-utils"))]
 pub mod test_data {
     use super::*;
 
+    /// A set of [KeyStore]s that all know each other, for arbitrary entities.
+    ///
+    /// Add entities with [entity](Self::entity) and [entities](Self::entities), then
+    /// [build](Self::build) one keystore per entity.
+    #[derive(Debug, Default, Clone)]
+    pub struct KeyStoreSetBuilder {
+        entities: Vec<(EntityId, Role)>,
+    }
+
+    impl KeyStoreSetBuilder {
+        pub fn new() -> Self {
+            KeyStoreSetBuilder::default()
+        }
+
+        /// Add an entity to the set.
+        pub fn entity(mut self, id: EntityId, role: Role) -> Self {
+            self.entities.push((id, role));
+            self
+        }
+
+        /// Add several entities with the same role to the set.
+        pub fn entities<I: IntoIterator<Item = EntityId>>(mut self, ids: I, role: Role) -> Self {
+            self.entities.extend(ids.into_iter().map(|id| (id, role)));
+            self
+        }
+
+        /// Generate keys for every entity, returning their keystores in the order they were added.
+        ///
+        /// Panics if the same id was added twice.
+        pub fn build(self) -> Vec<KeyStore> {
+            crate::ensure_init();
+
+            let entities: Vec<_> = self
+                .entities
+                .into_iter()
+                .map(|(id, role)| EntityPrivComponent::new(id, role))
+                .collect();
+
+            entities
+                .iter()
+                .map(|me| {
+                    let mut keystore = KeyStore::new(me.clone());
+                    for other in &entities {
+                        keystore
+                            .add_entity(other.pub_component())
+                            .expect("duplicate entity id");
+                    }
+
+                    keystore
+                })
+                .collect()
+        }
+    }
+
     pub struct KeyStoreTestData {
         pub user1: KeyStore,
         pub user2: KeyStore,
@@ -232,42 +467,20 @@ pub mod test_data {
 
     impl KeyStoreTestData {
         pub fn new() -> Self {
-            use itertools::Itertools;
-            use std::cell::RefCell;
-
-            crate::ensure_init();
-            let mut user1 = RefCell::new(KeyStore::new(EntityPrivComponent::new(1, Role::User)));
-            let mut user2 = RefCell::new(KeyStore::new(EntityPrivComponent::new(2, Role::User)));
-            let mut user3 = RefCell::new(KeyStore::new(EntityPrivComponent::new(3, Role::User)));
-            let mut server =
-                RefCell::new(KeyStore::new(EntityPrivComponent::new(100, Role::Server)));
-            let mut haclient =
-                RefCell::new(KeyStore::new(EntityPrivComponent::new(200, Role::HaClient)));
-
-            for (a, b) in [
-                &mut user1,
-                &mut user2,
-                &mut user3,
-                &mut server,
-                &mut haclient,
-            ]
-            .iter()
-            .tuple_combinations()
-            {
-                a.borrow_mut()
-                    .add_entity(b.borrow().me.pub_component())
-                    .unwrap();
-                b.borrow_mut()
-                    .add_entity(a.borrow().me.pub_component())
-                    .unwrap();
-            }
+            let mut keystores = KeyStoreSetBuilder::new()
+                .entities(1..=3, Role::User)
+                .entity(100, Role::Server)
+                .entity(200, Role::HaClient)
+                .build()
+                .into_iter();
+            let mut next = || keystores.next().unwrap();
 
             KeyStoreTestData {
-                user1: user1.into_inner(),
-                user2: user2.into_inner(),
-                user3: user3.into_inner(),
-                server: server.into_inner(),
-                haclient: haclient.into_inner(),
+                user1: next(),
+                user2: next(),
+                user3: next(),
+                server: next(),
+                haclient: next(),
             }
         }
 
@@ -305,11 +518,49 @@ mod test_manipulation {
 
         store.save_to_files(&registry_path, &me_path).unwrap();
 
-        let loaded_store = KeyStore::load_from_files(registry_path, me_path).unwrap();
+        let loaded_store = KeyStore::load_from_files(registry_path, Some(me_path)).unwrap();
         assert!(store.me == loaded_store.me);
         assert_eq!(store.registry, loaded_store.registry);
     }
 
+    #[test]
+    fn test_load_verifier_only() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let registry_path = tempdir.path().join("registry.json");
+        let me_path = tempdir.path().join("me.json");
+
+        let mut store = KeyStore::new(EntityPrivComponent::new(100, Role::Server));
+        store
+            .add_entity(EntityPrivComponent::new(101, Role::HaClient).pub_component())
+            .unwrap();
+        store.save_to_files(&registry_path, &me_path).unwrap();
+
+        let verifier = KeyStore::load_from_files(&registry_path, None::<&Path>).unwrap();
+        assert!(!verifier.has_identity());
+        assert!(!verifier.is_locked());
+        assert_eq!(verifier.registry, store.registry);
+
+        // verification works
+        let message = b"message";
+        let signature = store.sign(message);
+        assert!(verifier.verify_signature(100, message, &signature).is_ok());
+        assert!(verifier.verify_signature(101, message, &signature).is_err());
+
+        // but anything needing our own keys fails
+        assert!(matches!(
+            verifier.try_sign(message),
+            Err(KeyStoreError::NoIdentity)
+        ));
+        assert!(matches!(
+            verifier.cipher(100, message),
+            Err(KeyStoreError::NoIdentity)
+        ));
+        assert!(matches!(
+            verifier.save_to_files(&registry_path, &me_path),
+            Err(KeyStoreSaveError::NoIdentity)
+        ));
+    }
+
     #[test]
     fn test_load_save_consistency() {
         let tempdir = tempfile::tempdir().unwrap();
@@ -328,7 +579,7 @@ mod test_manipulation {
 
         store.save_to_files(&registry_path, &me_path).unwrap();
 
-        let mut loaded_store = KeyStore::load_from_files(&registry_path, &me_path).unwrap();
+        let mut loaded_store = KeyStore::load_from_files(&registry_path, Some(&me_path)).unwrap();
 
         // set new (different) me on both stores with same ID
         store
@@ -346,7 +597,7 @@ mod test_manipulation {
             .unwrap();
 
         // registry.json and me.json now have two different entities with the same ID
-        assert!(KeyStore::load_from_files(&registry_path, &me_path).is_err());
+        assert!(KeyStore::load_from_files(&registry_path, Some(&me_path)).is_err());
     }
 
     #[test]
@@ -402,7 +653,7 @@ mod test_manipulation {
         assert_eq!(store.my_id(), 0);
 
         assert!(
-            store.add_entity(store.me.pub_component()).is_ok(),
+            store.add_entity(store.me().pub_component()).is_ok(),
             "adding an existing entity is fine"
         );
         assert_eq!(store.role_of(0), Some(Role::User));
@@ -443,10 +694,10 @@ mod test_manipulation {
         assert_eq!(store.my_id(), 0);
 
         let same_me = EntityPrivComponent {
-            id: store.me.id,
-            role: store.me.role,
-            sig_skey: store.me.sig_skey.clone(),
-            cipher_skey: store.me.cipher_skey.clone(),
+            id: store.me().id,
+            role: store.me().role,
+            sig_skey: store.me().sig_skey.clone(),
+            cipher_skey: store.me().cipher_skey.clone(),
         };
         assert!(
             store.set_me(same_me).is_ok(),
@@ -472,6 +723,45 @@ mod test_manipulation {
     }
 }
 
+#[cfg(test)]
+mod test_builder {
+    use super::test_data::KeyStoreSetBuilder;
+    use super::*;
+
+    #[test]
+    fn ten_users() {
+        let keystores = KeyStoreSetBuilder::new()
+            .entities(0..10, Role::User)
+            .build();
+        assert_eq!(keystores.len(), 10);
+
+        let message = b"message";
+        for (id, signer) in keystores.iter().enumerate() {
+            assert_eq!(signer.my_id(), id as EntityId);
+            assert_eq!(signer.my_role(), Role::User);
+
+            let signature = signer.sign(message);
+            for verifier in &keystores {
+                assert!(verifier
+                    .verify_signature(id as EntityId, message, &signature)
+                    .is_ok());
+                assert!(verifier
+                    .verify_signature((id as EntityId + 1) % 10, message, &signature)
+                    .is_err());
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "duplicate entity id")]
+    fn duplicate_ids() {
+        KeyStoreSetBuilder::new()
+            .entity(0, Role::User)
+            .entity(0, Role::Server)
+            .build();
+    }
+}
+
 #[cfg(test)]
 mod test_crypto {
     use super::*;
@@ -487,12 +777,12 @@ mod test_crypto {
             let mut store1 = KeyStore::new(entity1);
             let mut store2 = KeyStore::new(entity2);
 
-            store0.add_entity(store1.me.pub_component()).unwrap();
-            store0.add_entity(store2.me.pub_component()).unwrap();
-            store1.add_entity(store0.me.pub_component()).unwrap();
-            store1.add_entity(store2.me.pub_component()).unwrap();
-            store2.add_entity(store0.me.pub_component()).unwrap();
-            store2.add_entity(store1.me.pub_component()).unwrap();
+            store0.add_entity(store1.me().pub_component()).unwrap();
+            store0.add_entity(store2.me().pub_component()).unwrap();
+            store1.add_entity(store0.me().pub_component()).unwrap();
+            store1.add_entity(store2.me().pub_component()).unwrap();
+            store2.add_entity(store0.me().pub_component()).unwrap();
+            store2.add_entity(store1.me().pub_component()).unwrap();
 
             [store0, store1, store2]
         };
@@ -526,4 +816,68 @@ mod test_crypto {
         assert!(STORES[2].decipher(0, &ciphertext, &nonce).is_err());
         assert!(STORES[2].decipher(1, &ciphertext, &nonce).is_err());
     }
+
+    #[test]
+    fn cached_uncached_interop() {
+        let message = vec![4, 2];
+        let (me0, me1) = (STORES[0].me(), STORES[1].me());
+
+        // twice, to go through both a cold and a warm cache
+        for _ in 0..2 {
+            let (ciphertext, nonce) = STORES[0].cipher(1, &message).unwrap();
+            assert_eq!(
+                me1.decipher(&me0.pub_component(), &ciphertext, &nonce)
+                    .unwrap(),
+                message
+            );
+
+            let (ciphertext, nonce) = me1.cipher(&me0.pub_component(), &message);
+            assert_eq!(STORES[0].decipher(1, &ciphertext, &nonce).unwrap(), message);
+        }
+    }
+
+    #[test]
+    fn shared_key_reuse() {
+        // a clone starts with an empty cache
+        let store = STORES[0].clone();
+        let message = vec![4, 2];
+
+        for _ in 0..10 {
+            let (ciphertext, nonce) = store.cipher(1, &message).unwrap();
+            assert_eq!(store.decipher(1, &ciphertext, &nonce).unwrap(), message);
+        }
+        // a single key exchange with 1, reused for everything else
+        assert_eq!(store.shared_keys.misses(), 1);
+
+        store.cipher(2, &message).unwrap();
+        assert_eq!(store.shared_keys.misses(), 2);
+    }
+
+    #[test]
+    fn remove_entity() {
+        let mut store = STORES[0].clone();
+        let (ciphertext, nonce) = STORES[1].cipher(0, &[4, 2]).unwrap();
+        assert!(store.decipher(1, &ciphertext, &nonce).is_ok());
+
+        assert_eq!(store.remove_entity(1), Some(STORES[1].me().pub_component()));
+        assert!(store.remove_entity(1).is_none());
+        assert!(store.remove_entity(0).is_none(), "can't remove me");
+        assert!(matches!(
+            store.decipher(1, &ciphertext, &nonce),
+            Err(KeyStoreError::EntityNotFound(1))
+        ));
+        assert!(matches!(
+            store.cipher(1, &[4, 2]),
+            Err(KeyStoreError::EntityNotFound(1))
+        ));
+
+        // a new entity with the same id gets a new shared key
+        let new_entity1 = EntityPrivComponent::new(1, Role::User);
+        store.add_entity(new_entity1.pub_component()).unwrap();
+        let (ciphertext, nonce) = store.cipher(1, &[4, 2]).unwrap();
+        assert!(new_entity1
+            .decipher(&store.me().pub_component(), &ciphertext, &nonce)
+            .is_ok());
+        assert!(STORES[1].decipher(0, &ciphertext, &nonce).is_err());
+    }
 }
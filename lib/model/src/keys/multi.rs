@@ -0,0 +1,143 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use super::{
+    assert_registry_consistent, insert_entity, EntityId, EntityPrivComponent, EntityPubComponent,
+    KeyStore, KeyStoreConsistencyError, KeyStoreLoadError, SharedKeyCache,
+    SHARED_KEY_CACHE_CAPACITY,
+};
+
+/// Several identities in one process: a [KeyStore] for each entity whose private keys we hold,
+/// all sharing a single registry.
+///
+/// Meant for tests and gateways acting on behalf of several entities.
+#[derive(Debug, Clone, Default)]
+pub struct MultiKeyStore {
+    registry: Arc<HashMap<EntityId, EntityPubComponent>>,
+    keystores: HashMap<EntityId, KeyStore>,
+}
+
+impl MultiKeyStore {
+    /// Create a store without any entities.
+    pub fn new() -> Self {
+        MultiKeyStore::default()
+    }
+
+    /// Create a store with the registry saved in a file, but no identities yet.
+    pub fn load_registry_from_file<P: AsRef<Path>>(
+        registry_path: P,
+    ) -> Result<Self, KeyStoreLoadError> {
+        let registry_enc = fs::read_to_string(registry_path)?;
+
+        Ok(MultiKeyStore {
+            registry: Arc::new(serde_json::from_str(&registry_enc)?),
+            keystores: HashMap::new(),
+        })
+    }
+
+    /// Add an identity, replacing the one with the same ID (if any).
+    ///
+    /// Its public component is added to the registry if missing, like in [KeyStore::set_me].
+    pub fn add_me(&mut self, me: EntityPrivComponent) -> Result<(), KeyStoreConsistencyError> {
+        assert_registry_consistent(Arc::make_mut(&mut self.registry), &me)?;
+
+        let keystore = KeyStore {
+            registry: Arc::clone(&self.registry),
+            me: Some(me),
+            shared_keys: SharedKeyCache::new(SHARED_KEY_CACHE_CAPACITY),
+            audit_sink: None,
+        };
+        self.keystores.insert(keystore.my_id(), keystore);
+        self.share_registry();
+
+        Ok(())
+    }
+
+    /// Add an entity to the shared registry, see [KeyStore::add_entity].
+    pub fn add_entity(
+        &mut self,
+        entity: EntityPubComponent,
+    ) -> Result<(), KeyStoreConsistencyError> {
+        insert_entity(&mut self.registry, entity)?;
+        self.share_registry();
+
+        Ok(())
+    }
+
+    /// The [KeyStore] of one of our identities.
+    pub fn as_entity(&self, id: EntityId) -> Option<&KeyStore> {
+        self.keystores.get(&id)
+    }
+
+    /// IDs of all our identities, in no particular order.
+    pub fn ids(&self) -> impl Iterator<Item = EntityId> + '_ {
+        self.keystores.keys().copied()
+    }
+
+    /// Make every identity see the current registry.
+    fn share_registry(&mut self) {
+        for keystore in self.keystores.values_mut() {
+            keystore.registry = Arc::clone(&self.registry);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::keys::Role;
+
+    #[test]
+    fn sign_as_many() {
+        crate::ensure_init();
+        let server = EntityPrivComponent::new(100, Role::Server);
+
+        let mut multi = MultiKeyStore::new();
+        multi
+            .add_me(EntityPrivComponent::new(1, Role::User))
+            .unwrap();
+        multi
+            .add_me(EntityPrivComponent::new(2, Role::User))
+            .unwrap();
+        multi.add_entity(server.pub_component()).unwrap();
+
+        let mut ids: Vec<_> = multi.ids().collect();
+        ids.sort_unstable();
+        assert_eq!(ids, vec![1, 2]);
+        assert!(multi.as_entity(100).is_none());
+
+        // one registry, that every identity sees in full (even entities added after it)
+        let user1 = multi.as_entity(1).unwrap();
+        let user2 = multi.as_entity(2).unwrap();
+        assert!(Arc::ptr_eq(&user1.registry, &user2.registry));
+        assert_eq!(user1.role_of(2), Some(Role::User));
+        assert_eq!(user1.role_of(100), Some(Role::Server));
+
+        let mut server = KeyStore::new(server);
+        server.add_entity(user1.me().pub_component()).unwrap();
+        server.add_entity(user2.me().pub_component()).unwrap();
+
+        let message = b"message";
+        for (id, keystore) in &[(1, user1), (2, user2)] {
+            assert_eq!(keystore.my_id(), *id);
+
+            let signature = keystore.sign(message);
+            for verifier in &[user1, user2, &server] {
+                assert!(verifier.verify_signature(*id, message, &signature).is_ok());
+                assert!(verifier
+                    .verify_signature(3 - *id, message, &signature)
+                    .is_err());
+            }
+        }
+
+        // the registry stays consistent
+        assert!(multi
+            .add_me(EntityPrivComponent::new(1, Role::User))
+            .is_err());
+        assert!(multi
+            .add_entity(EntityPrivComponent::new(2, Role::User).pub_component())
+            .is_err());
+    }
+}
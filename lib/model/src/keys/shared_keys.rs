@@ -0,0 +1,181 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Mutex;
+
+use sodiumoxide::crypto::box_::curve25519xsalsa20poly1305 as box_;
+
+use super::{EntityId, Nonce};
+
+use box_::PrecomputedKey;
+
+/// Thread-safe least-recently-used cache of keys shared with other entities.
+///
+/// Keys are computed outside the lock, so concurrent misses for the same partner may compute
+/// the key more than once, but never block each other.
+pub(super) struct SharedKeyCache {
+    capacity: usize,
+    inner: Mutex<SharedKeyCacheInner>,
+}
+
+struct SharedKeyCacheInner {
+    /// Incremented on every access, to tell which entry was used least recently
+    clock: u64,
+    /// Number of lookups that didn't find a key, so it had to be computed
+    misses: u64,
+    entries: HashMap<EntityId, (PrecomputedKey, u64)>,
+}
+
+impl SharedKeyCache {
+    pub fn new(capacity: usize) -> Self {
+        SharedKeyCache {
+            capacity,
+            inner: Mutex::new(SharedKeyCacheInner {
+                clock: 0,
+                misses: 0,
+                entries: HashMap::with_capacity(capacity),
+            }),
+        }
+    }
+
+    /// Obtain the key shared with a partner, computing it with `compute` if it's not cached.
+    pub fn get_or_insert_with<F>(&self, partner_id: EntityId, compute: F) -> PrecomputedKey
+    where
+        F: FnOnce() -> PrecomputedKey,
+    {
+        if let Some(key) = self.inner.lock().unwrap().get(partner_id) {
+            return key;
+        }
+
+        let key = compute();
+        self.inner
+            .lock()
+            .unwrap()
+            .insert(partner_id, key.clone(), self.capacity);
+
+        key
+    }
+
+    /// Forget the key shared with a partner.
+    pub fn remove(&self, partner_id: EntityId) {
+        self.inner.lock().unwrap().entries.remove(&partner_id);
+    }
+
+    /// Forget all keys.
+    pub fn clear(&self) {
+        self.inner.lock().unwrap().entries.clear();
+    }
+
+    #[cfg(test)]
+    fn contains(&self, partner_id: EntityId) -> bool {
+        self.inner.lock().unwrap().entries.contains_key(&partner_id)
+    }
+
+    /// How many times a key had to be computed
+    #[cfg(test)]
+    pub fn misses(&self) -> u64 {
+        self.inner.lock().unwrap().misses
+    }
+}
+
+impl SharedKeyCacheInner {
+    fn get(&mut self, partner_id: EntityId) -> Option<PrecomputedKey> {
+        self.clock += 1;
+        let now = self.clock;
+
+        let key = self.entries.get_mut(&partner_id).map(|(key, last_used)| {
+            *last_used = now;
+            key.clone()
+        });
+        if key.is_none() {
+            self.misses += 1;
+        }
+
+        key
+    }
+
+    fn insert(&mut self, partner_id: EntityId, key: PrecomputedKey, capacity: usize) {
+        if capacity == 0 {
+            return;
+        }
+
+        if !self.entries.contains_key(&partner_id) && self.entries.len() >= capacity {
+            let lru = self
+                .entries
+                .iter()
+                .min_by_key(|(_, (_, last_used))| *last_used)
+                .map(|(&id, _)| id);
+            if let Some(lru) = lru {
+                self.entries.remove(&lru);
+            }
+        }
+
+        self.clock += 1;
+        self.entries.insert(partner_id, (key, self.clock));
+    }
+}
+
+/// A copy starts out empty: the cache only ever saves work.
+impl Clone for SharedKeyCache {
+    fn clone(&self) -> Self {
+        SharedKeyCache::new(self.capacity)
+    }
+}
+
+impl fmt::Debug for SharedKeyCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SharedKeyCache")
+            .field("capacity", &self.capacity)
+            .field("len", &self.inner.lock().unwrap().entries.len())
+            .finish()
+    }
+}
+
+pub(super) fn cipher(key: &PrecomputedKey, plaintext: &[u8]) -> (Vec<u8>, Nonce) {
+    let nonce = box_::gen_nonce();
+    let ciphertext = box_::seal_precomputed(plaintext, &nonce, key);
+
+    (ciphertext, nonce)
+}
+
+pub(super) fn decipher(key: &PrecomputedKey, ciphertext: &[u8], nonce: &Nonce) -> Option<Vec<u8>> {
+    box_::open_precomputed(ciphertext, nonce, key).ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn key(byte: u8) -> PrecomputedKey {
+        PrecomputedKey([byte; box_::PRECOMPUTEDKEYBYTES])
+    }
+
+    #[test]
+    fn lru_eviction() {
+        let cache = SharedKeyCache::new(2);
+
+        assert_eq!(cache.get_or_insert_with(1, || key(1)), key(1));
+        assert_eq!(cache.get_or_insert_with(2, || key(2)), key(2));
+
+        // cached: not recomputed
+        assert_eq!(cache.get_or_insert_with(1, || unreachable!()), key(1));
+        assert_eq!(cache.misses(), 2);
+
+        // 2 is the least recently used
+        assert_eq!(cache.get_or_insert_with(3, || key(3)), key(3));
+        assert!(cache.contains(1));
+        assert!(!cache.contains(2));
+        assert!(cache.contains(3));
+
+        cache.remove(1);
+        assert!(!cache.contains(1));
+        cache.clear();
+        assert!(!cache.contains(3));
+    }
+
+    #[test]
+    fn no_capacity() {
+        let cache = SharedKeyCache::new(0);
+        assert_eq!(cache.get_or_insert_with(1, || key(1)), key(1));
+        assert!(!cache.contains(1));
+    }
+}
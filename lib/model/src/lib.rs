@@ -16,16 +16,32 @@ macro_rules! partial_eq_impl {
     };
 }
 
+/// Hash a verified type like its unverified counterpart (with a derived [Hash]),
+/// given the fields they share, in declaration order.
+macro_rules! hash_impl {
+    ($T:ty ; $($field:ident),+) => {
+        impl std::hash::Hash for $T {
+            fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+                $(self.$field.hash(state);)+
+            }
+        }
+    };
+}
+
 pub mod api;
 pub mod base64_serialization;
 pub mod keys;
 mod misbehavior_proof;
 pub mod neighbourhood;
 mod position_proof;
+mod proof_bundle;
 mod proximity_proof;
 mod proximity_proof_request;
+mod world_bounds;
 
 use serde::{Deserialize, Serialize};
+use std::num::NonZeroU32;
+
 #[derive(Debug, Default, PartialEq, Clone, Copy, Hash, Serialize, Deserialize, Eq)]
 pub struct Position(pub i64, pub i64);
 
@@ -33,12 +49,138 @@ impl Position {
     pub fn to_bytes(&self) -> Vec<u8> {
         [self.0.to_be_bytes(), self.1.to_be_bytes()].concat()
     }
+
+    /// Component-wise addition, or `None` on overflow.
+    pub fn checked_add(self, other: Position) -> Option<Position> {
+        Some(Position(
+            self.0.checked_add(other.0)?,
+            self.1.checked_add(other.1)?,
+        ))
+    }
+
+    /// Component-wise subtraction, or `None` on overflow.
+    pub fn checked_sub(self, other: Position) -> Option<Position> {
+        Some(Position(
+            self.0.checked_sub(other.0)?,
+            self.1.checked_sub(other.1)?,
+        ))
+    }
+
+    /// Every position in the rectangle between two corners, including both of them.
+    ///
+    /// Positions are yielded row by row. The rectangle is empty if `top_left` is not
+    /// above and to the left of (or the same as) `bottom_right`.
+    pub fn region(top_left: Position, bottom_right: Position) -> impl Iterator<Item = Position> {
+        (top_left.1..=bottom_right.1)
+            .flat_map(move |y| (top_left.0..=bottom_right.0).map(move |x| Position(x, y)))
+    }
+
+    /// Round position to the nearest point of a grid with the given spacing.
+    ///
+    /// Halfway positions are rounded up, so every point of the grid stands for a square
+    /// cell of `grid` by `grid` positions (see [Position::coarse_cell]).
+    /// Points past the numeric bounds saturate to them.
+    pub fn coarsen(self, grid: NonZeroU32) -> Position {
+        let grid = i128::from(grid.get());
+        let round = |c: i64| {
+            let rounded = (i128::from(c) + grid / 2).div_euclid(grid) * grid;
+            rounded.clamp(i64::MIN.into(), i64::MAX.into()) as i64
+        };
+
+        Position(round(self.0), round(self.1))
+    }
+
+    /// Corners of the cell of positions that [coarsen](Position::coarsen) to the same point as this one.
+    ///
+    /// Cells at the numeric bounds are cut short by them.
+    pub fn coarse_cell(self, grid: NonZeroU32) -> (Position, Position) {
+        let center = self.coarsen(grid);
+        let grid = i64::from(grid.get());
+        let top_left = center - Position(grid / 2, grid / 2);
+
+        (top_left, top_left + Position(grid - 1, grid - 1))
+    }
+}
+
+impl std::ops::Add for Position {
+    type Output = Position;
+
+    /// Component-wise addition, saturating at the numeric bounds.
+    /// See [Position::checked_add] to detect overflow.
+    fn add(self, other: Position) -> Position {
+        Position(
+            self.0.saturating_add(other.0),
+            self.1.saturating_add(other.1),
+        )
+    }
+}
+
+impl std::ops::Sub for Position {
+    type Output = Position;
+
+    /// Component-wise subtraction, saturating at the numeric bounds.
+    /// See [Position::checked_sub] to detect overflow.
+    fn sub(self, other: Position) -> Position {
+        Position(
+            self.0.saturating_sub(other.0),
+            self.1.saturating_sub(other.1),
+        )
+    }
+}
+
+/// [Position] with named coordinates, serialized as `{"x": .., "y": ..}`.
+///
+/// Positions serialize as a terse `[x, y]`, which is opaque to external consumers: convert them
+/// to this at external-facing APIs, or use [position_named] on [Position] fields.
+#[derive(Debug, Default, PartialEq, Clone, Copy, Hash, Serialize, Deserialize, Eq)]
+pub struct PositionNamed {
+    pub x: i64,
+    pub y: i64,
+}
+
+impl From<Position> for PositionNamed {
+    fn from(position: Position) -> Self {
+        PositionNamed {
+            x: position.0,
+            y: position.1,
+        }
+    }
+}
+
+impl From<PositionNamed> for Position {
+    fn from(position: PositionNamed) -> Self {
+        Position(position.x, position.y)
+    }
+}
+
+/// (De)serialize a [Position] with named coordinates, like [PositionNamed].
+///
+/// For use with `#[serde(with = "model::position_named")]`.
+pub mod position_named {
+    use super::{Position, PositionNamed};
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S>(position: &Position, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        PositionNamed::from(*position).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Position, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        PositionNamed::deserialize(deserializer).map(Position::from)
+    }
 }
 
 pub use misbehavior_proof::*;
 pub use position_proof::*;
+pub use proof_bundle::*;
 pub use proximity_proof::*;
 pub use proximity_proof_request::*;
+pub use world_bounds::*;
 
 use std::sync::atomic::{AtomicBool, Ordering};
 static INITIALIZED: AtomicBool = AtomicBool::new(false);
@@ -50,3 +192,126 @@ pub fn ensure_init() {
         INITIALIZED.store(true, Ordering::Release);
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn position_arithmetic() {
+        assert_eq!(Position(1, 2) + Position(3, -4), Position(4, -2));
+        assert_eq!(Position(1, 2) - Position(3, -4), Position(-2, 6));
+        assert_eq!(Position(5, 7) + Position::default(), Position(5, 7));
+        assert_eq!(Position(5, 7) - Position(5, 7), Position::default());
+    }
+
+    #[test]
+    fn position_add_overflow() {
+        assert_eq!(Position(i64::MAX, 0).checked_add(Position(1, 0)), None);
+        assert_eq!(
+            Position(i64::MAX, 0) + Position(1, 0),
+            Position(i64::MAX, 0)
+        );
+        assert_eq!(
+            Position(1, 2).checked_add(Position(3, -4)),
+            Some(Position(4, -2))
+        );
+    }
+
+    #[test]
+    fn position_sub_overflow() {
+        assert_eq!(Position(0, i64::MIN).checked_sub(Position(0, 1)), None);
+        assert_eq!(
+            Position(0, i64::MIN) - Position(0, 1),
+            Position(0, i64::MIN)
+        );
+        assert_eq!(
+            Position(1, 2).checked_sub(Position(3, -4)),
+            Some(Position(-2, 6))
+        );
+    }
+
+    #[test]
+    fn position_region() {
+        let region: Vec<_> = Position::region(Position(1, 2), Position(3, 5)).collect();
+        assert_eq!(region.len(), 3 * 4);
+        assert_eq!(region.first(), Some(&Position(1, 2)));
+        assert_eq!(region.last(), Some(&Position(3, 5)));
+        assert!(region.contains(&Position(3, 2)));
+        assert!(region.contains(&Position(1, 5)));
+        assert!(region
+            .iter()
+            .all(|p| (1..=3).contains(&p.0) && (2..=5).contains(&p.1)));
+
+        assert_eq!(
+            Position::region(Position(4, 4), Position(4, 4)).collect::<Vec<_>>(),
+            vec![Position(4, 4)]
+        );
+        assert_eq!(Position::region(Position(1, 1), Position(0, 5)).count(), 0);
+        assert_eq!(Position::region(Position(1, 1), Position(5, 0)).count(), 0);
+    }
+
+    #[test]
+    fn position_coarsen() {
+        let ten = NonZeroU32::new(10).unwrap();
+        assert_eq!(Position(14, 15).coarsen(ten), Position(10, 20));
+        assert_eq!(Position(-6, -5).coarsen(ten), Position(-10, 0));
+        assert_eq!(Position(0, 0).coarsen(ten), Position(0, 0));
+
+        let one = NonZeroU32::new(1).unwrap();
+        assert_eq!(Position(-7, 3).coarsen(one), Position(-7, 3));
+        assert_eq!(
+            Position(-7, 3).coarse_cell(one),
+            (Position(-7, 3), Position(-7, 3))
+        );
+
+        let (top_left, bottom_right) = Position(14, 15).coarse_cell(ten);
+        assert_eq!(
+            (top_left, bottom_right),
+            (Position(5, 15), Position(14, 24))
+        );
+        assert!(
+            Position::region(top_left, bottom_right).all(|p| p.coarsen(ten) == Position(10, 20))
+        );
+        assert_ne!(Position(4, 15).coarsen(ten), Position(10, 20));
+        assert_ne!(Position(15, 15).coarsen(ten), Position(10, 20));
+
+        // no overflow at the edges
+        let far = Position(i64::MAX, i64::MIN);
+        assert_eq!(far.coarsen(ten), Position(i64::MAX, i64::MIN));
+        let (top_left, bottom_right) = far.coarse_cell(ten);
+        assert!(top_left.0 <= far.0 && far.0 <= bottom_right.0);
+        assert!(top_left.1 <= far.1 && far.1 <= bottom_right.1);
+    }
+
+    #[test]
+    fn position_serialization() {
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct External {
+            #[serde(with = "position_named")]
+            position: Position,
+        }
+
+        // compact tuple internally
+        let position = Position(3, -4);
+        let json = serde_json::to_string(&position).unwrap();
+        assert_eq!(json, "[3,-4]");
+        assert_eq!(serde_json::from_str::<Position>(&json).unwrap(), position);
+
+        // named fields for external consumers
+        let named = PositionNamed::from(position);
+        let json = serde_json::to_string(&named).unwrap();
+        assert_eq!(json, r#"{"x":3,"y":-4}"#);
+        let deserialized: PositionNamed = serde_json::from_str(&json).unwrap();
+        assert_eq!(Position::from(deserialized), position);
+
+        let external = External { position };
+        let json = serde_json::to_string(&external).unwrap();
+        assert_eq!(json, r#"{"position":{"x":3,"y":-4}}"#);
+        assert_eq!(serde_json::from_str::<External>(&json).unwrap(), external);
+
+        // named positions also accept the compact form, but not the other way around
+        assert!(serde_json::from_str::<PositionNamed>("[3,-4]").is_ok());
+        assert!(serde_json::from_str::<Position>(r#"{"x":3,"y":-4}"#).is_err());
+    }
+}
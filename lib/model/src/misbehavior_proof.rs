@@ -1,6 +1,7 @@
 use crate::keys::EntityId;
 use crate::{
-    keys::KeyStore, ProximityProof, ProximityProofValidationError, UnverifiedProximityProof,
+    keys::KeyStore, Position, ProximityProof, ProximityProofValidationError,
+    UnverifiedProximityProof,
 };
 
 use serde::{Deserialize, Serialize};
@@ -63,12 +64,85 @@ pub enum MisbehaviorProofValidationError {
     InvalidProximityProof(#[from] ProximityProofValidationError),
 }
 
+/// What a proximity proof states, whether it was verified or not.
+#[derive(Clone, Copy)]
+struct Claims {
+    epoch: u64,
+    prover_id: EntityId,
+    position: Position,
+    witness_id: EntityId,
+    witness_position: Position,
+}
+
+impl From<&ProximityProof> for Claims {
+    fn from(p: &ProximityProof) -> Self {
+        Claims {
+            epoch: p.epoch(),
+            prover_id: p.prover_id(),
+            position: p.position(),
+            witness_id: p.witness_id(),
+            witness_position: p.witness_position(),
+        }
+    }
+}
+
+impl From<&UnverifiedProximityProof> for Claims {
+    fn from(p: &UnverifiedProximityProof) -> Self {
+        Claims {
+            epoch: p.request.epoch,
+            prover_id: p.request.prover_id,
+            position: p.request.position,
+            witness_id: p.witness_id,
+            witness_position: p.witness_position,
+        }
+    }
+}
+
+/// How two proofs show a user misbehaving (if at all), and whether they must be swapped so
+/// that the prover precedes the witness.
+fn judge(user_id: EntityId, a: Claims, b: Claims) -> Option<(MisbehaviorProofKind, bool)> {
+    if a.epoch != b.epoch {
+        return None;
+    }
+
+    let swap = a.prover_id != user_id && b.prover_id == user_id;
+    let (a, b) = if swap { (b, a) } else { (a, b) };
+
+    let kind = if a.prover_id == b.prover_id && a.prover_id == user_id && a.position != b.position {
+        MisbehaviorProofKind::ProverProver
+    } else if a.prover_id == b.witness_id
+        && a.prover_id == user_id
+        && a.position != b.witness_position
+    {
+        MisbehaviorProofKind::ProverWitness
+    } else if a.witness_id == b.witness_id
+        && a.witness_id == user_id
+        && a.witness_position != b.witness_position
+    {
+        MisbehaviorProofKind::WitnessWitness
+    } else {
+        return None;
+    };
+
+    Some((kind, swap))
+}
+
 impl UnverifiedMisbehaviorProof {
     /// Verifies this proof (including the underlying proximity proofs), converting it into a [MisbehaviorProof].
+    ///
+    /// Proofs that wouldn't show any misbehaviour are rejected before checking their signatures.
     pub fn verify(
         self,
         keystore: &KeyStore,
     ) -> Result<MisbehaviorProof, MisbehaviorProofValidationError> {
+        if judge(self.user_id, (&self.a).into(), (&self.b).into()).is_none() {
+            return Err(MisbehaviorProofValidationError::NoMisbehaviorHere {
+                user_id: self.user_id,
+                a: self.a,
+                b: self.b,
+            });
+        }
+
         let a = self.a.verify(&keystore)?;
         let b = self.b.verify(&keystore)?;
         MisbehaviorProof::new(self.user_id, a, b)
@@ -78,44 +152,24 @@ impl UnverifiedMisbehaviorProof {
 impl MisbehaviorProof {
     /// Construct a misbehavior proof for a given user from two proximity proofs.
     ///
-    /// Keep in mind that when trying to derive the proof from a user acting as
-    /// a witness and prover, the proximity proof where they act as the prover must
-    /// be passed first.
+    /// The proofs may be passed in any order: when the user acts as a witness in `a` and
+    /// as the prover in `b`, they are swapped so that the prover always precedes the witness.
     pub fn new(
         user_id: EntityId,
         a: ProximityProof,
         b: ProximityProof,
     ) -> Result<Self, MisbehaviorProofValidationError> {
-        if a.epoch() != b.epoch() {
-            return Err(MisbehaviorProofValidationError::NoMisbehaviorHere {
-                user_id,
-                a: a.into(),
-                b: b.into(),
-            });
-        }
-
-        let kind = if a.prover_id() == b.prover_id()
-            && a.prover_id() == user_id
-            && a.position() != b.position()
-        {
-            MisbehaviorProofKind::ProverProver
-        } else if a.prover_id() == b.witness_id()
-            && a.prover_id() == user_id
-            && a.position() != b.witness_position()
-        {
-            MisbehaviorProofKind::ProverWitness
-        } else if a.witness_id() == b.witness_id()
-            && a.witness_id() == user_id
-            && a.witness_position() != b.witness_position()
-        {
-            MisbehaviorProofKind::WitnessWitness
-        } else {
-            return Err(MisbehaviorProofValidationError::NoMisbehaviorHere {
-                user_id,
-                a: a.into(),
-                b: b.into(),
-            });
+        let (kind, swap) = match judge(user_id, (&a).into(), (&b).into()) {
+            Some(judgement) => judgement,
+            None => {
+                return Err(MisbehaviorProofValidationError::NoMisbehaviorHere {
+                    user_id,
+                    a: a.into(),
+                    b: b.into(),
+                })
+            }
         };
+        let (a, b) = if swap { (b, a) } else { (a, b) };
 
         Ok(MisbehaviorProof {
             kind,
@@ -137,6 +191,19 @@ impl MisbehaviorProof {
     pub fn b(&self) -> ProximityProof {
         self.b.clone()
     }
+
+    /// Where the user claimed to be in each proof, as prover or witness.
+    pub fn positions(&self) -> (Position, Position) {
+        let claimed = |p: &ProximityProof| {
+            if p.prover_id() == self.user_id {
+                p.position()
+            } else {
+                p.witness_position()
+            }
+        };
+
+        (claimed(&self.a), claimed(&self.b))
+    }
 }
 
 impl From<MisbehaviorProof> for UnverifiedMisbehaviorProof {
@@ -187,6 +254,7 @@ mod test {
             .unwrap();
         assert_eq!(KEYSTORES.user1.my_id(), mp.user_id());
         assert_eq!(MisbehaviorProofKind::ProverProver, mp.kind);
+        assert_eq!((POS_A, POS_B), mp.positions());
 
         assert!(matches!(
             MisbehaviorProof::new(KEYSTORES.user2.my_id(), proof_a, proof_b),
@@ -213,6 +281,29 @@ mod test {
         ));
     }
 
+    #[test]
+    fn witness_prover() {
+        let req_a = ProximityProofRequest::new(1, POS_A, &KEYSTORES.user2);
+        let proof_a = ProximityProof::new(req_a, POS_B, &KEYSTORES.user1).unwrap();
+
+        let req_b = ProximityProofRequest::new(1, POS_A, &KEYSTORES.user1);
+        let proof_b = ProximityProof::new(req_b, POS_A, &KEYSTORES.user2).unwrap();
+
+        // witness account first: normalized to prover-witness
+        let mp = MisbehaviorProof::new(KEYSTORES.user1.my_id(), proof_a.clone(), proof_b.clone())
+            .unwrap();
+        assert_eq!(KEYSTORES.user1.my_id(), mp.user_id());
+        assert_eq!(MisbehaviorProofKind::ProverWitness, mp.kind);
+        assert_eq!(proof_b, mp.a());
+        assert_eq!(proof_a, mp.b());
+        assert_eq!((POS_A, POS_B), mp.positions());
+
+        assert_eq!(
+            mp,
+            MisbehaviorProof::new(KEYSTORES.user1.my_id(), proof_b, proof_a).unwrap()
+        );
+    }
+
     #[test]
     fn witness_witness() {
         let req_a = ProximityProofRequest::new(1, POS_A, &KEYSTORES.user1);
@@ -225,6 +316,7 @@ mod test {
             .unwrap();
         assert_eq!(KEYSTORES.user2.my_id(), mp.user_id());
         assert_eq!(MisbehaviorProofKind::WitnessWitness, mp.kind);
+        assert_eq!((POS_A, POS_B), mp.positions());
 
         assert!(matches!(
             MisbehaviorProof::new(KEYSTORES.user1.my_id(), proof_a, proof_b),
@@ -232,6 +324,40 @@ mod test {
         ));
     }
 
+    #[test]
+    fn unverified_checks_consistency_first() {
+        let req_a = ProximityProofRequest::new(1, POS_A, &KEYSTORES.user1);
+        let proof_a = ProximityProof::new(req_a, POS_A, &KEYSTORES.user2).unwrap();
+
+        let req_b = ProximityProofRequest::new(2, POS_B, &KEYSTORES.user1);
+        let proof_b = ProximityProof::new(req_b, POS_A, &KEYSTORES.user2).unwrap();
+
+        // different epochs, with a bad signature: rejected without getting to the signature
+        let mut unverified_b = UnverifiedProximityProof::from(proof_b);
+        unverified_b.signature = UnverifiedProximityProof::from(proof_a.clone()).signature;
+        let unverified = UnverifiedMisbehaviorProof {
+            user_id: KEYSTORES.user1.my_id(),
+            a: proof_a.clone().into(),
+            b: unverified_b.clone(),
+        };
+        assert!(matches!(
+            unverified.verify(&KEYSTORES.user1),
+            Err(MisbehaviorProofValidationError::NoMisbehaviorHere { .. })
+        ));
+
+        // same epoch: the signature is checked
+        unverified_b.request.epoch = 1;
+        let unverified = UnverifiedMisbehaviorProof {
+            user_id: KEYSTORES.user1.my_id(),
+            a: proof_a.into(),
+            b: unverified_b,
+        };
+        assert!(matches!(
+            unverified.verify(&KEYSTORES.user1),
+            Err(MisbehaviorProofValidationError::InvalidProximityProof(_))
+        ));
+    }
+
     #[test]
     fn not_misbehavior() {
         let req_a = ProximityProofRequest::new(1, POS_A, &KEYSTORES.user1);
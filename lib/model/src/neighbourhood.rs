@@ -1,3 +1,6 @@
+use std::collections::HashSet;
+
+use crate::keys::EntityId;
 use crate::Position;
 
 const NEIGHBOURHOOD_DISTANCE: usize = 100;
@@ -14,6 +17,280 @@ const NEIGHBOURHOOD_DISTANCE: usize = 100;
 /// the difference vector.
 ///
 pub fn are_neighbours(a: Position, b: Position) -> bool {
-    (((a.0 as isize - b.0 as isize) + (a.1 as isize - b.1 as isize)).abs() as usize)
-        < NEIGHBOURHOOD_DISTANCE
+    are_neighbours_with(&NeighbourhoodConfig::default(), a, b)
+}
+
+/// Like [are_neighbours], but with a custom neighbourhood definition.
+///
+/// Besides being close enough, a and b must have line of sight: no obstacle may cross
+/// (or touch) the segment between them. Positions too far apart to even compute their
+/// distance are never neighbours.
+///
+pub fn are_neighbours_with(config: &NeighbourhoodConfig, a: Position, b: Position) -> bool {
+    let distance = a
+        .checked_sub(b)
+        .and_then(|d| d.0.checked_add(d.1))
+        .and_then(i64::checked_abs);
+
+    matches!(distance, Some(distance) if (distance as u64) < config.distance as u64)
+        && !config.obstacles.iter().any(|o| o.blocks(a, b))
+}
+
+/// Entities a user may ask to witness its position: the candidates in its neighbourhood
+/// (as defined by `config`), never the user itself.
+///
+/// `origin` and `candidates` are (entity, position) pairs. The closest candidates are
+/// preferred, and at most `count` are returned, without repeats. Candidates too far away
+/// to even compute their distance are never chosen.
+///
+pub fn choose_witnesses<I>(
+    origin: (EntityId, Position),
+    candidates: I,
+    config: &NeighbourhoodConfig,
+    count: usize,
+) -> Vec<EntityId>
+where
+    I: IntoIterator<Item = (EntityId, Position)>,
+{
+    let (origin_id, origin_position) = origin;
+    let mut eligible: Vec<_> = candidates
+        .into_iter()
+        .filter(|&(id, _)| id != origin_id)
+        .filter(|&(_, position)| are_neighbours_with(config, origin_position, position))
+        .filter_map(|(id, position)| {
+            let d = position.checked_sub(origin_position)?;
+            let distance = d.0.checked_abs()?.checked_add(d.1.checked_abs()?)?;
+            Some((distance, id))
+        })
+        .collect();
+    eligible.sort_unstable();
+
+    let mut seen = HashSet::new();
+    eligible
+        .into_iter()
+        .map(|(_, id)| id)
+        .filter(|id| seen.insert(*id))
+        .take(count)
+        .collect()
+}
+
+/// Definition of which positions are neighbours, see [are_neighbours_with].
+#[derive(Debug, Clone, PartialEq)]
+pub struct NeighbourhoodConfig {
+    /// Positions must be closer than this to be neighbours.
+    pub distance: usize,
+
+    /// Walls that block communication between positions on opposite sides.
+    pub obstacles: Vec<Obstacle>,
+}
+
+impl Default for NeighbourhoodConfig {
+    fn default() -> Self {
+        NeighbourhoodConfig {
+            distance: NEIGHBOURHOOD_DISTANCE,
+            obstacles: vec![],
+        }
+    }
+}
+
+/// An axis-aligned wall segment, including both ends.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Obstacle {
+    start: Position,
+    end: Position,
+}
+
+impl Obstacle {
+    /// Creates a wall between two positions.
+    ///
+    /// Panics if the positions are not on the same horizontal or vertical line.
+    pub fn new(start: Position, end: Position) -> Self {
+        assert!(
+            start.0 == end.0 || start.1 == end.1,
+            "obstacles must be axis-aligned"
+        );
+
+        Obstacle { start, end }
+    }
+
+    /// One of the ends of the wall.
+    pub fn start(&self) -> Position {
+        self.start
+    }
+
+    /// The other end of the wall.
+    pub fn end(&self) -> Position {
+        self.end
+    }
+
+    /// Whether the wall crosses or touches the segment between a and b.
+    pub fn blocks(&self, a: Position, b: Position) -> bool {
+        let (p, q) = (self.start, self.end);
+        let (o1, o2) = (orientation(a, b, p), orientation(a, b, q));
+        let (o3, o4) = (orientation(p, q, a), orientation(p, q, b));
+
+        (o1 * o2 < 0 && o3 * o4 < 0)
+            || (o1 == 0 && in_bounding_box(a, b, p))
+            || (o2 == 0 && in_bounding_box(a, b, q))
+            || (o3 == 0 && in_bounding_box(p, q, a))
+            || (o4 == 0 && in_bounding_box(p, q, b))
+    }
+}
+
+/// Sign of the cross product of (b - a) and (c - a): which side of line ab c is on (0 if on it).
+fn orientation(a: Position, b: Position, c: Position) -> i8 {
+    let cross = (b.0 as i128 - a.0 as i128) * (c.1 as i128 - a.1 as i128)
+        - (b.1 as i128 - a.1 as i128) * (c.0 as i128 - a.0 as i128);
+
+    cross.signum() as i8
+}
+
+/// Whether c is within the bounding box of the segment ab.
+fn in_bounding_box(a: Position, b: Position, c: Position) -> bool {
+    a.0.min(b.0) <= c.0 && c.0 <= a.0.max(b.0) && a.1.min(b.1) <= c.1 && c.1 <= a.1.max(b.1)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn default_config() {
+        assert!(are_neighbours(Position(0, 0), Position(10, 10)));
+        assert!(!are_neighbours(Position(0, 0), Position(100, 0)));
+        assert_eq!(
+            are_neighbours(Position(0, 0), Position(10, 10)),
+            are_neighbours_with(
+                &NeighbourhoodConfig::default(),
+                Position(0, 0),
+                Position(10, 10)
+            )
+        );
+    }
+
+    #[test]
+    fn far_apart() {
+        assert!(!are_neighbours(
+            Position(i64::MIN, 0),
+            Position(i64::MAX, 0)
+        ));
+        assert!(!are_neighbours(
+            Position(0, i64::MAX),
+            Position(0, i64::MIN)
+        ));
+        assert!(!are_neighbours(
+            Position(i64::MIN, i64::MIN),
+            Position(0, 0)
+        ));
+        assert!(are_neighbours(
+            Position(i64::MAX, i64::MAX),
+            Position(i64::MAX - 1, i64::MAX)
+        ));
+    }
+
+    #[test]
+    fn wall_blocks_line_of_sight() {
+        let (a, b) = (Position(0, 0), Position(10, 0));
+        let mut config = NeighbourhoodConfig::default();
+        assert!(are_neighbours_with(&config, a, b));
+
+        // vertical wall between them
+        config
+            .obstacles
+            .push(Obstacle::new(Position(5, -5), Position(5, 5)));
+        assert!(!are_neighbours_with(&config, a, b));
+        assert!(!are_neighbours_with(&config, b, a));
+
+        // positions on the same side can still see each other
+        assert!(are_neighbours_with(&config, a, Position(0, 10)));
+
+        config.obstacles.clear();
+        assert!(are_neighbours_with(&config, a, b));
+    }
+
+    #[test]
+    fn wall_edges() {
+        let wall = Obstacle::new(Position(5, 0), Position(5, 10));
+
+        // touching the end of the wall blocks
+        assert!(wall.blocks(Position(0, 10), Position(10, 10)));
+        assert!(wall.blocks(Position(5, 5), Position(10, 5)));
+
+        // passing beyond it doesn't
+        assert!(!wall.blocks(Position(0, 11), Position(10, 11)));
+        assert!(!wall.blocks(Position(0, -1), Position(10, -1)));
+
+        // neither does stopping short of it
+        assert!(!wall.blocks(Position(0, 5), Position(4, 5)));
+
+        // collinear overlap blocks, collinear but apart doesn't
+        assert!(wall.blocks(Position(5, -5), Position(5, 2)));
+        assert!(!wall.blocks(Position(5, 11), Position(5, 20)));
+    }
+
+    fn candidates() -> Vec<(EntityId, Position)> {
+        vec![
+            (1, Position(0, 0)),
+            (2, Position(3, 0)),
+            (3, Position(1, 1)),
+            (4, Position(500, 500)),
+            (5, Position(-2, 0)),
+            (3, Position(1, 1)),
+        ]
+    }
+
+    #[test]
+    fn witnesses_never_origin() {
+        let config = NeighbourhoodConfig::default();
+        let chosen = choose_witnesses((1, Position(0, 0)), candidates(), &config, usize::MAX);
+        assert!(!chosen.contains(&1));
+    }
+
+    #[test]
+    fn witnesses_in_neighbourhood() {
+        let mut config = NeighbourhoodConfig::default();
+        let chosen = choose_witnesses((1, Position(0, 0)), candidates(), &config, usize::MAX);
+
+        // closest first, without repeats or far away entities
+        assert_eq!(chosen, vec![3, 5, 2]);
+
+        // nor ones behind walls
+        config
+            .obstacles
+            .push(Obstacle::new(Position(-1, -5), Position(-1, 5)));
+        let chosen = choose_witnesses((1, Position(0, 0)), candidates(), &config, usize::MAX);
+        assert_eq!(chosen, vec![3, 2]);
+    }
+
+    #[test]
+    fn witnesses_count() {
+        let config = NeighbourhoodConfig::default();
+        for count in 0..5 {
+            let chosen = choose_witnesses((1, Position(0, 0)), candidates(), &config, count);
+            assert_eq!(chosen.len(), count.min(3));
+        }
+        assert_eq!(
+            choose_witnesses((1, Position(0, 0)), candidates(), &config, 2),
+            vec![3, 5]
+        );
+    }
+
+    #[test]
+    fn witnesses_far_away() {
+        let config = NeighbourhoodConfig::default();
+        let origin = (1, Position(0, 0));
+
+        // the coordinates cancel out, but the distance can't be computed
+        let far = vec![(2, Position(i64::MAX, -i64::MAX)), (3, Position(1, 0))];
+        assert_eq!(choose_witnesses(origin, far, &config, usize::MAX), vec![3]);
+
+        let far = vec![(2, Position(i64::MIN, 0))];
+        assert!(choose_witnesses(origin, far, &config, usize::MAX).is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "obstacles must be axis-aligned")]
+    fn diagonal_obstacle() {
+        Obstacle::new(Position(0, 0), Position(1, 1));
+    }
 }
@@ -1,11 +1,12 @@
 use itertools::Itertools;
 use serde::{Deserialize, Serialize};
+use sodiumoxide::crypto::hash::sha256;
 use thiserror::Error;
 
 use crate::keys::{EntityId, KeyStore};
 use crate::{
     Position, ProximityProof, ProximityProofRequest, ProximityProofValidationError,
-    UnverifiedProximityProof,
+    UnverifiedProximityProof, WorldBounds,
 };
 
 #[derive(Error, Debug)]
@@ -18,6 +19,29 @@ pub enum PositionProofValidationError {
 
     #[error("Invalid witness")]
     InvalidWitness(#[from] ProximityProofValidationError),
+
+    #[error("Prover {} is a witness of their own position", .0)]
+    ProverIsWitness(EntityId),
+
+    #[error("Witnesses are not spread out enough (needs {} distinct positions, has {})", .required, .available)]
+    NotEnoughWitnessPositions { required: usize, available: usize },
+
+    #[error("Position {:?} is outside the world", .0)]
+    OutOfBounds(Position),
+}
+
+/// Extra requirements for a [PositionProof] to be valid, on top of the number of witnesses.
+///
+/// See [PositionProof::new_with_policy]. The default policy imposes no extra requirements.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct PositionProofPolicy {
+    /// Minimum number of distinct positions the witnesses must be in.
+    ///
+    /// Hardens against a cluster of colluding witnesses all in one spot.
+    pub min_witness_positions: usize,
+
+    /// World the prover and every witness must be in, if any.
+    pub bounds: Option<WorldBounds>,
 }
 
 /// A proof that a user was in some position at some epoch, derived from a quorum
@@ -30,7 +54,7 @@ pub enum PositionProofValidationError {
 /// Instances of this struct are guaranteed to be valid and therefore it implements [Serialize]
 /// but not [Deserialize]. To deserialize a [PositionProof] see [UnverifiedPositionProof::verify].
 /// A serialized [PositionProof] deserialized as an [UnverifiedPositionProof] is guaranteed to be equal to the original proof.
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct PositionProof {
     /// Witness accounts of a user being in a position at an epoch. Guaranteed to be free of duplicates.
     witnesses: Vec<ProximityProof>,
@@ -61,6 +85,16 @@ impl UnverifiedPositionProof {
         self,
         neighbour_faults: usize,
         keystore: &KeyStore,
+    ) -> Result<PositionProof, PositionProofValidationError> {
+        self.verify_with_policy(neighbour_faults, &PositionProofPolicy::default(), keystore)
+    }
+
+    /// Like [verify](Self::verify), but also enforcing the given `policy`.
+    pub fn verify_with_policy(
+        self,
+        neighbour_faults: usize,
+        policy: &PositionProofPolicy,
+        keystore: &KeyStore,
     ) -> Result<PositionProof, PositionProofValidationError> {
         let witnesses = self
             .witnesses
@@ -68,7 +102,7 @@ impl UnverifiedPositionProof {
             .map(|p| p.verify(keystore))
             .try_collect()?;
 
-        PositionProof::new(witnesses, neighbour_faults)
+        PositionProof::new_with_policy(witnesses, neighbour_faults, policy)
     }
 
     /// Marks a position proof as verified without actually performing any checks.
@@ -90,19 +124,58 @@ impl UnverifiedPositionProof {
 
         PositionProof { witnesses }
     }
+
+    /// Epoch the proof claims to be for (that of its first witness), if it has any witnesses.
+    ///
+    /// Not to be trusted before verification: meant to pick how to [verify](Self::verify) it.
+    pub fn claimed_epoch(&self) -> Option<u64> {
+        self.witnesses.first().map(|w| w.request.epoch)
+    }
+
+    /// SHA-256 digest of the serialized proof, suitable for use as an idempotency key.
+    pub fn digest(&self) -> [u8; 32] {
+        let bytes = bincode::serialize(self).expect("could not serialize position proof");
+        let sha256::Digest(digest) = sha256::hash(&bytes);
+
+        digest
+    }
 }
 
 impl PositionProof {
     /// Construct a PositionProof from a set of witness accounts.
     ///
     /// The list of witness accounts may contain duplicates, they will be ignored.
-    /// Will return an error if witnesses refer to different [ProximityProofRequest]s or
-    /// if there are not enough witnesses to satisfy the given `neighbour_faults`.
+    /// Will return an error if witnesses refer to different [ProximityProofRequest]s,
+    /// if the prover is among them, or if there are not enough witnesses to satisfy
+    /// the given `neighbour_faults`.
     ///
     /// Will panic if passed an empty list of witnesess.
     pub fn new(
+        witnesses: Vec<ProximityProof>,
+        neighbour_faults: usize,
+    ) -> Result<PositionProof, PositionProofValidationError> {
+        Self::new_with_policy(witnesses, neighbour_faults, &PositionProofPolicy::default())
+    }
+
+    /// Like [new](Self::new), but not having enough witnesses (or none at all) is not an error.
+    ///
+    /// For callers that skip users without a proof, but must not ignore any other problem.
+    pub fn from_witnesses_or_none(
+        witnesses: Vec<ProximityProof>,
+        neighbour_faults: usize,
+    ) -> Result<Option<PositionProof>, PositionProofValidationError> {
+        match Self::new(witnesses, neighbour_faults) {
+            Ok(proof) => Ok(Some(proof)),
+            Err(PositionProofValidationError::NotEnoughWitnesess { .. }) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Like [new](Self::new), but also enforcing the given `policy`.
+    pub fn new_with_policy(
         mut witnesses: Vec<ProximityProof>,
         neighbour_faults: usize,
+        policy: &PositionProofPolicy,
     ) -> Result<PositionProof, PositionProofValidationError> {
         if witnesses.is_empty() {
             return Err(PositionProofValidationError::NotEnoughWitnesess {
@@ -121,6 +194,13 @@ impl PositionProof {
             }
         }
 
+        // proximity proofs are never self-signed, unless constructed without checks
+        if witnesses.iter().any(|w| w.witness_id() == req.prover_id()) {
+            return Err(PositionProofValidationError::ProverIsWitness(
+                req.prover_id(),
+            ));
+        }
+
         // Remove duplicates
         witnesses.sort_unstable_by_key(|w| w.witness_id());
         witnesses.dedup_by_key(|w| w.witness_id());
@@ -133,6 +213,29 @@ impl PositionProof {
             });
         }
 
+        if let Some(bounds) = &policy.bounds {
+            let positions = std::iter::once(proof.position())
+                .chain(proof.witnesses.iter().map(|w| w.witness_position()));
+            for position in positions {
+                if !bounds.contains(&position) {
+                    return Err(PositionProofValidationError::OutOfBounds(position));
+                }
+            }
+        }
+
+        let witness_positions = proof
+            .witnesses
+            .iter()
+            .map(|w| w.witness_position())
+            .unique()
+            .count();
+        if witness_positions < policy.min_witness_positions {
+            return Err(PositionProofValidationError::NotEnoughWitnessPositions {
+                required: policy.min_witness_positions,
+                available: witness_positions,
+            });
+        }
+
         Ok(proof)
     }
 
@@ -148,6 +251,14 @@ impl PositionProof {
         self.witnesses[0].request().prover_id()
     }
 
+    /// Identifiers of every entity involved in this proof: the prover followed by the witnesses, without duplicates.
+    pub fn involved_ids(&self) -> Vec<EntityId> {
+        std::iter::once(self.prover_id())
+            .chain(self.witnesses.iter().map(|w| w.witness_id()))
+            .unique()
+            .collect()
+    }
+
     /// Position as stated by the prover.
     ///
     /// Shortcut for [`proof.witnesses()[i].request().position()`](ProximityProofRequest::position)
@@ -173,6 +284,7 @@ impl PositionProof {
 }
 
 partial_eq_impl!(PositionProof, UnverifiedPositionProof; witnesses);
+hash_impl!(PositionProof; witnesses);
 
 impl From<PositionProof> for UnverifiedPositionProof {
     fn from(verified: PositionProof) -> Self {
@@ -228,6 +340,12 @@ mod test {
         assert_eq!(PROOF2.neighbour_faults(), 1);
     }
 
+    #[test]
+    fn involved_ids() {
+        assert_eq!(PROOF1.involved_ids(), vec![1, 2, 3]);
+        assert_eq!(PROOF2.involved_ids(), vec![2, 1]);
+    }
+
     #[test]
     fn verified_unverified_equality() {
         let unverified: UnverifiedPositionProof = PROOF2.clone().into();
@@ -242,6 +360,20 @@ mod test {
         assert_eq!(unverified, unverified_deserialized);
     }
 
+    #[test]
+    fn hash_set_dedup() {
+        use std::collections::HashSet;
+
+        let proofs: HashSet<_> = vec![PROOF1.clone(), PROOF1.clone(), PROOF2.clone()]
+            .into_iter()
+            .collect();
+        assert_eq!(proofs.len(), 2);
+        assert!(proofs.contains(&*PROOF1));
+
+        let unverified: HashSet<UnverifiedPositionProof> = proofs.into_iter().map_into().collect();
+        assert!(unverified.contains(&PROOF2.clone().into()));
+    }
+
     #[test]
     fn verify_ok() {
         let unverified1: UnverifiedPositionProof = PROOF1.clone().into();
@@ -303,6 +435,104 @@ mod test {
         ));
     }
 
+    #[test]
+    fn from_witnesses_or_none() {
+        assert_eq!(
+            PositionProof::from_witnesses_or_none(vec![CPROOF1_2.clone(), CPROOF1_3.clone()], 2)
+                .unwrap(),
+            Some(PROOF1.clone())
+        );
+
+        // not enough witnesses, or none at all
+        assert!(
+            PositionProof::from_witnesses_or_none(vec![CPROOF1_2.clone()], 2)
+                .unwrap()
+                .is_none()
+        );
+        assert!(PositionProof::from_witnesses_or_none(vec![], 1)
+            .unwrap()
+            .is_none());
+
+        // anything else is still an error
+        assert!(matches!(
+            PositionProof::from_witnesses_or_none(vec![CPROOF1_2.clone(), CPROOF2_1.clone()], 1),
+            Err(PositionProofValidationError::InconsistentRequest(..))
+        ));
+    }
+
+    #[test]
+    fn witness_spread_policy() {
+        let policy = PositionProofPolicy {
+            min_witness_positions: 2,
+            ..PositionProofPolicy::default()
+        };
+
+        // spread out
+        let proof =
+            PositionProof::new_with_policy(vec![CPROOF1_2.clone(), CPROOF1_3.clone()], 2, &policy)
+                .unwrap();
+        assert_eq!(proof, *PROOF1);
+
+        // clustered in the same spot
+        let cproof1_3_clustered =
+            ProximityProof::new(CREQ1.clone(), Position(3, 3), &KEYSTORES.user3).unwrap();
+        let clustered = vec![CPROOF1_2.clone(), cproof1_3_clustered];
+        assert!(PositionProof::new(clustered.clone(), 2).is_ok());
+        assert!(matches!(
+            PositionProof::new_with_policy(clustered.clone(), 2, &policy).unwrap_err(),
+            PositionProofValidationError::NotEnoughWitnessPositions {
+                available: 1,
+                required: 2
+            }
+        ));
+
+        let unverified = UnverifiedPositionProof {
+            witnesses: clustered.into_iter().map_into().collect(),
+        };
+        KEYSTORES.iter().for_each(|keystore| {
+            assert!(matches!(
+                unverified.clone().verify_with_policy(2, &policy, keystore),
+                Err(PositionProofValidationError::NotEnoughWitnessPositions { .. })
+            ));
+        });
+    }
+
+    #[test]
+    fn bounds_policy() {
+        // PROOF1 has the prover at (1, 1) and witnesses at (3, 3) and (4, 4)
+        let witnesses = vec![CPROOF1_2.clone(), CPROOF1_3.clone()];
+        let policy = |min, max| PositionProofPolicy {
+            bounds: Some(WorldBounds::new(min, max).unwrap()),
+            ..PositionProofPolicy::default()
+        };
+
+        let exact = policy(Position(1, 1), Position(4, 4));
+        assert!(PositionProof::new_with_policy(witnesses.clone(), 2, &exact).is_ok());
+
+        assert!(matches!(
+            PositionProof::new_with_policy(
+                witnesses.clone(),
+                2,
+                &policy(Position(2, 0), Position(9, 9))
+            )
+            .unwrap_err(),
+            PositionProofValidationError::OutOfBounds(Position(1, 1))
+        ));
+
+        let unverified = UnverifiedPositionProof {
+            witnesses: witnesses.into_iter().map_into().collect(),
+        };
+        let too_small = policy(Position(0, 0), Position(3, 3));
+        KEYSTORES.iter().for_each(|keystore| {
+            assert!(matches!(
+                unverified
+                    .clone()
+                    .verify_with_policy(2, &too_small, keystore),
+                Err(PositionProofValidationError::OutOfBounds(Position(4, 4)))
+            ));
+        });
+    }
+
     #[test]
     fn create_bad_no_witnesses() {
         assert!(matches!(
@@ -322,6 +552,24 @@ mod test {
         ));
     }
 
+    #[test]
+    fn create_bad_prover_is_witness() {
+        // Safety: always memory-safe, the check being skipped is the one under test
+        let cproof1_1 = unsafe {
+            ProximityProof::new_unchecked(CREQ1.clone(), Position(1, 1), &KEYSTORES.user1)
+        };
+        assert!(matches!(
+            PositionProof::new(vec![CPROOF1_2.clone(), cproof1_1.clone()], 1).unwrap_err(),
+            PositionProofValidationError::ProverIsWitness(1)
+        ));
+        assert!(matches!(
+            PositionProof::new(vec![cproof1_1], 1).unwrap_err(),
+            PositionProofValidationError::ProverIsWitness(1)
+        ));
+
+        assert!(PositionProof::new(vec![CPROOF1_2.clone(), CPROOF1_3.clone()], 2).is_ok());
+    }
+
     #[test]
     fn create_bad_different_requests() {
         assert!(matches!(
@@ -0,0 +1,204 @@
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::keys::{EntityId, KeyStore};
+use crate::{
+    MisbehaviorProofValidationError, PositionProofValidationError, UnverifiedMisbehaviorProof,
+    UnverifiedPositionProof,
+};
+
+/// All the evidence about a user in a range of epochs, in a single exportable document.
+///
+/// Bundles are meant to be handed to third parties, so they are always untrusted:
+/// use [ProofBundle::verify] to check every proof in it (and that it's really about the
+/// bundle's user and epochs) before relying on it.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct ProofBundle {
+    /// User the evidence is about.
+    pub user_id: EntityId,
+
+    /// Epochs covered by the bundle.
+    pub range: Range<u64>,
+
+    /// Position reports of the user, tagged with their epoch.
+    pub reports: Vec<(u64, UnverifiedPositionProof)>,
+
+    /// Proofs of misbehaviour of the user.
+    pub misbehaviour: Vec<UnverifiedMisbehaviorProof>,
+}
+
+/// Error while verifying a [ProofBundle].
+#[derive(Debug, Error)]
+pub enum ProofBundleValidationError {
+    #[error("Evidence for epoch {} is outside of the bundle's range", .0)]
+    OutOfRange(u64),
+
+    #[error("Report tagged with epoch {} is for epoch {}", .tagged, .actual)]
+    EpochMismatch { tagged: u64, actual: u64 },
+
+    #[error("Evidence for epoch {} is about another user ({})", .epoch, .user_id)]
+    WrongUser { epoch: u64, user_id: EntityId },
+
+    #[error("Invalid position report for epoch {}", .0)]
+    InvalidReport(u64, #[source] PositionProofValidationError),
+
+    #[error("Invalid misbehaviour proof")]
+    InvalidMisbehaviour(#[from] MisbehaviorProofValidationError),
+}
+
+impl ProofBundle {
+    /// Verifies every proof in the bundle, and that all of them are about
+    /// the bundle's user and within its range of epochs.
+    pub fn verify(
+        &self,
+        neighbour_faults: usize,
+        keystore: &KeyStore,
+    ) -> Result<(), ProofBundleValidationError> {
+        for (tagged, proof) in &self.reports {
+            let proof = proof
+                .clone()
+                .verify(neighbour_faults, keystore)
+                .map_err(|e| ProofBundleValidationError::InvalidReport(*tagged, e))?;
+
+            self.check(proof.epoch(), proof.prover_id())?;
+            if proof.epoch() != *tagged {
+                return Err(ProofBundleValidationError::EpochMismatch {
+                    tagged: *tagged,
+                    actual: proof.epoch(),
+                });
+            }
+        }
+
+        for proof in &self.misbehaviour {
+            let proof = proof.clone().verify(keystore)?;
+            self.check(proof.a().epoch(), proof.user_id())?;
+        }
+
+        Ok(())
+    }
+
+    fn check(&self, epoch: u64, user_id: EntityId) -> Result<(), ProofBundleValidationError> {
+        if user_id != self.user_id {
+            Err(ProofBundleValidationError::WrongUser { epoch, user_id })
+        } else if !self.range.contains(&epoch) {
+            Err(ProofBundleValidationError::OutOfRange(epoch))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::keys::test_data::KeyStoreTestData;
+    use crate::{MisbehaviorProof, Position, PositionProof, ProximityProof, ProximityProofRequest};
+    use lazy_static::lazy_static;
+
+    lazy_static! {
+        static ref KEYSTORES: KeyStoreTestData = KeyStoreTestData::new();
+    }
+
+    fn report(epoch: u64, position: Position) -> (u64, UnverifiedPositionProof) {
+        let req = ProximityProofRequest::new(epoch, position, &KEYSTORES.user1);
+        let witness = ProximityProof::new(req, position, &KEYSTORES.user2).unwrap();
+        let proof = PositionProof::new(vec![witness], 1).unwrap();
+
+        (epoch, proof.into())
+    }
+
+    fn misbehaviour(epoch: u64) -> UnverifiedMisbehaviorProof {
+        let req_a = ProximityProofRequest::new(epoch, Position(1, 1), &KEYSTORES.user1);
+        let proof_a = ProximityProof::new(req_a, Position(1, 1), &KEYSTORES.user2).unwrap();
+        let req_b = ProximityProofRequest::new(epoch, Position(2, 2), &KEYSTORES.user1);
+        let proof_b = ProximityProof::new(req_b, Position(1, 1), &KEYSTORES.user2).unwrap();
+
+        MisbehaviorProof::new(KEYSTORES.user1.my_id(), proof_a, proof_b)
+            .unwrap()
+            .into()
+    }
+
+    fn bundle() -> ProofBundle {
+        ProofBundle {
+            user_id: KEYSTORES.user1.my_id(),
+            range: 1..4,
+            reports: vec![report(1, Position(1, 1)), report(3, Position(3, 3))],
+            misbehaviour: vec![misbehaviour(2)],
+        }
+    }
+
+    #[test]
+    fn valid() {
+        let bundle = bundle();
+        bundle.verify(1, &KEYSTORES.user1).unwrap();
+
+        let serialized = serde_json::to_string(&bundle).unwrap();
+        let deserialized: ProofBundle = serde_json::from_str(&serialized).unwrap();
+        assert_eq!(deserialized, bundle);
+        deserialized.verify(1, &KEYSTORES.user1).unwrap();
+    }
+
+    #[test]
+    fn not_enough_witnesses() {
+        assert!(matches!(
+            bundle().verify(2, &KEYSTORES.user1),
+            Err(ProofBundleValidationError::InvalidReport(1, _))
+        ));
+    }
+
+    #[test]
+    fn tampered_report() {
+        let mut bundle = bundle();
+        let witness = &mut bundle.reports[1].1.witnesses[0];
+        witness.witness_position = Position(4, 4);
+        assert!(matches!(
+            bundle.verify(1, &KEYSTORES.user1),
+            Err(ProofBundleValidationError::InvalidReport(3, _))
+        ));
+    }
+
+    #[test]
+    fn tampered_epoch_tag() {
+        let mut bundle = bundle();
+        bundle.reports[1].0 = 2;
+        assert!(matches!(
+            bundle.verify(1, &KEYSTORES.user1),
+            Err(ProofBundleValidationError::EpochMismatch {
+                tagged: 2,
+                actual: 3
+            })
+        ));
+    }
+
+    #[test]
+    fn tampered_range() {
+        let mut bundle = bundle();
+        bundle.range = 1..3;
+        assert!(matches!(
+            bundle.verify(1, &KEYSTORES.user1),
+            Err(ProofBundleValidationError::OutOfRange(3))
+        ));
+    }
+
+    #[test]
+    fn tampered_user() {
+        let mut bundle = bundle();
+        bundle.user_id = KEYSTORES.user2.my_id();
+        assert!(matches!(
+            bundle.verify(1, &KEYSTORES.user1),
+            Err(ProofBundleValidationError::WrongUser { epoch: 1, user_id }) if user_id == KEYSTORES.user1.my_id()
+        ));
+    }
+
+    #[test]
+    fn tampered_misbehaviour() {
+        let mut bundle = bundle();
+        bundle.misbehaviour = vec![misbehaviour(4)];
+        assert!(matches!(
+            bundle.verify(1, &KEYSTORES.user1),
+            Err(ProofBundleValidationError::OutOfRange(4))
+        ));
+    }
+}
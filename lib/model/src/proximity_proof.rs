@@ -38,7 +38,7 @@ pub enum ProximityProofValidationError {
 /// **IMPORTANT**: a valid [ProximityProof] must have been created after validating
 /// the prover's position at the same epoch as the [ProximityProofRequest].
 /// This is not automatically guaranteed by the type system and **must be checked by callers**.
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct ProximityProof {
     /// The prover position data being asserted by the witness.
     request: ProximityProofRequest,
@@ -101,15 +101,7 @@ impl UnverifiedProximityProof {
 
         let request = self.request.verify(keystore)?;
 
-        let bytes = [
-            &request.prover_id().to_be_bytes(),
-            request.position().to_bytes().as_slice(),
-            &request.epoch().to_be_bytes(),
-            request.signature().as_ref(),
-            &self.witness_id.to_be_bytes(),
-            &self.witness_position.to_bytes(),
-        ]
-        .concat();
+        let bytes = signed_bytes(&request, self.witness_id, self.witness_position);
         keystore.verify_signature(self.witness_id, &bytes, &self.signature)?;
 
         Ok(ProximityProof {
@@ -179,16 +171,7 @@ impl ProximityProof {
     ) -> ProximityProof {
         let witness_id = keystore.my_id().to_owned();
 
-        let bytes: Vec<u8> = [
-            &request.prover_id().to_be_bytes(),
-            request.position().to_bytes().as_slice(),
-            &request.epoch().to_be_bytes(),
-            request.signature().as_ref(),
-            &witness_id.to_be_bytes(),
-            &witness_position.to_bytes(),
-        ]
-        .concat();
-        let signature = keystore.sign(&bytes);
+        let signature = keystore.sign(&signed_bytes(&request, witness_id, witness_position));
 
         ProximityProof {
             request,
@@ -239,6 +222,40 @@ impl ProximityProof {
     pub fn epoch(&self) -> u64 {
         self.request.epoch()
     }
+
+    /// Whether both proofs attest the same thing: same request contents, witness and witness position.
+    ///
+    /// Unlike `==`, ignores the request and witness signatures.
+    pub fn logically_eq(&self, other: &ProximityProof) -> bool {
+        self.prover_id() == other.prover_id()
+            && self.epoch() == other.epoch()
+            && self.position() == other.position()
+            && self.witness_id == other.witness_id
+            && self.witness_position == other.witness_position
+    }
+
+    /// The exact bytes signed by the witness.
+    ///
+    /// The [request bytes](ProximityProofRequest::signed_bytes) followed by the request signature,
+    /// witness id and witness position (big-endian byte order).
+    /// Allows third parties to verify the [signature](Self::signature) on their own.
+    pub fn signed_bytes(&self) -> Vec<u8> {
+        signed_bytes(&self.request, self.witness_id, self.witness_position)
+    }
+}
+
+fn signed_bytes(
+    request: &ProximityProofRequest,
+    witness_id: EntityId,
+    witness_position: Position,
+) -> Vec<u8> {
+    [
+        request.signed_bytes().as_slice(),
+        request.signature().as_ref(),
+        &witness_id.to_be_bytes(),
+        &witness_position.to_bytes(),
+    ]
+    .concat()
 }
 
 partial_eq_impl!(
@@ -249,6 +266,8 @@ partial_eq_impl!(
     signature
 );
 
+hash_impl!(ProximityProof; request, witness_id, witness_position, signature);
+
 impl From<ProximityProof> for UnverifiedProximityProof {
     fn from(verified: ProximityProof) -> Self {
         UnverifiedProximityProof {
@@ -314,6 +333,81 @@ mod test {
         assert_eq!(unverified, unverified_deserialized);
     }
 
+    #[test]
+    fn hash_set_dedup() {
+        use std::collections::hash_map::DefaultHasher;
+        use std::collections::HashSet;
+        use std::hash::{Hash, Hasher};
+
+        let proofs: HashSet<_> = vec![PROOF1.clone(), PROOF2.clone(), PROOF1.clone()]
+            .into_iter()
+            .collect();
+        assert_eq!(proofs.len(), 2);
+
+        // hashed like the unverified representation
+        fn hash_of<T: Hash>(value: &T) -> u64 {
+            let mut hasher = DefaultHasher::new();
+            value.hash(&mut hasher);
+            hasher.finish()
+        }
+        let unverified: UnverifiedProximityProof = PROOF1.clone().into();
+        assert_eq!(hash_of(&*PROOF1), hash_of(&unverified));
+    }
+
+    #[test]
+    fn signature_equality() {
+        let mut unverified: UnverifiedProximityProof = PROOF1.clone().into();
+        assert_eq!(&unverified, &*PROOF1);
+        assert_ne!(&unverified, &*PROOF2);
+
+        unverified.signature.0[63] ^= 1;
+        assert_ne!(&unverified, &*PROOF1);
+
+        unverified.signature.0[63] ^= 1;
+        unverified.request.signature.0[0] ^= 1;
+        assert_ne!(&unverified, &*PROOF1);
+        assert_ne!(&unverified.request, &*REQ1);
+    }
+
+    #[test]
+    fn logical_equality() {
+        let mut unverified: UnverifiedProximityProof = PROOF1.clone().into();
+        unverified.signature.0[63] ^= 1;
+        unverified.request.signature.0[0] ^= 1;
+        // Safety: only compared, never trusted
+        let resigned = unsafe { unverified.clone().verify_unchecked() };
+        assert_ne!(resigned, *PROOF1);
+        assert!(resigned.logically_eq(&PROOF1));
+        assert!(PROOF1.logically_eq(&resigned));
+        assert!(PROOF1.logically_eq(&PROOF1));
+
+        unverified.witness_position = Position(1, 3);
+        let moved = unsafe { unverified.verify_unchecked() };
+        assert!(!moved.logically_eq(&PROOF1));
+        assert!(!PROOF1.logically_eq(&PROOF2));
+    }
+
+    #[test]
+    fn signed_bytes() {
+        KEYSTORES.iter().for_each(|keystore| {
+            keystore
+                .verify_signature(
+                    PROOF1.witness_id(),
+                    &PROOF1.signed_bytes(),
+                    PROOF1.signature(),
+                )
+                .unwrap();
+        });
+        assert!(KEYSTORES
+            .user3
+            .verify_signature(
+                PROOF1.witness_id(),
+                &PROOF2.signed_bytes(),
+                PROOF1.signature()
+            )
+            .is_err());
+    }
+
     #[test]
     fn verify_ok() {
         let unverified: UnverifiedProximityProof = PROOF2.clone().into();
@@ -25,7 +25,7 @@ pub enum ProximityProofRequestValidationError {
 ///
 /// **IMPORTANT**: a valid [ProximityProofRequest] must have been created in the current or an earlier epoch.
 /// This is not automatically guaranteed by the type system and **must be checked by callers**.
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct ProximityProofRequest {
     /// Identifier of the request creator (trying to prove they're in [position](Self::position)).
     prover_id: EntityId,
@@ -38,6 +38,43 @@ pub struct ProximityProofRequest {
 
     /// Prover signature of the request
     signature: Signature,
+
+    /// Format of the bytes covered by the [signature](Self::signature)
+    format: SignedBytesFormat,
+}
+
+/// Layout of the bytes signed by the prover of a [ProximityProofRequest].
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SignedBytesFormat {
+    /// Prover id, position and epoch, concatenated with no framing.
+    Legacy,
+
+    /// Version byte and domain tag, followed by tagged and length-prefixed fields.
+    V1,
+}
+
+impl SignedBytesFormat {
+    /// Version byte of the format, 0 for [SignedBytesFormat::Legacy] (which has none).
+    pub fn version(self) -> u8 {
+        match self {
+            SignedBytesFormat::Legacy => 0,
+            SignedBytesFormat::V1 => SIGNED_BYTES_VERSION,
+        }
+    }
+
+    /// Format with the given [version](SignedBytesFormat::version), if it exists.
+    pub fn from_version(version: u8) -> Option<SignedBytesFormat> {
+        match version {
+            0 => Some(SignedBytesFormat::Legacy),
+            SIGNED_BYTES_VERSION => Some(SignedBytesFormat::V1),
+            _ => None,
+        }
+    }
+
+    /// Requests serialized before the format was recorded are in the legacy one.
+    fn serde_default() -> SignedBytesFormat {
+        SignedBytesFormat::Legacy
+    }
 }
 
 /// A unverified/untrusted call for position proof witnesess.
@@ -59,12 +96,18 @@ pub struct UnverifiedProximityProofRequest {
     /// Prover signature of the request
     #[serde(with = "Base64SerializationExt")]
     pub signature: Signature,
+
+    /// Format of the bytes covered by the [signature](Self::signature)
+    #[serde(default = "SignedBytesFormat::serde_default")]
+    pub format: SignedBytesFormat,
 }
 
 impl UnverifiedProximityProofRequest {
     /// Verifies a request.
     ///
     /// As documented in [ProximityProofRequest], any valid request must be signed by some user entity.
+    /// The signature is checked against the stated [format](Self::format) first, then the other one:
+    /// the verified request always has the format the signature is actually in.
     pub fn verify(
         self,
         keystore: &KeyStore,
@@ -75,26 +118,37 @@ impl UnverifiedProximityProofRequest {
             ));
         }
 
-        let bytes: Vec<u8> = [
-            &self.prover_id.to_be_bytes(),
-            self.position.to_bytes().as_slice(),
-            &self.epoch.to_be_bytes(),
-        ]
-        .concat();
-        keystore.verify_signature(self.prover_id, &bytes, &self.signature)?;
+        let other_format = match self.format {
+            SignedBytesFormat::Legacy => SignedBytesFormat::V1,
+            SignedBytesFormat::V1 => SignedBytesFormat::Legacy,
+        };
+        let signed_with = |format| {
+            let bytes = signed_bytes(format, self.prover_id, self.position, self.epoch);
+            keystore.verify_signature(self.prover_id, &bytes, &self.signature)
+        };
+        let format = match signed_with(self.format) {
+            Ok(()) => self.format,
+            // requests signed before signed bytes were versioned are still accepted
+            Err(e) => {
+                signed_with(other_format).map_err(|_| e)?;
+                other_format
+            }
+        };
 
         Ok(ProximityProofRequest {
             prover_id: self.prover_id,
             position: self.position,
             epoch: self.epoch,
             signature: self.signature,
+            format,
         })
     }
 
     /// Marks a request as verified without actually checking anything.
     ///
     /// # Safety
-    /// Caller must guarantee that the request is signed by a user entity.
+    /// Caller must guarantee that the request is signed by a user entity, using the stated
+    /// [format](Self::format).
     /// This function is always memory-safe, even if the above above conditions don't apply.
     pub unsafe fn verify_unchecked(self) -> ProximityProofRequest {
         ProximityProofRequest {
@@ -102,6 +156,7 @@ impl UnverifiedProximityProofRequest {
             position: self.position,
             epoch: self.epoch,
             signature: self.signature,
+            format: self.format,
         }
     }
 }
@@ -116,19 +171,15 @@ impl ProximityProofRequest {
             "only users can create ProximityProofRequests"
         );
 
-        let req_bytes: Vec<u8> = [
-            &prover_id.to_be_bytes(),
-            position.to_bytes().as_slice(),
-            &epoch.to_be_bytes(),
-        ]
-        .concat();
-        let signature = keystore.sign(&req_bytes);
+        let format = SignedBytesFormat::V1;
+        let signature = keystore.sign(&signed_bytes(format, prover_id, position, epoch));
 
         ProximityProofRequest {
             prover_id,
             position,
             epoch,
             signature,
+            format,
         }
     }
 
@@ -151,6 +202,58 @@ impl ProximityProofRequest {
     pub fn signature(&self) -> &Signature {
         &self.signature
     }
+
+    /// Format of the bytes covered by the [signature](Self::signature)
+    pub fn format(&self) -> SignedBytesFormat {
+        self.format
+    }
+
+    /// The exact bytes signed by the prover.
+    ///
+    /// A version byte and a domain tag, followed by the prover id, position and epoch in big-endian
+    /// byte order, each preceded by a field tag and its length.
+    /// Requests signed before this format was introduced use the bare concatenation of the fields.
+    /// Allows third parties to verify the [signature](Self::signature) on their own.
+    pub fn signed_bytes(&self) -> Vec<u8> {
+        signed_bytes(self.format, self.prover_id, self.position, self.epoch)
+    }
+}
+
+const SIGNED_BYTES_VERSION: u8 = 1;
+const SIGNED_BYTES_DOMAIN: &[u8] = b"ProximityProofRequest";
+
+const FIELD_PROVER_ID: u8 = 1;
+const FIELD_POSITION: u8 = 2;
+const FIELD_EPOCH: u8 = 3;
+
+fn signed_bytes(
+    format: SignedBytesFormat,
+    prover_id: EntityId,
+    position: Position,
+    epoch: u64,
+) -> Vec<u8> {
+    let fields = [
+        (FIELD_PROVER_ID, prover_id.to_be_bytes().to_vec()),
+        (FIELD_POSITION, position.to_bytes()),
+        (FIELD_EPOCH, epoch.to_be_bytes().to_vec()),
+    ];
+
+    match format {
+        SignedBytesFormat::Legacy => fields.iter().flat_map(|(_, f)| f.clone()).collect(),
+        SignedBytesFormat::V1 => {
+            let mut bytes = vec![SIGNED_BYTES_VERSION];
+            bytes.extend_from_slice(&(SIGNED_BYTES_DOMAIN.len() as u32).to_be_bytes());
+            bytes.extend_from_slice(SIGNED_BYTES_DOMAIN);
+
+            for (tag, field) in fields.iter() {
+                bytes.push(*tag);
+                bytes.extend_from_slice(&(field.len() as u32).to_be_bytes());
+                bytes.extend_from_slice(field);
+            }
+
+            bytes
+        }
+    }
 }
 
 partial_eq_impl!(
@@ -159,9 +262,12 @@ partial_eq_impl!(
     prover_id,
     position,
     epoch,
-    signature
+    signature,
+    format
 );
 
+hash_impl!(ProximityProofRequest; prover_id, position, epoch, signature, format);
+
 impl From<ProximityProofRequest> for UnverifiedProximityProofRequest {
     fn from(verified: ProximityProofRequest) -> Self {
         UnverifiedProximityProofRequest {
@@ -169,6 +275,7 @@ impl From<ProximityProofRequest> for UnverifiedProximityProofRequest {
             position: verified.position,
             epoch: verified.epoch,
             signature: verified.signature,
+            format: verified.format,
         }
     }
 }
@@ -217,6 +324,118 @@ mod test {
         assert_eq!(unverified, unverified_deserialized);
     }
 
+    #[test]
+    fn signed_bytes() {
+        KEYSTORES.iter().for_each(|keystore| {
+            keystore
+                .verify_signature(REQ1.prover_id(), &REQ1.signed_bytes(), REQ1.signature())
+                .unwrap();
+        });
+        assert!(KEYSTORES
+            .user3
+            .verify_signature(REQ1.prover_id(), &REQ2.signed_bytes(), REQ1.signature())
+            .is_err());
+    }
+
+    #[test]
+    fn signed_bytes_formats() {
+        let legacy_bytes = signed_bytes(
+            SignedBytesFormat::Legacy,
+            REQ2.prover_id,
+            REQ2.position,
+            REQ2.epoch,
+        );
+        assert_eq!(REQ2.signed_bytes()[0], SIGNED_BYTES_VERSION);
+        assert_ne!(REQ2.signed_bytes(), legacy_bytes);
+
+        let legacy = UnverifiedProximityProofRequest {
+            signature: KEYSTORES.user2.sign(&legacy_bytes),
+            format: SignedBytesFormat::Legacy,
+            ..UnverifiedProximityProofRequest::from(REQ2.clone())
+        };
+
+        // each signature only covers the bytes of its own format
+        assert!(KEYSTORES
+            .user3
+            .verify_signature(REQ2.prover_id(), &legacy_bytes, REQ2.signature())
+            .is_err());
+        assert!(KEYSTORES
+            .user3
+            .verify_signature(REQ2.prover_id(), &REQ2.signed_bytes(), &legacy.signature)
+            .is_err());
+
+        // legacy requests still verify, and keep their format
+        KEYSTORES.iter().for_each(|keystore| {
+            let verified = legacy.clone().verify(keystore).unwrap();
+            assert_eq!(verified.format, SignedBytesFormat::Legacy);
+            assert_eq!(verified.signed_bytes(), legacy_bytes);
+            keystore
+                .verify_signature(
+                    verified.prover_id(),
+                    &verified.signed_bytes(),
+                    verified.signature(),
+                )
+                .unwrap();
+
+            let verified = UnverifiedProximityProofRequest::from(REQ2.clone())
+                .verify(keystore)
+                .unwrap();
+            assert_eq!(verified.format, SignedBytesFormat::V1);
+
+            // a wrongly stated format is corrected
+            let misstated = UnverifiedProximityProofRequest {
+                format: SignedBytesFormat::V1,
+                ..legacy.clone()
+            };
+            assert_eq!(
+                misstated.verify(keystore).unwrap().format,
+                SignedBytesFormat::Legacy
+            );
+        });
+    }
+
+    #[test]
+    fn format_round_trip() {
+        let legacy_bytes = signed_bytes(
+            SignedBytesFormat::Legacy,
+            REQ2.prover_id,
+            REQ2.position,
+            REQ2.epoch,
+        );
+        let legacy = UnverifiedProximityProofRequest {
+            signature: KEYSTORES.user2.sign(&legacy_bytes),
+            format: SignedBytesFormat::Legacy,
+            ..UnverifiedProximityProofRequest::from(REQ2.clone())
+        }
+        .verify(&KEYSTORES.user1)
+        .unwrap();
+
+        // the format survives serialization, so unchecked requests keep signing the right bytes
+        for req in &[legacy, REQ2.clone()] {
+            let serialized = serde_json::to_string(req).unwrap();
+            let unverified: UnverifiedProximityProofRequest =
+                serde_json::from_str(&serialized).unwrap();
+            // Safety: it was verified before being serialized
+            let unchecked = unsafe { unverified.verify_unchecked() };
+            assert_eq!(&unchecked, req);
+            assert_eq!(unchecked.signed_bytes(), req.signed_bytes());
+        }
+
+        // requests serialized before the format was recorded are legacy ones
+        let mut value = serde_json::to_value(&*REQ2).unwrap();
+        value.as_object_mut().unwrap().remove("format");
+        let unverified: UnverifiedProximityProofRequest = serde_json::from_value(value).unwrap();
+        assert_eq!(unverified.format, SignedBytesFormat::Legacy);
+
+        for format in &[SignedBytesFormat::Legacy, SignedBytesFormat::V1] {
+            assert_eq!(
+                SignedBytesFormat::from_version(format.version()),
+                Some(*format)
+            );
+        }
+        assert_eq!(SignedBytesFormat::from_version(2), None);
+    }
+
     #[test]
     fn verify_ok() {
         let unverified: UnverifiedProximityProofRequest = REQ2.clone().into();
@@ -0,0 +1,133 @@
+use thiserror::Error;
+
+use crate::Position;
+
+/// Rectangle of the positions that exist in the world, both corners included.
+///
+/// Can only be constructed with the minimum corner above and to the left of (or the same as)
+/// the maximum corner, so it always contains at least one position.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct WorldBounds {
+    min: Position,
+    max: Position,
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum WorldBoundsError {
+    #[error("Minimum corner {:?} is past the maximum corner {:?}", .0, .1)]
+    Inverted(Position, Position),
+
+    #[error("World must have at least one position (is {}x{})", .0, .1)]
+    Empty(usize, usize),
+}
+
+impl WorldBounds {
+    /// Bounds between two corners, failing if `min` is past `max` in any coordinate.
+    pub fn new(min: Position, max: Position) -> Result<WorldBounds, WorldBoundsError> {
+        if min.0 > max.0 || min.1 > max.1 {
+            return Err(WorldBoundsError::Inverted(min, max));
+        }
+
+        Ok(WorldBounds { min, max })
+    }
+
+    /// Bounds of a `width` by `height` world, from the origin ([Position::default]) up to,
+    /// but excluding, `Position(width, height)`.
+    pub fn from_dims(width: usize, height: usize) -> Result<WorldBounds, WorldBoundsError> {
+        if width == 0 || height == 0 {
+            return Err(WorldBoundsError::Empty(width, height));
+        }
+
+        WorldBounds::new(
+            Position::default(),
+            Position(width as i64 - 1, height as i64 - 1),
+        )
+    }
+
+    /// Top left corner, the smallest position in the world.
+    pub fn min(&self) -> Position {
+        self.min
+    }
+
+    /// Bottom right corner, the largest position in the world.
+    pub fn max(&self) -> Position {
+        self.max
+    }
+
+    /// Whether the position is in the world, corners and edges included.
+    pub fn contains(&self, pos: &Position) -> bool {
+        (self.min.0..=self.max.0).contains(&pos.0) && (self.min.1..=self.max.1).contains(&pos.1)
+    }
+
+    /// Closest position in the world.
+    pub fn clamp(&self, pos: Position) -> Position {
+        Position(
+            pos.0.clamp(self.min.0, self.max.0),
+            pos.1.clamp(self.min.1, self.max.1),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn contains_edges() {
+        let bounds = WorldBounds::new(Position(-2, 1), Position(3, 4)).unwrap();
+
+        for corner in &[
+            Position(-2, 1),
+            Position(3, 1),
+            Position(-2, 4),
+            Position(3, 4),
+        ] {
+            assert!(bounds.contains(corner), "{:?} is not contained", corner);
+        }
+        assert!(bounds.contains(&Position(0, 2)));
+
+        for outside in &[
+            Position(-3, 1),
+            Position(4, 4),
+            Position(0, 0),
+            Position(0, 5),
+        ] {
+            assert!(!bounds.contains(outside), "{:?} is contained", outside);
+        }
+
+        assert_eq!(bounds.clamp(Position(10, -10)), Position(3, 1));
+        assert_eq!(bounds.clamp(Position(0, 2)), Position(0, 2));
+
+        // a single position
+        let bounds = WorldBounds::new(Position(7, 7), Position(7, 7)).unwrap();
+        assert!(bounds.contains(&Position(7, 7)));
+        assert!(!bounds.contains(&Position(7, 8)));
+    }
+
+    #[test]
+    fn from_dims() {
+        let bounds = WorldBounds::from_dims(400, 300).unwrap();
+        assert_eq!(bounds.min(), Position(0, 0));
+        assert_eq!(bounds.max(), Position(399, 299));
+        assert!(bounds.contains(&Position(399, 0)));
+        assert!(!bounds.contains(&Position(400, 0)));
+        assert!(!bounds.contains(&Position(-1, 0)));
+
+        assert_eq!(
+            WorldBounds::from_dims(0, 3),
+            Err(WorldBoundsError::Empty(0, 3))
+        );
+    }
+
+    #[test]
+    fn inverted() {
+        assert_eq!(
+            WorldBounds::new(Position(1, 0), Position(0, 5)),
+            Err(WorldBoundsError::Inverted(Position(1, 0), Position(0, 5)))
+        );
+        assert_eq!(
+            WorldBounds::new(Position(0, 6), Position(0, 5)),
+            Err(WorldBoundsError::Inverted(Position(0, 6), Position(0, 5)))
+        );
+    }
+}
@@ -0,0 +1,197 @@
+#![deny(unsafe_op_in_unsafe_fn)]
+
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use socket2::{SockRef, TcpKeepalive};
+
+use tokio::net::{TcpSocket, TcpStream};
+use tokio_stream::wrappers::TcpListenerStream;
+use tokio_stream::{Stream, StreamExt};
+
+/// Socket options for [create_tcp_incoming_with].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IncomingOptions {
+    /// Set `SO_REUSEADDR`, to be able to bind while old connections linger in `TIME_WAIT`.
+    pub reuse_addr: bool,
+
+    /// Maximum number of connections waiting to be accepted.
+    pub backlog: u32,
+
+    /// Disable Nagle's algorithm in accepted connections.
+    pub nodelay: bool,
+
+    /// Probe idle connections, to drop them once the peer is gone.
+    pub keepalive: Option<Keepalive>,
+}
+
+/// Like [tokio::net::TcpListener::bind] on Unix, but with keepalive and without Nagle's algorithm:
+/// requests and replies are small, and callback servers may be waited on for a long time.
+impl Default for IncomingOptions {
+    fn default() -> Self {
+        IncomingOptions {
+            reuse_addr: true,
+            backlog: 1024,
+            nodelay: true,
+            keepalive: Some(Keepalive::default()),
+        }
+    }
+}
+
+/// TCP keepalive parameters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Keepalive {
+    /// How long a connection must be idle before it's probed.
+    pub idle: Duration,
+
+    /// Time between probes. Only set on platforms that support it (e.g. Linux, macOS and BSDs).
+    pub interval: Duration,
+
+    /// Unanswered probes before the connection is dropped. Only set on platforms that support it.
+    pub retries: u32,
+}
+
+impl Default for Keepalive {
+    fn default() -> Self {
+        Keepalive {
+            idle: Duration::from_secs(60),
+            interval: Duration::from_secs(10),
+            retries: 5,
+        }
+    }
+}
+
+impl Keepalive {
+    fn to_socket2(self) -> TcpKeepalive {
+        #[allow(unused_mut)]
+        let mut keepalive = TcpKeepalive::new().with_time(self.idle);
+
+        #[cfg(any(
+            target_os = "android",
+            target_os = "freebsd",
+            target_os = "linux",
+            target_os = "netbsd",
+            target_vendor = "apple",
+        ))]
+        {
+            keepalive = keepalive
+                .with_interval(self.interval)
+                .with_retries(self.retries);
+        }
+
+        keepalive
+    }
+}
+
+/// Listen for TCP connections, to be served with tonic's `serve_with_incoming`.
+///
+/// See [create_tcp_incoming_with].
+pub async fn create_tcp_incoming(
+    bind_addr: &SocketAddr,
+) -> io::Result<(impl Stream<Item = io::Result<TcpStream>>, SocketAddr)> {
+    create_tcp_incoming_with(bind_addr, IncomingOptions::default()).await
+}
+
+/// Listen for TCP connections, to be served with tonic's `serve_with_incoming`.
+///
+/// The options are applied to the listening socket and to every accepted connection.
+/// Returns the stream of connections and the address actually bound, which tells the port
+/// picked when binding to port 0.
+pub async fn create_tcp_incoming_with(
+    bind_addr: &SocketAddr,
+    options: IncomingOptions,
+) -> io::Result<(impl Stream<Item = io::Result<TcpStream>>, SocketAddr)> {
+    let socket = match bind_addr {
+        SocketAddr::V4(_) => TcpSocket::new_v4()?,
+        SocketAddr::V6(_) => TcpSocket::new_v6()?,
+    };
+    socket.set_reuseaddr(options.reuse_addr)?;
+    socket.bind(*bind_addr)?;
+    let listener = socket.listen(options.backlog)?;
+    let listen_addr = listener.local_addr()?;
+
+    let listener_stream = TcpListenerStream::new(listener).map(move |res| {
+        res.and_then(|socket| {
+            socket.set_nodelay(options.nodelay)?;
+            if let Some(keepalive) = options.keepalive {
+                SockRef::from(&socket).set_tcp_keepalive(&keepalive.to_socket2())?;
+            }
+            Ok(socket)
+        })
+    });
+
+    Ok((listener_stream, listen_addr))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    async fn roundtrip(bind_addr: &str, options: IncomingOptions) -> TcpStream {
+        let (incoming, addr) = create_tcp_incoming_with(&bind_addr.parse().unwrap(), options)
+            .await
+            .unwrap();
+        assert_ne!(addr.port(), 0);
+
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        client.write_all(b"ping").await.unwrap();
+
+        tokio::pin!(incoming);
+        let mut server = incoming.next().await.unwrap().unwrap();
+
+        let mut buf = [0; 4];
+        server.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"ping");
+
+        server
+    }
+
+    #[tokio::test]
+    async fn bind_any_port() {
+        let server = roundtrip("127.0.0.1:0", IncomingOptions::default()).await;
+        assert!(server.nodelay().unwrap());
+        assert!(SockRef::from(&server).keepalive().unwrap());
+    }
+
+    #[tokio::test]
+    async fn custom_options() {
+        let server = roundtrip(
+            "127.0.0.1:0",
+            IncomingOptions {
+                reuse_addr: false,
+                backlog: 1,
+                nodelay: false,
+                keepalive: None,
+            },
+        )
+        .await;
+        assert!(!server.nodelay().unwrap());
+        assert!(!SockRef::from(&server).keepalive().unwrap());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn keepalive_parameters() {
+        let keepalive = Keepalive {
+            idle: Duration::from_secs(42),
+            interval: Duration::from_secs(7),
+            retries: 3,
+        };
+        let server = roundtrip(
+            "127.0.0.1:0",
+            IncomingOptions {
+                keepalive: Some(keepalive),
+                ..IncomingOptions::default()
+            },
+        )
+        .await;
+
+        let socket = SockRef::from(&server);
+        assert!(socket.keepalive().unwrap());
+        assert_eq!(socket.keepalive_time().unwrap(), keepalive.idle);
+        assert_eq!(socket.keepalive_interval().unwrap(), keepalive.interval);
+        assert_eq!(socket.keepalive_retries().unwrap(), keepalive.retries);
+    }
+}
@@ -5,5 +5,6 @@ fn main() -> Result<()> {
     tonic_build::compile_protos("./src/hdlt.proto")?;
     tonic_build::compile_protos("./src/driver.proto")?;
     tonic_build::compile_protos("./src/witness.proto")?;
+    tonic_build::compile_protos("./src/admin.proto")?;
     Ok(())
 }
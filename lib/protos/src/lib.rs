@@ -6,6 +6,9 @@ pub mod hdlt {
 pub mod driver {
     tonic::include_proto!("driver");
 }
+pub mod admin {
+    tonic::include_proto!("admin");
+}
 pub mod util {
     tonic::include_proto!("util");
 
@@ -71,6 +74,8 @@ pub mod witness {
                 position: r.prover_position.ok_or(ParseError::MissingPosition)?.into(),
                 epoch: r.epoch,
                 signature: Signature::from_slice(&r.signature).ok_or(ParseError::BadSignature)?,
+                // not carried by the message, verification falls back to the legacy format
+                format: model::SignedBytesFormat::V1,
             })
         }
     }
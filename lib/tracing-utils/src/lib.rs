@@ -7,6 +7,9 @@ pub use metadata_mappers::{
     inject_ctx_into_tonic_request_metadata, set_parent_ctx_from_tonic_request_metadata,
 };
 
+#[cfg(any(test, feature = "test-utils"))]
+pub mod recorder;
+
 // Expose instrument_tonic_method attribute macro and its dependencies
 pub use tracing::instrument as _macro_aux_tracing_instrument;
 pub use tracing_utils_macros::instrument_tonic_service;
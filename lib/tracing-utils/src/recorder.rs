@@ -0,0 +1,106 @@
+//! Layer recording the values of span and event fields, for tests about what gets traced.
+//!
+//! Available to other crates with the `test-utils` feature.
+
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+
+use tracing::field::{Field, Visit};
+use tracing::{span, Event, Subscriber};
+use tracing_subscriber::layer::Context;
+
+/// Records every value given to some fields, in order: when spans are created, when values are
+/// recorded on them later, and in events.
+///
+/// Values are kept as text: strings as they are, anything else in its [Debug] format.
+/// Clones share what was recorded, so one can be installed while another is inspected.
+#[derive(Clone, Debug)]
+pub struct FieldRecorder {
+    fields: &'static [&'static str],
+    recorded: Arc<Mutex<Vec<(&'static str, String)>>>,
+}
+
+impl FieldRecorder {
+    /// Recorder of the fields with these names
+    pub fn new(fields: &'static [&'static str]) -> Self {
+        FieldRecorder {
+            fields,
+            recorded: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// Every (field, value) recorded so far, in order
+    pub fn recorded(&self) -> Vec<(&'static str, String)> {
+        self.recorded.lock().unwrap().clone()
+    }
+
+    /// Values recorded for one field, in order, skipping those that aren't numbers
+    pub fn numbers(&self, field: &str) -> Vec<u64> {
+        self.recorded
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(name, _)| *name == field)
+            .filter_map(|(_, value)| value.parse().ok())
+            .collect()
+    }
+
+    fn push(&self, field: &Field, value: String) {
+        if self.fields.contains(&field.name()) {
+            self.recorded.lock().unwrap().push((field.name(), value));
+        }
+    }
+}
+
+impl Visit for &FieldRecorder {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.push(field, value.to_owned());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        self.push(field, format!("{:?}", value));
+    }
+}
+
+impl<S: Subscriber> tracing_subscriber::Layer<S> for FieldRecorder {
+    fn new_span(&self, attrs: &span::Attributes<'_>, _id: &span::Id, _ctx: Context<'_, S>) {
+        attrs.record(&mut &*self);
+    }
+
+    fn on_record(&self, _span: &span::Id, values: &span::Record<'_>, _ctx: Context<'_, S>) {
+        values.record(&mut &*self);
+    }
+
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        event.record(&mut &*self);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tracing::field;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    #[test]
+    fn records_named_fields() {
+        let recorder = FieldRecorder::new(&["len", "name"]);
+        let _guard =
+            tracing::subscriber::set_default(tracing_subscriber::registry().with(recorder.clone()));
+
+        let span = tracing::info_span!("op", len = field::Empty, other = 1u64);
+        span.record("len", &3u64);
+        tracing::info!(name = "x", len = 4u64, other = 5u64, "event");
+
+        assert_eq!(
+            recorder.recorded(),
+            vec![
+                ("len", "3".to_owned()),
+                ("name", "x".to_owned()),
+                ("len", "4".to_owned())
+            ]
+        );
+        assert_eq!(recorder.numbers("len"), vec![3, 4]);
+        assert!(recorder.numbers("name").is_empty());
+    }
+}
@@ -1,27 +1,65 @@
+use eyre::eyre;
 use tracing::info;
 
-use server::{Options, Server};
+use server::{Options, ReverifyOptions, Server};
 use structopt::StructOpt;
 
+/// HDLT server
+#[derive(StructOpt)]
+enum Command {
+    /// Serve API requests
+    Serve(Options),
+
+    /// Check the storage for misbehaviour it failed to flag, without changing it
+    Reverify(ReverifyOptions),
+}
+
 #[tokio::main]
 async fn main() -> eyre::Result<()> {
     model::ensure_init();
-    let options = Options::from_args();
 
     // pretty-print panics
     color_eyre::install()?;
 
+    match Command::from_args() {
+        Command::Serve(options) => serve(&options).await,
+        Command::Reverify(options) => reverify(&options).await,
+    }
+}
+
+async fn serve(options: &Options) -> eyre::Result<()> {
     // trace stuff: do not remove
-    let id =
-        model::keys::KeyStore::load_from_files(&options.entity_registry_path, &options.skeys_path)?
-            .my_id()
-            .to_string();
+    let id = model::keys::KeyStore::load_from_files(
+        &options.entity_registry_path,
+        Some(&options.skeys_path),
+    )?
+    .my_id()
+    .to_string();
     let _guard = tracing_utils::setup(env!("CARGO_PKG_NAME"), vec![("id", id)])?;
 
-    let (_server, task_handle) = Server::new(&options).await?;
+    let (_server, task_handle) = Server::new(options).await?;
 
     task_handle.await??;
     info!("Bye!");
 
     Ok(())
 }
+
+async fn reverify(options: &ReverifyOptions) -> eyre::Result<()> {
+    let missed = server::reverify(options).await?;
+    if missed.is_empty() {
+        println!("Storage is consistent");
+        return Ok(());
+    }
+
+    for proof in &missed {
+        println!(
+            "User {} misbehaved in epoch {} but was not flagged: {:?}",
+            proof.user_id(),
+            proof.a().epoch(),
+            proof
+        );
+    }
+
+    Err(eyre!("{} misbehaving users were not flagged", missed.len()))
+}
@@ -1,17 +1,101 @@
 use model::{
-    keys::{EntityId, Signature},
-    MisbehaviorProof, Position, PositionProof, ProximityProof,
+    keys::{EntityId, KeyStore, KeyStoreError, Signature, SignatureVerificationError},
+    MisbehaviorProof, Position, PositionProof, ProximityProof, ProximityProofValidationError,
+    SignedBytesFormat, UnverifiedProximityProof,
 };
-use std::collections::HashMap;
+use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use thiserror::Error;
 use tracing::*;
 
+use crate::group_by::group_by;
+
+/// How many epochs ahead of the current one a proof may be, to tolerate clocks drifting apart
+pub const MAX_EPOCH_SKEW: u64 = 1;
+
+/// Schema changes, applied in order to databases that don't have them yet.
+///
+/// The number of applied migrations is kept in the database's `user_version`.
+/// Databases from before migrations existed are at version 0, like new ones.
+const MIGRATIONS: &[&str] = &[
+    include_str!("hdlt_store_init.sql"),
+    "ALTER TABLE proximity_proofs ADD COLUMN deleted_at BIGINT;",
+    "CREATE INDEX IF NOT EXISTS proximity_proofs_by_position
+        ON proximity_proofs (epoch, prover_position_x, prover_position_y);",
+    /* NULL for rows stored before the format was recorded, which are all in the legacy one */
+    "ALTER TABLE proximity_proofs ADD COLUMN request_signature_format INT;",
+    /* rows of the misbehavior_proofs view that involved deleted reports, one per epoch and user */
+    "CREATE TABLE IF NOT EXISTS kept_misbehavior_proofs (
+        epoch BIGINT,
+        user_id INT,
+        a_prover_id INT,
+        a_prover_position_x BIGINT,
+        a_prover_position_y BIGINT,
+        a_request_signature BLOB,
+        a_request_signature_format INT,
+        a_witness_id INT,
+        a_witness_position_x BIGINT,
+        a_witness_position_y BIGINT,
+        a_signature BLOB,
+        b_prover_id INT,
+        b_prover_position_x BIGINT,
+        b_prover_position_y BIGINT,
+        b_request_signature BLOB,
+        b_request_signature_format INT,
+        b_witness_id INT,
+        b_witness_position_x BIGINT,
+        b_witness_position_y BIGINT,
+        b_signature BLOB,
+
+        PRIMARY KEY (epoch, user_id)
+    );",
+];
+
 #[derive(Debug)]
 pub struct HdltLocalStore {
     db_pool: sqlx::Pool<sqlx::Sqlite>,
 }
 
+/// Connection pool settings for [HdltLocalStore::open_with_options].
+#[derive(Debug, Clone)]
+pub struct HdltLocalStoreOptions {
+    /// Maximum number of open connections.
+    pub max_connections: u32,
+
+    /// Number of connections kept open even when idle.
+    pub min_connections: u32,
+
+    /// How long a connection may stay idle before being closed (`None` keeps it forever).
+    pub idle_timeout: Option<Duration>,
+
+    /// How long to wait for a connection, and for the database lock once connected.
+    pub acquire_timeout: Duration,
+}
+
+impl Default for HdltLocalStoreOptions {
+    fn default() -> Self {
+        HdltLocalStoreOptions {
+            max_connections: 64,
+            min_connections: 0,
+            idle_timeout: Some(Duration::from_secs(10 * 60)),
+            acquire_timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Summary of what a store holds, see [HdltLocalStore::stats].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StoreStats {
+    /// Proximity proofs stored, not counting deleted ones.
+    pub proofs: u64,
+
+    /// Users known to have misbehaved in some epoch.
+    pub misbehaving_users: u64,
+}
+
 #[derive(Error, Debug)]
 pub enum HdltLocalStoreError {
     #[error("Database Error")]
@@ -22,27 +106,94 @@ pub enum HdltLocalStoreError {
 
     #[error("User {} is trying to be in two places at the same time", .0.user_id())]
     InconsistentUser(Box<MisbehaviorProof>),
+
+    #[error("User {} was deleted, their proofs can only be restored or purged", .0)]
+    DeletedUser(EntityId),
+
+    #[error("Proof is for epoch {}, too far ahead of the current one ({})", .epoch, .current_epoch)]
+    FutureProof { epoch: u64, current_epoch: u64 },
+
+    #[error("Storage is at schema version {}, but {} is needed: open it for writing to migrate", .version, .expected)]
+    OutdatedSchema { version: usize, expected: usize },
+
+    #[error("Stored proof of user {} at epoch {} by witness {} is invalid", .prover_id, .epoch, .witness_id)]
+    InvalidProof {
+        epoch: u64,
+        prover_id: EntityId,
+        witness_id: EntityId,
+        #[source]
+        source: ProximityProofValidationError,
+    },
 }
 
 impl HdltLocalStore {
     pub async fn open<P: AsRef<Path>>(path: P) -> Result<Self, HdltLocalStoreError> {
-        let path = path
-            .as_ref()
-            .to_str()
-            .expect("bad string used as db path. stick to unicode chars");
-        let conn_uri = format!("sqlite://{}?mode=rwc", path);
-        let db_pool = sqlx::sqlite::SqlitePoolOptions::new()
-            .max_connections(64)
-            .connect(&conn_uri)
+        HdltLocalStore::open_with_options(path, &HdltLocalStoreOptions::default()).await
+    }
+
+    /// Like [open](Self::open), for storage that can't be trusted (e.g. imported from elsewhere).
+    ///
+    /// Every stored proof is verified (in parallel) before opening, instead of being trusted to
+    /// have been verified when it was added. Refuses to open if any of them is invalid.
+    pub async fn open_verified<P: AsRef<Path>>(
+        path: P,
+        keystore: &KeyStore,
+    ) -> Result<Self, HdltLocalStoreError> {
+        let store = HdltLocalStore::open(path).await?;
+        store.verify_all(keystore).await?;
+
+        Ok(store)
+    }
+
+    pub async fn open_with_options<P: AsRef<Path>>(
+        path: P,
+        options: &HdltLocalStoreOptions,
+    ) -> Result<Self, HdltLocalStoreError> {
+        // WAL lets readers proceed alongside a writer, but writers still serialize on the
+        // database lock: wait for it as long as we would wait for a connection
+        let conn_options = SqliteConnectOptions::new()
+            .filename(path)
+            .create_if_missing(true)
+            .journal_mode(SqliteJournalMode::Wal)
+            .busy_timeout(options.acquire_timeout);
+
+        let db_pool = SqlitePoolOptions::new()
+            .max_connections(options.max_connections)
+            .min_connections(options.min_connections)
+            .idle_timeout(options.idle_timeout)
+            .connect_timeout(options.acquire_timeout)
+            .connect_with(conn_options)
+            .await?;
+
+        HdltLocalStore::new(db_pool).await
+    }
+
+    /// Open existing storage without changing it, e.g. to inspect it while a server uses it.
+    ///
+    /// Fails if there is no storage at `path`, or if its schema needs migrating first.
+    pub async fn open_read_only<P: AsRef<Path>>(path: P) -> Result<Self, HdltLocalStoreError> {
+        let conn_options = SqliteConnectOptions::new()
+            .filename(path)
+            .create_if_missing(false)
+            .read_only(true);
+        let db_pool = SqlitePoolOptions::new().connect_with(conn_options).await?;
+
+        let (version,): (i64,) = sqlx::query_as("PRAGMA user_version;")
+            .fetch_one(&db_pool)
             .await?;
+        if version as usize != MIGRATIONS.len() {
+            return Err(HdltLocalStoreError::OutdatedSchema {
+                version: version as usize,
+                expected: MIGRATIONS.len(),
+            });
+        }
 
-        let r = HdltLocalStore::new(db_pool).await;
-        r
+        Ok(HdltLocalStore { db_pool })
     }
 
     #[cfg(test)]
     pub async fn open_memory() -> Self {
-        let db_pool = sqlx::sqlite::SqlitePoolOptions::new()
+        let db_pool = SqlitePoolOptions::new()
             .min_connections(1)
             .max_connections(1)
             .idle_timeout(None)
@@ -55,17 +206,134 @@ impl HdltLocalStore {
     }
 
     pub async fn new(db_pool: sqlx::Pool<sqlx::Sqlite>) -> Result<Self, HdltLocalStoreError> {
-        sqlx::query(include_str!("hdlt_store_init.sql"))
-            .execute(&db_pool)
+        let mut tx = db_pool.begin().await?;
+
+        let (version,): (i64,) = sqlx::query_as("PRAGMA user_version;")
+            .fetch_one(&mut tx)
+            .await?;
+        for (idx, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+            info!(event = "Migrating storage", version = idx + 1);
+            sqlx::query(migration).execute(&mut tx).await?;
+            // pragmas can't have bound parameters
+            sqlx::query(&format!("PRAGMA user_version = {};", idx + 1))
+                .execute(&mut tx)
+                .await?;
+        }
+
+        sqlx::query(include_str!("hdlt_store_views.sql"))
+            .execute(&mut tx)
             .await?;
+        tx.commit().await?;
 
         Ok(HdltLocalStore { db_pool })
     }
 
-    /// Add a proof iff it is more recent than the last proof
-    pub async fn add_proof(&self, proof: PositionProof) -> Result<(), HdltLocalStoreError> {
+    /// Delete every proof of a user, reversibly: they are kept (but hidden) until purged.
+    ///
+    /// Only the user's own reports are deleted, their accounts as witness belong to the reports
+    /// of others. Misbehaviour the reports showed (of anyone) is kept, so it can't be erased
+    /// by forgetting them. Until purged (see [HdltLocalStore::purge_tombstones]) or restored
+    /// (see [HdltLocalStore::restore_user]), the user can't submit new proofs.
+    /// Returns the number of deleted position reports, one per epoch however many witnesses it had.
+    pub async fn delete_user(&self, user_id: EntityId) -> Result<u64, HdltLocalStoreError> {
+        let mut tx = self.db_pool.begin().await?;
+
+        let (reports,): (i64,) = sqlx::query_as(
+            "SELECT COUNT(DISTINCT epoch) FROM proximity_proofs
+            WHERE prover_id = ? AND deleted_at IS NULL;",
+        )
+        .bind(user_id)
+        .fetch_one(&mut tx)
+        .await?;
+        sqlx::query(
+            "INSERT OR IGNORE INTO kept_misbehavior_proofs
+            SELECT * FROM misbehavior_proofs WHERE a_prover_id = ? OR b_prover_id = ?;",
+        )
+        .bind(user_id)
+        .bind(user_id)
+        .execute(&mut tx)
+        .await?;
+        sqlx::query(
+            "UPDATE proximity_proofs SET deleted_at = ?
+            WHERE prover_id = ? AND deleted_at IS NULL;",
+        )
+        .bind(unix_secs(SystemTime::now()))
+        .bind(user_id)
+        .execute(&mut tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(reports as u64)
+    }
+
+    /// Undo [HdltLocalStore::delete_user] for proofs that weren't purged yet.
+    ///
+    /// Returns the number of restored position reports, counted like in [HdltLocalStore::delete_user].
+    pub async fn restore_user(&self, user_id: EntityId) -> Result<u64, HdltLocalStoreError> {
+        let mut tx = self.db_pool.begin().await?;
+
+        let (reports,): (i64,) = sqlx::query_as(
+            "SELECT COUNT(DISTINCT epoch) FROM proximity_proofs
+            WHERE prover_id = ? AND deleted_at IS NOT NULL;",
+        )
+        .bind(user_id)
+        .fetch_one(&mut tx)
+        .await?;
+        sqlx::query(
+            "UPDATE proximity_proofs SET deleted_at = NULL
+            WHERE prover_id = ? AND deleted_at IS NOT NULL;",
+        )
+        .bind(user_id)
+        .execute(&mut tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(reports as u64)
+    }
+
+    /// Permanently remove proofs deleted before a given time.
+    ///
+    /// Returns the number of removed proximity proofs.
+    pub async fn purge_tombstones(&self, before: SystemTime) -> Result<u64, HdltLocalStoreError> {
+        let res = sqlx::query(
+            "DELETE FROM proximity_proofs
+            WHERE deleted_at IS NOT NULL AND deleted_at < ?;",
+        )
+        .bind(unix_secs(before))
+        .execute(&self.db_pool)
+        .await?;
+
+        Ok(res.rows_affected())
+    }
+
+    /// Add a proof iff it is more recent than the last proof, and not more than
+    /// [MAX_EPOCH_SKEW] epochs ahead of `current_epoch`
+    pub async fn add_proof(
+        &self,
+        proof: PositionProof,
+        current_epoch: u64,
+    ) -> Result<(), HdltLocalStoreError> {
+        if proof.epoch() > current_epoch.saturating_add(MAX_EPOCH_SKEW) {
+            return Err(HdltLocalStoreError::FutureProof {
+                epoch: proof.epoch(),
+                current_epoch,
+            });
+        }
+
         let mut tx = self.db_pool.begin().await?;
 
+        // whether a deleted user may come back is not for the storage to decide
+        if sqlx::query(
+            "SELECT 1 FROM proximity_proofs WHERE prover_id = ? AND deleted_at IS NOT NULL",
+        )
+        .bind(proof.prover_id())
+        .fetch_optional(&mut tx)
+        .await?
+        .is_some()
+        {
+            return Err(HdltLocalStoreError::DeletedUser(proof.prover_id()));
+        }
+
         if sqlx::query("SELECT signature FROM proximity_proofs WHERE epoch >= ? AND prover_id = ?")
             .bind(proof.epoch() as i64)
             .bind(proof.prover_id())
@@ -94,17 +362,19 @@ impl HdltLocalStore {
                     prover_position_x,
                     prover_position_y,
                     request_signature,
+                    request_signature_format,
                     witness_id,
                     witness_position_x,
                     witness_position_y,
                     signature
-                ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?);",
+                ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?);",
             )
             .bind(prox_proof.epoch() as i64)
             .bind(prox_proof.prover_id())
             .bind(prox_proof.request().position().0)
             .bind(prox_proof.request().position().1)
             .bind(prox_proof.request().signature().as_ref())
+            .bind(prox_proof.request().format().version())
             .bind(prox_proof.witness_id())
             .bind(prox_proof.witness_position().0)
             .bind(prox_proof.witness_position().1)
@@ -122,27 +392,30 @@ impl HdltLocalStore {
     pub async fn add_misbehaviour_proof(&self, proof: MisbehaviorProof) -> Result<(), HdltLocalStoreError> {
         let mut tx = self.db_pool.begin().await?;
 
+        // either account may already be stored, e.g. when it was submitted by its prover
         let prox_proof_a = proof.a();
         let prox_proof_b = proof.b();
 
         sqlx::query(
-            "INSERT INTO proximity_proofs (
+            "INSERT OR IGNORE INTO proximity_proofs (
                 epoch,
                 prover_id,
                 prover_position_x,
                 prover_position_y,
                 request_signature,
+                request_signature_format,
                 witness_id,
                 witness_position_x,
                 witness_position_y,
                 signature
-            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?);",
+            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?);",
         )
         .bind(prox_proof_a.epoch() as i64)
         .bind(prox_proof_a.prover_id())
         .bind(prox_proof_a.request().position().0)
         .bind(prox_proof_a.request().position().1)
         .bind(prox_proof_a.request().signature().as_ref())
+        .bind(prox_proof_a.request().format().version())
         .bind(prox_proof_a.witness_id())
         .bind(prox_proof_a.witness_position().0)
         .bind(prox_proof_a.witness_position().1)
@@ -151,23 +424,25 @@ impl HdltLocalStore {
         .await?;
 
         sqlx::query(
-            "INSERT INTO proximity_proofs (
+            "INSERT OR IGNORE INTO proximity_proofs (
                 epoch,
                 prover_id,
                 prover_position_x,
                 prover_position_y,
                 request_signature,
+                request_signature_format,
                 witness_id,
                 witness_position_x,
                 witness_position_y,
                 signature
-            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?);",
+            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?);",
         )
         .bind(prox_proof_b.epoch() as i64)
         .bind(prox_proof_b.prover_id())
         .bind(prox_proof_b.request().position().0)
         .bind(prox_proof_b.request().position().1)
         .bind(prox_proof_b.request().signature().as_ref())
+        .bind(prox_proof_b.request().format().version())
         .bind(prox_proof_b.witness_id())
         .bind(prox_proof_b.witness_position().0)
         .bind(prox_proof_b.witness_position().1)
@@ -216,7 +491,7 @@ impl HdltLocalStore {
         // get all proximity proofs for non-misbehaving provers (non-misbehaving in this epoch)
         let proofs: Vec<_> = sqlx::query_as::<_, DbProximityProof>(
             "SELECT p.* FROM proximity_proofs AS p
-            WHERE p.epoch = ? AND p.prover_id = ?
+            WHERE p.epoch = ? AND p.prover_id = ? AND p.deleted_at IS NULL
                 AND p.prover_id NOT IN (
                     SELECT m.user_id FROM misbehavior_proofs AS m
                     WHERE m.epoch = ? AND m.user_id = ?
@@ -236,18 +511,99 @@ impl HdltLocalStore {
         self.verify_proofs(epoch, prover_id, proofs).await
     }
 
+    /// Every account a user took part in at an epoch, as the prover or as a witness
+    pub async fn query_epoch_user(
+        &self,
+        epoch: u64,
+        user_id: EntityId,
+    ) -> Result<Vec<ProximityProof>, HdltLocalStoreError> {
+        Ok(sqlx::query_as::<_, DbProximityProof>(
+            "SELECT * FROM proximity_proofs
+            WHERE epoch = ? AND (prover_id = ? OR witness_id = ?) AND deleted_at IS NULL
+            ORDER BY prover_id ASC, witness_id ASC;",
+        )
+        .bind(epoch as i64)
+        .bind(user_id)
+        .bind(user_id)
+        .fetch_all(&self.db_pool)
+        .await?
+        .into_iter()
+        .map(|r| r.into())
+        .collect())
+    }
+
+    /// Number of distinct witnesses for a prover in a given epoch
+    ///
+    /// Cheaper than [HdltLocalStore::query_epoch_prover] when only the quorum size matters.
+    /// Like it, misbehaving provers have no witnesses.
+    pub async fn witness_count(
+        &self,
+        epoch: u64,
+        prover_id: EntityId,
+    ) -> Result<usize, HdltLocalStoreError> {
+        let (count,): (i64,) = sqlx::query_as(
+            "SELECT COUNT(DISTINCT p.witness_id) FROM proximity_proofs AS p
+            WHERE p.epoch = ? AND p.prover_id = ? AND p.deleted_at IS NULL
+                AND p.prover_id NOT IN (
+                    SELECT m.user_id FROM misbehavior_proofs AS m
+                    WHERE m.epoch = ? AND m.user_id = ?
+                );",
+        )
+        .bind(epoch as i64)
+        .bind(prover_id)
+        .bind(epoch as i64)
+        .bind(prover_id)
+        .fetch_one(&self.db_pool)
+        .await?;
+
+        Ok(count as usize)
+    }
+
+    /// Most recent epoch a prover has (non-deleted) proofs for, if any
+    pub async fn latest_epoch(
+        &self,
+        prover_id: EntityId,
+    ) -> Result<Option<u64>, HdltLocalStoreError> {
+        let (epoch,): (Option<i64>,) = sqlx::query_as(
+            "SELECT MAX(epoch) FROM proximity_proofs
+            WHERE prover_id = ? AND deleted_at IS NULL;",
+        )
+        .bind(prover_id)
+        .fetch_one(&self.db_pool)
+        .await?;
+
+        Ok(epoch.map(|e| e as u64))
+    }
+
+    /// Epochs a prover has (non-deleted) proofs for, oldest first
+    pub async fn epochs_for_prover(
+        &self,
+        prover_id: EntityId,
+    ) -> Result<Vec<u64>, HdltLocalStoreError> {
+        let epochs: Vec<(i64,)> = sqlx::query_as(
+            "SELECT DISTINCT epoch FROM proximity_proofs
+            WHERE prover_id = ? AND deleted_at IS NULL
+            ORDER BY epoch;",
+        )
+        .bind(prover_id)
+        .fetch_all(&self.db_pool)
+        .await?;
+
+        Ok(epochs.into_iter().map(|(epoch,)| epoch as u64).collect())
+    }
+
     pub async fn query_epoch_prover_range(
         &self,
         epoch_range: std::ops::Range<u64>,
         prover_id: EntityId,
     ) -> Result<Vec<(u64, Vec<ProximityProof>)>, HdltLocalStoreError> {
         // get all proximity proofs for non-misbehaving provers
-        // (non-misbehaving in this epoch range)
+        // (non-misbehaving in this epoch range), in epoch order
 
-        let mut proofs = HashMap::with_capacity((epoch_range.end - epoch_range.start) as usize);
+        let mut proofs = BTreeMap::new();
         for prox_proof in sqlx::query_as::<_, DbProximityProof>(
             "SELECT p.* FROM proximity_proofs AS p
-            WHERE p.epoch >= ? AND p.epoch < ? AND p.prover_id = ?
+            WHERE p.epoch >= ? AND p.epoch < ? AND p.prover_id = ? AND p.deleted_at IS NULL
                 AND p.prover_id NOT IN (
                     SELECT m.user_id FROM misbehavior_proofs AS m
                     WHERE m.epoch >= ? AND m.epoch < ? AND m.user_id = ?
@@ -284,6 +640,7 @@ impl HdltLocalStore {
         let proofs = sqlx::query_as::<_, DbProximityProof>(
             "SELECT p.* FROM proximity_proofs AS p
             WHERE p.epoch = ? AND p.prover_position_x = ? AND p.prover_position_y = ?
+                AND p.deleted_at IS NULL
                 AND prover_id NOT IN (
                     SELECT m.user_id FROM misbehavior_proofs AS m
                     WHERE m.epoch = ? AND m.user_id = p.prover_id
@@ -303,6 +660,105 @@ impl HdltLocalStore {
         Ok(proofs)
     }
 
+    /// Like [HdltLocalStore::query_epoch_prover_position], for every position in the rectangle
+    /// between two corners (including both of them)
+    pub async fn query_epoch_prover_region(
+        &self,
+        epoch: u64,
+        top_left: Position,
+        bottom_right: Position,
+    ) -> Result<Vec<ProximityProof>, HdltLocalStoreError> {
+        let proofs = sqlx::query_as::<_, DbProximityProof>(
+            "SELECT p.* FROM proximity_proofs AS p
+            WHERE p.epoch = ?
+                AND p.prover_position_x BETWEEN ? AND ?
+                AND p.prover_position_y BETWEEN ? AND ?
+                AND p.deleted_at IS NULL
+                AND prover_id NOT IN (
+                    SELECT m.user_id FROM misbehavior_proofs AS m
+                    WHERE m.epoch = ? AND m.user_id = p.prover_id
+                )
+            ORDER BY p.prover_id ASC, p.witness_id ASC;",
+        )
+        .bind(epoch as i64)
+        .bind(top_left.0)
+        .bind(bottom_right.0)
+        .bind(top_left.1)
+        .bind(bottom_right.1)
+        .bind(epoch as i64)
+        .fetch_all(&self.db_pool)
+        .await?
+        .into_iter()
+        .map(|r| r.into())
+        .collect();
+
+        Ok(proofs)
+    }
+
+    /// Number of distinct provers in each position at a given epoch, ordered by position
+    ///
+    /// Like [HdltLocalStore::query_epoch_prover_position], misbehaving provers are not counted.
+    /// Provers are counted regardless of how many witnesses they have.
+    pub async fn position_histogram(
+        &self,
+        epoch: u64,
+    ) -> Result<Vec<(Position, usize)>, HdltLocalStoreError> {
+        let histogram = sqlx::query_as::<_, (i64, i64, i64)>(
+            "SELECT p.prover_position_x, p.prover_position_y, COUNT(DISTINCT p.prover_id)
+            FROM proximity_proofs AS p
+            WHERE p.epoch = ? AND p.deleted_at IS NULL
+                AND prover_id NOT IN (
+                    SELECT m.user_id FROM misbehavior_proofs AS m
+                    WHERE m.epoch = ? AND m.user_id = p.prover_id
+                )
+            GROUP BY p.prover_position_x, p.prover_position_y
+            ORDER BY p.prover_position_x ASC, p.prover_position_y ASC;",
+        )
+        .bind(epoch as i64)
+        .bind(epoch as i64)
+        .fetch_all(&self.db_pool)
+        .await?
+        .into_iter()
+        .map(|(x, y, count)| (Position(x, y), count as usize))
+        .collect();
+
+        Ok(histogram)
+    }
+
+    /// Position of every prover at a given epoch, ordered by prover
+    ///
+    /// Like [HdltLocalStore::query_epoch_prover_position], misbehaving provers are left out.
+    /// So are provers without enough witnesses for a [PositionProof] tolerating `neighbour_faults`.
+    pub async fn query_epoch_positions(
+        &self,
+        epoch: u64,
+        neighbour_faults: usize,
+    ) -> Result<Vec<(EntityId, Position)>, HdltLocalStoreError> {
+        let proofs: Vec<ProximityProof> = sqlx::query_as::<_, DbProximityProof>(
+            "SELECT p.* FROM proximity_proofs AS p
+            WHERE p.epoch = ? AND p.deleted_at IS NULL
+                AND prover_id NOT IN (
+                    SELECT m.user_id FROM misbehavior_proofs AS m
+                    WHERE m.epoch = ? AND m.user_id = p.prover_id
+                )
+            ORDER BY p.prover_id ASC, p.witness_id ASC;",
+        )
+        .bind(epoch as i64)
+        .bind(epoch as i64)
+        .fetch_all(&self.db_pool)
+        .await?
+        .into_iter()
+        .map(|r| r.into())
+        .collect();
+
+        let positions = group_by(&proofs, |a, b| a.prover_id() == b.prover_id())
+            .filter_map(|witnesses| PositionProof::new(witnesses.to_vec(), neighbour_faults).ok())
+            .map(|proof| (proof.prover_id(), proof.position()))
+            .collect();
+
+        Ok(positions)
+    }
+
     pub async fn query_misbehaved(
         &self,
         id: EntityId,
@@ -316,6 +772,155 @@ impl HdltLocalStore {
         .map(|r| r.map(|proof| proof.into()))
         .map_err(|e| e.into())
     }
+
+    /// Obtain proofs of misbehaviour for a user in every epoch they misbehaved in, oldest first
+    pub async fn query_all_misbehaviour(
+        &self,
+        id: EntityId,
+    ) -> Result<Vec<MisbehaviorProof>, HdltLocalStoreError> {
+        sqlx::query_as::<_, DbMisbehaviorProof>(
+            "SELECT * FROM misbehavior_proofs WHERE user_id = ? ORDER BY epoch ASC;",
+        )
+        .bind(id)
+        .fetch_all(&self.db_pool)
+        .await
+        .map(|v| v.into_iter().map(|proof| proof.into()).collect())
+        .map_err(|e| e.into())
+    }
+
+    /// Raw rows of the misbehavior_proofs view for an epoch, ordered by user
+    ///
+    /// Lets the view be tested without turning its rows into [MisbehaviorProof]s.
+    #[cfg(test)]
+    async fn debug_misbehaviour_rows(
+        &self,
+        epoch: u64,
+    ) -> Result<Vec<DbMisbehaviorProof>, HdltLocalStoreError> {
+        sqlx::query_as::<_, DbMisbehaviorProof>(
+            "SELECT * FROM misbehavior_proofs WHERE epoch = ? ORDER BY user_id ASC;",
+        )
+        .bind(epoch as i64)
+        .fetch_all(&self.db_pool)
+        .await
+        .map_err(|e| e.into())
+    }
+
+    /// Count what the store holds, for monitoring
+    pub async fn stats(&self) -> Result<StoreStats, HdltLocalStoreError> {
+        let (proofs, misbehaving_users) = sqlx::query_as::<_, (i64, i64)>(
+            "SELECT
+                (SELECT COUNT(*) FROM proximity_proofs WHERE deleted_at IS NULL),
+                (SELECT COUNT(DISTINCT user_id) FROM misbehavior_proofs);",
+        )
+        .fetch_one(&self.db_pool)
+        .await?;
+
+        Ok(StoreStats {
+            proofs: proofs as u64,
+            misbehaving_users: misbehaving_users as u64,
+        })
+    }
+
+    /// Re-derive misbehaviour from scratch, by trying every pair of proximity proofs in each epoch
+    ///
+    /// Returns a proof for every (epoch, user) that misbehaved but is missing from the
+    /// misbehavior_proofs view, ordered by epoch and user.
+    /// Meant for checking consistency after a migration or suspected corruption: it loads every
+    /// proof in memory.
+    pub async fn reverify(&self) -> Result<Vec<MisbehaviorProof>, HdltLocalStoreError> {
+        let proofs: Vec<ProximityProof> = sqlx::query_as::<_, DbProximityProof>(
+            "SELECT * FROM proximity_proofs WHERE deleted_at IS NULL ORDER BY epoch ASC;",
+        )
+        .fetch_all(&self.db_pool)
+        .await?
+        .into_iter()
+        .map(|r| r.into())
+        .collect();
+
+        let flagged: HashSet<(u64, EntityId)> =
+            sqlx::query_as::<_, (i64, u32)>("SELECT epoch, user_id FROM misbehavior_proofs;")
+                .fetch_all(&self.db_pool)
+                .await?
+                .into_iter()
+                .map(|(epoch, user_id)| (epoch as u64, user_id))
+                .collect();
+
+        let mut missed = BTreeMap::new();
+        for epoch_proofs in group_by(&proofs, |a, b| a.epoch() == b.epoch()) {
+            // a user misbehaved iff some account contradicts the first one they appear in
+            let mut first_seen: HashMap<EntityId, &ProximityProof> = HashMap::new();
+            for proof in epoch_proofs {
+                for &user_id in &[proof.prover_id(), proof.witness_id()] {
+                    let key = (proof.epoch(), user_id);
+                    if flagged.contains(&key) || missed.contains_key(&key) {
+                        continue;
+                    }
+
+                    let first = *first_seen.entry(user_id).or_insert(proof);
+                    if let Ok(misbehaviour) =
+                        MisbehaviorProof::new(user_id, first.clone(), proof.clone())
+                    {
+                        warn!(
+                            event = "Misbehaviour missing from view",
+                            epoch = key.0,
+                            user_id
+                        );
+                        missed.insert(key, misbehaviour);
+                    }
+                }
+            }
+        }
+
+        Ok(missed.into_iter().map(|(_, proof)| proof).collect())
+    }
+
+    /// Verify every stored proximity proof, spreading the work over all available cores
+    async fn verify_all(&self, keystore: &KeyStore) -> Result<(), HdltLocalStoreError> {
+        let proofs: Vec<UnverifiedProximityProof> =
+            sqlx::query_as::<_, DbProximityProof>("SELECT * FROM proximity_proofs;")
+                .fetch_all(&self.db_pool)
+                .await?
+                .into_iter()
+                .map(DbProximityProof::into_unverified)
+                .collect::<Result<_, _>>()?;
+
+        let n_tasks = std::thread::available_parallelism().map_or(1, |n| n.get());
+        let chunk_size = ((proofs.len() + n_tasks - 1) / n_tasks).max(1);
+        let keystore = Arc::new(keystore.clone());
+        let tasks = proofs.chunks(chunk_size).map(|chunk| {
+            let chunk = chunk.to_vec();
+            let keystore = Arc::clone(&keystore);
+            tokio::task::spawn_blocking(move || {
+                chunk.into_iter().try_for_each(|proof| {
+                    let (epoch, prover_id, witness_id) = (
+                        proof.request.epoch,
+                        proof.request.prover_id,
+                        proof.witness_id,
+                    );
+                    proof.verify(&keystore).map(|_| ()).map_err(|source| {
+                        HdltLocalStoreError::InvalidProof {
+                            epoch,
+                            prover_id,
+                            witness_id,
+                            source,
+                        }
+                    })
+                })
+            })
+        });
+
+        for res in futures::future::join_all(tasks).await {
+            res.expect("proof verification task panicked")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Seconds since the unix epoch, as stored in the database
+fn unix_secs(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |since_epoch| since_epoch.as_secs() as i64)
 }
 
 #[derive(sqlx::FromRow)]
@@ -325,33 +930,54 @@ struct DbProximityProof {
     prover_position_x: i64,
     prover_position_y: i64,
     request_signature: Vec<u8>,
+    request_signature_format: Option<u8>,
     witness_id: u32,
     witness_position_x: i64,
     witness_position_y: i64,
     signature: Vec<u8>,
 }
 
-impl From<DbProximityProof> for ProximityProof {
-    fn from(p: DbProximityProof) -> Self {
-        use model::{UnverifiedProximityProof, UnverifiedProximityProofRequest};
-
-        let request = UnverifiedProximityProofRequest {
-            epoch: p.epoch as u64,
-            prover_id: p.prover_id,
-            position: Position(p.prover_position_x, p.prover_position_y),
-            signature: Signature::from_slice(&p.request_signature)
-                .expect("DB stored invalid signature"),
+impl DbProximityProof {
+    /// Fails with [HdltLocalStoreError::InvalidProof] if a signature (or its format) is malformed.
+    fn into_unverified(self) -> Result<UnverifiedProximityProof, HdltLocalStoreError> {
+        let malformed = || HdltLocalStoreError::InvalidProof {
+            epoch: self.epoch as u64,
+            prover_id: self.prover_id,
+            witness_id: self.witness_id,
+            source: KeyStoreError::from(SignatureVerificationError).into(),
         };
 
-        let proof = UnverifiedProximityProof {
-            request,
-            witness_id: p.witness_id,
-            witness_position: Position(p.witness_position_x, p.witness_position_y),
-            signature: Signature::from_slice(&p.signature).expect("DB stored invalid signature"),
+        let request = model::UnverifiedProximityProofRequest {
+            epoch: self.epoch as u64,
+            prover_id: self.prover_id,
+            position: Position(self.prover_position_x, self.prover_position_y),
+            signature: Signature::from_slice(&self.request_signature).ok_or_else(malformed)?,
+            format: self
+                .request_signature_format
+                .map_or(
+                    Some(SignedBytesFormat::Legacy),
+                    SignedBytesFormat::from_version,
+                )
+                .ok_or_else(malformed)?,
         };
 
+        Ok(UnverifiedProximityProof {
+            request,
+            witness_id: self.witness_id,
+            witness_position: Position(self.witness_position_x, self.witness_position_y),
+            signature: Signature::from_slice(&self.signature).ok_or_else(malformed)?,
+        })
+    }
+}
+
+impl From<DbProximityProof> for ProximityProof {
+    fn from(p: DbProximityProof) -> Self {
         // Safety: only previously-verified proximity proofs are inserted in the database
-        unsafe { proof.verify_unchecked() }
+        unsafe {
+            p.into_unverified()
+                .expect("DB stored invalid signature")
+                .verify_unchecked()
+        }
     }
 }
 
@@ -371,6 +997,8 @@ where
     i64: ::sqlx::types::Type<R::Database>,
     Vec<u8>: ::sqlx::decode::Decode<'a, R::Database>,
     Vec<u8>: ::sqlx::types::Type<R::Database>,
+    Option<u8>: ::sqlx::decode::Decode<'a, R::Database>,
+    Option<u8>: ::sqlx::types::Type<R::Database>,
 {
     fn from_row(row: &'a R) -> ::sqlx::Result<Self> {
         let epoch: i64 = row.try_get("epoch")?;
@@ -382,6 +1010,8 @@ where
                 let prover_position_y: i64 = row.try_get(concat!($prefix, "prover_position_y"))?;
                 let request_signature: Vec<u8> =
                     row.try_get(concat!($prefix, "request_signature"))?;
+                let request_signature_format: Option<u8> =
+                    row.try_get(concat!($prefix, "request_signature_format"))?;
                 let witness_id: u32 = row.try_get(concat!($prefix, "witness_id"))?;
                 let witness_position_x: i64 =
                     row.try_get(concat!($prefix, "witness_position_x"))?;
@@ -395,6 +1025,7 @@ where
                     prover_position_x,
                     prover_position_y,
                     request_signature,
+                    request_signature_format,
                     witness_id,
                     witness_position_x,
                     witness_position_y,
@@ -432,6 +1063,9 @@ pub(crate) mod test {
         UnverifiedProximityProofRequest,
     };
 
+    /// Current epoch under which no proof is too far ahead
+    const ANY_EPOCH: u64 = u64::MAX;
+
     fn sig(a: u8, b: u8) -> Signature {
         let mut s = [0u8; 64];
         s[0] = a;
@@ -447,24 +1081,28 @@ pub(crate) mod test {
                 epoch: 0,
                 position: Position(0, 0),
                 signature: sig(42, 43),
+                format: SignedBytesFormat::V1,
             },
             UnverifiedProximityProofRequest {
                 prover_id: 1,
                 epoch: 0,
                 position: Position(1, 0),
                 signature: sig(43, 42),
+                format: SignedBytesFormat::V1,
             },
             UnverifiedProximityProofRequest {
                 prover_id: 0,
                 epoch: 1,
                 position: Position(0, 1),
                 signature: sig(42, 43),
+                format: SignedBytesFormat::V1,
             },
             UnverifiedProximityProofRequest {
                 prover_id: 1,
                 epoch: 1,
                 position: Position(0, 1),
                 signature: sig(43, 43),
+                format: SignedBytesFormat::V1,
             },
         ];
         static ref PPROOFS: Vec<UnverifiedProximityProof> = vec![
@@ -517,7 +1155,7 @@ pub(crate) mod test {
         let store = HdltLocalStore::open_memory().await;
 
         for p in &*PROOFS {
-            store.add_proof(p.clone()).await.unwrap();
+            store.add_proof(p.clone(), ANY_EPOCH).await.unwrap();
         }
 
         store
@@ -530,8 +1168,8 @@ pub(crate) mod test {
 
         {
             let store = HdltLocalStore::open(&store_file_path).await.unwrap();
-            store.add_proof(PROOFS[0].clone()).await.unwrap();
-            store.add_proof(PROOFS[1].clone()).await.unwrap();
+            store.add_proof(PROOFS[0].clone(), ANY_EPOCH).await.unwrap();
+            store.add_proof(PROOFS[1].clone(), ANY_EPOCH).await.unwrap();
         }
 
         {
@@ -549,15 +1187,178 @@ pub(crate) mod test {
     }
 
     #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
-    async fn query_user_position_at_epoch() {
-        let store = build_store().await;
+    async fn open_read_only() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let store_file_path = tmpdir.path().join("db");
+
+        // missing storage is not created
+        assert!(matches!(
+            HdltLocalStore::open_read_only(&store_file_path).await,
+            Err(HdltLocalStoreError::DbError(_))
+        ));
+        assert!(!store_file_path.exists());
 
+        {
+            let store = HdltLocalStore::open(&store_file_path).await.unwrap();
+            store.add_proof(PROOFS[0].clone(), ANY_EPOCH).await.unwrap();
+        }
+
+        let store = HdltLocalStore::open_read_only(&store_file_path)
+            .await
+            .unwrap();
         assert_eq!(
             vec![PPROOFS[0].clone()],
             store.query_epoch_prover(0, 0).await.unwrap(),
         );
-        assert_eq!(
-            vec![PPROOFS[1].clone()],
+        assert!(store.reverify().await.unwrap().is_empty());
+        assert!(matches!(
+            store.add_proof(PROOFS[1].clone(), ANY_EPOCH).await,
+            Err(HdltLocalStoreError::DbError(_))
+        ));
+
+        // nor is outdated storage migrated
+        sqlx::query("PRAGMA user_version = 1;")
+            .execute(
+                &HdltLocalStore::open(&store_file_path)
+                    .await
+                    .unwrap()
+                    .db_pool,
+            )
+            .await
+            .unwrap();
+        assert!(matches!(
+            HdltLocalStore::open_read_only(&store_file_path).await,
+            Err(HdltLocalStoreError::OutdatedSchema { version: 1, .. })
+        ));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn open_verified() {
+        use model::keys::test_data::KeyStoreTestData;
+        use model::ProximityProofRequest;
+
+        let keystores = KeyStoreTestData::new();
+        let tmpdir = tempfile::tempdir().unwrap();
+        let store_file_path = tmpdir.path().join("db");
+
+        {
+            let store = HdltLocalStore::open(&store_file_path).await.unwrap();
+            for epoch in 0..10 {
+                let req = ProximityProofRequest::new(epoch, Position(1, 1), &keystores.user1);
+                let proof = ProximityProof::new(req, Position(2, 2), &keystores.user2).unwrap();
+                store
+                    .add_proof(PositionProof::new(vec![proof], 1).unwrap(), ANY_EPOCH)
+                    .await
+                    .unwrap();
+            }
+        }
+        HdltLocalStore::open_verified(&store_file_path, &keystores.server)
+            .await
+            .unwrap();
+
+        // requests keep the format they were signed in
+        {
+            let store = HdltLocalStore::open(&store_file_path).await.unwrap();
+            let proof = &store.query_epoch_prover(9, 1).await.unwrap()[0];
+            assert_eq!(proof.request().format(), SignedBytesFormat::V1);
+
+            sqlx::query(
+                "UPDATE proximity_proofs SET request_signature_format = 0 WHERE epoch = 9;",
+            )
+            .execute(&store.db_pool)
+            .await
+            .unwrap();
+            let proof = store.query_epoch_prover(9, 1).await.unwrap().remove(0);
+            assert_eq!(proof.request().format(), SignedBytesFormat::Legacy);
+            // verifying finds the format the signature is really in
+            let reverified = UnverifiedProximityProof::from(proof)
+                .verify(&keystores.server)
+                .unwrap();
+            assert_eq!(reverified.request().format(), SignedBytesFormat::V1);
+        }
+
+        // test data has bogus signatures
+        HdltLocalStore::open(&store_file_path)
+            .await
+            .unwrap()
+            .add_proof(PROOFS[0].clone(), ANY_EPOCH)
+            .await
+            .unwrap();
+        assert!(HdltLocalStore::open(&store_file_path).await.is_ok());
+        assert!(matches!(
+            HdltLocalStore::open_verified(&store_file_path, &keystores.server).await,
+            Err(HdltLocalStoreError::InvalidProof {
+                epoch: 0,
+                prover_id: 0,
+                ..
+            })
+        ));
+
+        // malformed signatures are invalid proofs too, not a reason to panic
+        {
+            let store = HdltLocalStore::open(&store_file_path).await.unwrap();
+            sqlx::query("DELETE FROM proximity_proofs WHERE epoch = 0 AND prover_id = 0;")
+                .execute(&store.db_pool)
+                .await
+                .unwrap();
+            sqlx::query("UPDATE proximity_proofs SET signature = zeroblob(3) WHERE epoch = 5;")
+                .execute(&store.db_pool)
+                .await
+                .unwrap();
+        }
+        assert!(matches!(
+            HdltLocalStore::open_verified(&store_file_path, &keystores.server).await,
+            Err(HdltLocalStoreError::InvalidProof {
+                epoch: 5,
+                prover_id: 1,
+                witness_id: 2,
+                source: ProximityProofValidationError::BadSignature(_),
+            })
+        ));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn custom_pool_options() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let options = HdltLocalStoreOptions {
+            max_connections: 4,
+            min_connections: 2,
+            idle_timeout: None,
+            acquire_timeout: Duration::from_secs(5),
+        };
+
+        let store = HdltLocalStore::open_with_options(tmpdir.path().join("db"), &options)
+            .await
+            .unwrap();
+        assert!(store.db_pool.size() >= options.min_connections);
+
+        for p in &*PROOFS {
+            store.add_proof(p.clone(), ANY_EPOCH).await.unwrap();
+        }
+
+        // more concurrent reads than connections
+        let results = futures::future::try_join_all(
+            (0..3 * options.max_connections).map(|_| store.query_epoch_prover(0, 0)),
+        )
+        .await
+        .unwrap();
+        assert!(results.iter().all(|r| r == &vec![PPROOFS[0].clone()]));
+        assert!(matches!(
+            store.add_proof(PROOFS[0].clone(), ANY_EPOCH).await,
+            Err(HdltLocalStoreError::StaleProof)
+        ));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn query_user_position_at_epoch() {
+        let store = build_store().await;
+
+        assert_eq!(
+            vec![PPROOFS[0].clone()],
+            store.query_epoch_prover(0, 0).await.unwrap(),
+        );
+        assert_eq!(
+            vec![PPROOFS[1].clone()],
             store.query_epoch_prover(0, 1).await.unwrap(),
         );
         assert_eq!(
@@ -622,6 +1423,41 @@ pub(crate) mod test {
         );
     }
 
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn query_users_in_region_at_epoch() {
+        let store = build_store().await;
+
+        assert_eq!(
+            vec![PPROOFS[0].clone(), PPROOFS[1].clone()],
+            store
+                .query_epoch_prover_region(0, Position(0, 0), Position(1, 1))
+                .await
+                .unwrap()
+        );
+        assert_eq!(
+            vec![PPROOFS[1].clone()],
+            store
+                .query_epoch_prover_region(0, Position(1, -5), Position(5, 5))
+                .await
+                .unwrap()
+        );
+        assert_eq!(
+            store
+                .query_epoch_prover_position(1, Position(0, 1))
+                .await
+                .unwrap(),
+            store
+                .query_epoch_prover_region(1, Position(0, 1), Position(0, 1))
+                .await
+                .unwrap()
+        );
+        assert!(store
+            .query_epoch_prover_region(0, Position(2, 2), Position(9, 9))
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
     macro_rules! pos_proof {
         ($epoch:expr, $prover_id:expr => ($prover_pos_x:expr, $prover_pos_y:expr) ; $($witness_id:expr => ($witness_pos_x:expr, $witness_pos_y:expr)),+) => {{
             use model::{keys::EntityId, UnverifiedProximityProofRequest, UnverifiedProximityProof, UnverifiedPositionProof};
@@ -634,6 +1470,7 @@ pub(crate) mod test {
                 prover_id,
                 position: Position($prover_pos_x, $prover_pos_y),
                 signature: sig(epoch as u8, prover_id as u8 * 2),
+                format: model::SignedBytesFormat::V1,
             };
 
             let witnesses = vec![
@@ -687,9 +1524,9 @@ pub(crate) mod test {
             1 => (100, 100)
         };
 
-        store.add_proof(p0).await.unwrap();
-        store.add_proof(p1_1.clone()).await.unwrap();
-        store.add_proof(p1_2.clone()).await.unwrap();
+        store.add_proof(p0, ANY_EPOCH).await.unwrap();
+        store.add_proof(p1_1.clone(), ANY_EPOCH).await.unwrap();
+        store.add_proof(p1_2.clone(), ANY_EPOCH).await.unwrap();
 
         // no conflicts yet
         for &epoch in &[0u64, 1] {
@@ -715,7 +1552,7 @@ pub(crate) mod test {
         );
 
         // now add conflicts
-        store.add_proof(p1_3.clone()).await.unwrap();
+        store.add_proof(p1_3.clone(), ANY_EPOCH).await.unwrap();
 
         // epoch 0 should still be fine
         assert!(store.query_epoch_prover(0, 0).await.is_ok());
@@ -745,7 +1582,7 @@ pub(crate) mod test {
             .map(|proofs| async move {
                 let store = HdltLocalStore::open_memory().await;
                 for &p in proofs {
-                    store.add_proof(p.to_owned()).await.unwrap();
+                    store.add_proof(p.to_owned(), ANY_EPOCH).await.unwrap();
                 }
 
                 let mp1 = match store.query_epoch_prover(1, 1).await {
@@ -769,4 +1606,612 @@ pub(crate) mod test {
         })
         .await;
     }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn position_histogram() {
+        let store = HdltLocalStore::open_memory().await;
+
+        let proofs = vec![
+            pos_proof! {
+                0, 0 => (0, 0);
+                1 => (0, 0)
+            },
+            pos_proof! {
+                0, 1 => (0, 0);
+                0 => (0, 0),
+                2 => (5, 5)
+            },
+            pos_proof! {
+                0, 2 => (5, 5);
+                3 => (5, 5)
+            },
+            pos_proof! {
+                0, 3 => (5, 5);
+                4 => (5, 5)
+            },
+            // user 4 was just seen at (5, 5): misbehaving, doesn't count
+            pos_proof! {
+                0, 4 => (9, 9);
+                5 => (9, 9)
+            },
+            pos_proof! {
+                1, 0 => (1, 1);
+                1 => (1, 1)
+            },
+        ];
+        for p in proofs {
+            store.add_proof(p, ANY_EPOCH).await.unwrap();
+        }
+
+        assert_eq!(
+            store.position_histogram(0).await.unwrap(),
+            vec![(Position(0, 0), 2), (Position(5, 5), 2)]
+        );
+        assert_eq!(
+            store.position_histogram(1).await.unwrap(),
+            vec![(Position(1, 1), 1)]
+        );
+        assert!(store.position_histogram(2).await.unwrap().is_empty());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn query_epoch_positions() {
+        let store = HdltLocalStore::open_memory().await;
+
+        let proofs = vec![
+            pos_proof! {
+                0, 0 => (0, 0);
+                1 => (0, 0)
+            },
+            pos_proof! {
+                0, 1 => (0, 0);
+                0 => (0, 0),
+                2 => (5, 5)
+            },
+            pos_proof! {
+                0, 2 => (5, 5);
+                3 => (5, 5)
+            },
+            // user 4 was just seen at (5, 5): misbehaving, left out
+            pos_proof! {
+                0, 4 => (9, 9);
+                3 => (5, 5)
+            },
+            pos_proof! {
+                0, 3 => (5, 5);
+                4 => (5, 5)
+            },
+            pos_proof! {
+                1, 0 => (1, 1);
+                1 => (1, 1)
+            },
+        ];
+        for p in proofs {
+            store.add_proof(p, ANY_EPOCH).await.unwrap();
+        }
+
+        assert_eq!(
+            store.query_epoch_positions(0, 1).await.unwrap(),
+            vec![
+                (0, Position(0, 0)),
+                (1, Position(0, 0)),
+                (2, Position(5, 5)),
+                (3, Position(5, 5))
+            ]
+        );
+
+        // only user 1 has enough witnesses
+        assert_eq!(
+            store.query_epoch_positions(0, 2).await.unwrap(),
+            vec![(1, Position(0, 0))]
+        );
+
+        assert_eq!(
+            store.query_epoch_positions(1, 1).await.unwrap(),
+            vec![(0, Position(1, 1))]
+        );
+        assert!(store.query_epoch_positions(2, 1).await.unwrap().is_empty());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn epochs_for_prover() {
+        let store = HdltLocalStore::open_memory().await;
+
+        let proofs = vec![
+            // several witnesses, one epoch
+            pos_proof! {
+                0, 0 => (0, 0);
+                1 => (0, 0),
+                2 => (0, 1)
+            },
+            pos_proof! {
+                2, 0 => (0, 0);
+                1 => (0, 0)
+            },
+            pos_proof! {
+                5, 0 => (1, 1);
+                1 => (1, 1),
+                2 => (1, 1)
+            },
+            pos_proof! {
+                3, 1 => (0, 0);
+                0 => (0, 0)
+            },
+        ];
+        for p in proofs {
+            store.add_proof(p, ANY_EPOCH).await.unwrap();
+        }
+
+        assert_eq!(store.epochs_for_prover(0).await.unwrap(), vec![0, 2, 5]);
+        assert_eq!(store.epochs_for_prover(1).await.unwrap(), vec![3]);
+
+        // witnessing doesn't count
+        assert!(store.epochs_for_prover(2).await.unwrap().is_empty());
+
+        // nor do deleted proofs
+        store.delete_user(0).await.unwrap();
+        assert!(store.epochs_for_prover(0).await.unwrap().is_empty());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn stats() {
+        let store = build_store().await;
+        assert_eq!(
+            store.stats().await.unwrap(),
+            StoreStats {
+                proofs: 4,
+                misbehaving_users: 0
+            }
+        );
+
+        // witness 1 in two places at epoch 2
+        store
+            .add_proof(
+                pos_proof! {
+                    2, 0 => (0, 0);
+                    1 => (1, 1)
+                },
+                ANY_EPOCH,
+            )
+            .await
+            .unwrap();
+        store
+            .add_proof(
+                pos_proof! {
+                    2, 2 => (5, 5);
+                    1 => (5, 5)
+                },
+                ANY_EPOCH,
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            store.stats().await.unwrap(),
+            StoreStats {
+                proofs: 6,
+                misbehaving_users: 1
+            }
+        );
+
+        // deleted proofs don't count, but the misbehaviour they showed does
+        store.delete_user(0).await.unwrap();
+        assert_eq!(
+            store.stats().await.unwrap(),
+            StoreStats {
+                proofs: 3,
+                misbehaving_users: 1
+            }
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn reverify() {
+        let store = build_store().await;
+        assert!(store.reverify().await.unwrap().is_empty());
+
+        // conflicts the view knows about are not reported
+        store
+            .add_proof(
+                pos_proof! {
+                    2, 0 => (0, 0);
+                    1 => (1, 1)
+                },
+                ANY_EPOCH,
+            )
+            .await
+            .unwrap();
+        store
+            .add_proof(
+                pos_proof! {
+                    2, 2 => (5, 5);
+                    1 => (5, 5)
+                },
+                ANY_EPOCH,
+            )
+            .await
+            .unwrap();
+        assert!(store.query_misbehaved(1).await.unwrap().is_some());
+        assert!(store.reverify().await.unwrap().is_empty());
+
+        // a view that lost track of every conflict, e.g. after a bad migration
+        sqlx::query(
+            "DROP VIEW misbehavior_proofs;
+            CREATE VIEW misbehavior_proofs AS
+                SELECT epoch, prover_id AS user_id FROM proximity_proofs WHERE 0;",
+        )
+        .execute(&store.db_pool)
+        .await
+        .unwrap();
+
+        let missed = store.reverify().await.unwrap();
+        assert_eq!(missed.len(), 1);
+        assert_eq!(missed[0].user_id(), 1);
+        assert_eq!(missed[0].a().epoch(), 2);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn misbehaviour_view() {
+        let store = HdltLocalStore::open_memory().await;
+
+        /// (user, (prover, witness) of a, (prover, witness) of b) of each row
+        async fn rows(
+            store: &HdltLocalStore,
+            epoch: u64,
+        ) -> Vec<(EntityId, (EntityId, EntityId), (EntityId, EntityId))> {
+            store
+                .debug_misbehaviour_rows(epoch)
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|row| {
+                    (
+                        row.user_id,
+                        (row.a.prover_id, row.a.witness_id),
+                        (row.b.prover_id, row.b.witness_id),
+                    )
+                })
+                .collect()
+        }
+
+        // prover-witness: user 1 proves one position and witnesses from another
+        store
+            .add_proof(pos_proof! { 0, 1 => (0, 0); 2 => (0, 1) }, ANY_EPOCH)
+            .await
+            .unwrap();
+        store
+            .add_proof(pos_proof! { 0, 3 => (5, 5); 1 => (5, 6) }, ANY_EPOCH)
+            .await
+            .unwrap();
+        assert_eq!(rows(&store, 0).await, vec![(1, (1, 2), (3, 1))]);
+
+        // witness-witness: user 3 witnesses from two positions
+        store
+            .add_proof(pos_proof! { 1, 1 => (0, 0); 3 => (1, 1) }, ANY_EPOCH)
+            .await
+            .unwrap();
+        store
+            .add_proof(pos_proof! { 1, 2 => (9, 9); 3 => (9, 8) }, ANY_EPOCH)
+            .await
+            .unwrap();
+        assert_eq!(rows(&store, 1).await, vec![(3, (1, 3), (2, 3))]);
+
+        // users showing up several times, always in the same position, are fine
+        for proof in vec![
+            pos_proof! { 2, 1 => (0, 0); 2 => (0, 1), 3 => (1, 0) },
+            pos_proof! { 2, 4 => (0, 0); 2 => (0, 1), 3 => (1, 0) },
+            pos_proof! { 2, 2 => (0, 1); 1 => (0, 0) },
+        ] {
+            store.add_proof(proof, ANY_EPOCH).await.unwrap();
+        }
+        assert!(rows(&store, 2).await.is_empty());
+
+        // prover-prover: user 1 proves two positions (add_proof only takes one of them,
+        // but a reported misbehaviour proof has both)
+        let a = pos_proof! { 3, 1 => (0, 0); 2 => (0, 1) }.witnesses()[0].clone();
+        let b = pos_proof! { 3, 1 => (7, 7); 4 => (7, 8) }.witnesses()[0].clone();
+        store
+            .add_misbehaviour_proof(MisbehaviorProof::new(1, a, b).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(rows(&store, 3).await, vec![(1, (1, 2), (1, 4))]);
+        assert!(store.reverify().await.unwrap().is_empty());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn all_misbehaviour() {
+        let store = HdltLocalStore::open_memory().await;
+
+        // user 1 witnesses from two different positions in epochs 0 and 2, but behaves in epoch 1
+        for &epoch in &[0, 1, 2] {
+            store
+                .add_proof(
+                    pos_proof! {
+                        epoch, 0 => (0, 0);
+                        1 => (1, 1),
+                        2 => (2, 2)
+                    },
+                    ANY_EPOCH,
+                )
+                .await
+                .unwrap();
+
+            if epoch != 1 {
+                store
+                    .add_proof(
+                        pos_proof! {
+                            epoch, 3 => (2, 2);
+                            1 => (9, 9),
+                            2 => (2, 2)
+                        },
+                        ANY_EPOCH,
+                    )
+                    .await
+                    .unwrap();
+            }
+        }
+
+        let mps = store.query_all_misbehaviour(1).await.unwrap();
+        assert_eq!(
+            vec![0, 2],
+            mps.iter().map(|mp| mp.a().epoch()).collect::<Vec<_>>()
+        );
+        assert!(mps.iter().all(|mp| mp.user_id() == 1));
+
+        for &uid in &[0, 2, 3, 4] {
+            assert!(store.query_all_misbehaviour(uid).await.unwrap().is_empty());
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn witness_account_first() {
+        let store = HdltLocalStore::open_memory().await;
+
+        // user 2 witnesses at (5, 5)...
+        store
+            .add_proof(
+                pos_proof! {
+                    0, 1 => (5, 5);
+                    2 => (5, 5)
+                },
+                ANY_EPOCH,
+            )
+            .await
+            .unwrap();
+
+        // ...and then proves to be at (0, 0)
+        store
+            .add_proof(
+                pos_proof! {
+                    0, 2 => (0, 0);
+                    3 => (0, 0)
+                },
+                ANY_EPOCH,
+            )
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            store.query_epoch_prover(0, 2).await,
+            Err(HdltLocalStoreError::InconsistentUser(mp))
+                if mp.user_id() == 2 && mp.a().prover_id() == 2 && mp.b().witness_id() == 2
+        ));
+
+        // nothing for reverification to catch either
+        assert!(store.reverify().await.unwrap().is_empty());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn witness_count() {
+        let store = build_store().await;
+
+        for (epoch, prover_id) in [(0, 0), (0, 1), (1, 0), (1, 1)].iter() {
+            assert_eq!(1, store.witness_count(*epoch, *prover_id).await.unwrap());
+        }
+        assert_eq!(0, store.witness_count(0, 2).await.unwrap());
+        assert_eq!(0, store.witness_count(2, 0).await.unwrap());
+
+        let store = HdltLocalStore::open_memory().await;
+        store
+            .add_proof(
+                pos_proof! {
+                    0, 0 => (0, 0);
+                    1 => (1, 1),
+                    2 => (2, 2),
+                    3 => (3, 3)
+                },
+                ANY_EPOCH,
+            )
+            .await
+            .unwrap();
+        assert_eq!(3, store.witness_count(0, 0).await.unwrap());
+        assert_eq!(0, store.witness_count(0, 1).await.unwrap());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn delete_counts_reports() {
+        let store = HdltLocalStore::open_memory().await;
+        store
+            .add_proof(
+                pos_proof! { 0, 0 => (0, 0); 1 => (0, 0), 2 => (0, 1), 3 => (1, 0) },
+                ANY_EPOCH,
+            )
+            .await
+            .unwrap();
+        store
+            .add_proof(
+                pos_proof! { 1, 0 => (0, 0); 1 => (0, 0), 2 => (0, 1) },
+                ANY_EPOCH,
+            )
+            .await
+            .unwrap();
+
+        // one per epoch, however many witnesses each report had
+        assert_eq!(2, store.delete_user(0).await.unwrap());
+        assert_eq!(2, store.restore_user(0).await.unwrap());
+        assert_eq!(5, store.stats().await.unwrap().proofs);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn tombstones() {
+        let store = build_store().await;
+
+        assert_eq!(2, store.delete_user(0).await.unwrap());
+        assert_eq!(0, store.delete_user(0).await.unwrap());
+
+        // deleted proofs are invisible
+        assert!(store.query_epoch_prover(0, 0).await.unwrap().is_empty());
+        assert!(store
+            .query_epoch_prover_range(0..2, 0)
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(0, store.witness_count(1, 0).await.unwrap());
+        assert!(store
+            .query_epoch_prover_position(0, Position(0, 0))
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            vec![(Position(0, 1), 1)],
+            store.position_histogram(1).await.unwrap()
+        );
+
+        // other users are not affected
+        assert_eq!(
+            vec![PPROOFS[1].clone()],
+            store.query_epoch_prover(0, 1).await.unwrap()
+        );
+
+        // no coming back without a decision
+        assert!(matches!(
+            store
+                .add_proof(pos_proof! { 2, 0 => (0, 0); 1 => (0, 0) }, ANY_EPOCH)
+                .await,
+            Err(HdltLocalStoreError::DeletedUser(0))
+        ));
+
+        assert_eq!(2, store.restore_user(0).await.unwrap());
+        assert_eq!(
+            vec![PPROOFS[0].clone()],
+            store.query_epoch_prover(0, 0).await.unwrap()
+        );
+
+        // purged tombstones are gone for good, and the user is free to submit again
+        store.delete_user(0).await.unwrap();
+        assert_eq!(0, store.purge_tombstones(UNIX_EPOCH).await.unwrap());
+        let later = SystemTime::now() + Duration::from_secs(1);
+        assert_eq!(2, store.purge_tombstones(later).await.unwrap());
+        assert_eq!(0, store.restore_user(0).await.unwrap());
+        assert!(store.query_epoch_prover(0, 0).await.unwrap().is_empty());
+        store.add_proof(PROOFS[0].clone(), ANY_EPOCH).await.unwrap();
+        assert_eq!(
+            vec![PPROOFS[0].clone()],
+            store.query_epoch_prover(0, 0).await.unwrap()
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn delete_keeps_misbehaviour() {
+        let store = build_store().await;
+
+        // witness 1 in two places at epoch 2, in the reports of users 0 and 2
+        store
+            .add_proof(pos_proof! { 2, 0 => (0, 0); 1 => (1, 1) }, ANY_EPOCH)
+            .await
+            .unwrap();
+        store
+            .add_proof(pos_proof! { 2, 2 => (5, 5); 1 => (5, 5) }, ANY_EPOCH)
+            .await
+            .unwrap();
+        let misbehaviour = store.query_misbehaved(1).await.unwrap().unwrap();
+        assert_eq!(misbehaviour.user_id(), 1);
+
+        // forgetting either report, or both, doesn't clear the witness
+        store.delete_user(0).await.unwrap();
+        assert_eq!(
+            store.query_misbehaved(1).await.unwrap(),
+            Some(misbehaviour.clone())
+        );
+        store.delete_user(2).await.unwrap();
+        assert_eq!(
+            store.query_misbehaved(1).await.unwrap(),
+            Some(misbehaviour.clone())
+        );
+
+        // restoring them doesn't show it twice
+        store.restore_user(2).await.unwrap();
+        assert_eq!(
+            store.query_all_misbehaviour(1).await.unwrap(),
+            vec![misbehaviour.clone()]
+        );
+
+        // nor does purging them clear it
+        store.delete_user(2).await.unwrap();
+        let later = SystemTime::now() + Duration::from_secs(1);
+        store.purge_tombstones(later).await.unwrap();
+        assert_eq!(store.query_misbehaved(1).await.unwrap(), Some(misbehaviour));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn future_proof() {
+        let store = HdltLocalStore::open_memory().await;
+
+        assert!(matches!(
+            store
+                .add_proof(pos_proof! { 5, 0 => (0, 0); 1 => (0, 0) }, 3)
+                .await,
+            Err(HdltLocalStoreError::FutureProof {
+                epoch: 5,
+                current_epoch: 3
+            })
+        ));
+        assert!(store.query_epoch_prover(5, 0).await.unwrap().is_empty());
+
+        // a little ahead is fine
+        store
+            .add_proof(pos_proof! { 4, 0 => (0, 0); 1 => (0, 0) }, 3)
+            .await
+            .unwrap();
+        assert_eq!(1, store.query_epoch_prover(4, 0).await.unwrap().len());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn migrate_unversioned() {
+        let db_pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .idle_timeout(None)
+            .max_lifetime(None)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+
+        // storage from before migrations existed
+        sqlx::query(MIGRATIONS[0]).execute(&db_pool).await.unwrap();
+        sqlx::query(
+            "INSERT INTO proximity_proofs VALUES (0, 0, 0, 0, zeroblob(64), 1, 0, 0, zeroblob(64));",
+        )
+        .execute(&db_pool)
+        .await
+        .unwrap();
+
+        let store = HdltLocalStore::new(db_pool.clone()).await.unwrap();
+        let (version,): (i64,) = sqlx::query_as("PRAGMA user_version;")
+            .fetch_one(&db_pool)
+            .await
+            .unwrap();
+        assert_eq!(version as usize, MIGRATIONS.len());
+        assert_eq!(1, store.witness_count(0, 0).await.unwrap());
+        // rows from before the signed bytes format was stored are legacy ones
+        assert_eq!(
+            store.query_epoch_prover(0, 0).await.unwrap()[0]
+                .request()
+                .format(),
+            SignedBytesFormat::Legacy
+        );
+
+        // reopening is a no-op
+        let store = HdltLocalStore::new(db_pool).await.unwrap();
+        assert_eq!(1, store.witness_count(0, 0).await.unwrap());
+        assert_eq!(1, store.delete_user(0).await.unwrap());
+    }
 }
@@ -0,0 +1,114 @@
+/* views are recreated on every start, to always match the current schema */
+DROP VIEW IF EXISTS misbehavior_proofs;
+
+CREATE VIEW misbehavior_proofs AS
+WITH live_misbehavior_proofs AS (
+    WITH users AS (
+        SELECT prover_id AS id FROM proximity_proofs WHERE deleted_at IS NULL
+        UNION
+        SELECT witness_id AS id FROM proximity_proofs WHERE deleted_at IS NULL
+    )
+    SELECT a.epoch AS epoch,
+        users.id AS user_id,
+        a.prover_id AS a_prover_id,
+        a.prover_position_x AS a_prover_position_x,
+        a.prover_position_y AS a_prover_position_y,
+        a.request_signature AS a_request_signature,
+        a.request_signature_format AS a_request_signature_format,
+        a.witness_id AS a_witness_id,
+        a.witness_position_x AS a_witness_position_x,
+        a.witness_position_y AS a_witness_position_y,
+        a.signature AS a_signature,
+        b.prover_id AS b_prover_id,
+        b.prover_position_x AS b_prover_position_x,
+        b.prover_position_y AS b_prover_position_y,
+        b.request_signature AS b_request_signature,
+        b.request_signature_format AS b_request_signature_format,
+        b.witness_id AS b_witness_id,
+        b.witness_position_x AS b_witness_position_x,
+        b.witness_position_y AS b_witness_position_y,
+        b.signature AS b_signature
+    FROM proximity_proofs AS a, proximity_proofs AS b, users
+    WHERE a.epoch = b.epoch
+        AND a.rowid != b.rowid
+        AND a.deleted_at IS NULL
+        AND b.deleted_at IS NULL
+        AND (
+            (
+                /* prover-prover conflicts: add_proof only takes one prover proof per epoch,
+                   but both may come in a reported misbehaviour proof */
+                a.prover_id = users.id
+                AND a.prover_id = b.prover_id
+                AND (a.prover_position_x != b.prover_position_x OR a.prover_position_y != b.prover_position_y)
+            )
+            OR
+            (
+                /* prover-witness conflicts (and witness-prover: just swap tables a and b) */
+                a.prover_id = users.id
+                AND a.prover_id = b.witness_id
+                AND (a.prover_position_x != b.witness_position_x OR a.prover_position_y != b.witness_position_y)
+            )
+            OR
+            (
+                /* witness-witness conflicts */
+                a.witness_id = users.id
+                AND a.witness_id = b.witness_id
+                AND (a.witness_position_x != b.witness_position_x OR a.witness_position_y != b.witness_position_y)
+            )
+        )
+),
+all_misbehavior_proofs AS (
+    SELECT *,
+        ROW_NUMBER() OVER (
+            PARTITION BY epoch, user_id
+            /* impose some total order on misbehavior proofs to ensure convergence */
+            ORDER BY a_prover_id ASC, a_prover_position_x ASC, a_prover_position_y ASC, a_request_signature ASC, a_witness_id ASC, a_witness_position_x ASC, a_witness_position_y ASC, a_signature ASC, b_prover_id ASC, b_prover_position_x ASC, b_prover_position_y ASC, b_request_signature ASC, b_witness_id ASC, b_witness_position_x ASC, b_witness_position_y ASC, b_signature ASC
+        ) AS rank
+    FROM (
+        SELECT * FROM live_misbehavior_proofs
+        UNION
+        /* conflicts shown by reports deleted since, see HdltLocalStore::delete_user */
+        SELECT epoch,
+            user_id,
+            a_prover_id,
+            a_prover_position_x,
+            a_prover_position_y,
+            a_request_signature,
+            a_request_signature_format,
+            a_witness_id,
+            a_witness_position_x,
+            a_witness_position_y,
+            a_signature,
+            b_prover_id,
+            b_prover_position_x,
+            b_prover_position_y,
+            b_request_signature,
+            b_request_signature_format,
+            b_witness_id,
+            b_witness_position_x,
+            b_witness_position_y,
+            b_signature
+        FROM kept_misbehavior_proofs
+    )
+)
+SELECT epoch,
+    user_id,
+    a_prover_id,
+    a_prover_position_x,
+    a_prover_position_y,
+    a_request_signature,
+    a_request_signature_format,
+    a_witness_id,
+    a_witness_position_x,
+    a_witness_position_y,
+    a_signature,
+    b_prover_id,
+    b_prover_position_x,
+    b_prover_position_y,
+    b_request_signature,
+    b_request_signature_format,
+    b_witness_id,
+    b_witness_position_x,
+    b_witness_position_y,
+    b_signature
+FROM all_misbehavior_proofs WHERE rank = 1;
\ No newline at end of file
@@ -1,32 +1,38 @@
 #![deny(unsafe_op_in_unsafe_fn)]
 
 use std::net::SocketAddr;
+use std::num::{NonZeroU32, NonZeroUsize};
 use std::path::PathBuf;
 use std::sync::Arc;
 
-use model::keys::KeyStore;
+use model::api::Capabilities;
+use model::keys::{KeyStore, Role};
+use model::MisbehaviorProof;
+use net_utils::create_tcp_incoming;
 use protos::{
+    admin::admin_server::AdminServer,
     driver::correct_server_driver_server::CorrectServerDriverServer,
     hdlt::hdlt_api_server::HdltApiServer,
 };
 use structopt::StructOpt;
-use tokio::net::{TcpListener, TcpStream};
-use tokio_stream::wrappers::TcpListenerStream;
-use tokio_stream::{Stream, StreamExt};
+use tokio::sync::RwLock;
 use tonic::transport::Server as TonicServer;
 
-use eyre::eyre;
 use tracing::*;
 
 pub type ServerBgTaskHandle = tokio::task::JoinHandle<eyre::Result<()>>;
 pub use tonic::transport::Uri;
 
-use hdlt_store::HdltLocalStore;
-use services::{Driver, HdltApiService};
+use services::{
+    auth_interceptor, reload_config, AdminService, Driver, HdltApiService, PositionCoarsening,
+    ServerConfig,
+};
+use sharded_store::ShardedHdltLocalStore;
 
 pub mod group_by;
 pub(crate) mod hdlt_store;
 pub(crate) mod services;
+pub(crate) mod sharded_store;
 
 #[derive(StructOpt)]
 pub struct Options {
@@ -50,28 +56,139 @@ pub struct Options {
     #[structopt(long = "storage")]
     pub storage_path: PathBuf,
 
+    /// Split storage over this many files, by prover, to spread write contention.
+    ///
+    /// With more than one, shard `i` is kept at the storage path with `.i` appended.
+    /// Must stay the same for the lifetime of the storage.
+    #[structopt(long, default_value = "1")]
+    pub storage_shards: NonZeroUsize,
+
     /// Secret keys password.
     #[structopt(long, short = "p", env = "SECRET_KEYS_PASSWORD")]
     pub skeys_password: Option<String>,
+
+    /// Maximum number of requests handled at the same time.
+    ///
+    /// Requests beyond this limit are refused right away instead of waiting for storage.
+    #[structopt(long, default_value = "256")]
+    pub max_in_flight: usize,
+
+    /// Never call clients back: answer atomic reads right away, like regular reads.
+    ///
+    /// For deployments where the server can't open outbound connections.
+    #[structopt(long)]
+    pub no_callbacks: bool,
+
+    /// Verify every proof in storage before serving, refusing to start if any is invalid.
+    ///
+    /// For storage that can't be trusted, e.g. imported from another server.
+    #[structopt(long)]
+    pub verify_storage: bool,
+
+    /// Only let HA clients see positions rounded to a grid with this spacing.
+    ///
+    /// They get bare positions instead of position proofs, which carry exact ones.
+    #[structopt(long)]
+    pub coarsen_grid: Option<NonZeroU32>,
+
+    /// Refuse position proofs with more than this many witnesses per tolerated
+    /// neighbourhood fault (plus one), before verifying them.
+    ///
+    /// Keeps clients from making the server verify arbitrarily large proofs.
+    #[structopt(long, default_value = "8")]
+    pub max_witnesses_factor: usize,
+
+    /// Bind address for the admin endpoints (metrics, reverify), kept apart from the API.
+    ///
+    /// Requires an admin token. They are served without TLS, so the token travels in plaintext:
+    /// only loopback addresses are accepted, unless remote admin access is allowed.
+    #[structopt(long, requires = "admin-token")]
+    pub admin_bind_addr: Option<SocketAddr>,
+
+    /// Accept a non-loopback admin bind address.
+    ///
+    /// Anyone on the network path can read the admin token: only for trusted networks or tunnels.
+    #[structopt(long, requires = "admin-bind-addr")]
+    pub admin_allow_remote: bool,
+
+    /// Token admin requests must carry, as `authorization: Bearer <token>` metadata.
+    #[structopt(long, env = "ADMIN_TOKEN")]
+    pub admin_token: Option<String>,
+
+    /// Path to a config file with fault tolerances and the last epoch, re-read on SIGHUP (unix only).
+    ///
+    /// Settings from it override the driver's initial config, and last until it pushes an update.
+    #[structopt(long = "config")]
+    pub config_path: Option<PathBuf>,
+
+    /// Reject API requests without a valid authentication token in their metadata.
+    ///
+    /// Turns away unknown entities at the transport layer, before their requests are deciphered.
+    #[structopt(long)]
+    pub require_auth_metadata: bool,
+}
+
+/// Options for checking a server's storage, see [reverify].
+#[derive(StructOpt)]
+pub struct ReverifyOptions {
+    /// Path to storage file.
+    #[structopt(long = "storage")]
+    pub storage_path: PathBuf,
+
+    /// Number of files the storage is split over, see [Options::storage_shards].
+    #[structopt(long, default_value = "1")]
+    pub storage_shards: NonZeroUsize,
+}
+
+/// Re-derive misbehaviour from every proof in a server's storage.
+///
+/// The storage is opened read-only, and must already exist.
+/// Returns proofs of misbehaviour the storage failed to detect on its own, if any.
+pub async fn reverify(options: &ReverifyOptions) -> eyre::Result<Vec<MisbehaviorProof>> {
+    let store =
+        ShardedHdltLocalStore::open_read_only(&options.storage_path, options.storage_shards)
+            .await?;
+    Ok(store.reverify().await?)
 }
 
 /// A HDLT Server, which can be polled to serve requests.
 ///
 /// Only exists to facilitate integration testing.
 pub struct Server {
-    store: Arc<HdltLocalStore>,
+    store: Arc<ShardedHdltLocalStore>,
     listen_addr: SocketAddr,
+    admin_addr: Option<SocketAddr>,
 }
 
 impl Server {
     pub async fn new(options: &Options) -> eyre::Result<(Self, ServerBgTaskHandle)> {
         let keystore = open_keystore(options)?;
-
-        let store = Arc::new(HdltLocalStore::open(&options.storage_path).await?);
+        self_test_keystore(&keystore)?;
+        check_admin_bind_addr(options)?;
+
+        let store = if options.verify_storage {
+            ShardedHdltLocalStore::open_verified(
+                &options.storage_path,
+                options.storage_shards,
+                &keystore,
+            )
+            .await?
+        } else {
+            ShardedHdltLocalStore::open(&options.storage_path, options.storage_shards).await?
+        };
+        let store = Arc::new(store);
 
         let (incoming, listen_addr) = create_tcp_incoming(&options.bind_addr).await?;
 
-        let driver = Driver::default();
+        let driver = match &options.config_path {
+            Some(config_path) => {
+                let driver = Driver::with_config_file(config_path.clone());
+                reload_config(&driver.state(), config_path).await?;
+                spawn_reload_on_sighup(driver.state(), config_path.clone());
+                driver
+            }
+            None => Driver::default(),
+        };
 
         let entity_id = keystore.my_id();
         let state = driver.state();
@@ -83,19 +200,64 @@ impl Server {
             .map(|id| conf.id_uri_map[id].clone())
             .collect();
 
+        let (admin_server, admin_addr) = match &options.admin_bind_addr {
+            Some(admin_bind_addr) => {
+                let token = options
+                    .admin_token
+                    .clone()
+                    .ok_or_else(|| eyre::eyre!("admin endpoints require an admin token"))?;
+                let (admin_incoming, admin_addr) = create_tcp_incoming(admin_bind_addr).await?;
+                let admin_server = TonicServer::builder()
+                    .add_service(AdminServer::new(AdminService::new(
+                        token,
+                        Arc::clone(&store),
+                        driver.state(),
+                    )))
+                    .serve_with_incoming_shutdown(admin_incoming, ctrl_c());
+
+                (Some(admin_server), Some(admin_addr))
+            }
+            None => (None, None),
+        };
+
+        let api_service = HdltApiService::new(
+            Arc::clone(&keystore),
+            Arc::clone(&store),
+            driver.state(),
+            server_uris,
+            options.max_in_flight,
+            !options.no_callbacks,
+            options.coarsen_grid.map(|grid| PositionCoarsening {
+                grid,
+                roles: vec![Role::HaClient],
+            }),
+            options.max_witnesses_factor,
+            // no optional feature is implemented yet
+            Capabilities::default(),
+        );
+        let api_server = if options.require_auth_metadata {
+            HdltApiServer::with_interceptor(api_service, auth_interceptor(keystore))
+        } else {
+            HdltApiServer::new(api_service)
+        };
+
         let server_bg_task = TonicServer::builder()
-            .add_service(HdltApiServer::new(HdltApiService::new(
-                keystore,
-                Arc::clone(&store),
-                driver.state(),
-                server_uris,
-            )))
+            .add_service(api_server)
             .add_service(CorrectServerDriverServer::new(driver))
             .serve_with_incoming_shutdown(incoming, ctrl_c());
         let server_bg_task = tokio::spawn(
             async move {
                 info!("Server listening");
-                let res = server_bg_task.await.map_err(eyre::Report::from);
+                let admin_bg_task = async move {
+                    match admin_server {
+                        Some(admin_server) => admin_server.await,
+                        None => Ok(()),
+                    }
+                };
+                let res = futures::future::try_join(server_bg_task, admin_bg_task)
+                    .await
+                    .map(|_| ())
+                    .map_err(eyre::Report::from);
                 info!("Server stopped");
 
                 if let Err(err) = &res {
@@ -106,12 +268,16 @@ impl Server {
             .instrument(info_span!("server task", entity_id, %listen_addr)),
         );
 
-        let server = Server { store, listen_addr };
+        let server = Server {
+            store,
+            listen_addr,
+            admin_addr,
+        };
         Ok((server, server_bg_task))
     }
 
     /// The server's underlying data store.
-    pub fn store(&self) -> Arc<HdltLocalStore> {
+    pub fn store(&self) -> Arc<ShardedHdltLocalStore> {
         Arc::clone(&self.store)
     }
 
@@ -123,6 +289,13 @@ impl Server {
         &self.listen_addr
     }
 
+    /// Address where the server is listening for admin requests, if it is.
+    ///
+    /// Like [Self::listen_addr], shows the actual bound port.
+    pub fn admin_addr(&self) -> Option<&SocketAddr> {
+        self.admin_addr.as_ref()
+    }
+
     /// Compute server's URI/endpoint for a client-to-be.
     ///
     /// Assumes the address [Self::listen_addr] is accessible to the client-to-be.
@@ -143,38 +316,100 @@ impl Server {
 }
 
 fn open_keystore(options: &Options) -> eyre::Result<Arc<KeyStore>> {
-    let mut keystore = KeyStore::load_from_files(
+    let keystore = KeyStore::load_from_files_with_password(
         options.entity_registry_path.clone(),
-        options.skeys_path.clone(),
+        Some(options.skeys_path.clone()),
+        options.skeys_password.as_deref(),
     )?;
 
+    Ok(Arc::new(keystore))
+}
+
+/// Sign, verify, cipher and decipher a message with our own keys, to find out about a
+/// misloaded or still locked keystore now instead of on the first request.
+fn self_test_keystore(keystore: &KeyStore) -> eyre::Result<()> {
+    const MESSAGE: &[u8] = b"hdlt server keystore self-test";
+
+    if !keystore.has_identity() {
+        return Err(eyre::eyre!(
+            "keystore self-test failed: no secret keys were loaded"
+        ));
+    }
     if keystore.is_locked() {
-        let password = options.skeys_password.as_ref().ok_or_else(|| {
-            eyre!("Secret keys are password-protected. Please supply their password")
+        return Err(eyre::eyre!(
+            "keystore self-test failed: secret keys are still locked, check the secret keys password"
+        ));
+    }
+    if keystore.my_role() != Role::Server {
+        return Err(eyre::eyre!(
+            "keystore self-test failed: entity {} is not a server",
+            keystore.my_id()
+        ));
+    }
+
+    let me = keystore.my_id();
+    let signature = keystore.sign(MESSAGE);
+    keystore
+        .verify_signature(me, MESSAGE, &signature)
+        .map_err(|e| {
+            eyre::eyre!(e)
+                .wrap_err("keystore self-test failed: secret keys don't match the entity registry")
         })?;
-        keystore.unlock(password)?;
+
+    let (ciphertext, nonce) = keystore.cipher(me, MESSAGE)?;
+    match keystore.decipher(me, &ciphertext, &nonce) {
+        Ok(plaintext) if plaintext == MESSAGE => Ok(()),
+        Ok(_) => Err(eyre::eyre!(
+            "keystore self-test failed: deciphered message doesn't match"
+        )),
+        Err(e) => Err(eyre::eyre!(e)
+            .wrap_err("keystore self-test failed: secret keys don't match the entity registry")),
     }
+}
 
-    Ok(Arc::new(keystore))
+/// Refuse to serve the admin endpoints (and their plaintext token) beyond this host,
+/// unless explicitly allowed.
+fn check_admin_bind_addr(options: &Options) -> eyre::Result<()> {
+    match options.admin_bind_addr {
+        Some(addr) if !addr.ip().is_loopback() && !options.admin_allow_remote => Err(eyre::eyre!(
+            "admin endpoints are served without TLS, refusing to bind them to {} (a loopback address is safe, or pass --admin-allow-remote)",
+            addr
+        )),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(unix)]
+fn spawn_reload_on_sighup(state: Arc<RwLock<ServerConfig>>, config_path: PathBuf) {
+    tokio::spawn(reload_on_sighup(state, config_path));
+}
+
+#[cfg(not(unix))]
+fn spawn_reload_on_sighup(_state: Arc<RwLock<ServerConfig>>, _config_path: PathBuf) {
+    warn!(event = "Config is only reloaded on SIGHUP on unix, it will stay as loaded at startup");
 }
 
-async fn create_tcp_incoming(
-    bind_addr: &SocketAddr,
-) -> eyre::Result<(
-    impl Stream<Item = Result<TcpStream, std::io::Error>>,
-    SocketAddr,
-)> {
-    let listener = TcpListener::bind(bind_addr).await?;
-    let listen_addr = listener.local_addr()?;
-
-    let listener_stream = TcpListenerStream::new(listener).map(|res| {
-        res.and_then(|socket| {
-            socket.set_nodelay(true)?;
-            Ok(socket)
-        })
-    });
-
-    Ok((listener_stream, listen_addr))
+/// Reload the config file whenever SIGHUP is received, see [reload_config]
+#[cfg(unix)]
+async fn reload_on_sighup(state: Arc<RwLock<ServerConfig>>, config_path: PathBuf) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangups = match signal(SignalKind::hangup()) {
+        Ok(hangups) => hangups,
+        Err(err) => {
+            error!(
+                event = "Failed to listen for SIGHUP, config won't be reloaded",
+                ?err
+            );
+            return;
+        }
+    };
+
+    while hangups.recv().await.is_some() {
+        if let Err(err) = reload_config(&state, &config_path).await {
+            warn!(event = "Config not reloaded", ?err);
+        }
+    }
 }
 
 async fn ctrl_c() {
@@ -185,3 +420,62 @@ async fn ctrl_c() {
         future::pending().await // never completes
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use model::keys::EntityPrivComponent;
+
+    #[test]
+    fn keystore_self_test() {
+        model::ensure_init();
+
+        let mut keystore = KeyStore::new(EntityPrivComponent::new(1, Role::Server));
+        self_test_keystore(&keystore).unwrap();
+
+        keystore.lock("password").unwrap();
+        let err = self_test_keystore(&keystore).unwrap_err();
+        assert!(
+            err.to_string().contains("still locked"),
+            "unhelpful error: {}",
+            err
+        );
+
+        keystore.unlock("password").unwrap();
+        self_test_keystore(&keystore).unwrap();
+
+        let user = KeyStore::new(EntityPrivComponent::new(2, Role::User));
+        assert!(self_test_keystore(&user).is_err());
+    }
+
+    #[test]
+    fn admin_bind_addr_loopback_only() {
+        let mut options = Options::from_iter(&[
+            "server",
+            "[::1]:0",
+            "--entities",
+            "registry.json",
+            "--secrets",
+            "secrets.json",
+            "--storage",
+            "storage.db",
+        ]);
+        check_admin_bind_addr(&options).unwrap();
+
+        for loopback in &["127.0.0.1:9000", "[::1]:9000"] {
+            options.admin_bind_addr = Some(loopback.parse().unwrap());
+            check_admin_bind_addr(&options).unwrap();
+        }
+
+        options.admin_bind_addr = Some("0.0.0.0:9000".parse().unwrap());
+        let err = check_admin_bind_addr(&options).unwrap_err();
+        assert!(
+            err.to_string().contains("without TLS"),
+            "unhelpful error: {}",
+            err
+        );
+
+        options.admin_allow_remote = true;
+        check_admin_bind_addr(&options).unwrap();
+    }
+}
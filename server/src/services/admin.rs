@@ -0,0 +1,160 @@
+use std::sync::Arc;
+
+use super::driver::ServerConfig;
+use crate::sharded_store::ShardedHdltLocalStore;
+use protos::admin::admin_server::Admin;
+use protos::admin::{Metrics, ReverifyReply};
+use protos::util::Empty;
+use tokio::sync::RwLock;
+use tonic::{Request, Response, Status};
+use tracing::*;
+use tracing_utils::instrument_tonic_service;
+
+type GrpcResult<T> = Result<Response<T>, Status>;
+
+/// Operational endpoints, meant to be served on their own address (away from clients).
+///
+/// Every request must carry the admin token, as `authorization: Bearer <token>` metadata.
+#[derive(Debug)]
+pub struct AdminService {
+    token: String,
+    store: Arc<ShardedHdltLocalStore>,
+    config: Arc<RwLock<ServerConfig>>,
+}
+
+impl AdminService {
+    pub fn new(
+        token: String,
+        store: Arc<ShardedHdltLocalStore>,
+        config: Arc<RwLock<ServerConfig>>,
+    ) -> Self {
+        AdminService {
+            token,
+            store,
+            config,
+        }
+    }
+
+    fn authorize<T>(&self, request: &Request<T>) -> Result<(), Status> {
+        let presented = request
+            .metadata()
+            .get("authorization")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+
+        match presented {
+            Some(token) if constant_time_eq(token.as_bytes(), self.token.as_bytes()) => Ok(()),
+            _ => {
+                warn!("Admin request without a valid token");
+                Err(Status::unauthenticated("invalid admin token"))
+            }
+        }
+    }
+}
+
+/// Compares without short-circuiting, so the time taken doesn't tell how much of a guess was right
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[instrument_tonic_service]
+#[tonic::async_trait]
+impl Admin for AdminService {
+    #[instrument(skip(self, request))]
+    async fn metrics(&self, request: Request<Empty>) -> GrpcResult<Metrics> {
+        self.authorize(&request)?;
+
+        let epoch = self.config.read().await.epoch;
+        let stats = self
+            .store
+            .stats()
+            .await
+            .map_err(|e| Status::internal(e.to_string()))?;
+
+        Ok(Response::new(Metrics {
+            epoch,
+            stored_proofs: stats.proofs,
+            misbehaving_users: stats.misbehaving_users,
+        }))
+    }
+
+    #[instrument(skip(self, request))]
+    async fn reverify(&self, request: Request<Empty>) -> GrpcResult<ReverifyReply> {
+        self.authorize(&request)?;
+
+        let missed = self
+            .store
+            .reverify()
+            .await
+            .map_err(|e| Status::internal(e.to_string()))?;
+
+        Ok(Response::new(ReverifyReply {
+            misbehaving_user_ids: missed.iter().map(|proof| proof.user_id()).collect(),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::hdlt_store::test::build_store;
+
+    fn request(token: Option<&str>) -> Request<Empty> {
+        let mut request = Request::new(Empty {});
+        if let Some(token) = token {
+            request
+                .metadata_mut()
+                .insert("authorization", token.parse().unwrap());
+        }
+        request
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn metrics_require_token() {
+        let config = Arc::new(RwLock::new(ServerConfig::default()));
+        config.write().await.epoch = 7;
+        let service = AdminService::new(
+            "secret".to_owned(),
+            Arc::new(build_store().await.into()),
+            config,
+        );
+
+        for token in &[
+            None,
+            Some("secret"),
+            Some("Bearer"),
+            Some("Bearer secre"),
+            Some("Bearer secret!"),
+        ] {
+            assert_eq!(
+                service.metrics(request(*token)).await.unwrap_err().code(),
+                tonic::Code::Unauthenticated
+            );
+            assert_eq!(
+                service.reverify(request(*token)).await.unwrap_err().code(),
+                tonic::Code::Unauthenticated
+            );
+        }
+
+        let metrics = service
+            .metrics(request(Some("Bearer secret")))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(
+            metrics,
+            Metrics {
+                epoch: 7,
+                stored_proofs: 4,
+                misbehaving_users: 0,
+            }
+        );
+        assert!(service
+            .reverify(request(Some("Bearer secret")))
+            .await
+            .unwrap()
+            .into_inner()
+            .misbehaving_user_ids
+            .is_empty());
+    }
+}
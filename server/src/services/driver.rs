@@ -1,10 +1,14 @@
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
+use eyre::eyre;
 use model::keys::EntityId;
+use model::WorldBounds;
 use protos::driver::correct_server_driver_server::CorrectServerDriver;
 use protos::driver::{InitialConfigRequest, ServerConfigUpdate};
 use protos::util::Empty;
-use std::collections::HashMap;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
 use tokio::sync::RwLock;
 use tonic::{transport::Uri, Request, Response};
 
@@ -14,16 +18,20 @@ use tracing_utils::instrument_tonic_service;
 #[derive(Default, Debug)]
 pub struct Driver {
     state: Arc<RwLock<ServerConfig>>,
+
+    /// Config file applied on top of the initial config, see [reload_config]
+    config_path: Option<PathBuf>,
 }
 
 #[derive(Debug)]
 pub struct ServerConfig {
     pub epoch: u64,
 
-    /// f', maximum number of byzantine users in a region
+    /// f', maximum number of byzantine users in a region, by the epoch it takes effect in
     ///
+    /// Proofs are judged by the value for their own epoch, see [ServerConfig::max_neigh_faults_at].
     /// See [model::PositionProof] for more information.
-    pub max_neigh_faults: u64,
+    pub neigh_faults_schedule: BTreeMap<u64, u64>,
 
     /// max number of server faults
     pub max_server_faults: u64,
@@ -33,15 +41,29 @@ pub struct ServerConfig {
 
     /// Id to URI
     pub id_uri_map: HashMap<EntityId, Uri>,
+
+    /// Last epoch proofs may be submitted for
+    pub max_epoch: u64,
+
+    /// World proofs must be in, if known
+    pub bounds: Option<WorldBounds>,
 }
 
 impl Driver {
+    /// Driver whose initial config is overridden by the config file at `path`
+    pub fn with_config_file(path: PathBuf) -> Self {
+        Driver {
+            config_path: Some(path),
+            ..Driver::default()
+        }
+    }
+
     pub fn state(&self) -> Arc<RwLock<ServerConfig>> {
         Arc::clone(&self.state)
     }
 
-    pub async fn id_to_uri(&self, id: &EntityId) -> Uri {
-        self.state.read().await.id_uri_map.get(id).unwrap().clone()
+    pub async fn id_to_uri(&self, id: EntityId) -> Uri {
+        self.state.read().await.id_uri_map.get(&id).unwrap().clone()
     }
 }
 
@@ -49,10 +71,12 @@ impl Default for ServerConfig {
     fn default() -> Self {
         ServerConfig {
             epoch: 0,
-            max_neigh_faults: 0,
+            neigh_faults_schedule: BTreeMap::new(),
             max_server_faults: 0,
             servers: vec![],
             id_uri_map: HashMap::new(),
+            max_epoch: u64::MAX,
+            bounds: None,
         }
     }
 }
@@ -61,6 +85,100 @@ impl ServerConfig {
     pub fn n_servers(&self) -> u64 {
         (self.servers.len() + 1) as u64
     }
+
+    /// f' for proofs of the given epoch (0 if none was set by then)
+    pub fn max_neigh_faults_at(&self, epoch: u64) -> u64 {
+        self.neigh_faults_schedule
+            .range(..=epoch)
+            .next_back()
+            .map_or(0, |(_, &faults)| faults)
+    }
+
+    /// f' for proofs of the current epoch
+    pub fn max_neigh_faults(&self) -> u64 {
+        self.max_neigh_faults_at(self.epoch)
+    }
+
+    /// Set f' from an epoch on, dropping any change scheduled after it
+    pub fn set_max_neigh_faults(&mut self, from_epoch: u64, faults: u64) {
+        self.neigh_faults_schedule.split_off(&from_epoch);
+        self.neigh_faults_schedule.insert(from_epoch, faults);
+    }
+}
+
+/// Settings of a [ServerConfig] that can be changed from a file, see [reload_config].
+///
+/// The epoch and the set of servers are left to the driver.
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct ServerConfigFile {
+    max_neigh_faults: u64,
+    max_server_faults: u64,
+    #[serde(default = "no_max_epoch")]
+    max_epoch: u64,
+
+    /// Later changes to `max_neigh_faults`, by the epoch they take effect in
+    #[serde(default)]
+    neigh_faults_schedule: BTreeMap<u64, u64>,
+}
+
+fn no_max_epoch() -> u64 {
+    u64::MAX
+}
+
+/// Re-read the config file at `path` and apply it to `state`, all at once.
+///
+/// The new settings are validated against the current ones first: on error nothing is changed.
+/// They last until the driver pushes a config update.
+/// Neighbourhood faults apply from the current epoch on, so past proofs are still judged as before.
+pub async fn reload_config(state: &RwLock<ServerConfig>, path: &Path) -> eyre::Result<()> {
+    let file = read_config_file(path).await?;
+    apply_config_file(&mut *state.write().await, file)
+}
+
+async fn read_config_file(path: &Path) -> eyre::Result<ServerConfigFile> {
+    Ok(serde_json::from_str(
+        &tokio::fs::read_to_string(path).await?,
+    )?)
+}
+
+/// See [reload_config].
+///
+/// Server faults are only checked against the set of servers once the driver has sent it,
+/// see [Driver::with_config_file].
+fn apply_config_file(state: &mut ServerConfig, file: ServerConfigFile) -> eyre::Result<()> {
+    if !state.servers.is_empty() && 3 * file.max_server_faults >= state.n_servers() {
+        return Err(eyre!(
+            "{} server faults can't be tolerated with {} servers",
+            file.max_server_faults,
+            state.n_servers()
+        ));
+    }
+    if file.max_epoch < state.epoch {
+        return Err(eyre!(
+            "last epoch ({}) is already past (current epoch is {})",
+            file.max_epoch,
+            state.epoch
+        ));
+    }
+    if let Some((&epoch, _)) = file.neigh_faults_schedule.range(..=state.epoch).next() {
+        return Err(eyre!(
+            "neighbourhood faults can't be changed for epoch {}, it's not in the future (current epoch is {})",
+            epoch,
+            state.epoch
+        ));
+    }
+
+    let epoch = state.epoch;
+    state.set_max_neigh_faults(epoch, file.max_neigh_faults);
+    state
+        .neigh_faults_schedule
+        .extend(file.neigh_faults_schedule);
+    state.max_server_faults = file.max_server_faults;
+    state.max_epoch = file.max_epoch;
+    info!(event = "Config reloaded", ?state);
+
+    Ok(())
 }
 
 type GrpcResult<T> = Result<Response<T>, tonic::Status>;
@@ -74,8 +192,9 @@ impl CorrectServerDriver for Driver {
 
         let mut state = self.state.write().await;
         state.epoch = request.new_epoch;
-        state.max_neigh_faults = request.neighbour_faults;
+        state.set_max_neigh_faults(request.new_epoch, request.neighbour_faults);
         state.max_server_faults = request.server_faults;
+        state.max_epoch = request.max_epoch;
 
         info!(event = "New state received", ?state);
 
@@ -84,19 +203,236 @@ impl CorrectServerDriver for Driver {
 
     async fn initial_config(&self, request: Request<InitialConfigRequest>) -> GrpcResult<Empty> {
         let request = request.into_inner();
+        let bounds = match (request.world_min, request.world_max) {
+            (Some(min), Some(max)) => Some(
+                WorldBounds::new(min.into(), max.into())
+                    .map_err(|e| tonic::Status::invalid_argument(e.to_string()))?,
+            ),
+            _ => None,
+        };
+        let file = match &self.config_path {
+            Some(path) => match read_config_file(path).await {
+                Ok(file) => Some(file),
+                Err(err) => {
+                    warn!(event = "Config file not applied", ?err);
+                    None
+                }
+            },
+            None => None,
+        };
+
         let mut state = self.state.write().await;
         state.epoch = 0;
-        state.max_neigh_faults = 0;
+        state.neigh_faults_schedule.clear();
         state.max_server_faults = 0;
         state.servers = request.servers;
+        state.bounds = bounds;
         state.id_uri_map = request
             .id_uri_map
             .iter()
             .map(|(k, v)| (*k, v.parse::<Uri>().unwrap()))
             .collect();
 
+        // the file overrides the driver, now that the set of servers is known to check it against
+        if let Some(file) = file {
+            if let Err(err) = apply_config_file(&mut state, file) {
+                warn!(event = "Config file not applied", ?err);
+            }
+        }
+
         info!(event = "New config received", ?state);
 
         Ok(Response::new(Empty {}))
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn reload() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let path = tmpdir.path().join("config.json");
+        let state = RwLock::new(ServerConfig {
+            epoch: 5,
+            servers: vec![1, 2, 3],
+            ..ServerConfig::default()
+        });
+
+        std::fs::write(
+            &path,
+            r#"{ "max_neigh_faults": 2, "max_server_faults": 1, "max_epoch": 10 }"#,
+        )
+        .unwrap();
+        reload_config(&state, &path).await.unwrap();
+        {
+            let state = state.read().await;
+            assert_eq!(state.max_neigh_faults(), 2);
+            assert_eq!(state.max_server_faults, 1);
+            assert_eq!(state.max_epoch, 10);
+            assert_eq!(state.epoch, 5);
+        }
+
+        // invalid configs are not applied, not even in part
+        for bad in &[
+            r#"{ "max_neigh_faults": 3, "max_server_faults": 2 }"#,
+            r#"{ "max_neigh_faults": 3, "max_server_faults": 0, "max_epoch": 4 }"#,
+            r#"{ "max_neigh_faults": 3, "max_server_faults": 0, "epoch": 7 }"#,
+            r#"{ "max_neigh_faults": 3 }"#,
+            r#"{ "max_neigh_faults": 3, "max_server_faults": 0, "neigh_faults_schedule": { "5": 1 } }"#,
+        ] {
+            std::fs::write(&path, bad).unwrap();
+            assert!(reload_config(&state, &path).await.is_err());
+            assert_eq!(state.read().await.max_neigh_faults(), 2);
+        }
+
+        std::fs::write(
+            &path,
+            r#"{ "max_neigh_faults": 0, "max_server_faults": 0 }"#,
+        )
+        .unwrap();
+        reload_config(&state, &path).await.unwrap();
+        assert_eq!(state.read().await.max_epoch, u64::MAX);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn file_overrides_initial_config() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let path = tmpdir.path().join("config.json");
+        std::fs::write(
+            &path,
+            r#"{ "max_neigh_faults": 2, "max_server_faults": 1, "max_epoch": 10 }"#,
+        )
+        .unwrap();
+
+        // before the driver sends the set of servers, there is nothing to check against
+        let driver = Driver::with_config_file(path.clone());
+        reload_config(&driver.state(), &path).await.unwrap();
+        assert_eq!(driver.state().read().await.max_server_faults, 1);
+
+        driver
+            .initial_config(Request::new(InitialConfigRequest {
+                servers: vec![1, 2, 3],
+                id_uri_map: HashMap::new(),
+                world_min: None,
+                world_max: None,
+            }))
+            .await
+            .unwrap();
+        {
+            let state = driver.state();
+            let state = state.read().await;
+            assert_eq!(state.servers, vec![1, 2, 3]);
+            assert_eq!(state.max_neigh_faults(), 2);
+            assert_eq!(state.max_server_faults, 1);
+            assert_eq!(state.max_epoch, 10);
+        }
+
+        // too many faults for the servers the driver sent: its config is kept
+        std::fs::write(
+            &path,
+            r#"{ "max_neigh_faults": 2, "max_server_faults": 2 }"#,
+        )
+        .unwrap();
+        driver
+            .initial_config(Request::new(InitialConfigRequest {
+                servers: vec![1, 2, 3],
+                id_uri_map: HashMap::new(),
+                world_min: None,
+                world_max: None,
+            }))
+            .await
+            .unwrap();
+        assert_eq!(driver.state().read().await.max_server_faults, 0);
+        assert!(reload_config(&driver.state(), &path).await.is_err());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn initial_config_bounds() {
+        use model::Position;
+
+        let driver = Driver::default();
+        driver
+            .initial_config(Request::new(InitialConfigRequest {
+                servers: vec![],
+                id_uri_map: HashMap::new(),
+                world_min: Some(Position(0, 0).into()),
+                world_max: Some(Position(9, 4).into()),
+            }))
+            .await
+            .unwrap();
+        assert_eq!(
+            driver.state().read().await.bounds,
+            Some(WorldBounds::new(Position(0, 0), Position(9, 4)).unwrap())
+        );
+
+        // inverted corners are refused, keeping the config as it was
+        assert!(driver
+            .initial_config(Request::new(InitialConfigRequest {
+                servers: vec![],
+                id_uri_map: HashMap::new(),
+                world_min: Some(Position(9, 4).into()),
+                world_max: Some(Position(0, 0).into()),
+            }))
+            .await
+            .is_err());
+        assert!(driver.state().read().await.bounds.is_some());
+
+        // without both corners the world is unbounded
+        driver
+            .initial_config(Request::new(InitialConfigRequest {
+                servers: vec![],
+                id_uri_map: HashMap::new(),
+                world_min: Some(Position(0, 0).into()),
+                world_max: None,
+            }))
+            .await
+            .unwrap();
+        assert_eq!(driver.state().read().await.bounds, None);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn reload_schedule() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let path = tmpdir.path().join("config.json");
+        let state = RwLock::new(ServerConfig {
+            epoch: 5,
+            ..ServerConfig::default()
+        });
+        state.write().await.set_max_neigh_faults(0, 1);
+
+        std::fs::write(
+            &path,
+            r#"{ "max_neigh_faults": 2, "max_server_faults": 0, "neigh_faults_schedule": { "8": 3 } }"#,
+        )
+        .unwrap();
+        reload_config(&state, &path).await.unwrap();
+
+        // past epochs keep their value, tighter security comes later
+        let state = state.read().await;
+        assert_eq!(state.max_neigh_faults_at(0), 1);
+        assert_eq!(state.max_neigh_faults_at(4), 1);
+        assert_eq!(state.max_neigh_faults_at(5), 2);
+        assert_eq!(state.max_neigh_faults_at(7), 2);
+        assert_eq!(state.max_neigh_faults_at(8), 3);
+        assert_eq!(state.max_neigh_faults_at(1000), 3);
+    }
+
+    #[test]
+    fn neigh_faults_schedule() {
+        let mut config = ServerConfig::default();
+        assert_eq!(config.max_neigh_faults_at(3), 0);
+
+        config.set_max_neigh_faults(2, 1);
+        config.set_max_neigh_faults(6, 4);
+        assert_eq!(config.max_neigh_faults_at(1), 0);
+        assert_eq!(config.max_neigh_faults_at(2), 1);
+        assert_eq!(config.max_neigh_faults_at(6), 4);
+
+        // a change drops whatever was planned after it
+        config.set_max_neigh_faults(4, 2);
+        assert_eq!(config.max_neigh_faults_at(3), 1);
+        assert_eq!(config.max_neigh_faults_at(6), 2);
+    }
+}
@@ -3,20 +3,31 @@ use std::sync::Arc;
 
 use super::driver::ServerConfig;
 use crate::group_by::group_by;
-use crate::hdlt_store::{HdltLocalStore, HdltLocalStoreError};
+use crate::hdlt_store::{HdltLocalStoreError, MAX_EPOCH_SKEW};
+use crate::sharded_store::ShardedHdltLocalStore;
+use futures::StreamExt;
+use itertools::Itertools;
 use model::{
-    api::{ApiReply, ApiRequest, PoWCertified, RrMessage, RrMessageError, RrRequest},
-    keys::{EntityId, KeyStore, KeyStoreError, Nonce, Role},
-    Position, PositionProof, PositionProofValidationError, UnverifiedPositionProof,
+    api::{
+        ApiClientError, ApiErrorCode, ApiReply, ApiRequest, AuthToken, Capabilities,
+        EpochAttestation, EpochAttestationError, PoWCertified, ReadCertificateError, RrMessage,
+        RrRequest, SignedReadReply, AUTH_METADATA_KEY,
+    },
+    keys::{EntityId, KeyStore, Nonce, Role},
+    MisbehaviorProof, MisbehaviorProofValidationError, Position, PositionProof,
+    PositionProofPolicy, PositionProofValidationError, UnverifiedMisbehaviorProof,
+    UnverifiedPositionProof,
 };
 use protos::hdlt::hdlt_api_client::HdltApiClient as GrpcHdltApiClient;
 use protos::hdlt::hdlt_api_server::HdltApi;
 use protos::hdlt::CipheredRrMessage;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fmt::Debug;
-use std::time::Duration;
+use std::num::NonZeroU32;
+use std::time::{Duration, Instant};
 use thiserror::Error;
-use tokio::sync::RwLock;
+use tokio::sync::{Notify, RwLock, Semaphore};
+use tonic::metadata::MetadataMap;
 use tonic::transport::{Channel, Uri};
 use tonic::{Request, Response, Status};
 use tower::timeout::Timeout;
@@ -25,6 +36,19 @@ use tracing_utils::instrument_tonic_service;
 use tracing_utils::Request;
 
 const REQUEST_TIMEOUT: Duration = Duration::from_secs(15); // 15s ought to be enough
+const MAX_CONCURRENT_CALLBACKS: usize = 16;
+const MAX_LISTENERS_PER_REGISTER: usize = 256;
+const IDEMPOTENCY_KEY_TTL: Duration = Duration::from_secs(60);
+const RETURNED_VALUE_TTL: Duration = Duration::from_secs(60);
+
+/// How long a read waits for the requestor's latest write before answering without it
+const READ_YOUR_WRITES_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Most epochs a single bulk read may ask for
+const MAX_BULK_EPOCHS: usize = 1024;
+
+/// How far an authentication token's issue time may be from ours
+const AUTH_TOKEN_MAX_AGE: Duration = Duration::from_secs(5 * 60);
 
 type GrpcResult<T> = Result<Response<T>, Status>;
 type HdltResult<T> = Result<T, HdltError>;
@@ -32,12 +56,48 @@ type HdltResult<T> = Result<T, HdltError>;
 #[derive(Debug)]
 pub struct HdltApiService {
     keystore: Arc<KeyStore>,
-    store: Arc<HdltLocalStore>,
+    store: Arc<ShardedHdltLocalStore>,
     answers: Arc<RwLock<HashMap<EntityId, AtomicReadAnswers>>>,
-    server_listeners: Arc<RwLock<HashMap<EntityId, Vec<(EntityId, u64)>>>>,
-    client_listeners: Arc<RwLock<HashMap<EntityId, Vec<(u64, EntityId, Uri)>>>>,
+    server_listeners: Arc<RwLock<HashMap<EntityId, Vec<(EntityId, u64, u64)>>>>,
+    client_listeners: Arc<RwLock<HashMap<EntityId, Vec<(u64, u64, EntityId, Option<Uri>)>>>>,
+    returned_values: Arc<RwLock<HashMap<(EntityId, u64), (u64, UnverifiedPositionProof, Instant)>>>,
+    submitted_proofs: Arc<RwLock<HashMap<[u8; 32], Instant>>>,
+
+    /// Woken whenever a proof is stored, for reads waiting on their requestor's writes
+    proof_added: Arc<Notify>,
+
+    /// Senders and challenges of answered requests, by request epoch, see [HdltApiService::remember_challenge]
+    answered_challenges: Arc<RwLock<BTreeMap<u64, HashSet<(EntityId, u64)>>>>,
     config: Arc<RwLock<ServerConfig>>,
     server_uris: Vec<Uri>,
+
+    /// Permits for requests being handled, refusing any more instead of queueing them
+    in_flight: Arc<Semaphore>,
+
+    /// Whether atomic reads are supported. Otherwise they are answered like regular reads
+    callbacks_enabled: bool,
+
+    /// Which requestors only get to see coarse positions, if any
+    coarsening: Option<PositionCoarsening>,
+
+    /// Submitted proofs may have at most this many witnesses per tolerated neighbour fault (plus one)
+    max_witnesses_factor: usize,
+
+    /// Optional features advertised to clients, see [ApiRequest::Hello]
+    capabilities: Capabilities,
+}
+
+/// Privacy setting: requestors with some roles only see positions rounded to a grid.
+///
+/// Position proofs carry exact positions (and can't be changed without breaking their
+/// signatures), so these requestors get bare coarse positions instead of proofs.
+#[derive(Debug, Clone)]
+pub struct PositionCoarsening {
+    /// Spacing of the grid positions are rounded to, see [Position::coarsen]
+    pub grid: NonZeroU32,
+
+    /// Roles of the requestors that only see coarse positions
+    pub roles: Vec<Role>,
 }
 
 #[derive(Error, Debug)]
@@ -45,6 +105,9 @@ pub enum HdltApiError {
     #[error("Invalid Position Proof: {}", .0)]
     InvalidPositionProof(#[from] PositionProofValidationError),
 
+    #[error("Invalid Misbehaviour Proof: {}", .0)]
+    InvalidMisbehaviourProof(#[from] MisbehaviorProofValidationError),
+
     #[error("Invalid Proof of Work")]
     InvalidProofOfWork,
 
@@ -57,16 +120,92 @@ pub enum HdltApiError {
     #[error("There is not enough data to satisfy your request")]
     NoData,
 
+    #[error("Not enough witnesses for quorum (needs {}, has {})", .required, .available)]
+    InsufficientQuorum { required: usize, available: usize },
+
     #[error("invalid callback uri")]
     BadCallbackUri,
+
+    #[error("Request was already answered")]
+    ReplayedRequest,
+
+    #[error("Request is from epoch {}, too far ahead of the current one ({})", .epoch, .current_epoch)]
+    FutureRequest { epoch: u64, current_epoch: u64 },
+
+    #[error("Server is overloaded, try again later")]
+    Overloaded,
+
+    #[error("Epoch {} is past the last one ({})", .epoch, .max_epoch)]
+    PastMaxEpoch { epoch: u64, max_epoch: u64 },
+
+    #[error("Too many witnesses (has {}, at most {})", .count, .max)]
+    TooManyWitnesses { count: usize, max: usize },
+
+    #[error("Too many epochs requested (asked for {}, at most {})", .count, .max)]
+    TooManyEpochs { count: usize, max: usize },
+
+    #[error("Could not attest epoch: {}", .0)]
+    EpochAttestation(#[from] EpochAttestationError),
+
+    #[error("Could not sign reply: {}", .0)]
+    SignedReply(#[from] ReadCertificateError),
+}
+
+impl From<HdltLocalStoreError> for ApiReply {
+    fn from(err: HdltLocalStoreError) -> Self {
+        let code = match err {
+            HdltLocalStoreError::StaleProof => ApiErrorCode::StaleProof,
+            HdltLocalStoreError::InconsistentUser(_) => ApiErrorCode::InconsistentUser,
+            HdltLocalStoreError::DbError(_)
+            | HdltLocalStoreError::InvalidProof { .. }
+            | HdltLocalStoreError::OutdatedSchema { .. } => ApiErrorCode::StorageFailure,
+            HdltLocalStoreError::DeletedUser(_) | HdltLocalStoreError::FutureProof { .. } => {
+                ApiErrorCode::Other
+            }
+        };
+
+        ApiReply::Error(code, err.to_string())
+    }
+}
+
+impl From<HdltApiError> for ApiReply {
+    fn from(err: HdltApiError) -> Self {
+        match err {
+            HdltApiError::StorageError(e) => e.into(),
+            e @ HdltApiError::ReplayedRequest => {
+                ApiReply::Error(ApiErrorCode::ReplayedRequest, e.to_string())
+            }
+            e @ HdltApiError::NoData => ApiReply::Error(ApiErrorCode::NoData, e.to_string()),
+            e @ HdltApiError::Overloaded => {
+                ApiReply::Error(ApiErrorCode::ResourceExhausted, e.to_string())
+            }
+            HdltApiError::InsufficientQuorum {
+                required,
+                available,
+            } => ApiReply::Error(
+                ApiErrorCode::InsufficientQuorum {
+                    required,
+                    available,
+                },
+                err.to_string(),
+            ),
+            e => ApiReply::Error(ApiErrorCode::Other, e.to_string()),
+        }
+    }
 }
 
 impl HdltApiService {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         keystore: Arc<KeyStore>,
-        store: Arc<HdltLocalStore>,
+        store: Arc<ShardedHdltLocalStore>,
         config: Arc<RwLock<ServerConfig>>,
         server_uris: Vec<Uri>,
+        max_in_flight: usize,
+        callbacks_enabled: bool,
+        coarsening: Option<PositionCoarsening>,
+        max_witnesses_factor: usize,
+        capabilities: Capabilities,
     ) -> Self {
         HdltApiService {
             keystore,
@@ -75,42 +214,86 @@ impl HdltApiService {
             answers: Arc::new(RwLock::new(HashMap::new())),
             server_listeners: Arc::new(RwLock::new(HashMap::new())),
             client_listeners: Arc::new(RwLock::new(HashMap::new())),
+            returned_values: Arc::new(RwLock::new(HashMap::new())),
+            submitted_proofs: Arc::new(RwLock::new(HashMap::new())),
+            proof_added: Arc::new(Notify::new()),
+            answered_challenges: Arc::new(RwLock::new(BTreeMap::new())),
             server_uris,
+            in_flight: Arc::new(Semaphore::new(max_in_flight)),
+            callbacks_enabled,
+            coarsening,
+            max_witnesses_factor,
+            capabilities,
         }
     }
 
+    /// Grid the positions seen by a requestor are rounded to, if they are coarsened at all
+    fn coarsening_grid(&self, requestor_id: EntityId) -> Option<NonZeroU32> {
+        let role = self.keystore.role_of(requestor_id)?;
+        self.coarsening
+            .as_ref()
+            .filter(|coarsening| coarsening.roles.contains(&role))
+            .map(|coarsening| coarsening.grid)
+    }
+
+    /// Position of a user at a given epoch, rounded for the requestor (see [PositionCoarsening])
+    #[instrument(skip(self))]
+    pub async fn obtain_position_report_coarse(
+        &self,
+        requestor_id: EntityId,
+        prover_id: EntityId,
+        epoch: u64,
+    ) -> Result<(u64, Position), HdltApiError> {
+        let proof = self
+            .obtain_position_report_regular(requestor_id, prover_id, epoch, None)
+            .await?;
+        let position = match self.coarsening_grid(requestor_id) {
+            Some(grid) => proof.position().coarsen(grid),
+            None => proof.position(),
+        };
+
+        Ok((proof.epoch(), position))
+    }
+
     #[instrument(skip(self))]
     pub async fn obtain_position_report(
         &self,
         request_id: u64,
+        correlation_id: u64,
         requestor_id: EntityId,
         prover_id: EntityId,
         epoch: u64,
-        callback_uri: &str,
+        callback_uri: Option<&str>,
     ) -> Result<(u64, Position), HdltApiError> {
         if requestor_id == prover_id || self.keystore.role_of(requestor_id) == Some(Role::HaClient)
         {
-            let callback_uri: Uri = callback_uri
-                .try_into()
-                .map_err(|_| HdltApiError::BadCallbackUri)?;
+            let callback_uri: Option<Uri> = callback_uri
+                .map(|uri| uri.try_into().map_err(|_| HdltApiError::BadCallbackUri))
+                .transpose()?;
 
-            let max_neigh_faults = self.config.read().await.max_neigh_faults;
+            let max_neigh_faults = self.config.read().await.max_neigh_faults_at(epoch);
             let prox_proofs = self.store.query_epoch_prover(epoch, prover_id).await?;
-            self.client_listeners
-                .write()
-                .await
-                .entry(prover_id)
-                .or_insert(vec![(request_id, requestor_id, callback_uri.clone())])
-                .push((request_id, requestor_id, callback_uri));
+            push_listener(
+                self.client_listeners
+                    .write()
+                    .await
+                    .entry(prover_id)
+                    .or_default(),
+                (request_id, correlation_id, requestor_id, callback_uri),
+                |(request_id, _, requestor_id, _)| (*requestor_id, *request_id),
+            );
 
             match PositionProof::new(prox_proofs, max_neigh_faults as usize) {
                 Ok(proof) => {
-                    self.server_listeners
-                        .write()
-                        .await
-                        .entry(prover_id)
-                        .or_insert_with(|| vec![(requestor_id, request_id)])
-                        .push((requestor_id, request_id)); // yes we add the requestor id twice the first time
+                    push_listener(
+                        self.server_listeners
+                            .write()
+                            .await
+                            .entry(prover_id)
+                            .or_default(),
+                        (requestor_id, request_id, correlation_id),
+                        |(requestor_id, request_id, _)| (*requestor_id, *request_id),
+                    );
                     self.add_value(
                         requestor_id,
                         request_id,
@@ -121,9 +304,68 @@ impl HdltApiService {
                     .await?;
                     Ok((proof.epoch(), proof.position()))
                 }
-                Err(PositionProofValidationError::NotEnoughWitnesess { .. }) => {
+                Err(PositionProofValidationError::NotEnoughWitnesess { available: 0, .. }) => {
+                    Err(HdltApiError::NoData)
+                }
+                Err(PositionProofValidationError::NotEnoughWitnesess {
+                    required,
+                    available,
+                }) => Err(HdltApiError::InsufficientQuorum {
+                    required,
+                    available,
+                }),
+                Err(e) => Err(e.into()),
+            }
+        } else {
+            debug!("Permission denied");
+            Err(HdltApiError::PermissionDenied)
+        }
+    }
+
+    /// Take the value of an atomic read registered by `requestor_id` without a callback uri.
+    #[instrument(skip(self))]
+    pub async fn poll_return(
+        &self,
+        requestor_id: EntityId,
+        request_id: u64,
+    ) -> Option<(u64, UnverifiedPositionProof)> {
+        self.returned_values
+            .write()
+            .await
+            .remove(&(requestor_id, request_id))
+            .map(|(epoch, proof, _)| (epoch, proof))
+    }
+
+    #[instrument(skip(self))]
+    pub async fn obtain_position_report_regular(
+        &self,
+        requestor_id: EntityId,
+        prover_id: EntityId,
+        epoch: u64,
+        last_write: Option<u64>,
+    ) -> Result<PositionProof, HdltApiError> {
+        if requestor_id == prover_id || self.keystore.role_of(requestor_id) == Some(Role::HaClient)
+        {
+            // only users' own writes are waited for
+            if let Some(last_write) = last_write.filter(|_| requestor_id == prover_id) {
+                self.wait_for_write(prover_id, last_write).await?;
+            }
+
+            let max_neigh_faults = self.config.read().await.max_neigh_faults_at(epoch);
+            let prox_proofs = self.store.query_epoch_prover(epoch, prover_id).await?;
+
+            match PositionProof::new(prox_proofs, max_neigh_faults as usize) {
+                Ok(proof) => Ok(proof),
+                Err(PositionProofValidationError::NotEnoughWitnesess { available: 0, .. }) => {
                     Err(HdltApiError::NoData)
                 }
+                Err(PositionProofValidationError::NotEnoughWitnesess {
+                    required,
+                    available,
+                }) => Err(HdltApiError::InsufficientQuorum {
+                    required,
+                    available,
+                }),
                 Err(e) => Err(e.into()),
             }
         } else {
@@ -132,32 +374,180 @@ impl HdltApiService {
         }
     }
 
+    /// Position reports of a user at several epochs, skipping the ones without (enough) data
+    #[instrument(skip(self))]
+    pub async fn obtain_position_reports_bulk(
+        &self,
+        requestor_id: EntityId,
+        prover_id: EntityId,
+        epochs: &[u64],
+    ) -> Result<Vec<PositionProof>, HdltApiError> {
+        if self.coarsening_grid(requestor_id).is_some() {
+            debug!("Permission denied: requestor only sees coarse positions");
+            return Err(HdltApiError::PermissionDenied);
+        }
+        if epochs.len() > MAX_BULK_EPOCHS {
+            return Err(HdltApiError::TooManyEpochs {
+                count: epochs.len(),
+                max: MAX_BULK_EPOCHS,
+            });
+        }
+
+        let mut proofs = Vec::with_capacity(epochs.len());
+        for &epoch in epochs.iter().sorted().dedup() {
+            match self
+                .obtain_position_report_regular(requestor_id, prover_id, epoch, None)
+                .await
+            {
+                Ok(proof) => proofs.push(proof),
+                Err(HdltApiError::NoData) | Err(HdltApiError::InsufficientQuorum { .. }) => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(proofs)
+    }
+
+    /// Position report of a user at an epoch, signed along with the server's epoch so the
+    /// requestor can show it to others as part of a [ReadCertificate](model::api::ReadCertificate)
+    #[instrument(skip(self))]
+    pub async fn obtain_signed_read_reply(
+        &self,
+        requestor_id: EntityId,
+        prover_id: EntityId,
+        epoch: u64,
+        current_epoch: u64,
+    ) -> Result<SignedReadReply, HdltApiError> {
+        if self.coarsening_grid(requestor_id).is_some() {
+            debug!("Permission denied: requestor only sees coarse positions");
+            return Err(HdltApiError::PermissionDenied);
+        }
+
+        let proof = self
+            .obtain_position_report_regular(requestor_id, prover_id, epoch, None)
+            .await?;
+        Ok(SignedReadReply::new(
+            &self.keystore,
+            current_epoch,
+            prover_id,
+            epoch,
+            proof.into(),
+        )?)
+    }
+
+    /// Wait until a report of the prover at least as recent as `last_write` is stored,
+    /// or for [READ_YOUR_WRITES_TIMEOUT] at most
+    ///
+    /// Reports more than [MAX_EPOCH_SKEW] epochs ahead are never stored, so they aren't waited for.
+    async fn wait_for_write(
+        &self,
+        prover_id: EntityId,
+        last_write: u64,
+    ) -> Result<(), HdltApiError> {
+        let current_epoch = self.config.read().await.epoch;
+        if last_write > current_epoch.saturating_add(MAX_EPOCH_SKEW) {
+            return Err(HdltApiError::FutureRequest {
+                epoch: last_write,
+                current_epoch,
+            });
+        }
+
+        let written = async {
+            loop {
+                // registered before checking, so a proof stored in between isn't missed
+                let proof_added = self.proof_added.notified();
+                if self.store.latest_epoch(prover_id).await? >= Some(last_write) {
+                    return Ok(());
+                }
+                proof_added.await;
+            }
+        };
+
+        match tokio::time::timeout(READ_YOUR_WRITES_TIMEOUT, written).await {
+            Ok(res) => res,
+            Err(_) => {
+                debug!("Requestor's latest write not stored yet, answering without it");
+                Ok(())
+            }
+        }
+    }
+
     #[instrument(skip(self))]
     pub async fn get_position_reports(
         &self,
         requestor_id: EntityId,
+        prover_id: EntityId,
         epoch_start: u64,
         epoch_end: u64,
     ) -> Result<Vec<(u64, PositionProof)>, HdltApiError> {
-        let max_neigh_faults = self.config.read().await.max_neigh_faults;
+        // epochs without enough witnesses are ignored, on purpose
+        self.get_position_reports_with_diagnostics(requestor_id, prover_id, epoch_start, epoch_end)
+            .await
+            .map(|(reports, _)| reports)
+    }
 
-        let prox_proofs_vec = self
+    /// Like [get_position_reports](Self::get_position_reports), but also returning the epochs
+    /// that were left out and why, in epoch order.
+    ///
+    /// Epochs past the ones proofs can currently be submitted for (see [MAX_EPOCH_SKEW]) can't
+    /// have reports yet, so they are neither returned nor reported as skipped.
+    #[instrument(skip(self))]
+    pub async fn get_position_reports_with_diagnostics(
+        &self,
+        requestor_id: EntityId,
+        prover_id: EntityId,
+        epoch_start: u64,
+        epoch_end: u64,
+    ) -> Result<(Vec<(u64, PositionProof)>, Vec<(u64, ApiErrorCode)>), HdltApiError> {
+        if requestor_id != prover_id && self.keystore.role_of(requestor_id) != Some(Role::HaClient)
+        {
+            debug!("Permission denied");
+            return Err(HdltApiError::PermissionDenied);
+        }
+
+        let current_epoch = self.config.read().await.epoch;
+        let epoch_end = epoch_end.min(current_epoch.saturating_add(MAX_EPOCH_SKEW + 1));
+        let epoch_start = epoch_start.min(epoch_end);
+
+        let mut prox_proofs_by_epoch = self
             .store
-            .query_epoch_prover_range(epoch_start..epoch_end, requestor_id)
-            .await?;
-        let mut results = Vec::with_capacity(prox_proofs_vec.len());
+            .query_epoch_prover_range(epoch_start..epoch_end, prover_id)
+            .await?
+            .into_iter()
+            .peekable();
+        let mut results = Vec::new();
+        let mut skipped = Vec::new();
+
+        let config = self.config.read().await;
+        for epoch in epoch_start..epoch_end {
+            let prox_proofs = match prox_proofs_by_epoch.next_if(|(e, _)| *e == epoch) {
+                Some((_, prox_proofs)) => prox_proofs,
+                None => {
+                    skipped.push((epoch, ApiErrorCode::NoData));
+                    continue;
+                }
+            };
 
-        for (epoch, prox_proofs) in prox_proofs_vec {
-            match PositionProof::new(prox_proofs, max_neigh_faults as usize) {
+            match PositionProof::new(prox_proofs, config.max_neigh_faults_at(epoch) as usize) {
                 Ok(proof) => results.push((epoch, proof)),
-                Err(PositionProofValidationError::NotEnoughWitnesess { .. }) => {
-                    // we ignore this, on purpose
+                Err(PositionProofValidationError::NotEnoughWitnesess { available: 0, .. }) => {
+                    skipped.push((epoch, ApiErrorCode::NoData))
                 }
+                Err(PositionProofValidationError::NotEnoughWitnesess {
+                    required,
+                    available,
+                }) => skipped.push((
+                    epoch,
+                    ApiErrorCode::InsufficientQuorum {
+                        required,
+                        available,
+                    },
+                )),
                 Err(e) => return Err(e.into()),
             }
         }
 
-        Ok(results)
+        Ok((results, skipped))
     }
 
     #[instrument(skip(self))]
@@ -168,17 +558,31 @@ impl HdltApiService {
         epoch: u64,
     ) -> Result<Vec<EntityId>, HdltApiError> {
         if self.keystore.role_of(requestor_id) == Some(Role::HaClient) {
-            let max_neigh_faults = self.config.read().await.max_neigh_faults;
-
-            let all_prox_proofs = self
-                .store
-                .query_epoch_prover_position(epoch, prover_position)
-                .await?;
+            let max_neigh_faults = self.config.read().await.max_neigh_faults_at(epoch);
+
+            // coarsened requestors can only tell apart cells of the grid, not positions in them
+            let all_prox_proofs = match self.coarsening_grid(requestor_id) {
+                Some(grid) => {
+                    let (top_left, bottom_right) = prover_position.coarse_cell(grid);
+                    self.store
+                        .query_epoch_prover_region(epoch, top_left, bottom_right)
+                        .await?
+                }
+                None => {
+                    self.store
+                        .query_epoch_prover_position(epoch, prover_position)
+                        .await?
+                }
+            };
             let uids = group_by(&all_prox_proofs, |a, b| a.prover_id() == b.prover_id())
-                .map(|witnesses| PositionProof::new(witnesses.to_vec(), max_neigh_faults as usize))
+                .map(|witnesses| {
+                    PositionProof::from_witnesses_or_none(
+                        witnesses.to_vec(),
+                        max_neigh_faults as usize,
+                    )
+                })
                 .filter_map(|res| match res {
-                    Ok(pos_proof) => Some(pos_proof.prover_id()),
-                    Err(PositionProofValidationError::NotEnoughWitnesess { .. }) => None,
+                    Ok(pos_proof) => pos_proof.map(|p| p.prover_id()),
                     Err(e) => unreachable!(
                         "DB stored bad stuff. This error should be impossible: {:?}",
                         e
@@ -193,25 +597,219 @@ impl HdltApiService {
         }
     }
 
+    /// Whether a user was at a position at an epoch, without revealing who else was there
+    ///
+    /// For coarsened requestors, whether the user was anywhere in the position's cell of the grid.
+    #[instrument(skip(self))]
+    pub async fn is_user_at_position(
+        &self,
+        requestor_id: EntityId,
+        user_id: EntityId,
+        position: Position,
+        epoch: u64,
+    ) -> Result<bool, HdltApiError> {
+        if self.keystore.role_of(requestor_id) != Some(Role::HaClient) {
+            debug!("Permission denied");
+            return Err(HdltApiError::PermissionDenied);
+        }
+
+        let max_neigh_faults = self.config.read().await.max_neigh_faults_at(epoch);
+        let prox_proofs = self.store.query_epoch_prover(epoch, user_id).await?;
+        let proof =
+            match PositionProof::from_witnesses_or_none(prox_proofs, max_neigh_faults as usize)? {
+                Some(proof) => proof,
+                None => return Ok(false),
+            };
+
+        Ok(match self.coarsening_grid(requestor_id) {
+            Some(grid) => proof.position().coarsen(grid) == position.coarsen(grid),
+            None => proof.position() == position,
+        })
+    }
+
+    #[instrument(skip(self))]
+    pub async fn misbehaviour_proofs(
+        &self,
+        requestor_id: EntityId,
+        user_id: EntityId,
+    ) -> Result<Vec<MisbehaviorProof>, HdltApiError> {
+        if self.keystore.role_of(requestor_id) == Some(Role::HaClient) {
+            Ok(self.store.query_all_misbehaviour(user_id).await?)
+        } else {
+            debug!("Permission denied");
+            Err(HdltApiError::PermissionDenied)
+        }
+    }
+
+    /// Erase every report of the requestor, returning how many there were.
+    #[instrument(skip(self))]
+    pub async fn forget_user(&self, requestor_id: EntityId) -> Result<u64, HdltApiError> {
+        if self.keystore.role_of(requestor_id) == Some(Role::User) {
+            Ok(self.store.delete_user(requestor_id).await?)
+        } else {
+            debug!("Permission denied");
+            Err(HdltApiError::PermissionDenied)
+        }
+    }
+
+    /// Store a proof of misbehaviour reported by a user who saw it.
+    #[instrument(skip(self, proof))]
+    pub async fn report_misbehaviour(
+        &self,
+        requestor_id: EntityId,
+        proof: UnverifiedMisbehaviorProof,
+    ) -> Result<(), HdltApiError> {
+        if self.keystore.role_of(requestor_id) != Some(Role::User) {
+            debug!("Permission denied");
+            return Err(HdltApiError::PermissionDenied);
+        }
+
+        let proof = proof.verify(&self.keystore)?;
+        info!(user_id = proof.user_id(), "Misbehaviour reported");
+        self.store.add_misbehaviour_proof(proof).await?;
+
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    pub async fn position_histogram(
+        &self,
+        requestor_id: EntityId,
+        epoch: u64,
+    ) -> Result<Vec<(Position, usize)>, HdltApiError> {
+        if self.keystore.role_of(requestor_id) == Some(Role::HaClient) {
+            let histogram = self.store.position_histogram(epoch).await?;
+            match self.coarsening_grid(requestor_id) {
+                Some(grid) => {
+                    let mut cells: HashMap<Position, usize> = HashMap::new();
+                    for (position, count) in histogram {
+                        *cells.entry(position.coarsen(grid)).or_default() += count;
+                    }
+                    let mut histogram: Vec<_> = cells.into_iter().collect();
+                    histogram.sort_unstable_by_key(|(position, _)| (position.0, position.1));
+
+                    Ok(histogram)
+                }
+                None => Ok(histogram),
+            }
+        } else {
+            debug!("Permission denied");
+            Err(HdltApiError::PermissionDenied)
+        }
+    }
+
+    /// Epochs a prover has reports for
+    #[instrument(skip(self))]
+    pub async fn prover_epochs(
+        &self,
+        requestor_id: EntityId,
+        prover_id: EntityId,
+    ) -> Result<Vec<u64>, HdltApiError> {
+        if requestor_id == prover_id || self.keystore.role_of(requestor_id) == Some(Role::HaClient)
+        {
+            Ok(self.store.epochs_for_prover(prover_id).await?)
+        } else {
+            debug!("Permission denied");
+            Err(HdltApiError::PermissionDenied)
+        }
+    }
+
+    /// Position of every user at a given epoch, rounded for the requestor (see [PositionCoarsening])
+    #[instrument(skip(self))]
+    pub async fn epoch_positions(
+        &self,
+        requestor_id: EntityId,
+        epoch: u64,
+    ) -> Result<Vec<(EntityId, Position)>, HdltApiError> {
+        if self.keystore.role_of(requestor_id) == Some(Role::HaClient) {
+            let max_neigh_faults = self.config.read().await.max_neigh_faults_at(epoch);
+            let positions = self
+                .store
+                .query_epoch_positions(epoch, max_neigh_faults as usize)
+                .await?;
+
+            Ok(match self.coarsening_grid(requestor_id) {
+                Some(grid) => positions
+                    .into_iter()
+                    .map(|(id, position)| (id, position.coarsen(grid)))
+                    .collect(),
+                None => positions,
+            })
+        } else {
+            debug!("Permission denied");
+            Err(HdltApiError::PermissionDenied)
+        }
+    }
+
     #[instrument(skip(self))]
     pub async fn submit_position_proof(
         &self,
         requestor_id: EntityId,
         pow_protected_proof: &PoWCertified<UnverifiedPositionProof>,
     ) -> Result<(), HdltApiError> {
+        // judged by the tolerance of the epoch it claims, verification makes sure it's all of it
+        let (max_neigh_faults, max_epoch, current_epoch, bounds) = {
+            let config = self.config.read().await;
+            let proof_epoch = pow_protected_proof.inner_unchecked().claimed_epoch();
+            (
+                config.max_neigh_faults_at(proof_epoch.unwrap_or(config.epoch)),
+                config.max_epoch,
+                config.epoch,
+                config.bounds,
+            )
+        };
+
+        // refuse oversized proofs before spending any work on them
+        let max_witnesses = self
+            .max_witnesses_factor
+            .saturating_mul(max_neigh_faults as usize + 1);
+        let count = pow_protected_proof.inner_unchecked().witnesses.len();
+        if count > max_witnesses {
+            return Err(HdltApiError::TooManyWitnesses {
+                count,
+                max: max_witnesses,
+            });
+        }
+
         let proof = pow_protected_proof
             .to_owned()
             .try_into_inner()
             .map_err(|_| HdltApiError::InvalidProofOfWork)?;
+        let idempotency_key = proof.digest();
 
-        let max_neigh_faults = self.config.read().await.max_neigh_faults;
-        let proof = proof.verify(max_neigh_faults as usize, self.keystore.as_ref())?;
+        let policy = PositionProofPolicy {
+            bounds,
+            ..PositionProofPolicy::default()
+        };
+        let proof =
+            proof.verify_with_policy(max_neigh_faults as usize, &policy, self.keystore.as_ref())?;
 
         if proof.prover_id() != requestor_id {
             return Err(HdltApiError::PermissionDenied);
         }
+        if proof.epoch() > max_epoch {
+            return Err(HdltApiError::PastMaxEpoch {
+                epoch: proof.epoch(),
+                max_epoch,
+            });
+        }
+
+        // a retried submission of the same proof succeeds again
+        if let Some(submitted_at) = self.submitted_proofs.read().await.get(&idempotency_key) {
+            if submitted_at.elapsed() < IDEMPOTENCY_KEY_TTL {
+                debug!("Proof already submitted, skipping");
+                return Ok(());
+            }
+        }
 
-        self.store.add_proof(proof.clone()).await?;
+        self.store.add_proof(proof.clone(), current_epoch).await?;
+        self.proof_added.notify_waiters();
+
+        {
+            let mut submitted_proofs = self.submitted_proofs.write().await;
+            submitted_proofs.retain(|_, submitted_at| submitted_at.elapsed() < IDEMPOTENCY_KEY_TTL);
+            submitted_proofs.insert(idempotency_key, Instant::now());
+        }
 
         self.send_to_server_listeners(requestor_id, proof.epoch(), proof.into())
             .await?;
@@ -269,7 +867,7 @@ impl HdltApiService {
             tokio::spawn(async move {
                 let clients: Vec<_> = listeners_to_send
                     .into_iter()
-                    .map(|(server_id, request_id)| {
+                    .map(|(server_id, request_id, correlation_id)| {
                         (
                             HdltApiClient::new(
                                 id_uri_map[&server_id].clone(),
@@ -278,20 +876,22 @@ impl HdltApiService {
                                 current_epoch,
                             ),
                             request_id,
+                            correlation_id,
                         )
                     })
-                    .filter(|(c, _)| c.is_ok())
-                    .map(|(c, id)| (c.unwrap(), id))
+                    .filter(|(c, _, _)| c.is_ok())
+                    .map(|(c, id, cid)| (c.unwrap(), id, cid))
                     .collect();
 
-                futures::future::join_all(
-                    clients
-                        .iter()
-                        .map(|(c, request_id)| {
-                            c.return_value(*request_id, proof.clone(), epoch, register_id)
-                        })
-                        .collect::<Vec<_>>(),
-                )
+                fan_out(clients.iter(), |(c, request_id, correlation_id)| {
+                    c.return_value(
+                        *request_id,
+                        *correlation_id,
+                        proof.clone(),
+                        epoch,
+                        register_id,
+                    )
+                })
                 .await;
             });
         }
@@ -307,9 +907,11 @@ impl HdltApiService {
         let (verified_proof, current_epoch) = {
             let config = self.config.read().await;
             (
-                proof
-                    .clone()
-                    .verify(config.max_neigh_faults as usize, &self.keystore)?,
+                proof.clone().verify(
+                    config.max_neigh_faults_at(proof.claimed_epoch().unwrap_or(config.epoch))
+                        as usize,
+                    &self.keystore,
+                )?,
                 config.epoch,
             )
         };
@@ -318,33 +920,48 @@ impl HdltApiService {
 
         if let Some(l) = self.client_listeners.write().await.get_mut(&register_id) {
             let keystore = self.keystore.clone();
-            let listeners_to_send: Vec<_> = l.drain(..).collect();
+            let mut listeners_to_send = Vec::with_capacity(l.len());
+            {
+                // values without a callback are kept until polled (or expired)
+                let mut returned_values = self.returned_values.write().await;
+                returned_values
+                    .retain(|_, (_, _, returned_at)| returned_at.elapsed() < RETURNED_VALUE_TTL);
+                for (rid, cid, client_id, uri) in l.drain(..) {
+                    match uri {
+                        Some(uri) => listeners_to_send.push((rid, cid, client_id, uri)),
+                        None => {
+                            returned_values.insert(
+                                (client_id, rid),
+                                (verified_proof.epoch(), proof.clone(), Instant::now()),
+                            );
+                        }
+                    }
+                }
+            }
+
             tokio::spawn(async move {
                 let clients: Vec<_> = listeners_to_send
                     .into_iter()
-                    .map(|(rid, client_id, uri)| {
+                    .map(|(rid, cid, client_id, uri)| {
                         (
                             rid,
+                            cid,
                             HdltApiClient::new(uri, client_id, keystore.clone(), current_epoch),
                         )
                     })
-                    .filter(|(_, c)| c.is_ok())
-                    .map(|(rid, c)| (rid, c.unwrap()))
+                    .filter(|(_, _, c)| c.is_ok())
+                    .map(|(rid, cid, c)| (rid, cid, c.unwrap()))
                     .collect();
 
-                futures::future::join_all(
-                    clients
-                        .iter()
-                        .map(|(rid, c)| {
-                            c.return_value(
-                                *rid,
-                                proof.clone(),
-                                verified_proof.epoch(),
-                                verified_proof.prover_id(),
-                            )
-                        })
-                        .collect::<Vec<_>>(),
-                )
+                fan_out(clients.iter(), |(rid, cid, c)| {
+                    c.return_value(
+                        *rid,
+                        *cid,
+                        proof.clone(),
+                        verified_proof.epoch(),
+                        verified_proof.prover_id(),
+                    )
+                })
                 .await;
             });
         }
@@ -353,6 +970,94 @@ impl HdltApiService {
     }
 }
 
+/// Register a listener, evicting the oldest ones if the register has too many
+///
+/// Listeners are identified by `key`: registering one again (e.g. a retried request)
+/// replaces it, so it is only sent the value once.
+fn push_listener<T, K: PartialEq>(listeners: &mut Vec<T>, listener: T, key: impl Fn(&T) -> K) {
+    let new_key = key(&listener);
+    listeners.retain(|old| key(old) != new_key);
+    listeners.push(listener);
+    if listeners.len() > MAX_LISTENERS_PER_REGISTER {
+        let excess = listeners.len() - MAX_LISTENERS_PER_REGISTER;
+        warn!(excess, "Too many listeners on register, evicting oldest");
+        listeners.drain(..excess);
+    }
+}
+
+/// Run a callback for every listener, with at most [MAX_CONCURRENT_CALLBACKS] in flight
+async fn fan_out<I, F, Fut>(listeners: I, callback: F) -> Vec<Fut::Output>
+where
+    I: IntoIterator,
+    F: FnMut(I::Item) -> Fut,
+    Fut: std::future::Future,
+{
+    futures::stream::iter(listeners)
+        .map(callback)
+        .buffer_unordered(MAX_CONCURRENT_CALLBACKS)
+        .collect()
+        .await
+}
+
+/// Interceptor turning away requests without a valid [AuthToken] before they reach the handlers.
+///
+/// Tokens are only checked to be fresh and signed by a known entity: the [RrMessage]s they come with
+/// are still authenticated as usual.
+pub fn auth_interceptor(
+    keystore: Arc<KeyStore>,
+) -> impl Fn(Request<()>) -> Result<Request<()>, Status> + Send + Sync + 'static {
+    move |request: Request<()>| {
+        let token = request
+            .metadata()
+            .get(AUTH_METADATA_KEY)
+            .ok_or_else(|| Status::unauthenticated("missing authentication token"))?
+            .to_str()
+            .map_err(|_| Status::unauthenticated("malformed authentication token"))
+            .and_then(|encoded| {
+                AuthToken::decode(encoded).map_err(|e| Status::unauthenticated(e.to_string()))
+            })?;
+
+        token.verify(&keystore, AUTH_TOKEN_MAX_AGE).map_err(|e| {
+            warn!(
+                entity_id = token.entity_id(),
+                "Request with invalid authentication token"
+            );
+            Status::unauthenticated(e.to_string())
+        })?;
+
+        Ok(request)
+    }
+}
+
+/// Refuses messages sent on behalf of an entity other than the one their [AuthToken] is for.
+///
+/// The token was already checked by [auth_interceptor], which can't see who the message is from.
+/// Requests without a token only get here if the interceptor isn't in place (e.g. in tests).
+fn check_token_sender(metadata: &MetadataMap, sender_id: EntityId) -> Result<(), Status> {
+    let encoded = match metadata.get(AUTH_METADATA_KEY) {
+        Some(encoded) => encoded,
+        None => return Ok(()),
+    };
+    let token = encoded
+        .to_str()
+        .map_err(|_| Status::unauthenticated("malformed authentication token"))
+        .and_then(|encoded| {
+            AuthToken::decode(encoded).map_err(|e| Status::unauthenticated(e.to_string()))
+        })?;
+
+    if token.entity_id() != sender_id {
+        warn!(
+            entity_id = token.entity_id(),
+            sender_id, "Request sent on behalf of another entity"
+        );
+        return Err(Status::unauthenticated(
+            "authentication token is for another entity",
+        ));
+    }
+
+    Ok(())
+}
+
 #[derive(Debug)]
 struct AtomicReadAnswers {
     n: usize,
@@ -400,15 +1105,39 @@ impl AtomicReadAnswers {
 #[instrument_tonic_service]
 #[tonic::async_trait]
 impl HdltApi for HdltApiService {
-    #[instrument(skip(self))]
+    #[instrument(skip(self), fields(correlation_id))]
     async fn invoke(&self, request: Request<CipheredRrMessage>) -> GrpcResult<CipheredRrMessage> {
+        check_token_sender(request.metadata(), request.get_ref().sender_id)?;
         let current_epoch = self.config.read().await.epoch;
         let (rr_message, requestor_id) = self.decipher_rr_message(request.into_inner());
+        // clients that don't know the current epoch need it answered from any older one
+        let oldest_epoch = match &rr_message {
+            RrMessage::Request(request) if matches!(**request, ApiRequest::GetEpoch) => 0,
+            _ => current_epoch,
+        };
         let request = rr_message
-            .downcast_request(current_epoch)
+            .downcast_request(oldest_epoch)
             .expect("cannot downcast request-reply message to request");
+        Span::current().record("correlation_id", &request.correlation_id());
         let grpc_error_mapper = self.grpc_error_mapper(requestor_id, &request, current_epoch);
 
+        // held until the reply is ready
+        let _permit = match self.in_flight.try_acquire() {
+            Ok(permit) => permit,
+            Err(_) => {
+                warn!("Too many requests in flight, refusing to answer");
+                return grpc_error_mapper(HdltApiError::Overloaded);
+            }
+        };
+
+        if let Err(err) = self
+            .remember_challenge(requestor_id, &request, current_epoch)
+            .await
+        {
+            warn!(?err, "Refusing to answer request");
+            return grpc_error_mapper(err);
+        }
+
         if let Some(proof) = self
             .store
             .query_misbehaved(requestor_id)
@@ -420,6 +1149,23 @@ impl HdltApi for HdltApiService {
             Ok(ApiReply::YouAreNoGood(proof.into()))
         } else {
             match request.as_ref() {
+                // coarsened requestors can't see proofs, they get a bare position right away
+                ApiRequest::ObtainPositionReport { user_id, epoch, .. }
+                | ApiRequest::ObtainPositionReportRegular { user_id, epoch, .. }
+                    if self.coarsening_grid(requestor_id).is_some() =>
+                {
+                    self.obtain_position_report_coarse(requestor_id, *user_id, *epoch)
+                        .await
+                        .map(|(epoch, position)| ApiReply::PositionReport(epoch, position))
+                }
+                // without callbacks, the value is computed right away and returned in the reply
+                ApiRequest::ObtainPositionReport { user_id, epoch, .. }
+                    if !self.callbacks_enabled =>
+                {
+                    self.obtain_position_report_regular(requestor_id, *user_id, *epoch, None)
+                        .await
+                        .map(|proof| ApiReply::PositionReports(vec![(proof.epoch(), proof.into())]))
+                }
                 ApiRequest::ObtainPositionReport {
                     request_id,
                     user_id,
@@ -428,29 +1174,112 @@ impl HdltApi for HdltApiService {
                 } => self
                     .obtain_position_report(
                         *request_id,
+                        request.correlation_id(),
                         requestor_id,
                         *user_id,
                         *epoch,
-                        callback_uri,
+                        callback_uri.as_deref(),
                     )
                     .await
                     .map(|_| ApiReply::Ok),
+                ApiRequest::PollReturn { request_id } => Ok(ApiReply::ReturnedValue(
+                    self.poll_return(requestor_id, *request_id).await,
+                )),
+                ApiRequest::ObtainPositionReportRegular {
+                    user_id,
+                    epoch,
+                    last_write,
+                } => self
+                    .obtain_position_report_regular(requestor_id, *user_id, *epoch, *last_write)
+                    .await
+                    .map(|proof| ApiReply::PositionReports(vec![(proof.epoch(), proof.into())])),
+                ApiRequest::ObtainPositionReportsBulk { user_id, epochs } => self
+                    .obtain_position_reports_bulk(requestor_id, *user_id, epochs)
+                    .await
+                    .map(|proofs| {
+                        proofs
+                            .into_iter()
+                            .map(|proof| (proof.epoch(), proof.into()))
+                            .collect()
+                    })
+                    .map(ApiReply::PositionReports),
+                ApiRequest::ObtainCertifiedPositionReport { user_id, epoch } => self
+                    .obtain_signed_read_reply(requestor_id, *user_id, *epoch, current_epoch)
+                    .await
+                    .map(ApiReply::SignedReadReply),
                 ApiRequest::RequestPositionReports {
+                    user_id,
                     epoch_start,
                     epoch_end,
+                    include_diagnostics,
                 } => self
-                    .get_position_reports(requestor_id, *epoch_start, *epoch_end)
+                    .get_position_reports_with_diagnostics(
+                        requestor_id,
+                        *user_id,
+                        *epoch_start,
+                        *epoch_end,
+                    )
                     .await
-                    .map(|v| {
-                        v.into_iter()
+                    .map(|(reports, skipped)| {
+                        let reports = reports
+                            .into_iter()
                             .map(|(epoch, proof)| (epoch, proof.into()))
-                            .collect()
-                    })
-                    .map(ApiReply::PositionReports),
+                            .collect();
+                        if *include_diagnostics {
+                            ApiReply::PositionReportsWithDiagnostics(reports, skipped)
+                        } else {
+                            ApiReply::PositionReports(reports)
+                        }
+                    }),
                 ApiRequest::ObtainUsersAtPosition { position, epoch } => self
                     .users_at_position(requestor_id, *position, *epoch)
                     .await
                     .map(ApiReply::UsersAtPosition),
+                ApiRequest::IsUserAtPosition {
+                    user_id,
+                    position,
+                    epoch,
+                } => self
+                    .is_user_at_position(requestor_id, *user_id, *position, *epoch)
+                    .await
+                    .map(ApiReply::Bool),
+                ApiRequest::ObtainMisbehaviourProofs { user_id } => self
+                    .misbehaviour_proofs(requestor_id, *user_id)
+                    .await
+                    .map(|v| match self.coarsening_grid(requestor_id) {
+                        // proofs carry exact positions, like position proofs
+                        Some(grid) => ApiReply::CoarseMisbehaviour(
+                            v.iter()
+                                .map(|proof| {
+                                    let (a, b) = proof.positions();
+                                    (proof.a().epoch(), a.coarsen(grid), b.coarsen(grid))
+                                })
+                                .collect(),
+                        ),
+                        None => {
+                            ApiReply::MisbehaviourProofs(v.into_iter().map(Into::into).collect())
+                        }
+                    }),
+                ApiRequest::ObtainPositionHistogram { epoch } => self
+                    .position_histogram(requestor_id, *epoch)
+                    .await
+                    .map(ApiReply::PositionHistogram),
+                ApiRequest::ObtainEpochPositions { epoch } => self
+                    .epoch_positions(requestor_id, *epoch)
+                    .await
+                    .map(ApiReply::EpochPositions),
+                ApiRequest::ObtainProverEpochs { user_id } => self
+                    .prover_epochs(requestor_id, *user_id)
+                    .await
+                    .map(ApiReply::Epochs),
+                ApiRequest::ForgetUser => {
+                    self.forget_user(requestor_id).await.map(ApiReply::Deleted)
+                }
+                ApiRequest::ReportMisbehaviour(proof)
+                | ApiRequest::SubmitMisbehaviourProof(proof) => self
+                    .report_misbehaviour(requestor_id, proof.clone())
+                    .await
+                    .map(|_| ApiReply::Ok),
                 ApiRequest::SubmitPositionReport(pow_protected_proof) => self
                     .submit_position_proof(requestor_id, pow_protected_proof)
                     .await
@@ -464,10 +1293,12 @@ impl HdltApi for HdltApiService {
                     .add_value(requestor_id, *request_id, *client_id, proof.clone(), *epoch)
                     .await
                     .map(|_| ApiReply::Ok),
-                ApiRequest::SubmitMisbehaviourProof(proof) => {
-                    let proof = proof.clone().verify(&self.keystore).unwrap();
-                    self.store.add_misbehaviour_proof(proof).await.unwrap();
-                    Ok(ApiReply::Ok)
+                ApiRequest::GetEpoch => EpochAttestation::new(&self.keystore, current_epoch)
+                    .map(ApiReply::Epoch)
+                    .map_err(HdltApiError::from),
+                ApiRequest::Hello(theirs) => {
+                    debug!(agreed = ?self.capabilities.negotiate(theirs), "Capabilities exchanged");
+                    Ok(ApiReply::Capabilities(self.capabilities))
                 }
                 _ => unimplemented!("invalid option for server API"),
             }
@@ -480,32 +1311,72 @@ impl HdltApi for HdltApiService {
 }
 
 impl HdltApiService {
-    fn grpc_error_mapper<'req, E: ToString>(
+    /// Record that a request is being answered, failing if it already was.
+    ///
+    /// Requests are identified by their sender and challenge (see [RrRequest::correlation_id]),
+    /// so a captured request can't be replayed to obtain a fresh reply.
+    /// Challenges are remembered until the epoch of their request is stale (and it is refused
+    /// anyway), so requests from more than [MAX_EPOCH_SKEW] epochs ahead are refused outright:
+    /// they would have to be remembered for too long. Stale requests that are still answered
+    /// ([ApiRequest::GetEpoch]) are remembered as if from the current epoch.
+    async fn remember_challenge(
+        &self,
+        requestor_id: EntityId,
+        request: &RrRequest<ApiRequest>,
+        current_epoch: u64,
+    ) -> Result<(), HdltApiError> {
+        if request.epoch() > current_epoch.saturating_add(MAX_EPOCH_SKEW) {
+            return Err(HdltApiError::FutureRequest {
+                epoch: request.epoch(),
+                current_epoch,
+            });
+        }
+
+        let mut answered = self.answered_challenges.write().await;
+        let fresh = answered.split_off(&current_epoch);
+        *answered = fresh;
+
+        if answered
+            .entry(request.epoch().max(current_epoch))
+            .or_default()
+            .insert((requestor_id, request.correlation_id()))
+        {
+            Ok(())
+        } else {
+            Err(HdltApiError::ReplayedRequest)
+        }
+    }
+
+    fn grpc_error_mapper<'req, E: Into<ApiReply>>(
         &'req self,
         partner_id: EntityId,
         request: &'req RrRequest<ApiRequest>,
         epoch: u64,
     ) -> impl (Fn(E) -> GrpcResult<CipheredRrMessage>) + 'req {
         move |err| {
-            let reply_payload = ApiReply::Error(err.to_string());
+            let reply_payload = err.into();
             let reply = RrMessage::new_reply(request, epoch, reply_payload);
 
             Ok(Response::new(self.cipher_rr_message(reply, partner_id)))
         }
     }
 
+    #[instrument(skip(self, message), fields(plaintext_len, ciphertext_len))]
     fn decipher_rr_message(&self, message: CipheredRrMessage) -> (RrMessage<ApiRequest>, EntityId) {
+        Span::current().record("ciphertext_len", &message.ciphertext.len());
         let nonce = Nonce::from_slice(&message.nonce).expect("invalid nonce in message");
         let plaintext = self
             .keystore
             .decipher(message.sender_id, &message.ciphertext, &nonce)
             .expect("cannot decipher incoming message");
+        Span::current().record("plaintext_len", &plaintext.len());
         let rr_message: RrMessage<ApiRequest> =
             bincode::deserialize(&plaintext).expect("cannot decode incoming message");
 
         (rr_message, message.sender_id)
     }
 
+    #[instrument(skip(self, message), fields(plaintext_len, ciphertext_len))]
     fn cipher_rr_message(
         &self,
         message: RrMessage<ApiReply>,
@@ -517,6 +1388,8 @@ impl HdltApiService {
             .keystore
             .cipher(partner_id, &plaintext)
             .expect("could not cipher reply");
+        Span::current().record("plaintext_len", &plaintext.len());
+        Span::current().record("ciphertext_len", &ciphertext.len());
 
         CipheredRrMessage {
             sender_id: self.keystore.my_id(),
@@ -531,8 +1404,10 @@ mod test {
     use super::*;
     use crate::hdlt_store::test::build_store;
     use lazy_static::lazy_static;
+    use model::api::RrMessageError;
     use model::keys::test_data::KeyStoreTestData;
-    use model::keys::Signature;
+    use model::keys::{EntityPrivComponent, Signature};
+    use tracing_utils::recorder::FieldRecorder;
 
     lazy_static! {
         static ref KEYSTORES: KeyStoreTestData = KeyStoreTestData::new();
@@ -541,18 +1416,39 @@ mod test {
     async fn build_service() -> HdltApiService {
         HdltApiService::new(
             Arc::new(KEYSTORES.server.clone()),
-            Arc::new(build_store().await),
+            Arc::new(build_store().await.into()),
             Arc::new(RwLock::new(ServerConfig {
                 epoch: 0,
-                max_neigh_faults: 1,
+                neigh_faults_schedule: std::iter::once((0, 1)).collect(),
                 max_server_faults: 0,
                 servers: vec![],
                 id_uri_map: HashMap::new(),
+                max_epoch: u64::MAX,
+                bounds: None,
             })),
             vec![],
+            16,
+            true,
+            None,
+            8,
+            Capabilities::default(),
         )
     }
 
+    /// Proof of the prover's position in an epoch, with a single witness
+    fn proof_at(
+        epoch: u64,
+        prover: &KeyStore,
+        position: Position,
+        witness: &KeyStore,
+        witness_position: Position,
+    ) -> PositionProof {
+        use model::{ProximityProof, ProximityProofRequest};
+        let preq = ProximityProofRequest::new(epoch, position, prover);
+        let pproof = ProximityProof::new(preq, witness_position, witness).unwrap();
+        PositionProof::new(vec![pproof], 1).unwrap()
+    }
+
     /*
     #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
     async fn obtain_position_report() {
@@ -627,6 +1523,143 @@ mod test {
     }
     */
 
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn obtain_position_report_regular() {
+        let service = build_service().await;
+
+        // non-HA clients cannot see other users' positions
+        let ha_client_id = KEYSTORES.haclient.my_id();
+        for id in KEYSTORES
+            .iter()
+            .map(|k| k.my_id())
+            .filter(|id| *id != ha_client_id)
+        {
+            assert!(matches!(
+                service
+                    .obtain_position_report_regular(id, 0, 0, None)
+                    .await
+                    .unwrap_err(),
+                HdltApiError::PermissionDenied
+            ));
+        }
+
+        // HA client can see everyone's positions, users can see their own
+        let proofs = &crate::hdlt_store::test::PROOFS;
+        for (requestor_id, prover_id, epoch, proof) in [
+            (ha_client_id, 0, 0, &proofs[0]),
+            (ha_client_id, 1, 0, &proofs[1]),
+            (ha_client_id, 0, 1, &proofs[2]),
+            (0, 0, 0, &proofs[0]),
+            (1, 1, 1, &proofs[3]),
+        ]
+        .iter()
+        {
+            assert_eq!(
+                &service
+                    .obtain_position_report_regular(*requestor_id, *prover_id, *epoch, None)
+                    .await
+                    .unwrap(),
+                *proof
+            );
+        }
+
+        // there may be no position data available
+        assert!(matches!(
+            service
+                .obtain_position_report_regular(ha_client_id, 50, 0, None)
+                .await
+                .unwrap_err(),
+            HdltApiError::NoData
+        ));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn no_data_vs_insufficient_quorum() {
+        let service = build_service().await;
+        let ha_client = &KEYSTORES.haclient;
+
+        // nothing stored for user 50
+        assert!(matches!(
+            invoke_as(
+                &service,
+                ha_client,
+                ApiRequest::ObtainPositionReportRegular {
+                    user_id: 50,
+                    epoch: 0,
+                    last_write: None,
+                },
+            )
+            .await,
+            ApiReply::Error(ApiErrorCode::NoData, _)
+        ));
+
+        // user 0 has a report with a single witness, one below quorum
+        service.config.write().await.set_max_neigh_faults(0, 2);
+        assert!(matches!(
+            invoke_as(
+                &service,
+                ha_client,
+                ApiRequest::ObtainPositionReportRegular {
+                    user_id: 0,
+                    epoch: 0,
+                    last_write: None,
+                },
+            )
+            .await,
+            ApiReply::Error(
+                ApiErrorCode::InsufficientQuorum {
+                    required: 2,
+                    available: 1
+                },
+                _
+            )
+        ));
+        assert!(matches!(
+            service
+                .obtain_position_report_regular(ha_client.my_id(), 50, 0, None)
+                .await
+                .unwrap_err(),
+            HdltApiError::NoData
+        ));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn no_callbacks() {
+        let service = HdltApiService {
+            callbacks_enabled: false,
+            ..build_service().await
+        };
+
+        let reply = invoke_as(
+            &service,
+            &KEYSTORES.haclient,
+            ApiRequest::ObtainPositionReport {
+                request_id: 1,
+                user_id: 0,
+                epoch: 0,
+                callback_uri: Some("http://[::1]:1".to_owned()),
+            },
+        )
+        .await;
+        match reply {
+            ApiReply::PositionReports(reports) => {
+                assert_eq!(reports.len(), 1);
+                let (epoch, proof) = reports[0].clone();
+                assert_eq!(epoch, 0);
+                assert_eq!(
+                    proof.verify(1, &KEYSTORES.haclient).unwrap().position(),
+                    Position(0, 0)
+                );
+            }
+            other => panic!("unexpected reply: {:?}", other),
+        }
+
+        // nobody is left waiting for a value
+        assert!(service.client_listeners.read().await.is_empty());
+        assert!(service.server_listeners.read().await.is_empty());
+        assert!(service.returned_values.read().await.is_empty());
+    }
+
     #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
     async fn users_at_position() {
         let service = build_service().await;
@@ -679,97 +1712,1430 @@ mod test {
     }
 
     #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
-    async fn add_proof() {
+    async fn is_user_at_position() {
         let service = build_service().await;
-        let mut bad_proof: UnverifiedPositionProof =
-            crate::hdlt_store::test::PROOFS[0].clone().into();
-
-        // just in case our test data for hdlt_store becomes valid at some point
-        bad_proof.witnesses[0].signature = Signature::from_slice(&[42u8; 64]).unwrap();
 
+        // only HA clients may ask
+        let ha_client_id = KEYSTORES.haclient.my_id();
         assert!(matches!(
             service
-                .submit_position_proof(1, &PoWCertified::new(bad_proof))
+                .is_user_at_position(KEYSTORES.user1.my_id(), 0, Position(0, 0), 0)
                 .await
                 .unwrap_err(),
-            HdltApiError::InvalidPositionProof(..)
+            HdltApiError::PermissionDenied
         ));
 
-        let good_proof: UnverifiedPositionProof = {
-            use model::{PositionProof, ProximityProof, ProximityProofRequest};
-            let preq = ProximityProofRequest::new(123, Position(123, 123), &KEYSTORES.user1);
-            let pproof = ProximityProof::new(preq, Position(100, 100), &KEYSTORES.user2).unwrap();
-
-            PositionProof::new(vec![pproof], 1).unwrap().into()
-        };
-        let good_proof = PoWCertified::new(good_proof);
+        // present
+        for (user_id, position, epoch) in &[
+            (0, Position(0, 0), 0),
+            (1, Position(1, 0), 0),
+            (0, Position(0, 1), 1),
+            (1, Position(0, 1), 1),
+        ] {
+            assert!(service
+                .is_user_at_position(ha_client_id, *user_id, *position, *epoch)
+                .await
+                .unwrap());
+        }
 
-        // can't submit someone else's stuff
-        assert!(matches!(
-            service.submit_position_proof(4321, &good_proof).await,
-            Err(HdltApiError::PermissionDenied)
-        ));
+        // absent: elsewhere, at another epoch, or without any report
+        for (user_id, position, epoch) in &[
+            (0, Position(1, 0), 0),
+            (1, Position(0, 0), 0),
+            (0, Position(0, 0), 1),
+            (0, Position(0, 0), 67981463),
+            (123, Position(0, 0), 0),
+        ] {
+            assert!(!service
+                .is_user_at_position(ha_client_id, *user_id, *position, *epoch)
+                .await
+                .unwrap());
+        }
 
-        // happy path
-        assert!(service.submit_position_proof(1, &good_proof).await.is_ok());
+        assert_eq!(
+            invoke_as(
+                &service,
+                &KEYSTORES.haclient,
+                ApiRequest::IsUserAtPosition {
+                    user_id: 1,
+                    position: Position(1, 0),
+                    epoch: 0
+                }
+            )
+            .await,
+            ApiReply::Bool(true)
+        );
     }
-}
 
-#[derive(Debug)]
-pub struct HdltApiClient {
-    /// All the GRPC channels
-    channel: Channel,
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn misbehaviour_proofs() {
+        use model::{ProximityProof, ProximityProofRequest};
 
-    /// Server Id
-    id: EntityId,
+        let service = build_service().await;
 
-    /// Key store
-    keystore: Arc<KeyStore>,
+        // only HA clients can see misbehaviour proofs
+        let ha_client_id = KEYSTORES.haclient.my_id();
+        for id in KEYSTORES
+            .iter()
+            .map(|k| k.my_id())
+            .filter(|id| *id != ha_client_id)
+        {
+            assert!(matches!(
+                service.misbehaviour_proofs(id, 0).await.unwrap_err(),
+                HdltApiError::PermissionDenied
+            ));
+        }
 
-    /// Current epoch: works as a timestamp in the procotol,
-    /// since there must only be one proof per epoch
-    ///
-    current_epoch: u64,
-}
+        // everyone behaved in the test data
+        for user_id in 0..2 {
+            assert!(service
+                .misbehaviour_proofs(ha_client_id, user_id)
+                .await
+                .unwrap()
+                .is_empty());
+        }
 
-#[derive(Debug, Error)]
-pub enum HdltError {
-    #[error("Error creating remote")]
-    InitializationError(#[source] tonic::transport::Error),
+        // user 3 claims to be in two places at once in epochs 7 and 5, twice in epoch 7
+        let user_id = KEYSTORES.user3.my_id();
+        for &(epoch, x) in &[(7, 2), (5, 2), (7, 3)] {
+            let req_a = ProximityProofRequest::new(epoch, Position(1, 1), &KEYSTORES.user3);
+            let proof_a = ProximityProof::new(req_a, Position(1, 1), &KEYSTORES.user2).unwrap();
+            let req_b = ProximityProofRequest::new(epoch, Position(x, x), &KEYSTORES.user3);
+            let proof_b = ProximityProof::new(req_b, Position(1, 1), &KEYSTORES.user2).unwrap();
+            service
+                .report_misbehaviour(
+                    KEYSTORES.user2.my_id(),
+                    MisbehaviorProof::new(user_id, proof_a, proof_b)
+                        .unwrap()
+                        .into(),
+                )
+                .await
+                .unwrap();
+        }
 
-    #[error("Failed to serialize request")]
-    SerializationError(#[source] Box<bincode::ErrorKind>),
+        // one proof per epoch, oldest first
+        let proofs = service
+            .misbehaviour_proofs(ha_client_id, user_id)
+            .await
+            .unwrap();
+        assert_eq!(
+            proofs
+                .iter()
+                .map(|proof| (proof.user_id(), proof.a().epoch()))
+                .collect::<Vec<_>>(),
+            vec![(user_id, 5), (user_id, 7)]
+        );
+        assert!(matches!(
+            invoke_as(
+                &service,
+                &KEYSTORES.haclient,
+                ApiRequest::ObtainMisbehaviourProofs { user_id }
+            )
+            .await,
+            ApiReply::MisbehaviourProofs(proofs) if proofs.len() == 2
+        ));
+    }
 
-    #[error("Failed to cipher request")]
-    CipherError(#[source] KeyStoreError),
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn report_misbehaviour() {
+        use model::{ProximityProof, ProximityProofRequest};
 
-    #[error("Server sent unexpected status")]
-    UnexpectedStatus(#[from] Status),
+        let service = build_service().await;
 
-    #[error("Invalid nonce")]
-    InvalidNonce,
+        // user 3 claims to be in two places at once, and user 2 saw it
+        let user_id = KEYSTORES.user3.my_id();
+        let req_a = ProximityProofRequest::new(5, Position(1, 1), &KEYSTORES.user3);
+        let proof_a = ProximityProof::new(req_a, Position(1, 1), &KEYSTORES.user2).unwrap();
+        let req_b = ProximityProofRequest::new(5, Position(2, 2), &KEYSTORES.user3);
+        let proof_b = ProximityProof::new(req_b, Position(1, 1), &KEYSTORES.user2).unwrap();
+        let proof: UnverifiedMisbehaviorProof = MisbehaviorProof::new(user_id, proof_a, proof_b)
+            .unwrap()
+            .into();
 
-    #[error("Failed to decipher reply")]
-    DecipherError(#[source] KeyStoreError),
+        // the same proximity proof twice is no misbehaviour
+        let not_conflicting: UnverifiedMisbehaviorProof = {
+            let mut value = serde_json::to_value(&proof).unwrap();
+            value["b"] = value["a"].clone();
+            serde_json::from_value(value).unwrap()
+        };
+        assert!(matches!(
+            service
+                .report_misbehaviour(KEYSTORES.user2.my_id(), not_conflicting.clone())
+                .await
+                .unwrap_err(),
+            HdltApiError::InvalidMisbehaviourProof(..)
+        ));
 
-    #[error("Failed to deserialize reply")]
-    DeserializationError(#[source] Box<bincode::ErrorKind>),
+        // the older request is checked the same way
+        assert!(matches!(
+            invoke_as(
+                &service,
+                &KEYSTORES.user2,
+                ApiRequest::SubmitMisbehaviourProof(not_conflicting)
+            )
+            .await,
+            ApiReply::Error(ApiErrorCode::Other, _)
+        ));
 
-    #[error("Request reply protocol violation")]
-    RequestReplyProtocolViolation(#[from] RrMessageError),
+        // only users can report
+        assert!(matches!(
+            service
+                .report_misbehaviour(KEYSTORES.server.my_id(), proof.clone())
+                .await
+                .unwrap_err(),
+            HdltApiError::PermissionDenied
+        ));
+        assert!(service
+            .store
+            .query_misbehaved(user_id)
+            .await
+            .unwrap()
+            .is_none());
 
-    #[error("Error executing request: {}", .0)]
-    ServerError(String),
+        assert_eq!(
+            invoke_as(
+                &service,
+                &KEYSTORES.user2,
+                ApiRequest::ReportMisbehaviour(proof)
+            )
+            .await,
+            ApiReply::Ok
+        );
+        assert_eq!(
+            service
+                .store
+                .query_misbehaved(user_id)
+                .await
+                .unwrap()
+                .map(|proof| proof.user_id()),
+            Some(user_id)
+        );
+    }
 
-    #[error("Server sent unexpected reply message: {:#?}", .0)]
-    UnexpectedReply(ApiReply),
-}
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn forget_user() {
+        let service = build_service().await;
 
-impl HdltApiClient {
-    pub fn new(
-        uri: Uri,
-        id: EntityId,
-        keystore: Arc<KeyStore>,
+        // only users can erase their reports
+        for keystore in &[&KEYSTORES.server, &KEYSTORES.haclient] {
+            assert!(matches!(
+                service.forget_user(keystore.my_id()).await.unwrap_err(),
+                HdltApiError::PermissionDenied
+            ));
+        }
+
+        // user 1 has a report in each of the two epochs, counted once however many witnesses
+        let user_id = KEYSTORES.user1.my_id();
+        let stored = service
+            .store
+            .query_epoch_prover_range(0..2, user_id)
+            .await
+            .unwrap()
+            .len();
+        assert_eq!(stored, 2);
+        assert_eq!(
+            invoke_as(&service, &KEYSTORES.user1, ApiRequest::ForgetUser).await,
+            ApiReply::Deleted(stored as u64)
+        );
+        assert!(matches!(
+            service
+                .obtain_position_report_regular(user_id, user_id, 0, None)
+                .await
+                .unwrap_err(),
+            HdltApiError::NoData
+        ));
+
+        // nothing left to erase
+        assert_eq!(
+            invoke_as(&service, &KEYSTORES.user1, ApiRequest::ForgetUser).await,
+            ApiReply::Deleted(0)
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn position_histogram() {
+        let service = build_service().await;
+
+        // only HA clients can see the histogram
+        let ha_client_id = KEYSTORES.haclient.my_id();
+        for id in KEYSTORES
+            .iter()
+            .map(|k| k.my_id())
+            .filter(|id| *id != ha_client_id)
+        {
+            assert!(matches!(
+                service.position_histogram(id, 0).await.unwrap_err(),
+                HdltApiError::PermissionDenied
+            ));
+        }
+
+        assert_eq!(
+            service.position_histogram(ha_client_id, 0).await.unwrap(),
+            vec![(Position(0, 0), 1), (Position(1, 0), 1)]
+        );
+        assert_eq!(
+            invoke_as(
+                &service,
+                &KEYSTORES.haclient,
+                ApiRequest::ObtainPositionHistogram { epoch: 1 }
+            )
+            .await,
+            ApiReply::PositionHistogram(vec![(Position(0, 1), 2)])
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn epoch_positions() {
+        let service = build_service().await;
+
+        // only HA clients can see everyone's positions
+        let ha_client_id = KEYSTORES.haclient.my_id();
+        for id in KEYSTORES
+            .iter()
+            .map(|k| k.my_id())
+            .filter(|id| *id != ha_client_id)
+        {
+            assert!(matches!(
+                service.epoch_positions(id, 0).await.unwrap_err(),
+                HdltApiError::PermissionDenied
+            ));
+        }
+
+        assert_eq!(
+            service.epoch_positions(ha_client_id, 0).await.unwrap(),
+            vec![(0, Position(0, 0)), (1, Position(1, 0))]
+        );
+        assert_eq!(
+            invoke_as(
+                &service,
+                &KEYSTORES.haclient,
+                ApiRequest::ObtainEpochPositions { epoch: 1 }
+            )
+            .await,
+            ApiReply::EpochPositions(vec![(0, Position(0, 1)), (1, Position(0, 1))])
+        );
+
+        // not enough witnesses for anyone
+        service.config.write().await.set_max_neigh_faults(0, 2);
+        assert!(service
+            .epoch_positions(ha_client_id, 0)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn signed_read_reply() {
+        let service = build_service().await;
+
+        let reply = match invoke_as(
+            &service,
+            &KEYSTORES.haclient,
+            ApiRequest::ObtainCertifiedPositionReport {
+                user_id: 0,
+                epoch: 0,
+            },
+        )
+        .await
+        {
+            ApiReply::SignedReadReply(reply) => reply,
+            other => panic!("unexpected reply: {:?}", other),
+        };
+
+        // anyone can check who signed it, and when
+        reply.verify(&KEYSTORES.user2).unwrap();
+        assert_eq!(reply.server_id(), KEYSTORES.server.my_id());
+        assert_eq!(reply.server_epoch(), 0);
+        assert_eq!((reply.user_id(), reply.epoch()), (0, 0));
+
+        // users may only read their own reports
+        assert!(matches!(
+            service
+                .obtain_signed_read_reply(KEYSTORES.user1.my_id(), 0, 0, 0)
+                .await
+                .unwrap_err(),
+            HdltApiError::PermissionDenied
+        ));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn hello() {
+        let service = build_service().await;
+        let compressing = Capabilities {
+            compression: true,
+            ..Capabilities::default()
+        };
+
+        // servers advertise what they support, whatever the client does
+        for theirs in [Capabilities::default(), compressing].iter() {
+            assert_eq!(
+                invoke_as(&service, &KEYSTORES.user1, ApiRequest::Hello(*theirs)).await,
+                ApiReply::Capabilities(Capabilities::default())
+            );
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn prover_epochs() {
+        let service = build_service().await;
+
+        // users can only see their own epochs
+        for keystore in &[&KEYSTORES.user1, &KEYSTORES.user2, &KEYSTORES.server] {
+            assert!(matches!(
+                service
+                    .prover_epochs(keystore.my_id(), 0)
+                    .await
+                    .unwrap_err(),
+                HdltApiError::PermissionDenied
+            ));
+        }
+        assert!(service
+            .prover_epochs(KEYSTORES.user1.my_id(), KEYSTORES.user1.my_id())
+            .await
+            .unwrap()
+            .is_empty());
+
+        assert_eq!(
+            invoke_as(
+                &service,
+                &KEYSTORES.haclient,
+                ApiRequest::ObtainProverEpochs { user_id: 0 }
+            )
+            .await,
+            ApiReply::Epochs(vec![0, 1])
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn coarse_positions() {
+        let exact = build_service().await;
+        let coarse = HdltApiService {
+            coarsening: Some(PositionCoarsening {
+                grid: NonZeroU32::new(10).unwrap(),
+                roles: vec![Role::HaClient],
+            }),
+            ..build_service().await
+        };
+        let ha_client_id = KEYSTORES.haclient.my_id();
+
+        // positions are rounded to the grid, and users in the same cell counted together
+        assert_eq!(
+            exact.position_histogram(ha_client_id, 0).await.unwrap(),
+            vec![(Position(0, 0), 1), (Position(1, 0), 1)]
+        );
+        assert_eq!(
+            coarse.position_histogram(ha_client_id, 0).await.unwrap(),
+            vec![(Position(0, 0), 2)]
+        );
+
+        // queries are for the whole cell
+        assert!(exact
+            .users_at_position(ha_client_id, Position(3, -2), 0)
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            coarse
+                .users_at_position(ha_client_id, Position(3, -2), 0)
+                .await
+                .unwrap(),
+            vec![0, 1]
+        );
+        assert!(!exact
+            .is_user_at_position(ha_client_id, 1, Position(3, -2), 0)
+            .await
+            .unwrap());
+        assert!(coarse
+            .is_user_at_position(ha_client_id, 1, Position(3, -2), 0)
+            .await
+            .unwrap());
+
+        // reports are bare coarse positions instead of proofs
+        let request = ApiRequest::ObtainPositionReportRegular {
+            user_id: 1,
+            epoch: 0,
+            last_write: None,
+        };
+        match invoke_as(&exact, &KEYSTORES.haclient, request.clone()).await {
+            ApiReply::PositionReports(reports) => assert_eq!(
+                reports[0]
+                    .1
+                    .clone()
+                    .verify(1, &KEYSTORES.haclient)
+                    .unwrap()
+                    .position(),
+                Position(1, 0)
+            ),
+            other => panic!("unexpected reply: {:?}", other),
+        }
+        assert_eq!(
+            invoke_as(&coarse, &KEYSTORES.haclient, request).await,
+            ApiReply::PositionReport(0, Position(0, 0))
+        );
+        assert_eq!(
+            invoke_as(
+                &coarse,
+                &KEYSTORES.haclient,
+                ApiRequest::ObtainPositionReport {
+                    request_id: 1,
+                    user_id: 1,
+                    epoch: 0,
+                    callback_uri: Some("http://[::1]:1".to_owned()),
+                },
+            )
+            .await,
+            ApiReply::PositionReport(0, Position(0, 0))
+        );
+        assert!(coarse.client_listeners.read().await.is_empty());
+
+        // so is misbehaviour, which comes with exact positions too
+        use model::{ProximityProof, ProximityProofRequest};
+        let user_id = KEYSTORES.user3.my_id();
+        let req_a = ProximityProofRequest::new(0, Position(4, 4), &KEYSTORES.user3);
+        let proof_a = ProximityProof::new(req_a, Position(5, 5), &KEYSTORES.user2).unwrap();
+        let req_b = ProximityProofRequest::new(0, Position(6, 6), &KEYSTORES.user3);
+        let proof_b = ProximityProof::new(req_b, Position(5, 5), &KEYSTORES.user2).unwrap();
+        let proof = MisbehaviorProof::new(user_id, proof_a, proof_b).unwrap();
+        coarse
+            .report_misbehaviour(KEYSTORES.user2.my_id(), proof.into())
+            .await
+            .unwrap();
+        assert_eq!(
+            invoke_as(
+                &coarse,
+                &KEYSTORES.haclient,
+                ApiRequest::ObtainMisbehaviourProofs { user_id }
+            )
+            .await,
+            ApiReply::CoarseMisbehaviour(vec![(0, Position(0, 0), Position(10, 10))])
+        );
+    }
+
+    #[tokio::test]
+    async fn correlation_id_in_logs() {
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let recorder = FieldRecorder::new(&["correlation_id"]);
+        let _guard =
+            tracing::subscriber::set_default(tracing_subscriber::registry().with(recorder.clone()));
+
+        let service = build_service().await;
+        let msg = RrMessage::new_request(
+            0,
+            ApiRequest::ObtainPositionReport {
+                request_id: 1,
+                user_id: 1,
+                epoch: 0,
+                callback_uri: Some("http://127.0.0.1:1/".to_owned()),
+            },
+        );
+        let correlation_id = msg.clone().downcast_request(0).unwrap().correlation_id();
+
+        let plaintext = bincode::serialize(&msg).unwrap();
+        let (ciphertext, nonce) = KEYSTORES
+            .haclient
+            .cipher(KEYSTORES.server.my_id(), &plaintext)
+            .unwrap();
+        let _ = service
+            .invoke(tonic::Request::new(CipheredRrMessage {
+                sender_id: KEYSTORES.haclient.my_id(),
+                ciphertext,
+                nonce: nonce.0.to_vec(),
+            }))
+            .await;
+
+        // both the request handler and the inner operation carry the id
+        let recorded = recorder.recorded();
+        assert!(recorded.len() >= 2);
+        assert!(recorded
+            .iter()
+            .all(|(_, v)| *v == correlation_id.to_string()));
+    }
+
+    #[tokio::test]
+    async fn message_sizes_in_logs() {
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let recorder = FieldRecorder::new(&["plaintext_len", "ciphertext_len"]);
+        let _guard =
+            tracing::subscriber::set_default(tracing_subscriber::registry().with(recorder.clone()));
+
+        let service = build_service().await;
+        invoke_as(&service, &KEYSTORES.haclient, ApiRequest::GetEpoch).await;
+
+        // one size of each kind for the request and for the reply
+        let (plaintext_lens, ciphertext_lens) = (
+            recorder.numbers("plaintext_len"),
+            recorder.numbers("ciphertext_len"),
+        );
+        assert_eq!(plaintext_lens.len(), 2);
+        assert_eq!(ciphertext_lens.len(), 2);
+
+        // ciphertexts carry an authentication tag on top of the plaintext
+        assert!(plaintext_lens
+            .iter()
+            .zip(&ciphertext_lens)
+            .all(|(plaintext_len, ciphertext_len)| ciphertext_len > plaintext_len));
+    }
+
+    /// Invoke a request as the owner of `keystore`, deciphering the reply like a client would
+    async fn invoke_as(
+        service: &HdltApiService,
+        keystore: &KeyStore,
+        request: ApiRequest,
+    ) -> ApiReply {
+        let server_id = KEYSTORES.server.my_id();
+        let epoch = service.config.read().await.epoch;
+        let msg = RrMessage::new_request(epoch, request);
+        let rr_request = msg.clone().downcast_request(epoch).unwrap();
+
+        let plaintext = bincode::serialize(&msg).unwrap();
+        let (ciphertext, nonce) = keystore.cipher(server_id, &plaintext).unwrap();
+        let response = service
+            .invoke(tonic::Request::new(CipheredRrMessage {
+                sender_id: keystore.my_id(),
+                ciphertext,
+                nonce: nonce.0.to_vec(),
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+
+        let nonce = Nonce::from_slice(&response.nonce).unwrap();
+        let plaintext = keystore
+            .decipher(server_id, &response.ciphertext, &nonce)
+            .unwrap();
+        let reply: RrMessage<ApiReply> = bincode::deserialize(&plaintext).unwrap();
+        reply
+            .downcast_reply(&rr_request, epoch)
+            .unwrap()
+            .into_inner()
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn replayed_request() {
+        let service = build_service().await;
+        let keystore = &KEYSTORES.haclient;
+        let server_id = KEYSTORES.server.my_id();
+
+        let msg = RrMessage::new_request(0, ApiRequest::GetEpoch);
+        let rr_request = msg.clone().downcast_request(0).unwrap();
+        let plaintext = bincode::serialize(&msg).unwrap();
+        let (ciphertext, nonce) = keystore.cipher(server_id, &plaintext).unwrap();
+        let captured = CipheredRrMessage {
+            sender_id: keystore.my_id(),
+            ciphertext,
+            nonce: nonce.0.to_vec(),
+        };
+
+        let reply_to = |response: CipheredRrMessage| {
+            let nonce = Nonce::from_slice(&response.nonce).unwrap();
+            let plaintext = keystore
+                .decipher(server_id, &response.ciphertext, &nonce)
+                .unwrap();
+            bincode::deserialize::<RrMessage<ApiReply>>(&plaintext).unwrap()
+        };
+
+        let reply = reply_to(
+            service
+                .invoke(tonic::Request::new(captured.clone()))
+                .await
+                .unwrap()
+                .into_inner(),
+        );
+        assert_eq!(
+            reply
+                .clone()
+                .downcast_reply(&rr_request, 0)
+                .unwrap()
+                .into_inner(),
+            ApiReply::Epoch(EpochAttestation::new(&KEYSTORES.server, 0).unwrap())
+        );
+
+        // the captured reply is no good for a new request
+        let new_request = RrMessage::<ApiRequest>::new_request(0, ApiRequest::GetEpoch)
+            .downcast_request(0)
+            .unwrap();
+        assert!(matches!(
+            reply.downcast_reply(&new_request, 0),
+            Err(RrMessageError::ChallengeResponseFailed { .. })
+        ));
+
+        // and replaying the captured request doesn't get a fresh one
+        let replayed_reply = reply_to(
+            service
+                .invoke(tonic::Request::new(captured))
+                .await
+                .unwrap()
+                .into_inner(),
+        );
+        assert!(matches!(
+            replayed_reply
+                .downcast_reply(&rr_request, 0)
+                .unwrap()
+                .into_inner(),
+            ApiReply::Error(ApiErrorCode::ReplayedRequest, _)
+        ));
+
+        // new requests are still answered
+        assert_eq!(
+            invoke_as(&service, keystore, ApiRequest::GetEpoch).await,
+            ApiReply::Epoch(EpochAttestation::new(&KEYSTORES.server, 0).unwrap())
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn replayed_request_across_epochs() {
+        let service = build_service().await;
+        let keystore = &KEYSTORES.haclient;
+        let server_id = KEYSTORES.server.my_id();
+
+        let capture = |epoch| {
+            let msg = RrMessage::new_request(epoch, ApiRequest::GetEpoch);
+            let rr_request = msg.clone().downcast_request(epoch).unwrap();
+            let plaintext = bincode::serialize(&msg).unwrap();
+            let (ciphertext, nonce) = keystore.cipher(server_id, &plaintext).unwrap();
+            let captured = CipheredRrMessage {
+                sender_id: keystore.my_id(),
+                ciphertext,
+                nonce: nonce.0.to_vec(),
+            };
+            (rr_request, captured)
+        };
+        let reply_to = |response: CipheredRrMessage, rr_request: &RrRequest<ApiRequest>| {
+            let nonce = Nonce::from_slice(&response.nonce).unwrap();
+            let plaintext = keystore
+                .decipher(server_id, &response.ciphertext, &nonce)
+                .unwrap();
+            bincode::deserialize::<RrMessage<ApiReply>>(&plaintext)
+                .unwrap()
+                .downcast_reply(rr_request, 0)
+                .unwrap()
+                .into_inner()
+        };
+
+        // a request from the next epoch is answered a bit early...
+        let (rr_request, captured) = capture(1);
+        let reply = service
+            .invoke(tonic::Request::new(captured.clone()))
+            .await
+            .unwrap()
+            .into_inner();
+        assert!(matches!(reply_to(reply, &rr_request), ApiReply::Epoch(_)));
+
+        // ...but not again once the server catches up
+        service.config.write().await.epoch = 1;
+        let reply = service
+            .invoke(tonic::Request::new(captured))
+            .await
+            .unwrap()
+            .into_inner();
+        assert!(matches!(
+            reply_to(reply, &rr_request),
+            ApiReply::Error(ApiErrorCode::ReplayedRequest, _)
+        ));
+
+        // requests from further ahead are refused outright
+        let (rr_request, captured) = capture(1 + MAX_EPOCH_SKEW + 1);
+        let reply = service
+            .invoke(tonic::Request::new(captured))
+            .await
+            .unwrap()
+            .into_inner();
+        assert!(matches!(
+            reply_to(reply, &rr_request),
+            ApiReply::Error(ApiErrorCode::Other, _)
+        ));
+
+        // only challenges of requests that aren't stale yet are remembered
+        assert_eq!(
+            service
+                .answered_challenges
+                .read()
+                .await
+                .keys()
+                .copied()
+                .collect::<Vec<_>>(),
+            vec![1]
+        );
+
+        // epochs are obtained from stale requests too, but only once
+        let (rr_request, captured) = capture(0);
+        let reply = service
+            .invoke(tonic::Request::new(captured.clone()))
+            .await
+            .unwrap()
+            .into_inner();
+        assert!(matches!(
+            reply_to(reply, &rr_request),
+            ApiReply::Epoch(attestation) if attestation.verify(keystore).unwrap() == 1
+        ));
+        let reply = service
+            .invoke(tonic::Request::new(captured))
+            .await
+            .unwrap()
+            .into_inner();
+        assert!(matches!(
+            reply_to(reply, &rr_request),
+            ApiReply::Error(ApiErrorCode::ReplayedRequest, _)
+        ));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn overloaded() {
+        let service = HdltApiService {
+            in_flight: Arc::new(Semaphore::new(2)),
+            ..build_service().await
+        };
+        let keystore = &KEYSTORES.haclient;
+
+        // two slow requests are being handled
+        let permits = service.in_flight.try_acquire_many(2).unwrap();
+
+        // a third one is refused right away instead of waiting for them
+        let reply = tokio::time::timeout(
+            Duration::from_secs(1),
+            invoke_as(&service, keystore, ApiRequest::GetEpoch),
+        )
+        .await
+        .expect("overloaded server should not queue requests");
+        assert!(matches!(
+            reply,
+            ApiReply::Error(ApiErrorCode::ResourceExhausted, _)
+        ));
+
+        drop(permits);
+        assert_eq!(
+            invoke_as(&service, keystore, ApiRequest::GetEpoch).await,
+            ApiReply::Epoch(EpochAttestation::new(&KEYSTORES.server, 0).unwrap())
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn store_error_codes() {
+        let service = build_service().await;
+
+        // the test data already has user 1 in epoch 0
+        let stale = proof_at(
+            0,
+            &KEYSTORES.user1,
+            Position(5, 5),
+            &KEYSTORES.user2,
+            Position(6, 6),
+        );
+        assert!(matches!(
+            invoke_as(
+                &service,
+                &KEYSTORES.user1,
+                ApiRequest::SubmitPositionReport(PoWCertified::new(stale.into())),
+            )
+            .await,
+            ApiReply::Error(ApiErrorCode::StaleProof, _)
+        ));
+
+        // user 3 witnesses from two places at once
+        for p in vec![
+            proof_at(
+                7,
+                &KEYSTORES.user1,
+                Position(0, 0),
+                &KEYSTORES.user3,
+                Position(1, 1),
+            ),
+            proof_at(
+                7,
+                &KEYSTORES.user2,
+                Position(5, 5),
+                &KEYSTORES.user3,
+                Position(6, 6),
+            ),
+        ] {
+            service.store.add_proof(p, u64::MAX).await.unwrap();
+        }
+        assert!(matches!(
+            invoke_as(
+                &service,
+                &KEYSTORES.haclient,
+                ApiRequest::ObtainPositionReportRegular {
+                    user_id: KEYSTORES.user3.my_id(),
+                    epoch: 7,
+                    last_write: None,
+                },
+            )
+            .await,
+            ApiReply::Error(ApiErrorCode::InconsistentUser, _)
+        ));
+
+        assert!(matches!(
+            ApiReply::from(HdltApiError::StorageError(HdltLocalStoreError::DbError(
+                sqlx::Error::PoolClosed
+            ))),
+            ApiReply::Error(ApiErrorCode::StorageFailure, _)
+        ));
+
+        // non-storage errors are not mistaken for storage ones
+        assert!(matches!(
+            invoke_as(
+                &service,
+                &KEYSTORES.user1,
+                ApiRequest::ObtainPositionReportRegular {
+                    user_id: KEYSTORES.user2.my_id(),
+                    epoch: 0,
+                    last_write: None,
+                },
+            )
+            .await,
+            ApiReply::Error(ApiErrorCode::Other, _)
+        ));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn add_proof() {
+        let service = build_service().await;
+        service.config.write().await.epoch = 123;
+        let mut bad_proof: UnverifiedPositionProof =
+            crate::hdlt_store::test::PROOFS[0].clone().into();
+
+        // just in case our test data for hdlt_store becomes valid at some point
+        bad_proof.witnesses[0].signature = Signature::from_slice(&[42u8; 64]).unwrap();
+
+        assert!(matches!(
+            service
+                .submit_position_proof(1, &PoWCertified::new(bad_proof))
+                .await
+                .unwrap_err(),
+            HdltApiError::InvalidPositionProof(..)
+        ));
+
+        let good_proof: UnverifiedPositionProof = proof_at(
+            123,
+            &KEYSTORES.user1,
+            Position(123, 123),
+            &KEYSTORES.user2,
+            Position(100, 100),
+        )
+        .into();
+        let good_proof = PoWCertified::new(good_proof);
+
+        // can't submit someone else's stuff
+        assert!(matches!(
+            service.submit_position_proof(4321, &good_proof).await,
+            Err(HdltApiError::PermissionDenied)
+        ));
+
+        // happy path
+        assert!(service.submit_position_proof(1, &good_proof).await.is_ok());
+
+        // retried submissions of the same proof succeed
+        assert!(service.submit_position_proof(1, &good_proof).await.is_ok());
+
+        // but different proofs for the same epoch are still stale
+        let other_proof: UnverifiedPositionProof = proof_at(
+            123,
+            &KEYSTORES.user1,
+            Position(120, 120),
+            &KEYSTORES.user2,
+            Position(100, 100),
+        )
+        .into();
+        assert!(matches!(
+            service
+                .submit_position_proof(1, &PoWCertified::new(other_proof))
+                .await,
+            Err(HdltApiError::StorageError(HdltLocalStoreError::StaleProof))
+        ));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn read_your_writes() {
+        let service = build_service().await;
+        service.config.write().await.epoch = 123;
+
+        let proof: UnverifiedPositionProof = proof_at(
+            123,
+            &KEYSTORES.user1,
+            Position(123, 123),
+            &KEYSTORES.user2,
+            Position(100, 100),
+        )
+        .into();
+
+        // the read reaches the server before the write does
+        let (read, _) = tokio::join!(
+            service.obtain_position_report_regular(1, 1, 123, Some(123)),
+            async {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                service
+                    .submit_position_proof(1, &PoWCertified::new(proof.clone()))
+                    .await
+                    .unwrap();
+            }
+        );
+        assert_eq!(UnverifiedPositionProof::from(read.unwrap()), proof);
+
+        // only users' own writes are waited for
+        let start = Instant::now();
+        assert!(matches!(
+            service
+                .obtain_position_report_regular(KEYSTORES.haclient.my_id(), 1, 124, Some(124))
+                .await,
+            Err(HdltApiError::NoData)
+        ));
+        assert!(start.elapsed() < READ_YOUR_WRITES_TIMEOUT);
+
+        // nor are writes that could never be stored
+        for &last_write in &[125, u64::MAX] {
+            let start = Instant::now();
+            assert!(matches!(
+                service
+                    .obtain_position_report_regular(1, 1, 124, Some(last_write))
+                    .await,
+                Err(HdltApiError::FutureRequest {
+                    current_epoch: 123,
+                    ..
+                })
+            ));
+            assert!(start.elapsed() < READ_YOUR_WRITES_TIMEOUT);
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn add_proof_past_max_epoch() {
+        let service = build_service().await;
+        service.config.write().await.epoch = 123;
+        service.config.write().await.max_epoch = 122;
+
+        let proof: UnverifiedPositionProof = proof_at(
+            123,
+            &KEYSTORES.user1,
+            Position(123, 123),
+            &KEYSTORES.user2,
+            Position(100, 100),
+        )
+        .into();
+        let proof = PoWCertified::new(proof);
+
+        assert!(matches!(
+            service.submit_position_proof(1, &proof).await,
+            Err(HdltApiError::PastMaxEpoch {
+                epoch: 123,
+                max_epoch: 122
+            })
+        ));
+
+        // the last epoch itself is fine
+        service.config.write().await.max_epoch = 123;
+        assert!(service.submit_position_proof(1, &proof).await.is_ok());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn add_proof_out_of_bounds() {
+        use model::WorldBounds;
+
+        let service = build_service().await;
+        service.config.write().await.epoch = 123;
+        service.config.write().await.bounds =
+            Some(WorldBounds::new(Position(0, 0), Position(110, 110)).unwrap());
+
+        let proof: UnverifiedPositionProof = proof_at(
+            123,
+            &KEYSTORES.user1,
+            Position(123, 123),
+            &KEYSTORES.user2,
+            Position(100, 100),
+        )
+        .into();
+        let proof = PoWCertified::new(proof);
+
+        assert!(matches!(
+            service.submit_position_proof(1, &proof).await,
+            Err(HdltApiError::InvalidPositionProof(
+                PositionProofValidationError::OutOfBounds(Position(123, 123))
+            ))
+        ));
+
+        // the same proof is fine in a larger world
+        service.config.write().await.bounds =
+            Some(WorldBounds::new(Position(0, 0), Position(200, 200)).unwrap());
+        assert!(service.submit_position_proof(1, &proof).await.is_ok());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn add_proof_too_many_witnesses() {
+        let service = build_service().await;
+
+        let proof: UnverifiedPositionProof = proof_at(
+            123,
+            &KEYSTORES.user1,
+            Position(123, 123),
+            &KEYSTORES.user2,
+            Position(100, 100),
+        )
+        .into();
+        let witness = proof.witnesses[0].clone();
+        let proof = UnverifiedPositionProof {
+            witnesses: vec![witness; 5000],
+        };
+
+        // refused before even checking the proof-of-work
+        let bad_pow = [0u8; 32];
+        let proof: PoWCertified<UnverifiedPositionProof> =
+            serde_json::from_value(serde_json::json!({ "inner": proof, "pow": bad_pow })).unwrap();
+        assert!(matches!(
+            service.submit_position_proof(1, &proof).await,
+            Err(HdltApiError::TooManyWitnesses {
+                count: 5000,
+                max: 16
+            })
+        ));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn position_reports_diagnostics() {
+        let service = build_service().await;
+        service.config.write().await.epoch = 103;
+
+        let user_id = KEYSTORES.user1.my_id();
+        for epoch in 100..103 {
+            let proof = proof_at(
+                epoch,
+                &KEYSTORES.user1,
+                Position(123, 123),
+                &KEYSTORES.user2,
+                Position(100, 100),
+            );
+            service
+                .submit_position_proof(user_id, &PoWCertified::new(proof.into()))
+                .await
+                .unwrap();
+        }
+
+        // a single witness is no longer enough for the last two
+        service.config.write().await.set_max_neigh_faults(101, 2);
+        let (reports, skipped) = service
+            .get_position_reports_with_diagnostics(user_id, user_id, 100, 200)
+            .await
+            .unwrap();
+        assert_eq!(
+            reports
+                .into_iter()
+                .map(|(epoch, _)| epoch)
+                .collect::<Vec<_>>(),
+            vec![100]
+        );
+        let under_quorum = ApiErrorCode::InsufficientQuorum {
+            required: 2,
+            available: 1,
+        };
+        // in epoch order, with the epochs that have nothing at all (up to the ones proofs can
+        // currently be submitted for)
+        let expected_skipped = vec![
+            (101, under_quorum),
+            (102, under_quorum),
+            (103, ApiErrorCode::NoData),
+            (104, ApiErrorCode::NoData),
+        ];
+        assert_eq!(skipped, expected_skipped);
+
+        let (_, skipped) = service
+            .get_position_reports_with_diagnostics(user_id, user_id, 98, 101)
+            .await
+            .unwrap();
+        assert_eq!(
+            skipped,
+            vec![(98, ApiErrorCode::NoData), (99, ApiErrorCode::NoData)]
+        );
+
+        // health authorities can ask about anyone, other users can't
+        let (reports, skipped) = service
+            .get_position_reports_with_diagnostics(KEYSTORES.haclient.my_id(), user_id, 100, 200)
+            .await
+            .unwrap();
+        assert_eq!(reports.len(), 1);
+        assert_eq!(skipped, expected_skipped);
+        assert!(matches!(
+            service
+                .get_position_reports_with_diagnostics(KEYSTORES.user2.my_id(), user_id, 100, 200)
+                .await,
+            Err(HdltApiError::PermissionDenied)
+        ));
+
+        // only replied with when asked for
+        let request = |include_diagnostics| ApiRequest::RequestPositionReports {
+            user_id,
+            epoch_start: 100,
+            epoch_end: 200,
+            include_diagnostics,
+        };
+        assert!(matches!(
+            invoke_as(&service, &KEYSTORES.user1, request(false)).await,
+            ApiReply::PositionReports(reports) if reports.len() == 1
+        ));
+        match invoke_as(&service, &KEYSTORES.user1, request(true)).await {
+            ApiReply::PositionReportsWithDiagnostics(reports, skipped) => {
+                assert_eq!(reports.len(), 1);
+                assert_eq!(skipped, expected_skipped);
+            }
+            other => panic!("unexpected reply: {:?}", other),
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn neigh_faults_per_epoch() {
+        let service = build_service().await;
+        service.config.write().await.epoch = 123;
+
+        let proof = |epoch| -> UnverifiedPositionProof {
+            proof_at(
+                epoch,
+                &KEYSTORES.user1,
+                Position(123, 123),
+                &KEYSTORES.user2,
+                Position(100, 100),
+            )
+            .into()
+        };
+
+        // a single witness is no longer enough from now on
+        service.config.write().await.set_max_neigh_faults(123, 2);
+        assert!(matches!(
+            service
+                .submit_position_proof(1, &PoWCertified::new(proof(123)))
+                .await,
+            Err(HdltApiError::InvalidPositionProof(..))
+        ));
+
+        // but it still is for earlier epochs, when writing...
+        service
+            .submit_position_proof(1, &PoWCertified::new(proof(122)))
+            .await
+            .unwrap();
+
+        // ...and reading
+        let read = service
+            .obtain_position_report_regular(1, 1, 122, None)
+            .await
+            .unwrap();
+        assert_eq!(UnverifiedPositionProof::from(read), proof(122));
+        let reports = service.get_position_reports(1, 1, 100, 200).await.unwrap();
+        assert_eq!(
+            reports
+                .into_iter()
+                .map(|(epoch, _)| epoch)
+                .collect::<Vec<_>>(),
+            vec![122]
+        );
+
+        // unless the old tolerance is revised too
+        service.config.write().await.set_max_neigh_faults(0, 2);
+        assert!(matches!(
+            service
+                .obtain_position_report_regular(1, 1, 122, None)
+                .await,
+            Err(HdltApiError::InsufficientQuorum { .. })
+        ));
+    }
+
+    #[test]
+    fn push_listener_caps_register() {
+        let mut listeners = Vec::new();
+        for i in 0..(MAX_LISTENERS_PER_REGISTER + 10) {
+            push_listener(&mut listeners, i, |l| *l);
+        }
+
+        // the oldest listeners are evicted
+        assert_eq!(listeners.len(), MAX_LISTENERS_PER_REGISTER);
+        assert_eq!(listeners[0], 10);
+        assert_eq!(*listeners.last().unwrap(), MAX_LISTENERS_PER_REGISTER + 9);
+    }
+
+    #[test]
+    fn push_listener_replaces_same_key() {
+        let mut listeners = Vec::new();
+        push_listener(&mut listeners, (1, "first"), |(id, _)| *id);
+        push_listener(&mut listeners, (2, "other"), |(id, _)| *id);
+        push_listener(&mut listeners, (1, "retry"), |(id, _)| *id);
+
+        assert_eq!(listeners, vec![(2, "other"), (1, "retry")]);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn single_listener_per_read() {
+        let service = build_service().await;
+        // 4 servers: the value is only returned once 3 of them answer
+        service.config.write().await.servers = vec![101, 102, 103];
+        let ha_client = KEYSTORES.haclient.my_id();
+
+        for _ in 0..2 {
+            // the second time around, a retry
+            service
+                .obtain_position_report(7, 1, ha_client, 0, 0, None)
+                .await
+                .unwrap();
+            assert_eq!(service.client_listeners.read().await[&0].len(), 1);
+            assert_eq!(service.server_listeners.read().await[&0].len(), 1);
+        }
+
+        // the other servers answer
+        let proof = service
+            .obtain_position_report_regular(ha_client, 0, 0, None)
+            .await
+            .unwrap();
+        for server_id in [101, 102].iter() {
+            service
+                .add_value(*server_id, 7, 0, proof.clone().into(), 0)
+                .await
+                .unwrap();
+        }
+
+        // returned exactly once
+        assert!(service.client_listeners.read().await[&0].is_empty());
+        assert_eq!(
+            service.poll_return(ha_client, 7).await,
+            Some((0, UnverifiedPositionProof::from(proof)))
+        );
+        assert_eq!(service.poll_return(ha_client, 7).await, None);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn fan_out_bounds_concurrency() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let in_flight = AtomicUsize::new(0);
+        let max_in_flight = AtomicUsize::new(0);
+
+        let n_listeners = MAX_LISTENERS_PER_REGISTER;
+        let mut results = fan_out(0..n_listeners, |i| {
+            let in_flight = &in_flight;
+            let max_in_flight = &max_in_flight;
+            async move {
+                let now = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                max_in_flight.fetch_max(now, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(1)).await;
+                in_flight.fetch_sub(1, Ordering::SeqCst);
+                i
+            }
+        })
+        .await;
+
+        assert!(max_in_flight.load(Ordering::SeqCst) <= MAX_CONCURRENT_CALLBACKS);
+
+        // no listener is lost
+        results.sort_unstable();
+        assert_eq!(results, (0..n_listeners).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn auth_interceptor_rejects() {
+        let interceptor = auth_interceptor(Arc::new(KEYSTORES.server.clone()));
+
+        // no token
+        let status = interceptor(Request::new(())).unwrap_err();
+        assert_eq!(status.code(), tonic::Code::Unauthenticated);
+
+        // garbage token
+        let mut request = Request::new(());
+        request
+            .metadata_mut()
+            .insert(AUTH_METADATA_KEY, "1.2.3".parse().unwrap());
+        let status = interceptor(request).unwrap_err();
+        assert_eq!(status.code(), tonic::Code::Unauthenticated);
+
+        // token from someone the server doesn't know
+        let unknown = KeyStore::new(EntityPrivComponent::new(12345, Role::User));
+        let mut request = Request::new(());
+        request.metadata_mut().insert(
+            AUTH_METADATA_KEY,
+            AuthToken::new(&unknown).unwrap().encode().parse().unwrap(),
+        );
+        let status = interceptor(request).unwrap_err();
+        assert_eq!(status.code(), tonic::Code::Unauthenticated);
+
+        // valid token
+        let mut request = Request::new(());
+        request.metadata_mut().insert(
+            AUTH_METADATA_KEY,
+            AuthToken::new(&KEYSTORES.user1)
+                .unwrap()
+                .encode()
+                .parse()
+                .unwrap(),
+        );
+        assert!(interceptor(request).is_ok());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn token_for_another_sender() {
+        let service = build_service().await;
+        let server_id = KEYSTORES.server.my_id();
+        let request_with_token = |token_keystore: &KeyStore| {
+            let msg = RrMessage::new_request(0, ApiRequest::GetEpoch);
+            let plaintext = bincode::serialize(&msg).unwrap();
+            let (ciphertext, nonce) = KEYSTORES.user1.cipher(server_id, &plaintext).unwrap();
+            let mut request = tonic::Request::new(CipheredRrMessage {
+                sender_id: KEYSTORES.user1.my_id(),
+                ciphertext,
+                nonce: nonce.0.to_vec(),
+            });
+            request.metadata_mut().insert(
+                AUTH_METADATA_KEY,
+                AuthToken::new(token_keystore)
+                    .unwrap()
+                    .encode()
+                    .parse()
+                    .unwrap(),
+            );
+            request
+        };
+
+        // user2's (valid) token can't be used to send user1's messages
+        let status = service
+            .invoke(request_with_token(&KEYSTORES.user2))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::Unauthenticated);
+
+        assert!(service
+            .invoke(request_with_token(&KEYSTORES.user1))
+            .await
+            .is_ok());
+    }
+}
+
+#[derive(Debug)]
+pub struct HdltApiClient {
+    /// All the GRPC channels
+    channel: Channel,
+
+    /// Server Id
+    id: EntityId,
+
+    /// Key store
+    keystore: Arc<KeyStore>,
+
+    /// Current epoch: works as a timestamp in the procotol,
+    /// since there must only be one proof per epoch
+    ///
+    current_epoch: u64,
+}
+
+#[derive(Debug, Error)]
+pub enum HdltError {
+    #[error("Error creating remote")]
+    InitializationError(#[source] tonic::transport::Error),
+
+    #[error("Server sent unexpected status")]
+    UnexpectedStatus(#[from] Status),
+
+    #[error(transparent)]
+    Api(#[from] ApiClientError),
+}
+
+impl HdltApiClient {
+    pub fn new(
+        uri: Uri,
+        id: EntityId,
+        keystore: Arc<KeyStore>,
         current_epoch: u64,
     ) -> HdltResult<Self> {
         let channel = Channel::builder(uri)
@@ -790,6 +3156,7 @@ impl HdltApiClient {
     pub async fn return_value<T: Into<UnverifiedPositionProof> + Debug>(
         &self,
         request_id: u64,
+        correlation_id: u64,
         proof: T,
         epoch: u64,
         client_id: EntityId,
@@ -797,6 +3164,7 @@ impl HdltApiClient {
         let proof = proof.into();
         self.invoke_no_wait(ApiRequest::ReturnAtomicValue {
             request_id,
+            correlation_id,
             proof,
             epoch,
             client_id,
@@ -846,6 +3214,7 @@ impl HdltApiClient {
     ///  - This has an implicit authenticated because both
     ///     the user and the server derive a key in the same way
     ///
+    #[instrument(skip(self, payload), fields(plaintext_len, ciphertext_len))]
     fn prepare_request(
         &self,
         payload: ApiRequest,
@@ -854,22 +3223,34 @@ impl HdltApiClient {
     ) -> HdltResult<(RrRequest<ApiRequest>, tonic::Request<CipheredRrMessage>)> {
         let request_msg = RrMessage::new_request(current_epoch, payload);
 
-        let plaintext = bincode::serialize(&request_msg).map_err(HdltError::SerializationError)?;
+        let plaintext =
+            bincode::serialize(&request_msg).map_err(ApiClientError::SerializationError)?;
         let (ciphertext, nonce) = self
             .keystore
             .cipher(server_id, &plaintext)
-            .map_err(HdltError::CipherError)?;
-        let grpc_request = Request!(CipheredRrMessage {
+            .map_err(ApiClientError::CipherError)?;
+        Span::current().record("plaintext_len", &plaintext.len());
+        Span::current().record("ciphertext_len", &ciphertext.len());
+        let mut grpc_request = Request!(CipheredRrMessage {
             sender_id: self.keystore.my_id(),
             ciphertext,
             nonce: nonce.0.to_vec(),
         });
+        let token = AuthToken::new(&self.keystore).map_err(ApiClientError::AuthTokenError)?;
+        grpc_request.metadata_mut().insert(
+            AUTH_METADATA_KEY,
+            token.encode().parse().unwrap(), // encoding is always valid ascii
+        );
 
         let request = request_msg.downcast_request(current_epoch).unwrap(); // impossible to fail
 
         Ok((request, grpc_request))
     }
 
+    #[instrument(
+        skip(self, grpc_response, request),
+        fields(plaintext_len, ciphertext_len)
+    )]
     fn parse_response(
         &self,
         grpc_response: tonic::Response<CipheredRrMessage>,
@@ -878,17 +3259,20 @@ impl HdltApiClient {
         server_id: u32,
     ) -> HdltResult<ApiReply> {
         let grpc_response = grpc_response.into_inner();
-        let nonce = Nonce::from_slice(&grpc_response.nonce).ok_or(HdltError::InvalidNonce)?;
+        Span::current().record("ciphertext_len", &grpc_response.ciphertext.len());
+        let nonce = Nonce::from_slice(&grpc_response.nonce).ok_or(ApiClientError::InvalidNonce)?;
 
         let plaintext = self
             .keystore
             .decipher(server_id, &grpc_response.ciphertext, &nonce)
-            .map_err(HdltError::DecipherError)?;
+            .map_err(ApiClientError::DecipherError)?;
+        Span::current().record("plaintext_len", &plaintext.len());
         let reply_rr_message: RrMessage<ApiReply> =
-            bincode::deserialize(&plaintext).map_err(HdltError::DeserializationError)?;
+            bincode::deserialize(&plaintext).map_err(ApiClientError::DeserializationError)?;
 
         Ok(reply_rr_message
-            .downcast_reply(&request, current_epoch)?
+            .downcast_reply(&request, current_epoch)
+            .map_err(ApiClientError::from)?
             .into_inner())
     }
 }
@@ -1,5 +1,8 @@
+mod admin;
+pub use admin::AdminService;
+
 mod driver;
-pub use driver::Driver;
+pub use driver::{reload_config, Driver, ServerConfig};
 
 mod hdlt_api;
-pub use hdlt_api::HdltApiService;
+pub use hdlt_api::{auth_interceptor, HdltApiService, PositionCoarsening};
@@ -0,0 +1,590 @@
+//! Proof storage split over several [HdltLocalStore]s, to spread write contention.
+//!
+//! Proofs go to the shard of their prover (`prover_id % shards`), along with the accounts of
+//! their witnesses. Queries about a prover only touch its shard, others are sent to every shard
+//! and their results merged.
+//!
+//! Misbehaviour within a shard is detected by that shard. A user contradicting themselves across
+//! shards (e.g. as the witness of provers in another shard) is caught when the second report is
+//! added, and recorded as a misbehaviour proof in the user's shard, see
+//! [ShardedHdltLocalStore::add_proof]. Single-shard stores behave exactly like a [HdltLocalStore].
+
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::future::Future;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use model::{
+    keys::{EntityId, KeyStore},
+    MisbehaviorProof, Position, PositionProof, ProximityProof,
+};
+
+use crate::hdlt_store::{HdltLocalStore, HdltLocalStoreError, StoreStats};
+
+#[derive(Debug)]
+pub struct ShardedHdltLocalStore {
+    shards: Vec<HdltLocalStore>,
+}
+
+impl ShardedHdltLocalStore {
+    /// Open (or create) the shards of a store.
+    ///
+    /// A single shard is kept at `path` itself, so existing storage can be opened as is.
+    /// Otherwise shard `i` is kept at `path` with `.i` appended.
+    pub async fn open<P: AsRef<Path>>(
+        path: P,
+        n_shards: NonZeroUsize,
+    ) -> Result<Self, HdltLocalStoreError> {
+        let mut shards = Vec::with_capacity(n_shards.get());
+        for i in 0..n_shards.get() {
+            shards.push(HdltLocalStore::open(shard_path(path.as_ref(), i, n_shards)).await?);
+        }
+
+        Ok(ShardedHdltLocalStore::new(shards))
+    }
+
+    /// Like [open](Self::open), verifying every shard, see [HdltLocalStore::open_verified].
+    pub async fn open_verified<P: AsRef<Path>>(
+        path: P,
+        n_shards: NonZeroUsize,
+        keystore: &KeyStore,
+    ) -> Result<Self, HdltLocalStoreError> {
+        let mut shards = Vec::with_capacity(n_shards.get());
+        for i in 0..n_shards.get() {
+            let shard_path = shard_path(path.as_ref(), i, n_shards);
+            shards.push(HdltLocalStore::open_verified(shard_path, keystore).await?);
+        }
+
+        Ok(ShardedHdltLocalStore::new(shards))
+    }
+
+    /// Like [open](Self::open), without changing any shard, see [HdltLocalStore::open_read_only].
+    pub async fn open_read_only<P: AsRef<Path>>(
+        path: P,
+        n_shards: NonZeroUsize,
+    ) -> Result<Self, HdltLocalStoreError> {
+        let mut shards = Vec::with_capacity(n_shards.get());
+        for i in 0..n_shards.get() {
+            let shard_path = shard_path(path.as_ref(), i, n_shards);
+            shards.push(HdltLocalStore::open_read_only(shard_path).await?);
+        }
+
+        Ok(ShardedHdltLocalStore::new(shards))
+    }
+
+    /// Use already open stores as shards, in order. Panics without any.
+    pub fn new(shards: Vec<HdltLocalStore>) -> Self {
+        assert!(!shards.is_empty(), "sharded store needs at least one shard");
+        ShardedHdltLocalStore { shards }
+    }
+
+    /// Shard holding the proofs of a prover
+    fn shard(&self, prover_id: EntityId) -> &HdltLocalStore {
+        &self.shards[prover_id as usize % self.shards.len()]
+    }
+
+    /// Run a query on every shard concurrently, returning their results in shard order
+    async fn all_shards<'a, T, F, Fut>(&'a self, query: F) -> Result<Vec<T>, HdltLocalStoreError>
+    where
+        F: FnMut(&'a HdltLocalStore) -> Fut,
+        Fut: Future<Output = Result<T, HdltLocalStoreError>>,
+    {
+        futures::future::try_join_all(self.shards.iter().map(query)).await
+    }
+
+    /// See [HdltLocalStore::delete_user].
+    pub async fn delete_user(&self, user_id: EntityId) -> Result<u64, HdltLocalStoreError> {
+        self.shard(user_id).delete_user(user_id).await
+    }
+
+    /// See [HdltLocalStore::restore_user].
+    pub async fn restore_user(&self, user_id: EntityId) -> Result<u64, HdltLocalStoreError> {
+        self.shard(user_id).restore_user(user_id).await
+    }
+
+    /// See [HdltLocalStore::purge_tombstones].
+    pub async fn purge_tombstones(&self, before: SystemTime) -> Result<u64, HdltLocalStoreError> {
+        Ok(self
+            .all_shards(|shard| shard.purge_tombstones(before))
+            .await?
+            .into_iter()
+            .sum())
+    }
+
+    /// See [HdltLocalStore::add_proof].
+    ///
+    /// Once stored, what the prover and each witness claim is checked against the other shards,
+    /// and contradictions are kept as misbehaviour proofs. Checking after storing means that of two
+    /// concurrent contradicting proofs, at least the one stored last sees the other.
+    pub async fn add_proof(
+        &self,
+        proof: PositionProof,
+        current_epoch: u64,
+    ) -> Result<(), HdltLocalStoreError> {
+        let prover_shard = proof.prover_id() as usize % self.shards.len();
+        self.shards[prover_shard]
+            .add_proof(proof.clone(), current_epoch)
+            .await?;
+
+        // every account has the prover's claim, and each its witness'
+        let claims = std::iter::once((proof.prover_id(), &proof.witnesses()[0])).chain(
+            proof
+                .witnesses()
+                .iter()
+                .map(|account| (account.witness_id(), account)),
+        );
+        for (user_id, claim) in claims {
+            for (_, shard) in self
+                .shards
+                .iter()
+                .enumerate()
+                .filter(|&(idx, _)| idx != prover_shard)
+            {
+                let contradiction = shard
+                    .query_epoch_user(proof.epoch(), user_id)
+                    .await?
+                    .into_iter()
+                    .find_map(|other| MisbehaviorProof::new(user_id, claim.clone(), other).ok());
+
+                if let Some(misbehaviour) = contradiction {
+                    self.add_misbehaviour_proof(misbehaviour).await?;
+                    break;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// See [HdltLocalStore::add_misbehaviour_proof]. Kept in the shard of the misbehaving user.
+    pub async fn add_misbehaviour_proof(
+        &self,
+        proof: MisbehaviorProof,
+    ) -> Result<(), HdltLocalStoreError> {
+        self.shard(proof.user_id())
+            .add_misbehaviour_proof(proof)
+            .await
+    }
+
+    /// See [HdltLocalStore::query_epoch_prover].
+    pub async fn query_epoch_prover(
+        &self,
+        epoch: u64,
+        prover_id: EntityId,
+    ) -> Result<Vec<ProximityProof>, HdltLocalStoreError> {
+        self.shard(prover_id)
+            .query_epoch_prover(epoch, prover_id)
+            .await
+    }
+
+    /// See [HdltLocalStore::witness_count].
+    pub async fn witness_count(
+        &self,
+        epoch: u64,
+        prover_id: EntityId,
+    ) -> Result<usize, HdltLocalStoreError> {
+        self.shard(prover_id).witness_count(epoch, prover_id).await
+    }
+
+    /// See [HdltLocalStore::latest_epoch].
+    pub async fn latest_epoch(
+        &self,
+        prover_id: EntityId,
+    ) -> Result<Option<u64>, HdltLocalStoreError> {
+        self.shard(prover_id).latest_epoch(prover_id).await
+    }
+
+    /// See [HdltLocalStore::epochs_for_prover].
+    pub async fn epochs_for_prover(
+        &self,
+        prover_id: EntityId,
+    ) -> Result<Vec<u64>, HdltLocalStoreError> {
+        self.shard(prover_id).epochs_for_prover(prover_id).await
+    }
+
+    /// See [HdltLocalStore::query_epoch_prover_range].
+    pub async fn query_epoch_prover_range(
+        &self,
+        epoch_range: std::ops::Range<u64>,
+        prover_id: EntityId,
+    ) -> Result<Vec<(u64, Vec<ProximityProof>)>, HdltLocalStoreError> {
+        self.shard(prover_id)
+            .query_epoch_prover_range(epoch_range, prover_id)
+            .await
+    }
+
+    /// See [HdltLocalStore::query_epoch_prover_position].
+    pub async fn query_epoch_prover_position(
+        &self,
+        epoch: u64,
+        prover_position: Position,
+    ) -> Result<Vec<ProximityProof>, HdltLocalStoreError> {
+        let per_shard = self
+            .all_shards(|shard| shard.query_epoch_prover_position(epoch, prover_position))
+            .await?;
+
+        Ok(merge_by_prover(per_shard))
+    }
+
+    /// See [HdltLocalStore::query_epoch_prover_region].
+    pub async fn query_epoch_prover_region(
+        &self,
+        epoch: u64,
+        top_left: Position,
+        bottom_right: Position,
+    ) -> Result<Vec<ProximityProof>, HdltLocalStoreError> {
+        let per_shard = self
+            .all_shards(|shard| shard.query_epoch_prover_region(epoch, top_left, bottom_right))
+            .await?;
+
+        Ok(merge_by_prover(per_shard))
+    }
+
+    /// See [HdltLocalStore::position_histogram].
+    pub async fn position_histogram(
+        &self,
+        epoch: u64,
+    ) -> Result<Vec<(Position, usize)>, HdltLocalStoreError> {
+        let mut counts: HashMap<Position, usize> = HashMap::new();
+        for (position, count) in self
+            .all_shards(|shard| shard.position_histogram(epoch))
+            .await?
+            .into_iter()
+            .flatten()
+        {
+            *counts.entry(position).or_default() += count;
+        }
+
+        let mut histogram: Vec<_> = counts.into_iter().collect();
+        histogram.sort_unstable_by_key(|(position, _)| (position.0, position.1));
+        Ok(histogram)
+    }
+
+    /// See [HdltLocalStore::query_epoch_positions].
+    pub async fn query_epoch_positions(
+        &self,
+        epoch: u64,
+        neighbour_faults: usize,
+    ) -> Result<Vec<(EntityId, Position)>, HdltLocalStoreError> {
+        let mut positions: Vec<_> = self
+            .all_shards(|shard| shard.query_epoch_positions(epoch, neighbour_faults))
+            .await?
+            .into_iter()
+            .flatten()
+            .collect();
+
+        positions.sort_by_key(|(prover_id, _)| *prover_id);
+        Ok(positions)
+    }
+
+    /// See [HdltLocalStore::query_misbehaved].
+    pub async fn query_misbehaved(
+        &self,
+        id: EntityId,
+    ) -> Result<Option<MisbehaviorProof>, HdltLocalStoreError> {
+        Ok(self
+            .all_shards(|shard| shard.query_misbehaved(id))
+            .await?
+            .into_iter()
+            .flatten()
+            .next())
+    }
+
+    /// See [HdltLocalStore::query_all_misbehaviour]. Still one proof per epoch, even if several
+    /// shards have one.
+    pub async fn query_all_misbehaviour(
+        &self,
+        id: EntityId,
+    ) -> Result<Vec<MisbehaviorProof>, HdltLocalStoreError> {
+        let mut proofs: Vec<_> = self
+            .all_shards(|shard| shard.query_all_misbehaviour(id))
+            .await?
+            .into_iter()
+            .flatten()
+            .collect();
+
+        proofs.sort_by_key(|proof| proof.a().epoch());
+        proofs.dedup_by_key(|proof| proof.a().epoch());
+        Ok(proofs)
+    }
+
+    /// See [HdltLocalStore::stats]. Users that misbehaved in several shards are counted once per shard.
+    pub async fn stats(&self) -> Result<StoreStats, HdltLocalStoreError> {
+        let per_shard = self.all_shards(|shard| shard.stats()).await?;
+
+        Ok(StoreStats {
+            proofs: per_shard.iter().map(|stats| stats.proofs).sum(),
+            misbehaving_users: per_shard.iter().map(|stats| stats.misbehaving_users).sum(),
+        })
+    }
+
+    /// See [HdltLocalStore::reverify]. Each shard is checked on its own.
+    pub async fn reverify(&self) -> Result<Vec<MisbehaviorProof>, HdltLocalStoreError> {
+        let mut missed: Vec<_> = self
+            .all_shards(|shard| shard.reverify())
+            .await?
+            .into_iter()
+            .flatten()
+            .collect();
+
+        missed.sort_by_key(|proof| (proof.a().epoch(), proof.user_id()));
+        Ok(missed)
+    }
+}
+
+impl From<HdltLocalStore> for ShardedHdltLocalStore {
+    /// A store with a single shard
+    fn from(store: HdltLocalStore) -> Self {
+        ShardedHdltLocalStore::new(vec![store])
+    }
+}
+
+/// Path of a shard's storage file, see [ShardedHdltLocalStore::open]
+fn shard_path(path: &Path, shard: usize, n_shards: NonZeroUsize) -> PathBuf {
+    if n_shards.get() == 1 {
+        return path.to_owned();
+    }
+
+    let mut shard_path = OsString::from(path);
+    shard_path.push(format!(".{}", shard));
+    shard_path.into()
+}
+
+/// Concatenate per-shard results, ordered by prover like in a single store.
+///
+/// A prover's proofs are all in one shard, already ordered by witness: a stable sort keeps them so.
+fn merge_by_prover(per_shard: Vec<Vec<ProximityProof>>) -> Vec<ProximityProof> {
+    let mut proofs: Vec<_> = per_shard.into_iter().flatten().collect();
+    proofs.sort_by_key(|proof| proof.prover_id());
+    proofs
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::hdlt_store::test::PROOFS;
+
+    /// Current epoch under which no proof is too far ahead
+    const ANY_EPOCH: u64 = u64::MAX;
+
+    /// Two shards with the test proofs: those of user 0 in the first, those of user 1 in the second
+    async fn build_sharded_store() -> ShardedHdltLocalStore {
+        let store = ShardedHdltLocalStore::new(vec![
+            HdltLocalStore::open_memory().await,
+            HdltLocalStore::open_memory().await,
+        ]);
+
+        for p in &*PROOFS {
+            store.add_proof(p.clone(), ANY_EPOCH).await.unwrap();
+        }
+
+        store
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn writes_land_in_prover_shard() {
+        let store = build_sharded_store().await;
+
+        for prover_id in 0..2 {
+            let (own, other) = (prover_id as usize, 1 - prover_id as usize);
+            assert_eq!(
+                store.shards[own]
+                    .epochs_for_prover(prover_id)
+                    .await
+                    .unwrap(),
+                vec![0, 1]
+            );
+            assert!(store.shards[other]
+                .epochs_for_prover(prover_id)
+                .await
+                .unwrap()
+                .is_empty());
+
+            // and are read back from there
+            assert_eq!(
+                store.query_epoch_prover(0, prover_id).await.unwrap(),
+                store.shards[own]
+                    .query_epoch_prover(0, prover_id)
+                    .await
+                    .unwrap()
+            );
+        }
+
+        // staleness is still checked, since a prover's proofs are all in one place
+        assert!(matches!(
+            store.add_proof(PROOFS[0].clone(), ANY_EPOCH).await,
+            Err(HdltLocalStoreError::StaleProof)
+        ));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn cross_shard_queries() {
+        let store = build_sharded_store().await;
+
+        // both users were at (0, 1) in epoch 1, each in their own shard
+        let proofs = store
+            .query_epoch_prover_position(1, Position(0, 1))
+            .await
+            .unwrap();
+        assert_eq!(
+            proofs.iter().map(|p| p.prover_id()).collect::<Vec<_>>(),
+            vec![0, 1]
+        );
+        assert_eq!(
+            store
+                .query_epoch_prover_region(0, Position(0, 0), Position(1, 0))
+                .await
+                .unwrap()
+                .iter()
+                .map(|p| p.prover_id())
+                .collect::<Vec<_>>(),
+            vec![0, 1]
+        );
+
+        assert_eq!(
+            store.position_histogram(1).await.unwrap(),
+            vec![(Position(0, 1), 2)]
+        );
+        assert_eq!(
+            store.position_histogram(0).await.unwrap(),
+            vec![(Position(0, 0), 1), (Position(1, 0), 1)]
+        );
+        assert_eq!(
+            store.query_epoch_positions(1, 1).await.unwrap(),
+            vec![(0, Position(0, 1)), (1, Position(0, 1))]
+        );
+        assert_eq!(store.stats().await.unwrap().proofs, 4);
+
+        // deleting a user only touches their shard, but every shard is purged
+        assert_eq!(store.delete_user(1).await.unwrap(), 2);
+        assert_eq!(
+            store.query_epoch_positions(1, 1).await.unwrap(),
+            vec![(0, Position(0, 1))]
+        );
+        assert_eq!(
+            store
+                .purge_tombstones(SystemTime::now() + std::time::Duration::from_secs(1))
+                .await
+                .unwrap(),
+            2
+        );
+        assert_eq!(store.stats().await.unwrap().proofs, 2);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn cross_shard_misbehaviour() {
+        use model::keys::test_data::KeyStoreTestData;
+        use model::ProximityProofRequest;
+
+        let keystores = KeyStoreTestData::new();
+        let proof = |epoch, prover: &KeyStore, position, witness: &KeyStore, witness_position| {
+            let req = ProximityProofRequest::new(epoch, position, prover);
+            let account = ProximityProof::new(req, witness_position, witness).unwrap();
+            PositionProof::new(vec![account], 1).unwrap()
+        };
+        let store = ShardedHdltLocalStore::new(vec![
+            HdltLocalStore::open_memory().await,
+            HdltLocalStore::open_memory().await,
+        ]);
+        let (user1, user2, user3) = (
+            keystores.user1.my_id(),
+            keystores.user2.my_id(),
+            keystores.user3.my_id(),
+        );
+        assert_ne!(
+            user1 % 2,
+            user2 % 2,
+            "users 1 and 2 must be in different shards"
+        );
+
+        // user 2 witnesses user 1 from (2, 2), but proves to be at (7, 7): each in its own shard
+        store
+            .add_proof(
+                proof(
+                    5,
+                    &keystores.user1,
+                    Position(1, 1),
+                    &keystores.user2,
+                    Position(2, 2),
+                ),
+                ANY_EPOCH,
+            )
+            .await
+            .unwrap();
+        store
+            .add_proof(
+                proof(
+                    5,
+                    &keystores.user2,
+                    Position(7, 7),
+                    &keystores.user3,
+                    Position(7, 8),
+                ),
+                ANY_EPOCH,
+            )
+            .await
+            .unwrap();
+
+        let misbehaviour = store.query_all_misbehaviour(user2).await.unwrap();
+        assert_eq!(misbehaviour.len(), 1);
+        assert_eq!(misbehaviour[0].a().epoch(), 5);
+        assert!(matches!(
+            store.query_epoch_prover(5, user2).await,
+            Err(HdltLocalStoreError::InconsistentUser(..))
+        ));
+        for &id in &[user1, user3] {
+            assert!(store.query_misbehaved(id).await.unwrap().is_none());
+        }
+
+        // the same claims across shards are fine
+        store
+            .add_proof(
+                proof(
+                    6,
+                    &keystores.user1,
+                    Position(1, 1),
+                    &keystores.user2,
+                    Position(2, 2),
+                ),
+                ANY_EPOCH,
+            )
+            .await
+            .unwrap();
+        store
+            .add_proof(
+                proof(
+                    6,
+                    &keystores.user2,
+                    Position(2, 2),
+                    &keystores.user1,
+                    Position(1, 1),
+                ),
+                ANY_EPOCH,
+            )
+            .await
+            .unwrap();
+        assert_eq!(store.query_all_misbehaviour(user2).await.unwrap().len(), 1);
+        assert!(store.query_misbehaved(user1).await.unwrap().is_none());
+    }
+
+    #[test]
+    fn shard_paths() {
+        let path = Path::new("/tmp/storage.db");
+        let one = NonZeroUsize::new(1).unwrap();
+        let three = NonZeroUsize::new(3).unwrap();
+
+        assert_eq!(shard_path(path, 0, one), path);
+        assert_eq!(
+            (0..3)
+                .map(|i| shard_path(path, i, three))
+                .collect::<Vec<_>>(),
+            vec![
+                PathBuf::from("/tmp/storage.db.0"),
+                PathBuf::from("/tmp/storage.db.1"),
+                PathBuf::from("/tmp/storage.db.2"),
+            ]
+        );
+    }
+}